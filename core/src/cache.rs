@@ -0,0 +1,161 @@
+//! Persistent caches of values computed from documents
+use anyhow::{Context, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::UNIX_EPOCH,
+};
+
+use crate::{
+    doc,
+    lock::FileLock,
+    root::DocRoot,
+    state::StateDir,
+    timing::{self, Counter},
+};
+
+/// A cache of values computed from files, stored in `.veisku/state/cache/NAME`.
+/// The entries are invalidated by the modification times and sizes of the
+/// files. Changes are saved when the cache is dropped.
+#[derive(Debug)]
+pub struct FileCache<T: Serialize> {
+    /// `None` if the cache is not persisted
+    path: Option<PathBuf>,
+    state_dir: StateDir,
+    state: Mutex<CacheState<T>>,
+}
+
+#[derive(Debug)]
+struct CacheState<T> {
+    entries: HashMap<String, CacheEntry<T>>,
+    dirty: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry<T> {
+    secs: u64,
+    nanos: u32,
+    len: u64,
+    value: T,
+}
+
+impl<T: Clone + Serialize + DeserializeOwned> FileCache<T> {
+    pub fn load(root: &DocRoot, name: &str) -> Self {
+        // Don't create the configuration directory, which would change the
+        // document root
+        let path = if root.cfg_dir_path.is_dir() {
+            Some(root.state_dir_path.join("cache").join(name))
+        } else {
+            None
+        };
+        let entries = path
+            .as_ref()
+            .and_then(|path| std::fs::read(path).ok())
+            .and_then(|bytes| match serde_json::from_slice(&bytes) {
+                Ok(entries) => Some(entries),
+                Err(e) => {
+                    log::debug!("Discarding the malformed cache {:?}: {:?}", path, e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        Self {
+            path,
+            state_dir: root.state_dir(),
+            state: Mutex::new(CacheState {
+                entries,
+                dirty: false,
+            }),
+        }
+    }
+
+    /// Get the value for the file at `path`, calling `f` to compute it if
+    /// necessary.
+    pub fn get(&self, path: &Path, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        let key = path.to_string_lossy().into_owned();
+        let metadata = std::fs::metadata(path)
+            .with_context(|| format!("Failed to get the metadata of {:?}", path))?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok());
+
+        if let Some(modified) = modified {
+            let state = self.state.lock().unwrap();
+            if let Some(entry) = state.entries.get(&key) {
+                if entry.secs == modified.as_secs()
+                    && entry.nanos == modified.subsec_nanos()
+                    && entry.len == metadata.len()
+                {
+                    timing::count(Counter::CacheHits);
+                    return Ok(entry.value.clone());
+                }
+            }
+        }
+        timing::count(Counter::CacheMisses);
+
+        let value = f()?;
+
+        if let Some(modified) = modified {
+            let mut state = self.state.lock().unwrap();
+            state.entries.insert(
+                key,
+                CacheEntry {
+                    secs: modified.as_secs(),
+                    nanos: modified.subsec_nanos(),
+                    len: metadata.len(),
+                    value: value.clone(),
+                },
+            );
+            state.dirty = true;
+        }
+
+        Ok(value)
+    }
+}
+
+impl<T: Serialize> FileCache<T> {
+    /// Write the cache back if it was modified.
+    pub fn save(&self) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let path = match &self.path {
+            Some(path) if state.dirty => path,
+            _ => return Ok(()),
+        };
+
+        self.state_dir.prepare()?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+        }
+
+        // Keep the entries added by other processes since the cache was
+        // loaded. Outdated entries are harmless because they are validated
+        // on lookup.
+        let _lock = FileLock::acquire(path)?;
+        if let Some(entries) = std::fs::read(path).ok().and_then(|bytes| {
+            serde_json::from_slice::<HashMap<String, serde_json::Value>>(&bytes).ok()
+        }) {
+            let mut json = serde_json::to_value(&state.entries).unwrap();
+            let ours = json.as_object_mut().unwrap();
+            for (key, entry) in entries {
+                ours.entry(key).or_insert(entry);
+            }
+            doc::write_atomic(path, &serde_json::to_vec(&json).unwrap())?;
+        } else {
+            doc::write_atomic(path, &serde_json::to_vec(&state.entries).unwrap())?;
+        }
+        state.dirty = false;
+        Ok(())
+    }
+}
+
+impl<T: Serialize> Drop for FileCache<T> {
+    fn drop(&mut self) {
+        if let Err(e) = self.save() {
+            log::warn!("Failed to save the cache {:?}: {:?}", self.path, e);
+        }
+    }
+}
@@ -0,0 +1,1517 @@
+//! Document root configuration and search criteria
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    str::FromStr,
+};
+
+// Search criteria
+// --------------------------------------------------------------------
+
+/// A search criterion. See the `v` command's help for the syntax.
+#[derive(Debug, Clone)]
+pub enum Criterion {
+    NameSmart(String),
+    Simple {
+        negate: bool,
+        simple_criterion: SimpleCriterion,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub enum SimpleCriterion {
+    NameRegex(RegexCriterion),
+    /// `name=VALUE`
+    NameExact(String),
+    MetaEq(String, String),
+    MetaRegex(String, RegexCriterion),
+    MetaCmp(String, CmpOp, String),
+    /// `KEY:LOW..HIGH` (inclusive)
+    MetaRange(String, String, String),
+    /// `KEY:all(A,B,...)`, `KEY:any(...)`, or `KEY:none(...)`
+    MetaSet(String, SetOp, Vec<String>),
+    /// `#TAG`, which also matches the descendants of `TAG` (see
+    /// [`crate::tag`])
+    Tag(String),
+}
+
+/// How the values of `KEY:all(...)` and its variants must be present in a
+/// field
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetOp {
+    All,
+    Any,
+    None,
+}
+
+/// A regex in the form `/REGEX/FLAGS`
+#[derive(Debug, Clone)]
+pub struct RegexCriterion {
+    pub pattern: String,
+    /// The flags following the closing `/`:
+    ///
+    ///  - `i`: case-insensitive
+    ///  - `c`: case-sensitive (overrides `regex.case_insensitive`)
+    ///  - `x`: ignore whitespace and allow `#` comments
+    ///  - `s`: `.` matches `\n`
+    ///  - `m`: `^` and `$` match line boundaries
+    ///  - `a`: anchored (must match the whole name or value)
+    pub flags: String,
+}
+
+impl RegexCriterion {
+    const FLAGS: &'static str = "icxsma";
+
+    /// Parse `/REGEX/FLAGS`. Returns `None` if `s` isn't in this form.
+    fn parse(s: &str) -> Option<Self> {
+        let s = s.strip_prefix('/')?;
+        let end = s.rfind('/')?;
+        let flags = &s[end + 1..];
+        if flags.chars().all(|c| Self::FLAGS.contains(c)) {
+            Some(Self {
+                pattern: s[..end].to_owned(),
+                flags: flags.to_owned(),
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn has_flag(&self, flag: char) -> bool {
+        self.flags.contains(flag)
+    }
+}
+
+/// A comparison operator of `KEY:<VALUE` and its variants
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CmpOp {
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Ne,
+}
+
+impl CmpOp {
+    /// Check if `ordering` (the field value compared with the operand)
+    /// satisfies the operator.
+    pub fn test(self, ordering: std::cmp::Ordering) -> bool {
+        use std::cmp::Ordering::*;
+        match self {
+            Self::Lt => ordering == Less,
+            Self::Gt => ordering == Greater,
+            Self::Le => ordering != Greater,
+            Self::Ge => ordering != Less,
+            Self::Ne => ordering != Equal,
+        }
+    }
+}
+
+impl FromStr for Criterion {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negate, s) = if let Some(s) = s.strip_prefix("!") {
+            (true, s)
+        } else {
+            (false, s)
+        };
+
+        if let Some(regex) = RegexCriterion::parse(s) {
+            Ok(Self::Simple {
+                negate,
+                simple_criterion: SimpleCriterion::NameRegex(regex),
+            })
+        } else if let Some(tag) = s.strip_prefix('#').filter(|tag| !tag.is_empty()) {
+            Ok(Self::Simple {
+                negate,
+                simple_criterion: SimpleCriterion::Tag(tag.to_owned()),
+            })
+        } else if let Some(name) = s.strip_prefix("name=") {
+            Ok(Self::Simple {
+                negate,
+                simple_criterion: SimpleCriterion::NameExact(name.to_owned()),
+            })
+        } else if s.starts_with("=") {
+            Err("`=EXPRESSION` syntax is not implemented")
+        } else if let Some(i) = s.find(":") {
+            let key = &s[..i];
+            let value = &s[i + 1..];
+            let cmp = [
+                ("<=", CmpOp::Le),
+                (">=", CmpOp::Ge),
+                ("<>", CmpOp::Ne),
+                ("<", CmpOp::Lt),
+                (">", CmpOp::Gt),
+            ]
+            .iter()
+            .find_map(|&(prefix, op)| Some((op, value.strip_prefix(prefix)?)));
+            if let Some((op, operand)) = cmp {
+                Ok(Self::Simple {
+                    negate,
+                    simple_criterion: SimpleCriterion::MetaCmp(
+                        key.to_owned(),
+                        op,
+                        operand.to_owned(),
+                    ),
+                })
+            } else if let Some(regex) = RegexCriterion::parse(value) {
+                Ok(Self::Simple {
+                    negate,
+                    simple_criterion: SimpleCriterion::MetaRegex(key.to_owned(), regex),
+                })
+            } else if let Some((op, values)) = parse_set(key, value) {
+                Ok(Self::Simple {
+                    negate,
+                    simple_criterion: SimpleCriterion::MetaSet(key.to_owned(), op, values),
+                })
+            } else if let Some((low, high)) = parse_range(key, value) {
+                Ok(Self::Simple {
+                    negate,
+                    simple_criterion: SimpleCriterion::MetaRange(
+                        key.to_owned(),
+                        low.to_owned(),
+                        high.to_owned(),
+                    ),
+                })
+            } else {
+                Ok(Self::Simple {
+                    negate,
+                    simple_criterion: SimpleCriterion::MetaEq(key.to_owned(), value.to_owned()),
+                })
+            }
+        } else {
+            // Smart name search
+            if negate {
+                Err("Smart name search cannot be used with negation")
+            } else {
+                Ok(Self::NameSmart(s.to_owned()))
+            }
+        }
+    }
+}
+
+/// Formats the criterion in the syntax accepted by [`FromStr`], so that a
+/// parsed criterion can be passed on (e.g., to `v daemon`).
+impl std::fmt::Display for Criterion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (negate, simple_criterion) = match self {
+            Self::NameSmart(name) => return f.write_str(name),
+            Self::Simple {
+                negate,
+                simple_criterion,
+            } => (*negate, simple_criterion),
+        };
+        if negate {
+            f.write_str("!")?;
+        }
+        match simple_criterion {
+            SimpleCriterion::NameRegex(regex) => write!(f, "{}", regex),
+            SimpleCriterion::NameExact(name) => write!(f, "name={}", name),
+            SimpleCriterion::MetaEq(key, value) => write!(f, "{}:{}", key, value),
+            SimpleCriterion::MetaRegex(key, regex) => write!(f, "{}:{}", key, regex),
+            SimpleCriterion::MetaCmp(key, op, operand) => {
+                let op = match op {
+                    CmpOp::Lt => "<",
+                    CmpOp::Gt => ">",
+                    CmpOp::Le => "<=",
+                    CmpOp::Ge => ">=",
+                    CmpOp::Ne => "<>",
+                };
+                write!(f, "{}:{}{}", key, op, operand)
+            }
+            SimpleCriterion::MetaRange(key, low, high) => write!(f, "{}:{}..{}", key, low, high),
+            SimpleCriterion::MetaSet(key, op, values) => {
+                let op = match op {
+                    SetOp::All => "all",
+                    SetOp::Any => "any",
+                    SetOp::None => "none",
+                };
+                write!(f, "{}:{}({})", key, op, values.join(","))
+            }
+            SimpleCriterion::Tag(tag) => write!(f, "#{}", tag),
+        }
+    }
+}
+
+impl std::fmt::Display for RegexCriterion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "/{}/{}", self.pattern, self.flags)
+    }
+}
+
+/// Check if `key` is a pseudo-field whose value is always taken literally
+/// (e.g., `changed:main..topic`).
+fn is_literal_key(key: &str) -> bool {
+    matches!(key, "changed" | "in" | "name" | "path" | "contents")
+}
+
+/// Parse `all(A,B,...)`, `any(...)`, or `none(...)`.
+fn parse_set(key: &str, value: &str) -> Option<(SetOp, Vec<String>)> {
+    if is_literal_key(key) {
+        return None;
+    }
+    let (op, rest) = [
+        ("all(", SetOp::All),
+        ("any(", SetOp::Any),
+        ("none(", SetOp::None),
+    ]
+    .iter()
+    .find_map(|&(prefix, op)| Some((op, value.strip_prefix(prefix)?)))?;
+    let values = rest
+        .strip_suffix(')')?
+        .split(',')
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_owned)
+        .collect();
+    Some((op, values))
+}
+
+/// Split `LOW..HIGH` into its bounds. The values of the pseudo-fields taking
+/// revisions or paths (e.g., `changed:main..topic`) are never ranges.
+fn parse_range<'a>(key: &str, value: &'a str) -> Option<(&'a str, &'a str)> {
+    if is_literal_key(key) {
+        return None;
+    }
+    let (low, high) = value.split_once("..")?;
+    if low.is_empty() || high.is_empty() || high.starts_with('.') {
+        return None;
+    }
+    Some((low, high))
+}
+
+// Document root configuration
+// --------------------------------------------------------------------
+
+/// Document root configuration (`.veisku/config.toml`)
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Cfg {
+    /// Modifies the document root. `\\` is treated as a path separator on
+    /// all platforms.
+    #[serde(default)]
+    pub root: String,
+
+    /// Allows the modification of document metadata. Only the modified fields
+    /// are rewritten, and the rest of a preamble (such as comments) is
+    /// preserved.
+    #[serde(default)]
+    pub writable: bool,
+
+    /// Refuses every operation modifying the document root or its
+    /// configuration, even if `writable` is set, as if `--read-only` were
+    /// given.
+    #[serde(default)]
+    pub read_only: bool,
+
+    /// The patterns of file names to recognize as documents. The patterns are
+    /// processed by [`::globwalk`], which supports `gitignore`'s syntax.
+    /// The paths are relative to the document root. `\\` is treated as a path
+    /// separator on all platforms.
+    #[serde(default = "files_default")]
+    pub files: Vec<String>,
+
+    /// Follows symbolic links to directories when enumerating documents.
+    /// Links forming a loop are skipped with a warning. Regardless of this
+    /// option, a document reachable through more than one path is only
+    /// yielded once.
+    #[serde(default = "follow_symlinks_default")]
+    pub follow_symlinks: bool,
+
+    /// The maximum number of directory levels to descend into when
+    /// enumerating documents. `0` only finds the documents directly in the
+    /// document root. Unlimited by default.
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+
+    /// Specifies the text styles applied to various elements
+    #[serde(default)]
+    pub theme: ThemeCfg,
+
+    /// The converters used by `v export`, keyed by target format names.
+    #[serde(default)]
+    pub export: HashMap<String, ExportCfg>,
+
+    /// Configures `v import`.
+    #[serde(default)]
+    pub import: ImportCfg,
+
+    /// Configures `v new`.
+    #[serde(default)]
+    pub new: NewCfg,
+
+    /// Configures how titles are converted to file names (the `{slug}`
+    /// placeholder of `new.name` and `import.name`).
+    #[serde(default)]
+    pub slug: SlugCfg,
+
+    /// The commands to handle encrypted documents, keyed by file extensions
+    /// (e.g., `age` for `*.md.age`). Note that `files` must include patterns
+    /// matching such documents.
+    #[serde(default)]
+    pub encryption: HashMap<String, EncryptionCfg>,
+
+    /// The metadata schema checked by `v validate`, keyed by field names.
+    #[serde(default)]
+    pub schema: BTreeMap<String, FieldCfg>,
+
+    /// Configures `v sync`.
+    #[serde(default)]
+    pub sync: SyncCfg,
+
+    /// The default commands of `v open` for document types (`markdown`,
+    /// `org`, `pdf`, `image`, or `plain`), e.g., `pdf = ["zathura"]`, or MIME
+    /// types detected from the file contents (e.g., `"application/pdf"` or
+    /// `"image/*"`), which take precedence. See [`OpenerCfg`].
+    #[serde(default)]
+    pub openers: HashMap<String, OpenerCfg>,
+
+    /// Configures `v open`, `v show`, and `v edit`.
+    #[serde(default)]
+    pub open: OpenCfg,
+
+    /// Configures `v capture`.
+    #[serde(default)]
+    pub capture: CaptureCfg,
+
+    /// Configures `v inbox`.
+    #[serde(default)]
+    pub inbox: InboxCfg,
+
+    /// Configures `v today` and `v periodic`.
+    #[serde(default)]
+    pub periodic: PeriodicCfg,
+
+    /// Recurring documents created by `v generate`, keyed by names.
+    #[serde(default)]
+    pub generate: BTreeMap<String, GenerateCfg>,
+
+    /// Configures `v bib`.
+    #[serde(default)]
+    pub bib: BibCfg,
+
+    /// Configures `--pick`.
+    #[serde(default)]
+    pub picker: PickerCfg,
+
+    /// Configures `/REGEX/` criteria.
+    #[serde(default)]
+    pub regex: RegexCfg,
+
+    /// Configures the criteria searching document contents (`contents:` and
+    /// `v grep`).
+    #[serde(default)]
+    pub contents: ContentsCfg,
+
+    /// The locale determining the order of text when sorting documents (e.g.,
+    /// `sv-SE`). Defaults to a language-neutral order.
+    #[serde(default)]
+    pub locale: String,
+
+    /// The `strftime`-style format of dates in listings (e.g., `%b %d, %Y`).
+    /// Dates are displayed as written in documents by default.
+    #[serde(default)]
+    pub date_format: Option<String>,
+
+    /// Displays dates in listings relative to today (e.g., `3 days ago`).
+    /// Takes precedence over `date_format`.
+    #[serde(default)]
+    pub relative_dates: bool,
+
+    /// The display width of the characters whose width depends on the
+    /// terminal (East Asian Ambiguous characters, e.g., `§` and `①`):
+    /// `narrow` (the default) or `wide`. Set this to `wide` if columns in
+    /// listings are misaligned by such characters (see `v ls --align-debug`).
+    #[serde(default)]
+    pub ambiguous_width: AmbiguousWidth,
+
+    /// The phases of resolving a smart name, tried in order until one of
+    /// them matches any document (`exact`, `id`, `alias`, `prefix`,
+    /// `substring`, `fuzzy`, `title-exact`, or `title`). Doesn't affect
+    /// `--rank`.
+    #[serde(default = "smart_phases_default")]
+    pub smart_phases: Vec<SmartPhase>,
+
+    /// The maximum number of candidates listed when search criteria select
+    /// more than one document where one is expected.
+    #[serde(default = "max_candidates_default")]
+    pub max_candidates: usize,
+
+    /// Orders matching documents by relevance as if `--rank` were given.
+    #[serde(default)]
+    pub rank: bool,
+
+    /// Matches names, metadata values, and regexes after transliterating
+    /// accented letters to ASCII and ignoring case, so that `zurich` matches
+    /// `Zürich`.
+    #[serde(default)]
+    pub ascii_search: bool,
+
+    /// Treats the metadata of documents with malformed preambles as empty
+    /// (logging the errors) instead of failing, as if `--skip-errors` were
+    /// given.
+    #[serde(default)]
+    pub skip_errors: bool,
+
+    /// Where to store machine-generated state such as caches, the history,
+    /// the journal, and the trash: `root` (`.veisku/state`), `user`
+    /// (`$XDG_CACHE_HOME/veisku/HASH` or `~/.cache/veisku/HASH`, where `HASH`
+    /// identifies the document root), or `auto` (the default), which uses
+    /// `root` unless `.veisku` isn't writable (e.g., on a read-only mount).
+    #[serde(default)]
+    pub state_dir: StateDirCfg,
+}
+
+impl Cfg {
+    /// Get the effective value of a configuration key, including the default
+    /// values. Returns `None` if the key doesn't exist or has no value.
+    pub fn get_value(&self, key: &[String]) -> Option<toml::Value> {
+        let mut value = toml::Value::try_from(self).ok()?;
+        // Include the styles taken from `theme.base`
+        if let Some(toml::Value::Table(theme)) = value.get_mut("theme") {
+            for (name, style) in [
+                ("tag_default", self.theme.tag_default()),
+                ("highlight", self.theme.highlight()),
+                ("diff_added", self.theme.diff_added()),
+                ("diff_removed", self.theme.diff_removed()),
+            ] {
+                if !theme.contains_key(name) {
+                    theme.insert(name.to_owned(), toml::Value::try_from(style).ok()?);
+                }
+            }
+        }
+        for component in key {
+            value = match value {
+                toml::Value::Table(mut table) => table.remove(component)?,
+                _ => return None,
+            };
+        }
+        Some(value)
+    }
+
+    /// Get the encryption configuration applicable to the specified document.
+    pub fn encryption_for(&self, path: &Path) -> Option<&EncryptionCfg> {
+        let ext = path.extension()?.to_str()?;
+        self.encryption.get(ext)
+    }
+}
+
+fn follow_symlinks_default() -> bool {
+    true
+}
+
+fn files_default() -> Vec<String> {
+    ["*.md", "*.mdown", "!*.swp", "!.git/", "!.svn/"]
+        .iter()
+        .cloned()
+        .map(String::from)
+        .collect()
+}
+
+/// Converter configuration for a target format of `v export`
+///
+/// ```toml
+/// [export.html]
+/// command = ["pandoc", "--standalone", "{input}", "-o", "{output}", "{meta}"]
+/// meta_args = ["--variable", "{key}={value}"]
+/// extension = "html"
+/// ```
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ExportCfg {
+    /// The command to convert a document. `{input}` and `{output}` are
+    /// replaced with the input and output paths, respectively. `{meta}` is
+    /// replaced with the arguments generated from `meta_args`; they are
+    /// appended to the command line if `{meta}` is absent.
+    pub command: Vec<String>,
+
+    /// The arguments generated for each scalar metadata field value. `{key}`
+    /// and `{value}` are replaced with the field name and value, respectively.
+    #[serde(default)]
+    pub meta_args: Vec<String>,
+
+    /// The file extension of output files. Defaults to the format name.
+    #[serde(default)]
+    pub extension: Option<String>,
+}
+
+/// The declaration of a metadata field
+///
+/// ```toml
+/// [schema.status]
+/// required = true
+/// values = ["todo", "doing", "done"]
+///
+/// [schema.due]
+/// type = "date"
+///
+/// [schema.priority]
+/// type = "enum"
+/// values = ["low", "normal", "high"]
+/// ```
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct FieldCfg {
+    /// Requires all documents to have this field.
+    #[serde(default)]
+    pub required: bool,
+
+    /// The type of the field value.
+    #[serde(default, rename = "type")]
+    pub ty: Option<FieldType>,
+
+    /// The allowed values. Each element of a sequence is checked individually.
+    /// Required by the `enum` type, in which case the order of the values
+    /// defines their ordering.
+    #[serde(default)]
+    pub values: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    String,
+    /// An integer
+    Int,
+    /// An integer or a floating-point number
+    Number,
+    Bool,
+    /// A date in the `YYYY-MM-DD` format, optionally followed by a time
+    Date,
+    /// One of `values`, ordered as they appear in `values`
+    Enum,
+    /// A sequence of scalar values
+    List,
+    /// A sequence of strings
+    #[serde(rename = "list[string]")]
+    ListString,
+}
+
+/// Commands for handling encrypted documents
+///
+/// ```toml
+/// [encryption.age]
+/// decrypt = ["age", "--decrypt", "-i", "/home/me/.age/key.txt", "{}"]
+/// encrypt = ["age", "--encrypt", "-r", "age1...", "-o", "{output}", "{input}"]
+/// ```
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct EncryptionCfg {
+    /// The command to decrypt a document and write the plaintext to the
+    /// standard output. If the value contains at least one `{}`, they will be
+    /// replaced with the document's path. Otherwise, the path will be appended
+    /// to the command line.
+    pub decrypt: Vec<String>,
+
+    /// The command to encrypt a plaintext file. `{input}` and `{output}` are
+    /// replaced with the plaintext file's path and the path to write the
+    /// encrypted document to, respectively.
+    pub encrypt: Vec<String>,
+}
+
+/// Configuration for `v import`
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ImportCfg {
+    /// The template of the base names of imported documents. The following
+    /// placeholders are recognized: `{slug}` (the slugified title), `{date}`
+    /// (today's date in the `YYYY-MM-DD` format), and `{stem}` (the original
+    /// base name).
+    #[serde(default = "import_name_default")]
+    pub name: String,
+
+    /// The directory to place imported documents in, relative to the document
+    /// root.
+    #[serde(default)]
+    pub dir: String,
+}
+
+impl Default for ImportCfg {
+    fn default() -> Self {
+        Self {
+            name: import_name_default(),
+            dir: String::new(),
+        }
+    }
+}
+
+fn import_name_default() -> String {
+    "{slug}".to_owned()
+}
+
+/// Configuration for `v new`
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct NewCfg {
+    /// The template of the base names of new documents. The following
+    /// placeholders are recognized: `{slug}` (the slugified title),
+    /// `{date}` (today's date in the `YYYY-MM-DD` format), and `{id}` (the
+    /// assigned ID, see `id`).
+    #[serde(default = "import_name_default")]
+    pub name: String,
+
+    /// The directory to place new documents in, relative to the document
+    /// root.
+    #[serde(default)]
+    pub dir: String,
+
+    /// The scheme of the IDs assigned to new documents (and by `v id
+    /// assign`). If unset, `v new` doesn't assign IDs.
+    #[serde(default)]
+    pub id: Option<IdScheme>,
+
+    /// Document classes selectable by `v new --class NAME`, each of which
+    /// can override the template, the directory, and the base name template.
+    #[serde(default)]
+    pub classes: HashMap<String, NewClassCfg>,
+}
+
+impl Default for NewCfg {
+    fn default() -> Self {
+        Self {
+            name: import_name_default(),
+            dir: String::new(),
+            id: None,
+            classes: HashMap::new(),
+        }
+    }
+}
+
+/// A document class for `v new --class`
+///
+/// ```toml
+/// [new.classes.meeting]
+/// template = "meeting"
+/// dir = "meetings"
+/// name = "{date}-{slug}"
+/// ```
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct NewClassCfg {
+    /// The template to use unless `--template` is given. Defaults to the
+    /// class name.
+    #[serde(default)]
+    pub template: Option<String>,
+
+    /// The directory to place new documents in, relative to the document
+    /// root. Defaults to `new.dir`.
+    #[serde(default)]
+    pub dir: Option<String>,
+
+    /// The template of the base names of new documents. Defaults to
+    /// `new.name`.
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// Configuration for `v capture`
+///
+/// ```toml
+/// [capture]
+/// path = "inbox.md"
+/// template = "inbox"
+/// prefix = "- %Y-%m-%d %H:%M "
+/// ```
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CaptureCfg {
+    /// The path of the inbox document, relative to the document root.
+    #[serde(default = "capture_path_default")]
+    pub path: String,
+
+    /// The template in `.veisku/templates` used to create the inbox document
+    /// if it doesn't exist. An empty document is created if unset.
+    #[serde(default)]
+    pub template: Option<String>,
+
+    /// The `strftime`-style prefix of captured entries.
+    #[serde(default = "capture_prefix_default")]
+    pub prefix: String,
+}
+
+impl Default for CaptureCfg {
+    fn default() -> Self {
+        Self {
+            path: capture_path_default(),
+            template: None,
+            prefix: capture_prefix_default(),
+        }
+    }
+}
+
+fn capture_path_default() -> String {
+    "inbox.md".to_owned()
+}
+
+fn capture_prefix_default() -> String {
+    "- %Y-%m-%d %H:%M ".to_owned()
+}
+
+/// Configuration for `v inbox`
+///
+/// ```toml
+/// [inbox]
+/// archive_dir = "archive"
+/// ```
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct InboxCfg {
+    /// The directory, relative to the document root, to which the archive
+    /// action moves documents.
+    #[serde(default = "inbox_archive_dir_default")]
+    pub archive_dir: String,
+}
+
+impl Default for InboxCfg {
+    fn default() -> Self {
+        Self {
+            archive_dir: inbox_archive_dir_default(),
+        }
+    }
+}
+
+fn inbox_archive_dir_default() -> String {
+    "archive".to_owned()
+}
+
+/// Configuration for periodic notes (`v today` and `v periodic`)
+///
+/// ```toml
+/// [periodic.daily]
+/// path = "journal/%Y/%m-%d.md"
+/// template = "daily"
+///
+/// [periodic.weekly]
+/// path = "journal/%G-W%V.md"
+/// ```
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PeriodicCfg {
+    #[serde(default = "daily_default")]
+    pub daily: PeriodicNoteCfg,
+    #[serde(default = "weekly_default")]
+    pub weekly: PeriodicNoteCfg,
+    #[serde(default = "monthly_default")]
+    pub monthly: PeriodicNoteCfg,
+}
+
+impl Default for PeriodicCfg {
+    fn default() -> Self {
+        Self {
+            daily: daily_default(),
+            weekly: weekly_default(),
+            monthly: monthly_default(),
+        }
+    }
+}
+
+/// Configuration for the periodic notes of a cadence
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PeriodicNoteCfg {
+    /// The `strftime`-style path of the notes, relative to the document
+    /// root, which is formatted with the first day of each period.
+    pub path: String,
+
+    /// The template in `.veisku/templates` used to create the notes. The
+    /// `default` template is used if unset. `{{date}}` is replaced with the
+    /// first day of the period, and `{{title}}` with the base name of the
+    /// note.
+    #[serde(default)]
+    pub template: Option<String>,
+}
+
+/// A recurring document created by `v generate`
+///
+/// ```toml
+/// [generate.weekly-review]
+/// schedule = "* * fri"
+/// path = "reviews/%Y-%m-%d-weekly.md"
+/// template = "weekly-review"
+/// ```
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct GenerateCfg {
+    /// The days on which the document is due, in the form `DAY MONTH WEEKDAY`
+    /// (the date fields of a cron expression). Each field is `*` or a
+    /// comma-separated list of values (`1`), ranges (`1-5`), and steps
+    /// (`*/2`, `1-15/7`). Weekdays are `0`-`7` (Sunday being `0` or `7`) or
+    /// `sun`-`sat`, and months can also be `jan`-`dec`. If both `DAY` and
+    /// `WEEKDAY` are restricted, a day matching either is due.
+    pub schedule: String,
+
+    /// The `strftime`-style path of the documents, relative to the document
+    /// root, which is formatted with the due date.
+    pub path: String,
+
+    /// The template in `.veisku/templates` used to create the documents. The
+    /// `default` template is used if unset. `{{date}}` is replaced with the
+    /// due date, and `{{title}}` with the base name of the document.
+    #[serde(default)]
+    pub template: Option<String>,
+}
+
+fn daily_default() -> PeriodicNoteCfg {
+    PeriodicNoteCfg {
+        path: "journal/%Y-%m-%d.md".to_owned(),
+        template: None,
+    }
+}
+
+fn weekly_default() -> PeriodicNoteCfg {
+    PeriodicNoteCfg {
+        path: "journal/%G-W%V.md".to_owned(),
+        template: None,
+    }
+}
+
+fn monthly_default() -> PeriodicNoteCfg {
+    PeriodicNoteCfg {
+        path: "journal/%Y-%m.md".to_owned(),
+        template: None,
+    }
+}
+
+/// Configuration for `v bib`
+///
+/// ```toml
+/// [bib]
+/// key = "citekey"
+///
+/// [bib.fields]
+/// journal = "venue"   # Output `venue` as `journal`
+/// url = ""            # Don't output `url`
+/// ```
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct BibCfg {
+    /// The metadata field containing the citation key. Documents without
+    /// this field are not exported.
+    #[serde(default = "bib_key_default")]
+    pub key: String,
+
+    /// The metadata field containing the entry type (e.g., `article`).
+    /// Defaults to `misc` if the field is absent.
+    #[serde(default = "bib_type_default")]
+    pub r#type: String,
+
+    /// Maps BibTeX fields to metadata fields, overriding the default
+    /// mapping, which outputs the standard fields (`author`, `title`,
+    /// `year`, `journal`, `doi`, etc.) from the metadata fields of the same
+    /// names. An empty string removes a field from the output.
+    #[serde(default)]
+    pub fields: BTreeMap<String, String>,
+}
+
+impl Default for BibCfg {
+    fn default() -> Self {
+        Self {
+            key: bib_key_default(),
+            r#type: bib_type_default(),
+            fields: BTreeMap::new(),
+        }
+    }
+}
+
+fn bib_key_default() -> String {
+    "citekey".to_owned()
+}
+
+fn bib_type_default() -> String {
+    "bibtype".to_owned()
+}
+
+/// Configuration for converting titles to file names
+///
+/// A date prefix can be added by including `{date}` in `new.name` or
+/// `import.name`, e.g., `name = "{date}-{slug}"`.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SlugCfg {
+    /// Convert letters to lowercase.
+    #[serde(default = "slug_lowercase_default")]
+    pub lowercase: bool,
+
+    /// Replace accented Latin letters with their ASCII counterparts (e.g.,
+    /// `ü` → `u`, `ß` → `ss`).
+    #[serde(default)]
+    pub transliterate: bool,
+
+    /// The maximum length of slugs in characters. Slugs are truncated at a
+    /// word boundary if possible.
+    #[serde(default)]
+    pub max_length: Option<usize>,
+
+    /// The string inserted between words.
+    #[serde(default = "slug_separator_default")]
+    pub separator: String,
+}
+
+impl Default for SlugCfg {
+    fn default() -> Self {
+        Self {
+            lowercase: slug_lowercase_default(),
+            transliterate: false,
+            max_length: None,
+            separator: slug_separator_default(),
+        }
+    }
+}
+
+fn slug_lowercase_default() -> bool {
+    true
+}
+
+fn slug_separator_default() -> String {
+    "-".to_owned()
+}
+
+/// Configuration for `/REGEX/` criteria
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RegexCfg {
+    /// Make regexes case-insensitive unless the `c` flag is given.
+    #[serde(default)]
+    pub case_insensitive: bool,
+
+    /// Require name regexes to match the whole file stem instead of any
+    /// substring.
+    #[serde(default)]
+    pub anchored: bool,
+}
+
+/// Configuration for the criteria searching document contents
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ContentsCfg {
+    /// Files larger than this (in bytes) aren't searched. `0` means no
+    /// limit.
+    #[serde(default = "contents_max_size_default")]
+    pub max_size: u64,
+
+    /// Searches files containing NUL bytes (e.g., PDF files), which are
+    /// skipped as binary files by default.
+    #[serde(default)]
+    pub binary: bool,
+}
+
+impl Default for ContentsCfg {
+    fn default() -> Self {
+        Self {
+            max_size: contents_max_size_default(),
+            binary: false,
+        }
+    }
+}
+
+fn contents_max_size_default() -> u64 {
+    16 << 20
+}
+
+/// The command to open documents of a particular type
+///
+/// ```toml
+/// [openers]
+/// pdf = ["zathura"]
+/// # macOS only: `open -a Preview`
+/// image = { app = "Preview" }
+/// # macOS only: `open -b com.example.OrgEditor`
+/// org = { bundle = "com.example.OrgEditor" }
+/// # The first one whose program is found is used
+/// markdown = [["glow", "-p"], ["bat"], ["less"]]
+/// ```
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum OpenerCfg {
+    /// A command, to which the path is appended
+    Command(Vec<String>),
+    /// A macOS application name, opened by `open -a APP`
+    App { app: String },
+    /// A macOS application bundle identifier, opened by `open -b BUNDLE`
+    Bundle { bundle: String },
+    /// Openers tried in order. An opener is skipped if its program isn't
+    /// found.
+    Chain(Vec<OpenerCfg>),
+}
+
+impl OpenerCfg {
+    /// Get the command lines to try in order, excluding empty ones.
+    pub fn candidates(&self) -> Vec<Vec<String>> {
+        match self {
+            Self::Command(cmd) if cmd.is_empty() => Vec::new(),
+            Self::Command(cmd) => vec![cmd.clone()],
+            Self::App { app } => vec![vec!["open".to_owned(), "-a".to_owned(), app.clone()]],
+            Self::Bundle { bundle } => {
+                vec![vec!["open".to_owned(), "-b".to_owned(), bundle.clone()]]
+            }
+            Self::Chain(openers) => openers.iter().flat_map(Self::candidates).collect(),
+        }
+    }
+}
+
+/// Configuration for `v open`, `v show`, and `v edit`
+///
+/// ```toml
+/// [open]
+/// spawn = true
+/// after = ["sh", "-c", "echo \"$1\" >> ~/.v-history", "-", "{}"]
+/// stamp_field = "modified"
+/// goto = ["+{line}", "{path}"]
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct OpenCfg {
+    /// Runs the command opening documents as a child process and waits for
+    /// it to exit instead of replacing the `v` process (the spawn mode).
+    #[serde(default)]
+    pub spawn: bool,
+
+    /// The command executed after the command opening documents exits in the
+    /// spawn mode. `{}` is replaced with the paths of the documents (appended
+    /// if absent), and `V_EXIT_STATUS` is set to the exit code of the opener.
+    #[serde(default)]
+    pub after: Vec<String>,
+
+    /// The metadata field set to the current time when a document edited by
+    /// `v edit` in the spawn mode was changed. Requires `writable = true`.
+    #[serde(default)]
+    pub stamp_field: Option<String>,
+
+    /// The `strftime`-style format of `stamp_field`. Defaults to
+    /// `%Y-%m-%d %H:%M:%S`.
+    #[serde(default)]
+    pub stamp_format: Option<String>,
+
+    /// The arguments passed to the editor by `v edit` instead of the document
+    /// path when a `contents:` criterion matched the document, e.g.,
+    /// `["+{line}", "{path}"]` (Vim) or `["--goto", "{path}:{line}:{column}"]`
+    /// (VS Code). `{path}`, `{line}`, and `{column}` are replaced with the
+    /// path and the location of the first match. The path is appended if
+    /// `{path}` is absent.
+    #[serde(default)]
+    pub goto: Vec<String>,
+}
+
+/// Configuration for `--pick`
+///
+/// ```toml
+/// [picker]
+/// command = ["fzf", "--delimiter=\t", "--with-nth=2..", "--preview={preview}"]
+/// preview = ["bat", "--style=plain", "--color=always", "{}"]
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PickerCfg {
+    /// The external picker command (e.g., `fzf`, `sk`, `rofi -dmenu`). The
+    /// candidates are written to its standard input, one per line, as
+    /// tab-separated columns of the path, title, tags, and aliases (see
+    /// [`SmartPhase::Alias`]). The selected line
+    /// (or just its path) is read back from its standard output. The builtin
+    /// prompt is used if empty.
+    #[serde(default)]
+    pub command: Vec<String>,
+
+    /// The command to preview the highlighted candidate, where `{}` is
+    /// replaced with its path. It's converted to a shell command line with
+    /// `{}` replaced with `{1}` (the first column in `fzf` and `sk`) and
+    /// passed to the picker by replacing `{preview}` in `command`, which must
+    /// contain it (e.g., `"--preview={preview}"`).
+    #[serde(default)]
+    pub preview: Vec<String>,
+}
+
+/// Configuration for `v sync`
+///
+/// ```toml
+/// [[sync.steps]]
+/// command = ["git", "pull", "--rebase"]
+///
+/// [[sync.steps]]
+/// command = ["git", "add", "-A"]
+///
+/// [[sync.steps]]
+/// command = ["git", "commit", "-m", "Sync on {date}"]
+/// allow_failure = true  # nothing to commit
+///
+/// [[sync.steps]]
+/// command = ["git", "push"]
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SyncCfg {
+    /// The commands executed in order in the document root
+    #[serde(default)]
+    pub steps: Vec<SyncStepCfg>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SyncStepCfg {
+    /// The command to execute. `{date}` is replaced with the current date
+    /// and time.
+    pub command: Vec<String>,
+
+    /// Proceed to the next step even if the command fails.
+    #[serde(default)]
+    pub allow_failure: bool,
+}
+
+/// A way to match a smart name against documents
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum SmartPhase {
+    /// The file name without the extension equals the smart name
+    Exact,
+    /// The `id` field equals the smart name
+    Id,
+    /// One of the alternative names in the `aliases` field equals the smart
+    /// name, ignoring case
+    Alias,
+    /// The file name starts with the smart name
+    Prefix,
+    /// The file name contains the smart name
+    Substring,
+    /// The characters of the smart name appear in order in the file name,
+    /// ignoring case
+    Fuzzy,
+    /// The title equals the smart name, ignoring case
+    TitleExact,
+    /// The title contains the smart name, ignoring case
+    Title,
+}
+
+fn max_candidates_default() -> usize {
+    10
+}
+
+fn smart_phases_default() -> Vec<SmartPhase> {
+    vec![
+        SmartPhase::Exact,
+        SmartPhase::Id,
+        SmartPhase::Alias,
+        SmartPhase::Prefix,
+        SmartPhase::TitleExact,
+        SmartPhase::Title,
+    ]
+}
+
+/// The display width of East Asian Ambiguous characters
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AmbiguousWidth {
+    #[default]
+    Narrow,
+    Wide,
+}
+
+/// The location of machine-generated state
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum StateDirCfg {
+    #[default]
+    Auto,
+    Root,
+    User,
+}
+
+/// The scheme of document IDs (the `id` metadata field)
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum IdScheme {
+    /// A zettel-style timestamp (`YYYYMMDDhhmmss`)
+    Timestamp,
+    /// A random UUID
+    Uuid,
+}
+
+impl FromStr for IdScheme {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "timestamp" => Ok(Self::Timestamp),
+            "uuid" => Ok(Self::Uuid),
+            _ => Err("Unknown ID scheme"),
+        }
+    }
+}
+
+/// Text styles in listings
+///
+/// The styles not specified here are taken from the built-in theme selected
+/// by `base`:
+///
+/// ```toml
+/// [theme]
+/// base = "solarized"
+/// highlight = { fg = "#cb4b16", bold = true }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ThemeCfg {
+    /// The built-in theme: `default`, `light`, `dark`, or `solarized`.
+    #[serde(default)]
+    pub base: ThemeBase,
+    /// The mapping between tags and text styles. A hierarchical tag without
+    /// its own style uses the style of its nearest ancestor (e.g., `project`
+    /// for `project/alpha`).
+    #[serde(default)]
+    pub tags: HashMap<String, StyleCfg>,
+    /// The style of tags not in `tags`.
+    #[serde(default)]
+    pub tag_default: Option<StyleCfg>,
+    /// The style of the parts of names and titles matched by search criteria.
+    #[serde(default)]
+    pub highlight: Option<StyleCfg>,
+    /// The style of added lines in diffs (e.g., `v meta set --diff`).
+    #[serde(default)]
+    pub diff_added: Option<StyleCfg>,
+    /// The style of removed lines in diffs.
+    #[serde(default)]
+    pub diff_removed: Option<StyleCfg>,
+}
+
+impl ThemeCfg {
+    /// Get the effective style of tags not in `tags`.
+    pub fn tag_default(&self) -> StyleCfg {
+        (self.tag_default.clone()).unwrap_or_else(|| self.base.tag_default())
+    }
+
+    /// Get the effective style of the matched parts of names and titles.
+    pub fn highlight(&self) -> StyleCfg {
+        (self.highlight.clone()).unwrap_or_else(|| self.base.highlight())
+    }
+
+    /// Get the effective style of added lines in diffs.
+    pub fn diff_added(&self) -> StyleCfg {
+        (self.diff_added.clone()).unwrap_or_else(|| self.base.diff(true))
+    }
+
+    /// Get the effective style of removed lines in diffs.
+    pub fn diff_removed(&self) -> StyleCfg {
+        (self.diff_removed.clone()).unwrap_or_else(|| self.base.diff(false))
+    }
+}
+
+/// A built-in theme
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeBase {
+    #[default]
+    Default,
+    /// For terminals with a light background
+    Light,
+    /// For terminals with a dark background
+    Dark,
+    /// Based on the Solarized (dark) palette
+    Solarized,
+}
+
+impl ThemeBase {
+    fn tag_default(self) -> StyleCfg {
+        let (fg, bg) = match self {
+            Self::Default => (ColorCfg::Green, ColorCfg::Rgb(64, 64, 64)),
+            Self::Light => (
+                ColorCfg::Rgb(0x00, 0x5f, 0x00),
+                ColorCfg::Rgb(0xe4, 0xe4, 0xe4),
+            ),
+            Self::Dark => (
+                ColorCfg::Rgb(0x87, 0xd7, 0xff),
+                ColorCfg::Rgb(0x30, 0x30, 0x30),
+            ),
+            Self::Solarized => (
+                ColorCfg::Rgb(0x2a, 0xa1, 0x98),
+                ColorCfg::Rgb(0x07, 0x36, 0x42),
+            ),
+        };
+        StyleCfg {
+            fg: Some(fg),
+            bg: Some(bg),
+            bold: false,
+            italic: false,
+        }
+    }
+
+    fn highlight(self) -> StyleCfg {
+        let fg = match self {
+            Self::Default => ColorCfg::Yellow,
+            Self::Light => ColorCfg::Rgb(0xaf, 0x00, 0x00),
+            Self::Dark => ColorCfg::Rgb(0xff, 0xd7, 0x5f),
+            Self::Solarized => ColorCfg::Rgb(0xb5, 0x89, 0x00),
+        };
+        StyleCfg {
+            fg: Some(fg),
+            bg: None,
+            bold: true,
+            italic: false,
+        }
+    }
+
+    /// Get the style of added (`added == true`) or removed lines in diffs.
+    fn diff(self, added: bool) -> StyleCfg {
+        let fg = match (self, added) {
+            (Self::Default, true) => ColorCfg::Green,
+            (Self::Default, false) => ColorCfg::Red,
+            (Self::Light, true) => ColorCfg::Rgb(0x00, 0x87, 0x00),
+            (Self::Light, false) => ColorCfg::Rgb(0xaf, 0x00, 0x00),
+            (Self::Dark, true) => ColorCfg::Rgb(0x87, 0xd7, 0x87),
+            (Self::Dark, false) => ColorCfg::Rgb(0xff, 0x87, 0x87),
+            (Self::Solarized, true) => ColorCfg::Rgb(0x85, 0x99, 0x00),
+            (Self::Solarized, false) => ColorCfg::Rgb(0xdc, 0x32, 0x2f),
+        };
+        StyleCfg {
+            fg: Some(fg),
+            bg: None,
+            bold: false,
+            italic: false,
+        }
+    }
+}
+
+impl FromStr for ThemeBase {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Self::Default),
+            "light" => Ok(Self::Light),
+            "dark" => Ok(Self::Dark),
+            "solarized" => Ok(Self::Solarized),
+            _ => Err("Expected `default`, `light`, `dark`, or `solarized`"),
+        }
+    }
+}
+
+/// Text style
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct StyleCfg {
+    /// The foreground color
+    #[serde(default)]
+    pub fg: Option<ColorCfg>,
+
+    /// The background color
+    #[serde(default)]
+    pub bg: Option<ColorCfg>,
+
+    #[serde(default)]
+    pub bold: bool,
+
+    #[serde(default)]
+    pub italic: bool,
+}
+
+/// Color specification: a color name or a hexadecimal color code (`#rgb` or
+/// `#rrggbb`)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ColorCfg {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Purple,
+    Cyan,
+    White,
+    Rgb(u8, u8, u8),
+}
+
+impl<'de> Deserialize<'de> for ColorCfg {
+    fn deserialize<D>(de: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de::Error;
+        let st = String::deserialize(de)?;
+
+        Ok(match &*st {
+            "black" => Self::Black,
+            "red" => Self::Red,
+            "green" => Self::Green,
+            "yellow" => Self::Yellow,
+            "blue" => Self::Blue,
+            "purple" => Self::Purple,
+            "cyan" => Self::Cyan,
+            "white" => Self::White,
+            _ => {
+                if let Some([r, g, b]) = parse_hex_color(&st) {
+                    Self::Rgb(r, g, b)
+                } else {
+                    return Err(D::Error::custom(format_args!(
+                        "invalid hexadecimal color specification: '{}'",
+                        st
+                    )));
+                }
+            }
+        })
+    }
+}
+
+impl Serialize for ColorCfg {
+    fn serialize<S>(&self, ser: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match *self {
+            Self::Black => ser.serialize_str("black"),
+            Self::Red => ser.serialize_str("red"),
+            Self::Green => ser.serialize_str("green"),
+            Self::Yellow => ser.serialize_str("yellow"),
+            Self::Blue => ser.serialize_str("blue"),
+            Self::Purple => ser.serialize_str("purple"),
+            Self::Cyan => ser.serialize_str("cyan"),
+            Self::White => ser.serialize_str("white"),
+            Self::Rgb(r, g, b) => ser.serialize_str(&format!("#{:02x}{:02x}{:02x}", r, g, b)),
+        }
+    }
+}
+
+impl JsonSchema for ColorCfg {
+    fn schema_name() -> String {
+        "ColorCfg".to_owned()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        let mut schema = String::json_schema(gen).into_object();
+        schema.metadata().description = Some(
+            "A color name (`black`, `red`, `green`, `yellow`, `blue`, `purple`, \
+            `cyan`, or `white`) or a hexadecimal color code (`#rgb` or `#rrggbb`)"
+                .to_owned(),
+        );
+        schema.string().pattern = Some(
+            "^(black|red|green|yellow|blue|purple|cyan|white|#[0-9a-fA-F]{3}|#[0-9a-fA-F]{6})$"
+                .to_owned(),
+        );
+        schema.into()
+    }
+}
+
+fn parse_hex_color(s: &str) -> Option<[u8; 3]> {
+    let bytes = s.as_bytes();
+    if bytes[0] == b'#' {
+        if bytes.len() == 4 {
+            if let [Ok(r), Ok(g), Ok(b)] =
+                [&s[1..], &s[2..], &s[3..]].map(|x| u8::from_str_radix(&x[..1], 16))
+            {
+                Some([r * 0x11, g * 0x11, b * 0x11])
+            } else {
+                None
+            }
+        } else if bytes.len() == 7 {
+            if let [Ok(r), Ok(g), Ok(b)] =
+                [&s[1..], &s[3..], &s[5..]].map(|x| u8::from_str_radix(&x[..2], 16))
+            {
+                Some([r, g, b])
+            } else {
+                None
+            }
+        } else {
+            None
+        }
+    } else {
+        None
+    }
+}
@@ -0,0 +1,162 @@
+//! Locale-aware string collation
+//!
+//! This implements a simplified version of the Unicode Collation Algorithm:
+//! strings are compared by their base letters first, then by accents, and
+//! then by letter case. Runs of digits are compared by their numeric values
+//! so that `Note 2` sorts before `Note 10`. A few locales tailor the order of
+//! accented letters (e.g., `sv` sorts `å`, `ä`, and `ö` after `z`).
+use std::cmp::Ordering;
+
+/// Compares strings according to the rules of a locale
+#[derive(Debug, Clone)]
+pub struct Collator {
+    /// The letters sorted after `z`, in order
+    tailored: &'static [char],
+}
+
+/// An element of a primary collation key
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Primary {
+    /// Whitespace and punctuation, which sort before digits and letters
+    Other(char),
+    /// A run of digits, represented by the number of significant digits and
+    /// the digits themselves
+    Number(usize, String),
+    Letter(u32),
+}
+
+impl Collator {
+    /// Construct a `Collator` for the specified locale (e.g., `en`, `sv-SE`).
+    /// Unknown locales get the default ordering.
+    pub fn new(locale: &str) -> Self {
+        let language = locale
+            .split(&['-', '_'][..])
+            .next()
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        let tailored: &[char] = match &*language {
+            "sv" | "fi" => &['å', 'ä', 'ö'],
+            "da" | "nb" | "nn" | "no" => &['æ', 'ø', 'å'],
+            "es" => &['ñ'],
+            _ => &[],
+        };
+        Self { tailored }
+    }
+
+    /// Compare two strings.
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        self.primary_key(a)
+            .cmp(&self.primary_key(b))
+            // Accents
+            .then_with(|| lowercase(a).cmp(lowercase(b)))
+            // Letter case (lowercase first)
+            .then_with(|| {
+                let case = |st: &str| st.chars().map(char::is_uppercase).collect::<Vec<_>>();
+                case(a).cmp(&case(b))
+            })
+            .then_with(|| a.cmp(b))
+    }
+
+    fn primary_key(&self, st: &str) -> Vec<Primary> {
+        let mut key = Vec::new();
+        let mut chars = st.chars().flat_map(char::to_lowercase).peekable();
+        while let Some(ch) = chars.next() {
+            if ch.is_ascii_digit() {
+                let mut digits = ch.to_string();
+                while let Some(&ch) = chars.peek().filter(|ch| ch.is_ascii_digit()) {
+                    digits.push(ch);
+                    chars.next();
+                }
+                let significant = digits.trim_start_matches('0').to_owned();
+                key.push(Primary::Number(significant.len(), significant));
+            } else if !ch.is_alphanumeric() {
+                key.push(Primary::Other(ch));
+            } else if let Some(i) = self.tailored.iter().position(|&x| x == ch) {
+                key.push(Primary::Letter('z' as u32 + 1 + i as u32));
+            } else if let Some(base) = transliterate(ch) {
+                key.extend(base.chars().map(|ch| Primary::Letter(ch as u32)));
+            } else {
+                key.push(Primary::Letter(ch as u32));
+            }
+        }
+        key
+    }
+}
+
+impl Default for Collator {
+    fn default() -> Self {
+        Self::new("")
+    }
+}
+
+fn lowercase(st: &str) -> impl Iterator<Item = char> + '_ {
+    st.chars().flat_map(char::to_lowercase)
+}
+
+/// Get the ASCII replacement of an accented Latin letter.
+pub fn transliterate(ch: char) -> Option<&'static str> {
+    Some(match ch {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => "a",
+        'À' | 'Á' | 'Â' | 'Ã' | 'Ä' | 'Å' | 'Ā' | 'Ă' | 'Ą' => "A",
+        'æ' => "ae",
+        'Æ' => "AE",
+        'ç' | 'ć' | 'č' => "c",
+        'Ç' | 'Ć' | 'Č' => "C",
+        'ď' | 'đ' | 'ð' => "d",
+        'Ď' | 'Đ' | 'Ð' => "D",
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ę' | 'ě' => "e",
+        'È' | 'É' | 'Ê' | 'Ë' | 'Ē' | 'Ę' | 'Ě' => "E",
+        'ğ' => "g",
+        'Ğ' => "G",
+        'ì' | 'í' | 'î' | 'ï' | 'ī' | 'ı' => "i",
+        'Ì' | 'Í' | 'Î' | 'Ï' | 'Ī' | 'İ' => "I",
+        'ł' => "l",
+        'Ł' => "L",
+        'ñ' | 'ń' | 'ň' => "n",
+        'Ñ' | 'Ń' | 'Ň' => "N",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ő' => "o",
+        'Ò' | 'Ó' | 'Ô' | 'Õ' | 'Ö' | 'Ø' | 'Ō' | 'Ő' => "O",
+        'œ' => "oe",
+        'Œ' => "OE",
+        'ř' => "r",
+        'Ř' => "R",
+        'ś' | 'š' | 'ş' => "s",
+        'Ś' | 'Š' | 'Ş' => "S",
+        'ß' => "ss",
+        'ť' | 'ţ' => "t",
+        'Ť' | 'Ţ' => "T",
+        'þ' => "th",
+        'Þ' => "TH",
+        'ù' | 'ú' | 'û' | 'ü' | 'ū' | 'ů' | 'ű' => "u",
+        'Ù' | 'Ú' | 'Û' | 'Ü' | 'Ū' | 'Ů' | 'Ű' => "U",
+        'ý' | 'ÿ' => "y",
+        'Ý' | 'Ÿ' => "Y",
+        'ź' | 'ż' | 'ž' => "z",
+        'Ź' | 'Ż' | 'Ž' => "Z",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        let mut words = vec![
+            "Zebra", "Öl", "Note 10", "apple", "Apple", "Äpfel", "Note 2",
+        ];
+
+        words.sort_by(|a, b| Collator::new("en").compare(a, b));
+        assert_eq!(
+            words,
+            ["Äpfel", "apple", "Apple", "Note 2", "Note 10", "Öl", "Zebra"]
+        );
+
+        words.sort_by(|a, b| Collator::new("sv-SE").compare(a, b));
+        assert_eq!(
+            words,
+            ["apple", "Apple", "Note 2", "Note 10", "Zebra", "Äpfel", "Öl"]
+        );
+    }
+}
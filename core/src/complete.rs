@@ -0,0 +1,111 @@
+//! Completion candidates for shell completion scripts and editor plugins
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::collections::BTreeSet;
+
+use crate::{
+    cache::FileCache,
+    doc::DocRead,
+    query::{self, Query},
+    root::DocRoot,
+    schema,
+};
+
+/// The parts of the metadata of a document used for completion
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct MetaSummary {
+    fields: Vec<String>,
+    tags: Vec<String>,
+}
+
+impl MetaSummary {
+    fn of(meta: &Value) -> Self {
+        let mapping = match meta {
+            Value::Mapping(mapping) => mapping,
+            _ => return Self::default(),
+        };
+        let fields = mapping
+            .iter()
+            .filter_map(|(key, _)| schema::scalar_text(key))
+            .collect();
+        let tags = match &meta["tags"] {
+            Value::Sequence(array) => array.iter().filter_map(schema::scalar_text).collect(),
+            value => schema::scalar_text(value).into_iter().collect(),
+        };
+        Self { fields, tags }
+    }
+}
+
+/// Enumerate all documents. Encrypted documents are excluded so that
+/// completion never asks for a passphrase.
+fn all_docs(root: &DocRoot) -> Result<Vec<DocRead>> {
+    let query = Query::new(root, "", &[])?;
+    query::select_all(root, &query)
+        .filter(|doc| match doc {
+            Ok(doc) => root.cfg.encryption_for(doc.path()).is_none(),
+            Err(_) => true,
+        })
+        .collect()
+}
+
+/// Summarize the metadata of all documents using the cache stored in
+/// `.veisku/state/cache/meta.json`. Documents with unreadable metadata are
+/// skipped.
+fn summaries(root: &DocRoot) -> Result<Vec<MetaSummary>> {
+    let cache: FileCache<MetaSummary> = FileCache::load(root, "meta.json");
+    let mut out = Vec::new();
+    for mut doc in all_docs(root)? {
+        let path = doc.path().to_owned();
+        match cache.get(&path, || Ok(MetaSummary::of(doc.ensure_meta()?))) {
+            Ok(summary) => out.push(summary),
+            Err(e) => log::debug!("Skipping {:?}: {:?}", path, e),
+        }
+    }
+    Ok(out)
+}
+
+/// Get the tags used by the documents.
+pub fn tags(root: &DocRoot) -> Result<BTreeSet<String>> {
+    Ok(summaries(root)?
+        .into_iter()
+        .flat_map(|summary| summary.tags)
+        .collect())
+}
+
+/// Get the metadata fields used by the documents or declared in the schema.
+pub fn fields(root: &DocRoot) -> Result<BTreeSet<String>> {
+    let mut fields: BTreeSet<String> = root.cfg.schema.keys().cloned().collect();
+    fields.extend(
+        summaries(root)?
+            .into_iter()
+            .flat_map(|summary| summary.fields),
+    );
+    Ok(fields)
+}
+
+/// Get the names (file stems, which can be used as smart names) of the
+/// documents.
+pub fn names(root: &DocRoot) -> Result<BTreeSet<String>> {
+    Ok(all_docs(root)?
+        .iter()
+        .filter_map(|doc| Some(doc.path().file_stem()?.to_str()?.to_owned()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_meta_summary() {
+        let meta: Value = serde_yaml::from_str("title: A\ntags: [x, 2]\n").unwrap();
+        let summary = MetaSummary::of(&meta);
+        assert_eq!(summary.fields, ["title", "tags"]);
+        assert_eq!(summary.tags, ["x", "2"]);
+
+        let summary = MetaSummary::of(&serde_yaml::from_str("tags: x").unwrap());
+        assert_eq!(summary.tags, ["x"]);
+        assert!(MetaSummary::of(&Value::Null).fields.is_empty());
+    }
+}
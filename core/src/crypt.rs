@@ -0,0 +1,70 @@
+//! Decryption and encryption of documents by external commands configured by
+//! [`EncryptionCfg`]
+use anyhow::{bail, Context, Result};
+use std::{
+    ffi::OsString,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use crate::cfg::EncryptionCfg;
+
+/// Decrypt the specified file and return the plaintext.
+pub fn decrypt(decrypt_cmd: &[String], path: &Path) -> Result<Vec<u8>> {
+    if decrypt_cmd.is_empty() {
+        bail!("The decryption command is empty");
+    }
+
+    let mut argv: Vec<OsString> = decrypt_cmd.iter().map(OsString::from).collect();
+    if argv.iter().any(|x| x == "{}") {
+        for e in argv.iter_mut() {
+            if *e == "{}" {
+                *e = path.into();
+            }
+        }
+    } else {
+        argv.push(path.into());
+    }
+
+    log::debug!("Decrypting {:?} using {:?}", path, argv);
+    // Let the command interact with the user (e.g., to ask for a passphrase)
+    let output = Command::new(&argv[0])
+        .args(&argv[1..])
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .with_context(|| format!("Failed to execute {:?}", argv[0]))?;
+    if !output.status.success() {
+        bail!("The decryption command exited with {}", output.status);
+    }
+
+    Ok(output.stdout)
+}
+
+/// Encrypt the plaintext file `input` and write the result to `output`.
+pub fn encrypt(enc_cfg: &EncryptionCfg, input: &Path, output: &Path) -> Result<()> {
+    if enc_cfg.encrypt.is_empty() {
+        bail!("The encryption command is empty");
+    }
+
+    let argv: Vec<OsString> = enc_cfg
+        .encrypt
+        .iter()
+        .map(|arg| match &**arg {
+            "{input}" => input.into(),
+            "{output}" => output.into(),
+            _ => arg.into(),
+        })
+        .collect();
+
+    log::debug!("Encrypting {:?} using {:?}", input, argv);
+    let status = Command::new(&argv[0])
+        .args(&argv[1..])
+        .status()
+        .with_context(|| format!("Failed to execute {:?}", argv[0]))?;
+    if !status.success() {
+        bail!("The encryption command exited with {}", status);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,119 @@
+//! Document dates derived from the Git history (the `created` and `modified`
+//! fields) and their cache
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    doc,
+    git::{self, FileDates},
+    root::DocRoot,
+};
+
+/// The names of the fields derived from the Git history. A metadata field of
+/// the same name written in a document takes precedence.
+pub const GIT_FIELDS: &[&str] = &["created", "modified"];
+
+/// The contents of `.veisku/state/cache/git-dates.json`
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheFile {
+    /// The commit from which `dates` was computed
+    head: String,
+    dates: HashMap<PathBuf, FileDates>,
+}
+
+/// The [`FileDates`] of the documents in a document root. They are cached in
+/// `.veisku/state/cache/git-dates.json` until `HEAD` changes. Empty if the
+/// document root is not in a Git repository.
+#[derive(Debug, Default)]
+pub struct GitDates {
+    root_path: PathBuf,
+    dates: HashMap<PathBuf, FileDates>,
+}
+
+impl GitDates {
+    pub fn load(root: &DocRoot) -> Self {
+        let dates = match Self::load_inner(root) {
+            Ok(dates) => dates,
+            Err(e) => {
+                log::debug!("Git dates are unavailable: {:?}", e);
+                HashMap::new()
+            }
+        };
+        Self {
+            root_path: root.path.clone(),
+            dates,
+        }
+    }
+
+    fn load_inner(root: &DocRoot) -> Result<HashMap<PathBuf, FileDates>> {
+        let head = git::head(&root.path)?;
+
+        // Don't create the configuration directory, which would change the
+        // document root
+        let cache_path = if root.cfg_dir_path.is_dir() {
+            Some(root.state_dir_path.join("cache").join("git-dates.json"))
+        } else {
+            None
+        };
+        let cached: Option<CacheFile> = cache_path
+            .as_ref()
+            .and_then(|path| std::fs::read(path).ok())
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok());
+        if let Some(cached) = cached {
+            if cached.head == head {
+                return Ok(cached.dates);
+            }
+        }
+
+        let cache = CacheFile {
+            head,
+            dates: git::file_dates(&root.path)?,
+        };
+
+        if let Some(path) = &cache_path {
+            let json = serde_json::to_vec(&cache).unwrap();
+            let result = root
+                .state_dir()
+                .prepare()
+                .and_then(|()| {
+                    path.parent()
+                        .map_or(Ok(()), std::fs::create_dir_all)
+                        .with_context(|| format!("Failed to create the parent of {:?}", path))
+                })
+                .and_then(|()| doc::write_atomic(path, &json));
+            if let Err(e) = result {
+                log::warn!("Failed to save the cache {:?}: {:?}", path, e);
+            }
+        }
+        Ok(cache.dates)
+    }
+
+    /// Get the value of a field in [`GIT_FIELDS`] for the document at `path`.
+    /// Returns `None` if the document has never been committed.
+    pub fn field(&self, path: &Path, key: &str) -> Option<&str> {
+        let path = path.strip_prefix(&self.root_path).unwrap_or(path);
+        let dates = self.dates.get(path)?;
+        match key {
+            "created" => Some(&dates.created),
+            "modified" => Some(&dates.modified),
+            _ => None,
+        }
+    }
+
+    /// Get the value of the field `key` of a document, falling back to the
+    /// Git history if the metadata doesn't have it and `key` is in
+    /// [`GIT_FIELDS`].
+    pub fn field_value(&self, path: &Path, meta: &Value, key: &str) -> Value {
+        match &meta[key] {
+            Value::Null => self
+                .field(path, key)
+                .map_or(Value::Null, |date| Value::String(date.to_owned())),
+            value => value.clone(),
+        }
+    }
+}
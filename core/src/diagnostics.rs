@@ -0,0 +1,95 @@
+//! Warnings aggregated over a command and summarized at its end, instead of
+//! being logged for every document
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+/// The documents having uncomparable values, keyed by field names
+static UNCOMPARABLE: Mutex<BTreeMap<String, Occurrences>> = Mutex::new(BTreeMap::new());
+
+/// The files skipped by the criteria searching contents
+static SKIPPED: Mutex<BTreeMap<PathBuf, SkipReason>> = Mutex::new(BTreeMap::new());
+
+/// The reason why a file wasn't searched by a `contents:` criterion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The file contains NUL bytes.
+    Binary,
+    /// The file is larger than `contents.max_size`.
+    TooLarge,
+}
+
+#[derive(Debug)]
+struct Occurrences {
+    count: usize,
+    example: PathBuf,
+}
+
+/// Make the conditions reported by this module errors (`--strict`).
+pub fn set_strict(strict: bool) {
+    STRICT.store(strict, Ordering::Relaxed);
+}
+
+pub fn is_strict() -> bool {
+    STRICT.load(Ordering::Relaxed)
+}
+
+/// Record that the field `key` of the document at `path` has a value that
+/// can't be compared with a search criterion.
+pub fn record_uncomparable(key: &str, path: &Path) {
+    let mut uncomparable = UNCOMPARABLE.lock().unwrap();
+    uncomparable
+        .entry(key.to_owned())
+        .and_modify(|occurrences| occurrences.count += 1)
+        .or_insert_with(|| Occurrences {
+            count: 1,
+            example: path.to_owned(),
+        });
+}
+
+/// Record that the contents of the file at `path` weren't searched.
+pub fn record_skipped(path: &Path, reason: SkipReason) {
+    SKIPPED.lock().unwrap().insert(path.to_owned(), reason);
+}
+
+/// Get a message describing the files skipped by the criteria searching
+/// contents and clear them. Returns `None` if no files were skipped.
+pub fn take_skipped_summary() -> Option<String> {
+    let skipped = std::mem::take(&mut *SKIPPED.lock().unwrap());
+    let count = |reason| skipped.values().filter(|&&r| r == reason).count();
+    let mut parts = Vec::new();
+    match count(SkipReason::Binary) {
+        0 => {}
+        n => parts.push(format!(
+            "{} binary file(s) (use `--binary` to search them)",
+            n
+        )),
+    }
+    match count(SkipReason::TooLarge) {
+        0 => {}
+        n => parts.push(format!("{} file(s) larger than `contents.max_size`", n)),
+    }
+    (!parts.is_empty()).then(|| format!("Skipped searching {}", parts.join(" and ")))
+}
+
+/// Get the messages summarizing the recorded conditions and clear them.
+pub fn take_summary() -> Vec<String> {
+    let uncomparable = std::mem::take(&mut *UNCOMPARABLE.lock().unwrap());
+    uncomparable
+        .into_iter()
+        .map(|(key, occurrences)| {
+            format!(
+                "The field '{}' contains a value of an uncomparable type in {} document(s) \
+                (e.g., {:?}); they were treated as non-matching",
+                key, occurrences.count, occurrences.example
+            )
+        })
+        .collect()
+}
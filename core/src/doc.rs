@@ -0,0 +1,971 @@
+//! Document metadata parsing and editing
+use anyhow::{bail, Context, Result};
+use serde_yaml::{Mapping, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    fmt::{self, Write as _},
+    io::{ErrorKind, Read, Write as _},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    doctype::{read_org_keywords, DocType},
+    timing::{self, Counter, Phase},
+};
+
+/// Represents a reference to a document. Metadata is read as needed (lazy
+/// loading).
+pub struct DocRead {
+    path: PathBuf,
+    meta: Option<Value>,
+    /// The command to decrypt the document. `None` if the document is not
+    /// encrypted.
+    decrypt_cmd: Option<Vec<String>>,
+    /// Treat unreadable metadata as null (`skip_errors`)
+    skip_errors: bool,
+    /// Set if [`Self::meta`] is null because the metadata couldn't be read
+    meta_error: bool,
+}
+
+impl DocRead {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            meta: None,
+            decrypt_cmd: None,
+            skip_errors: false,
+            meta_error: false,
+        }
+    }
+
+    /// Specify the command to decrypt the document.
+    /// See [`crate::cfg::EncryptionCfg::decrypt`].
+    pub fn with_decrypt_cmd(self, decrypt_cmd: Option<Vec<String>>) -> Self {
+        Self {
+            decrypt_cmd,
+            ..self
+        }
+    }
+
+    /// Make [`Self::ensure_meta`] log errors and return null instead of
+    /// failing. See [`crate::cfg::Cfg::skip_errors`].
+    pub fn with_skip_errors(self, skip_errors: bool) -> Self {
+        Self {
+            skip_errors,
+            ..self
+        }
+    }
+
+    /// Use `meta` as the metadata instead of reading it from the file.
+    pub fn with_meta(self, meta: Value) -> Self {
+        Self {
+            meta: Some(meta),
+            ..self
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn doc_type(&self) -> DocType {
+        DocType::detect(&self.path)
+    }
+
+    /// Get the metadata of the document. Depending on the document type,
+    /// it's read from a YAML preamble (Markdown and plain text), read from
+    /// keyword lines (Org; see [`crate::doctype::read_org_keywords`]), or
+    /// empty (others).
+    pub fn ensure_meta(&mut self) -> Result<&Value> {
+        if self.meta.is_none() {
+            self.meta = Some(match self.read_meta() {
+                Ok(meta) => meta,
+                Err(e) if self.skip_errors => {
+                    log::warn!("Skipping the metadata: {:?}", e);
+                    self.meta_error = true;
+                    Value::Null
+                }
+                Err(e) => return Err(e),
+            });
+        }
+        Ok(self.meta.as_ref().unwrap())
+    }
+
+    /// Check if the metadata of the document can't be read, regardless of
+    /// [`Self::with_skip_errors`]. The error is not reported, and the
+    /// metadata is treated as null from then on.
+    pub fn has_meta_error(&mut self) -> bool {
+        if self.meta.is_some() {
+            return self.meta_error;
+        }
+        match self.read_meta() {
+            Ok(meta) => self.meta = Some(meta),
+            Err(e) => {
+                log::debug!("{:?}", e);
+                self.meta = Some(Value::Null);
+                self.meta_error = true;
+            }
+        }
+        self.meta_error
+    }
+
+    /// Read the metadata anew and return the error if it can't be read,
+    /// regardless of [`Self::with_skip_errors`].
+    pub fn check_meta(&self) -> Result<()> {
+        self.read_meta().map(drop)
+    }
+
+    fn read_meta(&self) -> Result<Value> {
+        timing::count(Counter::PreamblesParsed);
+        timing::measure(Phase::MetaRead, || self.read_meta_inner())
+    }
+
+    fn read_meta_inner(&self) -> Result<Value> {
+        log::trace!("Reading the metadata of {:?}", self.path);
+
+        let doc_type = self.doc_type();
+        let read_meta = |file: &mut dyn Read| match doc_type {
+            DocType::Markdown | DocType::Plain => read_md_preamble(file),
+            DocType::Org => read_org_keywords(file).context("Failed to read the file"),
+            DocType::Pdf | DocType::Image => Ok(None),
+        };
+
+        let meta = if self.decrypt_cmd.is_some() {
+            read_meta(&mut &self.read_contents()?[..])
+        } else {
+            let mut file = std::fs::File::open(&self.path)
+                .with_context(|| format!("Failed to open {:?}", self.path))?;
+            read_meta(&mut file)
+        };
+
+        Ok(meta
+            .with_context(|| MetaReadError {
+                path: self.path.clone(),
+            })?
+            .unwrap_or(Value::Null))
+    }
+}
+
+/// The error context indicating that the metadata of the document at `path`
+/// couldn't be read. The underlying error is a `serde_yaml::Error` if the
+/// preamble is malformed.
+#[derive(Debug, thiserror::Error)]
+#[error("Failed to read metadata from {path:?}")]
+pub struct MetaReadError {
+    pub path: PathBuf,
+}
+
+impl DocRead {
+    /// Read the whole contents of the document, decrypting them if
+    /// necessary.
+    pub fn read_contents(&self) -> Result<Vec<u8>> {
+        if let Some(decrypt_cmd) = &self.decrypt_cmd {
+            crate::crypt::decrypt(decrypt_cmd, &self.path)
+                .with_context(|| format!("Failed to decrypt {:?}", self.path))
+        } else {
+            std::fs::read(&self.path).with_context(|| format!("Failed to read {:?}", self.path))
+        }
+    }
+
+    /// Read the document body, i.e., the file contents excluding the
+    /// preamble.
+    pub fn read_body(&self) -> Result<Vec<u8>> {
+        log::trace!("Reading the body of {:?}", self.path);
+
+        let mut bytes = self.read_contents()?;
+        let body_start = bytes.len() - split_md_preamble(&bytes).1.len();
+        bytes.drain(..body_start);
+        Ok(bytes)
+    }
+}
+
+impl fmt::Display for DocRead {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.path().display())
+    }
+}
+
+/// The pairs of the opening and closing separators of a preamble.
+const PREAMBLE_SEPARATORS: &[[&[u8]; 2]] = &[
+    [b"---\r\n", b"\r\n---\r\n"],
+    [b"---\n", b"\n---\n"],
+    [b"---\r", b"\r---\r"],
+];
+
+/// Split the contents of a Markdown file into the preamble body (excluding
+/// separators) and the document body. This follows the same rules as
+/// `read_md_preamble`.
+pub fn split_md_preamble(bytes: &[u8]) -> (Option<&[u8]>, &[u8]) {
+    for [sep1, sep2] in PREAMBLE_SEPARATORS.iter() {
+        if let Some(rest) = bytes.strip_prefix(*sep1) {
+            return if let Some(i) = rest.windows(sep2.len()).position(|w| w == *sep2) {
+                (Some(&rest[..i]), &rest[i + sep2.len()..])
+            } else {
+                (None, bytes)
+            };
+        }
+    }
+    (None, bytes)
+}
+
+/// Make a short excerpt of a document body, collapsing whitespace and
+/// truncating it to `max_chars` characters (followed by `…`).
+pub fn excerpt(body: &str, max_chars: usize) -> String {
+    let mut out = String::new();
+    let mut len = 0;
+    for word in body.split_whitespace() {
+        let word_len = word.chars().count() + (len > 0) as usize;
+        if len + word_len > max_chars {
+            if len == 0 {
+                out.extend(word.chars().take(max_chars));
+            }
+            out.push('…');
+            break;
+        }
+        if len > 0 {
+            out.push(' ');
+        }
+        out.push_str(word);
+        len += word_len;
+    }
+    out
+}
+
+/// Rewrite the preamble of the specified document file by applying `f` to its
+/// metadata. A preamble is created if the document doesn't have one.
+///
+/// Only the modified top-level fields are rewritten, and the rest of the
+/// preamble (including comments) is preserved as-is (see [`PreambleEditor`]).
+/// The caller is responsible for checking [`crate::cfg::Cfg::writable`].
+pub fn update_meta(path: &Path, f: impl FnOnce(&mut Mapping) -> Result<()>) -> Result<()> {
+    log::trace!("Updating the metadata of {:?}", path);
+
+    let doc_type = DocType::detect(path);
+    if !doc_type.has_preamble() {
+        bail!(
+            "Editing the metadata of {} documents is not supported: {:?}",
+            doc_type,
+            path
+        );
+    }
+
+    let bytes = std::fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+    let new_bytes = rewrite_meta(&bytes, f)
+        .with_context(|| format!("Failed to update the metadata of {:?}", path))?;
+
+    if new_bytes == bytes {
+        log::trace!("The contents of {:?} did not change", path);
+        return Ok(());
+    }
+
+    write_atomic(path, &new_bytes)
+}
+
+/// Rewrite the preamble in the given file contents by applying `f` to its
+/// metadata, returning the new file contents. See [`update_meta`].
+pub fn rewrite_meta(bytes: &[u8], f: impl FnOnce(&mut Mapping) -> Result<()>) -> Result<Vec<u8>> {
+    let (preamble, _) = split_md_preamble(bytes);
+
+    let preamble = std::str::from_utf8(preamble.unwrap_or(b""))
+        .context("Failed to decode the preamble as UTF-8")?;
+
+    let old_mapping = parse_preamble_mapping(preamble)?;
+
+    let mut new_mapping = old_mapping.clone();
+    f(&mut new_mapping)?;
+
+    let newline = preamble_newline(bytes);
+
+    let mut editor = PreambleEditor::new(&preamble.replace(newline, "\n"));
+    for (key, _) in old_mapping.iter() {
+        if !new_mapping.contains_key(key) {
+            editor.remove(key_str(key)?);
+        }
+    }
+    for (key, value) in new_mapping.iter() {
+        if old_mapping.get(key) != Some(value) {
+            editor.set(key_str(key)?, value)?;
+        }
+    }
+    let new_preamble = editor.to_string();
+
+    // Make sure the edit produced what we wanted
+    let reparsed = parse_preamble_mapping(&new_preamble).with_context(|| {
+        format!(
+            "Internal error: the rewritten preamble is malformed:\n{}",
+            new_preamble
+        )
+    })?;
+    if !mapping_eq_unordered(&reparsed, &new_mapping) {
+        bail!(
+            "Internal error: failed to rewrite the preamble while preserving \
+            its formatting. The rewritten preamble was:\n{}",
+            new_preamble
+        );
+    }
+
+    Ok(replace_preamble(bytes, &new_preamble))
+}
+
+/// Get the line terminator used by the preamble in the given file contents.
+fn preamble_newline(bytes: &[u8]) -> &'static str {
+    if bytes.starts_with(b"---\r\n") {
+        "\r\n"
+    } else if bytes.starts_with(b"---\r") {
+        "\r"
+    } else {
+        "\n"
+    }
+}
+
+/// Replace the preamble in the given file contents with `new_preamble`
+/// (excluding separators and the final line terminator, and using `\n` as
+/// line terminators), returning the new file contents. The line ending style
+/// of the existing preamble is preserved. The preamble is removed if
+/// `new_preamble` is empty.
+pub fn replace_preamble(bytes: &[u8], new_preamble: &str) -> Vec<u8> {
+    let (_, body) = split_md_preamble(bytes);
+    let newline = preamble_newline(bytes);
+
+    if new_preamble.is_empty() {
+        return body.to_owned();
+    }
+
+    let mut out = Vec::with_capacity(new_preamble.len() + body.len() + 16);
+    out.extend_from_slice(b"---");
+    out.extend_from_slice(newline.as_bytes());
+    out.extend_from_slice(new_preamble.replace('\n', newline).as_bytes());
+    out.extend_from_slice(newline.as_bytes());
+    out.extend_from_slice(b"---");
+    out.extend_from_slice(newline.as_bytes());
+    out.extend_from_slice(body);
+    out
+}
+
+/// Parse a preamble as a mapping. A preamble only consisting of comments and
+/// blank lines is treated as an empty mapping.
+pub fn parse_preamble_mapping(preamble: &str) -> Result<Mapping> {
+    let is_empty = preamble.lines().all(|line| {
+        let line = line.trim();
+        line.is_empty() || line.starts_with('#')
+    });
+    if is_empty {
+        return Ok(Mapping::new());
+    }
+
+    match serde_yaml::from_str(preamble).context("Failed to parse the preamble as YAML")? {
+        Value::Mapping(m) => Ok(m),
+        Value::Null => Ok(Mapping::new()),
+        _ => bail!("The preamble is not a mapping"),
+    }
+}
+
+/// Replace the contents of a file atomically by writing them to a temporary
+/// file in the same directory and renaming it over the original file (see
+/// [`replace_atomic`]). This is how all document writes are done, so that a
+/// crash or a full disk never leaves a document truncated.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let path = resolve_symlink(path)?;
+    let file_name = path
+        .file_name()
+        .with_context(|| format!("{:?} does not have a file name", path))?;
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(format!(".veisku-{}.tmp", std::process::id()));
+    let temp_path = path.with_file_name(temp_name);
+
+    // Give the temporary file the final permissions from the start so that
+    // the contents of a private document are never readable by others
+    #[cfg(unix)]
+    let mode = {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::metadata(&path).map_or(0o666, |metadata| metadata.permissions().mode() & 0o777)
+    };
+    #[cfg(not(unix))]
+    let mode = 0o666;
+
+    // Left by a process with the same ID that crashed
+    let _ = std::fs::remove_file(&temp_path);
+    if let Err(e) = write_new(&temp_path, contents, mode) {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    replace_atomic(&temp_path, &path)
+}
+
+/// Create a file at `path`, which must not exist, with the permission bits
+/// `mode` (masked by the umask). `mode` is ignored on non-Unix platforms.
+pub fn write_new(path: &Path, contents: &[u8], mode: u32) -> Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, mode);
+    #[cfg(not(unix))]
+    let _ = mode;
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Failed to create {:?}", path))?;
+    file.write_all(contents)
+        .with_context(|| format!("Failed to write {:?}", path))
+}
+
+/// Rename the file at `temp_path` over `path`, which must be in the same
+/// directory. The new contents are flushed to the disk before renaming, so
+/// `path` has either the old or new contents even if the system crashes.
+/// The permissions of the original file are preserved, and if `path` is a
+/// symbolic link, its target is replaced instead of the link. `temp_path` is
+/// removed on failure.
+pub fn replace_atomic(temp_path: &Path, path: &Path) -> Result<()> {
+    let result = (|| {
+        let path = resolve_symlink(path)?;
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(temp_path)
+            .with_context(|| format!("Failed to open {:?}", temp_path))?;
+        if let Ok(metadata) = std::fs::metadata(&path) {
+            file.set_permissions(metadata.permissions())
+                .with_context(|| format!("Failed to set the permissions of {:?}", temp_path))?;
+        }
+        file.sync_all()
+            .with_context(|| format!("Failed to flush {:?}", temp_path))?;
+        std::fs::rename(temp_path, &path)
+            .with_context(|| format!("Failed to replace {:?}", path))?;
+        sync_parent_dir(&path);
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(temp_path);
+    }
+    result
+}
+
+/// Get the target of `path` if it's a symbolic link.
+fn resolve_symlink(path: &Path) -> Result<std::borrow::Cow<'_, Path>> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => std::fs::canonicalize(path)
+            .map(Into::into)
+            .with_context(|| format!("Failed to resolve the symbolic link {:?}", path)),
+        _ => Ok(path.into()),
+    }
+}
+
+/// Flush the directory entries of the parent directory of `path` (e.g., after
+/// renaming a file into it). Failures are ignored because some file systems
+/// don't support this.
+fn sync_parent_dir(path: &Path) {
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        if let Err(e) = std::fs::File::open(dir).and_then(|dir| dir.sync_all()) {
+            log::debug!("Failed to flush the directory {:?}: {:?}", dir, e);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+/// Get the SHA-256 digest of `data` in lowercase hexadecimal.
+pub fn content_digest(data: &[u8]) -> String {
+    let mut out = String::with_capacity(64);
+    for byte in Sha256::digest(data) {
+        write!(out, "{:02x}", byte).unwrap();
+    }
+    out
+}
+
+fn key_str(key: &Value) -> Result<&str> {
+    key.as_str()
+        .context("Only string keys are supported by the preamble editor")
+}
+
+/// Compare two mappings, ignoring the order of the top-level entries.
+fn mapping_eq_unordered(x: &Mapping, y: &Mapping) -> bool {
+    x.len() == y.len() && x.iter().all(|(k, v)| y.get(k) == Some(v))
+}
+
+/// Format-preserving editor of YAML preambles.
+///
+/// This editor manipulates the top-level fields of a preamble at the line
+/// level. Fields are replaced, inserted, or removed without touching the
+/// other lines, so comments, key order, and formatting are preserved. A
+/// replaced field retains its quoting style (plain, single-quoted, or
+/// double-quoted), its sequence style (flow or block), and the trailing
+/// comment on its first line where possible.
+///
+/// This only recognizes a subset of YAML syntax. The caller should verify the
+/// result by parsing it.
+pub struct PreambleEditor {
+    /// The lines of the preamble, excluding line terminators.
+    lines: Vec<String>,
+}
+
+/// The presentation style of an existing field value
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ValueStyle {
+    Plain,
+    SingleQuoted,
+    DoubleQuoted,
+    FlowSequence,
+    /// A block sequence with the specified indentation
+    BlockSequence(usize),
+    Other,
+}
+
+impl PreambleEditor {
+    pub fn new(text: &str) -> Self {
+        Self {
+            lines: if text.is_empty() {
+                Vec::new()
+            } else {
+                text.split('\n').map(str::to_owned).collect()
+            },
+        }
+    }
+
+    /// Get the key of the top-level field starting at the specified line, and
+    /// the byte offset of the field value in the line.
+    fn entry_key(line: &str) -> Option<(String, usize)> {
+        if line.is_empty() || line.starts_with(|c: char| c.is_whitespace() || "#-".contains(c)) {
+            return None;
+        }
+
+        if let Some(quote) = line.chars().next().filter(|&c| c == '"' || c == '\'') {
+            // Quoted key
+            let key_len = find_closing_quote(&line[1..], quote)? + 2;
+            let key: String = serde_yaml::from_str(&line[..key_len]).ok()?;
+            let rest = line[key_len..].trim_start();
+            let value_start = line.len() - rest.strip_prefix(':')?.len();
+            Some((key, value_start))
+        } else {
+            // Plain key. The separating colon is followed by a whitespace or EOL.
+            let colon = line
+                .char_indices()
+                .find(|&(i, c)| {
+                    c == ':' && line[i + 1..].chars().next().is_none_or(char::is_whitespace)
+                })?
+                .0;
+            Some((line[..colon].trim_end().to_owned(), colon + 1))
+        }
+    }
+
+    /// Find the range of the lines constituting the specified top-level field.
+    fn find_entry(&self, key: &str) -> Option<(std::ops::Range<usize>, usize)> {
+        let start = self
+            .lines
+            .iter()
+            .position(|line| Self::entry_key(line).is_some_and(|(k, _)| k == key))?;
+        let value_start = Self::entry_key(&self.lines[start]).unwrap().1;
+
+        // The field continues while the lines are indented or constitute a
+        // block sequence. Blank lines are included only if they are followed
+        // by such lines.
+        let mut end = start + 1;
+        let mut i = start + 1;
+        while i < self.lines.len() {
+            let line = &self.lines[i];
+            if line.trim().is_empty() {
+                i += 1;
+                continue;
+            }
+            if line.starts_with(char::is_whitespace) || line == "-" || line.starts_with("- ") {
+                i += 1;
+                end = i;
+            } else {
+                break;
+            }
+        }
+
+        Some((start..end, value_start))
+    }
+
+    /// Determine the presentation style of an existing field value.
+    fn value_style(&self, range: &std::ops::Range<usize>, value_start: usize) -> ValueStyle {
+        let (inline, _) = split_comment(&self.lines[range.start][value_start..]);
+        let inline = inline.trim();
+        match inline.chars().next() {
+            Some('"') => ValueStyle::DoubleQuoted,
+            Some('\'') => ValueStyle::SingleQuoted,
+            Some('[') => ValueStyle::FlowSequence,
+            Some('{') | Some('|') | Some('>') | Some('&') | Some('*') | Some('!') => {
+                ValueStyle::Other
+            }
+            Some(_) => ValueStyle::Plain,
+            None => {
+                // Look for a block sequence
+                let item = self.lines[range.start + 1..range.end]
+                    .iter()
+                    .find(|line| !line.trim().is_empty());
+                match item {
+                    Some(line) if line.trim_start().starts_with('-') => {
+                        ValueStyle::BlockSequence(line.len() - line.trim_start().len())
+                    }
+                    _ => ValueStyle::Other,
+                }
+            }
+        }
+    }
+
+    /// Set the value of a top-level field, replacing the existing one or
+    /// appending a new one.
+    pub fn set(&mut self, key: &str, value: &Value) -> Result<()> {
+        if let Some((range, value_start)) = self.find_entry(key) {
+            let style = self.value_style(&range, value_start);
+            let first_line = &self.lines[range.start];
+            let key_text = &first_line[..value_start];
+            let (_, comment) = split_comment(&first_line[value_start..]);
+
+            let mut new_lines = render_field(key_text, value, style)?;
+            if new_lines.len() == 1 && !comment.is_empty() {
+                new_lines[0] += comment;
+            }
+
+            self.lines.splice(range, new_lines);
+        } else {
+            let key_text = format!("{}:", render_string(key, ValueStyle::Plain, false));
+            let new_lines = render_field(&key_text, value, ValueStyle::Plain)?;
+
+            // Insert before trailing blank lines
+            let i = self
+                .lines
+                .iter()
+                .rposition(|line| !line.trim().is_empty())
+                .map_or(0, |i| i + 1);
+            self.lines.splice(i..i, new_lines);
+        }
+        Ok(())
+    }
+
+    /// Remove a top-level field. Returns `true` if the field was found.
+    pub fn remove(&mut self, key: &str) -> bool {
+        if let Some((range, _)) = self.find_entry(key) {
+            self.lines.drain(range);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl fmt::Display for PreambleEditor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, line) in self.lines.iter().enumerate() {
+            if i > 0 {
+                f.write_str("\n")?;
+            }
+            f.write_str(line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Find the closing quotation mark in a quoted scalar (excluding the opening
+/// quotation mark).
+fn find_closing_quote(s: &str, quote: char) -> Option<usize> {
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if quote == '"' && c == '\\' {
+            chars.next();
+        } else if c == quote {
+            if quote == '\'' && s[i + 1..].starts_with('\'') {
+                // Escaped single quotation mark (`''`)
+                chars.next();
+            } else {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+/// Split a field value into the value part and a trailing comment (including
+/// the preceding whitespace characters).
+fn split_comment(s: &str) -> (&str, &str) {
+    let mut quote = None;
+    let mut chars = s.char_indices().peekable();
+    let mut prev_ws = true;
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (None, '#') if prev_ws => {
+                let value = s[..i].trim_end();
+                return (value, &s[value.len()..]);
+            }
+            (None, '"') | (None, '\'') if prev_ws || s[..i].trim().is_empty() => quote = Some(c),
+            (Some('"'), '\\') => {
+                chars.next();
+            }
+            (Some(q), _) if c == q => quote = None,
+            _ => {}
+        }
+        prev_ws = c.is_whitespace();
+    }
+    (s, "")
+}
+
+/// Render a top-level field. `key_text` includes the colon.
+fn render_field(key_text: &str, value: &Value, style: ValueStyle) -> Result<Vec<String>> {
+    Ok(match value {
+        Value::String(st) => vec![format!("{} {}", key_text, render_string(st, style, false))],
+        Value::Sequence(array) if array.iter().all(is_scalar) => {
+            match style {
+                ValueStyle::BlockSequence(indent) if !array.is_empty() => {
+                    std::iter::once(key_text.to_owned())
+                        .chain(array.iter().map(|e| {
+                            format!("{}- {}", " ".repeat(indent), render_scalar(e, false))
+                        }))
+                        .collect()
+                }
+                _ => {
+                    let items: Vec<String> = array.iter().map(|e| render_scalar(e, true)).collect();
+                    vec![format!("{} [{}]", key_text, items.join(", "))]
+                }
+            }
+        }
+        Value::Null | Value::Bool(_) | Value::Number(_) => {
+            vec![format!("{} {}", key_text, render_scalar(value, false))]
+        }
+        _ => {
+            // Fall back to `serde_yaml`'s block style output
+            let yaml = serde_yaml::to_string(value).context("Failed to serialize the value")?;
+            let yaml = yaml.strip_prefix("---\n").unwrap_or(&yaml);
+            std::iter::once(key_text.to_owned())
+                .chain(yaml.lines().map(|line| format!("  {}", line)))
+                .collect()
+        }
+    })
+}
+
+fn is_scalar(value: &Value) -> bool {
+    matches!(
+        value,
+        Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_)
+    )
+}
+
+/// Render a scalar value. `in_flow` indicates whether the value is placed in a
+/// flow collection.
+fn render_scalar(value: &Value, in_flow: bool) -> String {
+    match value {
+        Value::Null => "null".to_owned(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(st) => render_string(st, ValueStyle::Plain, in_flow),
+        _ => unreachable!(),
+    }
+}
+
+/// Render a string scalar in the preferred style, quoting it if necessary.
+fn render_string(st: &str, style: ValueStyle, in_flow: bool) -> String {
+    let needs_quotes = st.is_empty()
+        || st.contains('\n')
+        || st.trim() != st
+        || (in_flow && st.contains(|c| ",[]{}".contains(c)))
+        || serde_yaml::from_str::<Value>(st).ok() != Some(Value::String(st.to_owned()));
+
+    match style {
+        ValueStyle::SingleQuoted if !st.contains('\n') => format!("'{}'", st.replace('\'', "''")),
+        ValueStyle::DoubleQuoted => serde_json::to_string(st).unwrap(),
+        _ if needs_quotes => serde_json::to_string(st).unwrap(),
+        _ => st.to_owned(),
+    }
+}
+
+/// Move a file, falling back to copy-and-delete if they are on different file
+/// systems.
+pub fn move_file(src: &Path, dst: &Path) -> Result<()> {
+    if let Err(e) = std::fs::rename(src, dst) {
+        log::debug!(
+            "Failed to rename {:?} to {:?}: {:?}; copying it instead",
+            src,
+            dst,
+            e
+        );
+        std::fs::copy(src, dst)
+            .with_context(|| format!("Failed to copy {:?} to {:?}", src, dst))?;
+        std::fs::remove_file(src).with_context(|| format!("Failed to remove {:?}", src))?;
+    }
+    Ok(())
+}
+
+fn read_md_preamble(mut file: impl Read) -> Result<Option<Value>> {
+    // We need to find a preamble in the file stream. A preamble is supposed
+    // to look like the following:
+    //
+    //     ---
+    //     key1: value1
+    //     key2: value2
+    //     ---
+    //     <file body>
+    //
+    let separators = PREAMBLE_SEPARATORS;
+    let mut buf = [0u8; 1 << 12];
+    let mut pre_bytes: Vec<u8> = Vec::new();
+
+    // Find the first separator
+    match file.read_exact(&mut buf[..5]) {
+        Ok(()) => {}
+        // If we encountered EOF at this point, the file is clearly too short to
+        // contain the preamble.
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e).context("Failed to read the file"),
+    }
+
+    let sep2 = if let Some([sep1, sep2]) = separators
+        .iter()
+        .find(|[sep1, _]| buf[..5].starts_with(sep1))
+    {
+        // Found the first separator. `buf[..5]` might the first few bytes of
+        // the preamble body if `separator` is shorter than `buf[..5]`.
+        pre_bytes.extend_from_slice(&buf[sep1.len()..5]);
+        sep2
+    } else {
+        // Did not find the first separator.
+        return Ok(None);
+    };
+
+    // Munch the preamble body until we find the second separator
+    loop {
+        let num_bytes_read = file.read(&mut buf).context("Failed to read the file")?;
+
+        if num_bytes_read == 0 {
+            // We did not find the second separator. Maybe what we thought to be
+            // a preamble wasn't actually a preamble.
+            log::warn!("Encountered EOF while reading the preamble");
+            return Ok(None);
+        }
+
+        let search_start = pre_bytes.len().saturating_sub(sep2.len() - 1);
+        pre_bytes.extend_from_slice(&buf[..num_bytes_read]);
+
+        // Look for the second separator
+        if let Some((i, _)) = pre_bytes[search_start..]
+            .windows(sep2.len())
+            .enumerate()
+            .find(|(_, window)| window == sep2)
+        {
+            // Found the second separator at `pre_bytes[search_start + i..][..sep2.len()]`
+            pre_bytes.truncate(search_start + i);
+            break;
+        }
+    }
+
+    drop(file);
+
+    // Interpret the preamble as UTF-8
+    let pre_str =
+        std::str::from_utf8(&pre_bytes).context("Failed to decdoe the preamble as UTF-8")?;
+    log::trace!("pre_str = {:?}", pre_str);
+
+    // Now, parse the preamble.
+    let yaml_value =
+        serde_yaml::from_str(pre_str).context("Failed to parse the preamble as YAML")?;
+    Ok(Some(yaml_value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_digest() {
+        assert_eq!(
+            content_digest(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_read_md_preamble() {
+        assert!(read_md_preamble(&b"no preamble"[..]).unwrap().is_none());
+
+        read_md_preamble(&b"---\nval1: key1\n---\nbody"[..])
+            .unwrap()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_split_md_preamble() {
+        assert_eq!(
+            split_md_preamble(b"no preamble"),
+            (None, &b"no preamble"[..])
+        );
+        assert_eq!(
+            split_md_preamble(b"---\nval1: key1\n---\nbody"),
+            (Some(&b"val1: key1"[..]), &b"body"[..])
+        );
+        assert_eq!(
+            split_md_preamble(b"---\r\nval1: key1\r\n---\r\nbody"),
+            (Some(&b"val1: key1"[..]), &b"body"[..])
+        );
+        assert_eq!(
+            split_md_preamble(b"---\nunterminated"),
+            (None, &b"---\nunterminated"[..])
+        );
+    }
+
+    fn rewrite(contents: &str, f: impl FnOnce(&mut Mapping)) -> String {
+        let bytes = rewrite_meta(contents.as_bytes(), |m| {
+            f(m);
+            Ok(())
+        })
+        .unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    fn key(k: &str) -> Value {
+        Value::String(k.to_owned())
+    }
+
+    #[test]
+    fn test_rewrite_meta_preserves_formatting() {
+        let contents = "---\n\
+            # A comment\n\
+            title: 'Hello'  # trailing comment\n\
+            tags: [a, \"b\"]\n\
+            list:\n  - x\n  - y\n\
+            ---\nbody";
+
+        let out = rewrite(contents, |m| {
+            m.insert(key("title"), key("It's"));
+            m.insert(
+                key("list"),
+                Value::Sequence(vec![key("x"), key("y"), key("z")]),
+            );
+        });
+        assert_eq!(
+            out,
+            "---\n\
+            # A comment\n\
+            title: 'It''s'  # trailing comment\n\
+            tags: [a, \"b\"]\n\
+            list:\n  - x\n  - y\n  - z\n\
+            ---\nbody"
+        );
+    }
+
+    #[test]
+    fn test_rewrite_meta_insert_remove() {
+        let out = rewrite("---\r\na: 1\r\nb: 2\r\n---\r\nbody", |m| {
+            m.remove(&key("a"));
+            m.insert(key("tags"), Value::Sequence(vec![key("x, y"), key("true")]));
+        });
+        assert_eq!(
+            out,
+            "---\r\nb: 2\r\ntags: [\"x, y\", \"true\"]\r\n---\r\nbody"
+        );
+
+        let out = rewrite("no preamble", |m| {
+            m.insert(key("title"), key("New: document"));
+        });
+        assert_eq!(out, "---\ntitle: \"New: document\"\n---\nno preamble");
+    }
+
+    #[test]
+    fn test_split_comment() {
+        assert_eq!(split_comment(" value # c"), (" value", " # c"));
+        assert_eq!(split_comment(" 'a # b' # c"), (" 'a # b'", " # c"));
+        assert_eq!(split_comment(" a#b"), (" a#b", ""));
+    }
+}
@@ -0,0 +1,250 @@
+//! Document type detection
+use serde_yaml::{Mapping, Value};
+use std::{
+    fmt,
+    io::{BufRead, Read},
+    path::Path,
+    str::FromStr,
+};
+
+/// The type of a document, which can be queried by the `type:` pseudo-field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocType {
+    Markdown,
+    Org,
+    Pdf,
+    Image,
+    Plain,
+}
+
+impl DocType {
+    pub const ALL: &'static [Self] = &[
+        Self::Markdown,
+        Self::Org,
+        Self::Pdf,
+        Self::Image,
+        Self::Plain,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Markdown => "markdown",
+            Self::Org => "org",
+            Self::Pdf => "pdf",
+            Self::Image => "image",
+            Self::Plain => "plain",
+        }
+    }
+
+    /// Detect the type of the document at `path`. The type is decided by the
+    /// file extension, ignoring an unknown outer extension (e.g., `.age` of
+    /// `note.md.age`). The contents are sniffed if neither of the last two
+    /// extensions is known.
+    pub fn detect(path: &Path) -> Self {
+        let mut name = path;
+        for _ in 0..2 {
+            let ext = match name.extension().and_then(|ext| ext.to_str()) {
+                Some(ext) => ext.to_ascii_lowercase(),
+                None => break,
+            };
+            if let Some(doc_type) = Self::from_extension(&ext) {
+                return doc_type;
+            }
+            name = Path::new(name.file_stem().unwrap());
+        }
+        Self::sniff(path).unwrap_or(Self::Plain)
+    }
+
+    fn from_extension(ext: &str) -> Option<Self> {
+        Some(match ext {
+            "md" | "mdown" | "markdown" | "mkd" => Self::Markdown,
+            "org" => Self::Org,
+            "pdf" => Self::Pdf,
+            "png" | "jpg" | "jpeg" | "gif" | "webp" | "svg" | "bmp" | "tif" | "tiff" => Self::Image,
+            "txt" | "text" => Self::Plain,
+            _ => return None,
+        })
+    }
+
+    /// Detect the type by the magic number of the file contents.
+    fn sniff(path: &Path) -> Option<Self> {
+        match sniff_mime(path)? {
+            "application/pdf" => Some(Self::Pdf),
+            mime if mime.starts_with("image/") => Some(Self::Image),
+            _ => None,
+        }
+    }
+
+    /// Whether documents of this type store metadata in YAML preambles, which
+    /// can be edited.
+    pub fn has_preamble(self) -> bool {
+        matches!(self, Self::Markdown | Self::Plain)
+    }
+}
+
+impl fmt::Display for DocType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DocType {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .find(|doc_type| doc_type.as_str() == s)
+            .copied()
+            .ok_or("Expected `markdown`, `org`, `pdf`, `image`, or `plain`")
+    }
+}
+
+/// Detect the MIME type of the file at `path`. The contents are examined
+/// first so that files without or with wrong extensions are recognized, and
+/// then the extension.
+pub fn detect_mime(path: &Path) -> Option<&'static str> {
+    sniff_mime(path).or_else(|| {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        Some(match &*ext {
+            "md" | "mdown" | "markdown" | "mkd" => "text/markdown",
+            "org" => "text/org",
+            "txt" | "text" => "text/plain",
+            "html" | "htm" => "text/html",
+            "svg" => "image/svg+xml",
+            "bmp" => "image/bmp",
+            "csv" => "text/csv",
+            "json" => "application/json",
+            "epub" => "application/epub+zip",
+            _ => return None,
+        })
+    })
+}
+
+/// The magic numbers recognized by [`sniff_mime`]: the offset, the bytes,
+/// and the MIME type
+const MAGIC_NUMBERS: &[(usize, &[u8], &str)] = &[
+    (0, b"%PDF-", "application/pdf"),
+    (0, b"\x89PNG\r\n\x1a\n", "image/png"),
+    (0, b"\xff\xd8\xff", "image/jpeg"),
+    (0, b"GIF87a", "image/gif"),
+    (0, b"GIF89a", "image/gif"),
+    (8, b"WEBP", "image/webp"),
+    (0, b"II*\0", "image/tiff"),
+    (0, b"MM\0*", "image/tiff"),
+    (30, b"mimetypeapplication/epub+zip", "application/epub+zip"),
+    (0, b"PK\x03\x04", "application/zip"),
+    (0, b"\x1f\x8b", "application/gzip"),
+    (0, b"ID3", "audio/mpeg"),
+    (0, b"OggS", "audio/ogg"),
+    (0, b"fLaC", "audio/flac"),
+    (4, b"ftyp", "video/mp4"),
+    (0, b"\x1a\x45\xdf\xa3", "video/x-matroska"),
+];
+
+/// Detect the MIME type by the magic number of the file contents.
+fn sniff_mime(path: &Path) -> Option<&'static str> {
+    let mut magic = Vec::with_capacity(64);
+    std::fs::File::open(path)
+        .ok()?
+        .take(64)
+        .read_to_end(&mut magic)
+        .ok()?;
+    mime_from_magic(&magic)
+}
+
+fn mime_from_magic(magic: &[u8]) -> Option<&'static str> {
+    MAGIC_NUMBERS
+        .iter()
+        .find(|(offset, bytes, _)| magic.get(*offset..).is_some_and(|m| m.starts_with(bytes)))
+        .map(|&(_, _, mime)| mime)
+}
+
+/// Read the metadata of an Org document from the keyword lines at the
+/// beginning (e.g., `#+TITLE: Note`). The keys are lowercased, and
+/// `#+FILETAGS: :a:b:` is converted to `tags: [a, b]`. Returns `None` if
+/// there are no keywords.
+pub fn read_org_keywords(file: impl Read) -> std::io::Result<Option<Value>> {
+    let mut mapping = Mapping::new();
+    for line in std::io::BufReader::new(file).lines() {
+        let line = line?;
+        let line = line.trim();
+        let keyword = match line.strip_prefix("#+") {
+            Some(keyword) => keyword,
+            // Skip blank lines and comments preceding the keywords
+            None if line.is_empty() || line == "#" || line.starts_with("# ") => continue,
+            None => break,
+        };
+        let (key, value) = match keyword.find(':') {
+            Some(i) => (&keyword[..i], keyword[i + 1..].trim()),
+            None => continue,
+        };
+        let key = key.to_lowercase();
+        let value = if key == "filetags" {
+            Value::Sequence(
+                value
+                    .split(|c: char| c == ':' || c.is_whitespace())
+                    .filter(|tag| !tag.is_empty())
+                    .map(|tag| Value::String(tag.to_owned()))
+                    .collect(),
+            )
+        } else {
+            Value::String(value.to_owned())
+        };
+        let key = if key == "filetags" {
+            "tags".to_owned()
+        } else {
+            key
+        };
+        mapping.insert(Value::String(key), value);
+    }
+
+    Ok(if mapping.is_empty() {
+        None
+    } else {
+        Some(Value::Mapping(mapping))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(DocType::detect(Path::new("a.md")), DocType::Markdown);
+        assert_eq!(DocType::detect(Path::new("a.md.age")), DocType::Markdown);
+        assert_eq!(DocType::detect(Path::new("a.ORG")), DocType::Org);
+        assert_eq!(DocType::detect(Path::new("a.jpeg")), DocType::Image);
+        assert_eq!(DocType::detect(Path::new("/nonexistent/a")), DocType::Plain);
+    }
+
+    #[test]
+    fn test_mime_from_magic() {
+        assert_eq!(
+            mime_from_magic(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+            Some("image/png")
+        );
+        let mut epub = b"PK\x03\x04".to_vec();
+        epub.resize(30, 0);
+        epub.extend_from_slice(b"mimetypeapplication/epub+zip");
+        assert_eq!(mime_from_magic(&epub), Some("application/epub+zip"));
+        assert_eq!(mime_from_magic(&epub[..40]), Some("application/zip"));
+        assert_eq!(mime_from_magic(b"# Title\n"), None);
+
+        assert_eq!(
+            detect_mime(Path::new("/nonexistent/a.org")),
+            Some("text/org")
+        );
+        assert_eq!(detect_mime(Path::new("/nonexistent/a")), None);
+    }
+
+    #[test]
+    fn test_read_org_keywords() {
+        let text = "# comment\n#+TITLE: Note\n#+FILETAGS: :a:b:\n\n* Heading\n#+DATE: x\n";
+        let meta = read_org_keywords(text.as_bytes()).unwrap().unwrap();
+        assert_eq!(meta["title"], Value::from("Note"));
+        assert_eq!(meta["tags"], Value::from(vec!["a", "b"]));
+        assert_eq!(meta["date"], Value::Null);
+    }
+}
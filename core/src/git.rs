@@ -0,0 +1,205 @@
+//! Git integration
+//!
+//! This module shells out to the `git` command, so it works with any Git
+//! configuration the user has.
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// A working tree change reported by `git status`.
+#[derive(Debug)]
+pub struct StatusEntry {
+    /// The two-letter status code, e.g., ` M` or `??`.
+    pub code: String,
+    /// The absolute path of the file.
+    pub path: PathBuf,
+}
+
+/// Run `git` in `dir` and return its standard output.
+fn run_git<I, S>(dir: &Path, args: I) -> Result<Vec<u8>>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let mut cmd = Command::new("git");
+    cmd.args(args).current_dir(dir);
+    log::debug!("Running {:?}", cmd);
+
+    let output = cmd.output().context("Failed to execute git")?;
+    if !output.status.success() {
+        bail!(
+            "git exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        );
+    }
+    Ok(output.stdout)
+}
+
+/// Get the top-level directory of the working tree containing `dir`.
+pub fn toplevel(dir: &Path) -> Result<PathBuf> {
+    let stdout = run_git(dir, ["rev-parse", "--show-toplevel"])
+        .context("The document root is not in a Git repository")?;
+    let path = String::from_utf8(stdout).context("Non-UTF-8 output from git")?;
+    Ok(PathBuf::from(path.trim_end_matches('\n')))
+}
+
+/// Split NUL-separated output into absolute paths.
+fn nul_separated_paths<'a>(
+    toplevel: &'a Path,
+    stdout: &'a [u8],
+) -> impl Iterator<Item = PathBuf> + 'a {
+    stdout
+        .split(|&b| b == 0)
+        .filter(|x| !x.is_empty())
+        .map(move |x| toplevel.join(&*String::from_utf8_lossy(x)))
+}
+
+/// Get the working tree changes (including untracked files) in the repository
+/// containing `dir`.
+pub fn status(dir: &Path) -> Result<Vec<StatusEntry>> {
+    let toplevel = toplevel(dir)?;
+    let stdout = run_git(
+        &toplevel,
+        ["status", "--porcelain=v1", "-z", "--untracked-files=all"],
+    )?;
+
+    // Each entry looks like `XY PATH\0`. Renames and copies are followed by
+    // the original path (`XY PATH\0ORIG_PATH\0`).
+    let mut entries = Vec::new();
+    let mut it = stdout.split(|&b| b == 0).filter(|x| !x.is_empty());
+    while let Some(entry) = it.next() {
+        if entry.len() < 4 {
+            bail!(
+                "Unexpected output from git: {:?}",
+                String::from_utf8_lossy(entry)
+            );
+        }
+        let code = String::from_utf8_lossy(&entry[..2]).into_owned();
+        if code.contains('R') || code.contains('C') {
+            it.next();
+        }
+        entries.push(StatusEntry {
+            path: toplevel.join(&*String::from_utf8_lossy(&entry[3..])),
+            code,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Get the set of files that differ from the revision `rev` (including
+/// untracked files) in the repository containing `dir`.
+pub fn changed_since(dir: &Path, rev: &str) -> Result<HashSet<PathBuf>> {
+    // `rev` may come from outside (e.g., `v serve`), so it must not be
+    // interpreted as an option
+    if rev.starts_with('-') {
+        bail!("Invalid revision: '{}'", rev);
+    }
+
+    let toplevel = toplevel(dir)?;
+
+    let diff = run_git(
+        &toplevel,
+        ["diff", "--name-only", "-z", "--end-of-options", rev, "--"],
+    )?;
+    let untracked = run_git(
+        &toplevel,
+        [
+            "ls-files",
+            "--others",
+            "--exclude-standard",
+            "--full-name",
+            "-z",
+        ],
+    )?;
+
+    Ok(nul_separated_paths(&toplevel, &diff)
+        .chain(nul_separated_paths(&toplevel, &untracked))
+        .collect())
+}
+
+/// Get the commit ID of `HEAD` in the repository containing `dir`.
+pub fn head(dir: &Path) -> Result<String> {
+    let stdout = run_git(dir, ["rev-parse", "HEAD"])?;
+    let head = String::from_utf8(stdout).context("Non-UTF-8 output from git")?;
+    Ok(head.trim_end().to_owned())
+}
+
+/// The author dates of the first and last commits touching a file, in the
+/// local time zone (`YYYY-MM-DD HH:MM:SS`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileDates {
+    pub created: String,
+    pub modified: String,
+}
+
+/// Get the [`FileDates`] of the files under `dir`, keyed by the paths relative
+/// to `dir`. Renames are not followed.
+pub fn file_dates(dir: &Path) -> Result<HashMap<PathBuf, FileDates>> {
+    let stdout = run_git(
+        dir,
+        [
+            "log",
+            "--format=%x01%ad",
+            "--date=format-local:%Y-%m-%d %H:%M:%S",
+            "--name-only",
+            "--relative",
+            "--no-renames",
+            "-z",
+            "--",
+            ".",
+        ],
+    )?;
+    Ok(parse_file_dates(&stdout))
+}
+
+/// Parse the output of `git log` in [`file_dates`]. Each commit looks like
+/// `\x01DATE\0\nPATH\0PATH\0...`, newest first.
+fn parse_file_dates(stdout: &[u8]) -> HashMap<PathBuf, FileDates> {
+    let mut dates: HashMap<PathBuf, FileDates> = HashMap::new();
+    let mut date = String::new();
+    for token in stdout.split(|&b| b == 0) {
+        if let Some(token) = token.strip_prefix(b"\x01") {
+            date = String::from_utf8_lossy(token).into_owned();
+            continue;
+        }
+        let path = token.strip_prefix(b"\n").unwrap_or(token);
+        if path.is_empty() {
+            continue;
+        }
+        let path = PathBuf::from(&*String::from_utf8_lossy(path));
+        dates
+            .entry(path)
+            .and_modify(|dates| dates.created = date.clone())
+            .or_insert_with(|| FileDates {
+                created: date.clone(),
+                modified: date.clone(),
+            });
+    }
+    dates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_file_dates() {
+        let dates = parse_file_dates(
+            b"\x012021-03-01 10:00:00\0\na.md\0\
+              \x012021-02-01 10:00:00\0\na.md\0b.md\0\
+              \x012021-01-01 10:00:00\0\na.md\0",
+        );
+        assert_eq!(dates.len(), 2);
+        assert_eq!(dates[Path::new("a.md")].created, "2021-01-01 10:00:00");
+        assert_eq!(dates[Path::new("a.md")].modified, "2021-03-01 10:00:00");
+        assert_eq!(dates[Path::new("b.md")].created, "2021-02-01 10:00:00");
+        assert_eq!(dates[Path::new("b.md")].modified, "2021-02-01 10:00:00");
+    }
+}
@@ -0,0 +1,292 @@
+//! The history of opened documents, stored in `.veisku/state/history.jsonl`
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{doc, lock::FileLock, root::DocRoot, schema, state::Component};
+
+/// An entry of [`History`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// The path of the document relative to the document root
+    pub path: PathBuf,
+    /// The time when the document was opened, in seconds since the Unix epoch
+    pub time: i64,
+    /// The `id` field of the document, used to find the document if it's
+    /// moved
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+}
+
+/// The history of documents opened by `v open`, `v show`, and `v edit`
+#[derive(Debug, Default)]
+pub struct History {
+    /// The entries in chronological order
+    pub entries: Vec<HistoryEntry>,
+}
+
+/// The maximum number of entries kept in the history file. Older entries are
+/// removed when the file grows to twice this size.
+const MAX_ENTRIES: usize = 1000;
+
+/// The maximum value of [`History::frecency`]
+pub const MAX_FRECENCY: u32 = 49;
+
+impl History {
+    /// Get the path of the history file.
+    pub fn path(root: &DocRoot) -> PathBuf {
+        root.state_dir().component_path(Component::History)
+    }
+
+    /// Load the history. Returns an empty history if the file doesn't exist.
+    /// Malformed lines are ignored.
+    ///
+    /// If documents in the history no longer exist at the recorded paths
+    /// (e.g., because they were renamed outside veisku), they are looked up
+    /// by their `id` fields, and the history file is updated with their new
+    /// paths.
+    pub fn load(root: &DocRoot) -> Self {
+        let mut history = Self {
+            entries: read_entries(&Self::path(root)),
+        };
+        let is_lost =
+            |entry: &HistoryEntry| entry.id.is_some() && !root.path.join(&entry.path).exists();
+        if history.entries.iter().any(is_lost) {
+            if let Err(e) = history.relocate(root, is_lost) {
+                log::warn!(
+                    "Failed to update the moved documents in the history: {:?}",
+                    e
+                );
+            }
+        }
+        history
+    }
+
+    /// Find the new paths of the entries for which `is_lost` returns `true`
+    /// and rewrite the history file.
+    fn relocate(&mut self, root: &DocRoot, is_lost: impl Fn(&HistoryEntry) -> bool) -> Result<()> {
+        root.state_dir().prepare()?;
+        let history_path = Self::path(root);
+        let _lock = FileLock::acquire(&history_path)?;
+        // The file might have changed before the lock was acquired
+        self.entries = read_entries(&history_path);
+
+        let mut by_id = HashMap::new();
+        for doc in root.docs() {
+            let mut doc = doc?;
+            let path = doc
+                .path()
+                .strip_prefix(&root.path)
+                .unwrap_or(doc.path())
+                .to_owned();
+            // Don't ask for passphrases just to maintain the history
+            if root.cfg.encryption_for(&path).is_some() {
+                continue;
+            }
+            if let Some(id) = doc
+                .ensure_meta()
+                .ok()
+                .and_then(|meta| schema::scalar_text(&meta["id"]))
+            {
+                by_id.insert(id, path);
+            }
+        }
+
+        relocate_entries(&mut self.entries, is_lost, &by_id);
+        doc::write_atomic(&history_path, format_entries(&self.entries).as_bytes())
+    }
+
+    /// Append entries for `paths` to the history file. Nothing is recorded if
+    /// the configuration directory doesn't exist (creating it would change the
+    /// document root).
+    pub fn record(root: &DocRoot, paths: &[&Path]) -> Result<()> {
+        if !root.cfg_dir_path.is_dir() {
+            return Ok(());
+        }
+        root.state_dir().prepare()?;
+        let history_path = Self::path(root);
+        let _lock = FileLock::acquire(&history_path)?;
+        let time = now();
+        let entries: Vec<HistoryEntry> = paths
+            .iter()
+            .map(|path| {
+                let rel_path = path.strip_prefix(&root.path).unwrap_or(path).to_owned();
+                let id = if root.cfg.encryption_for(path).is_some() {
+                    None
+                } else {
+                    root.doc_at(path.to_path_buf())
+                        .ensure_meta()
+                        .ok()
+                        .and_then(|meta| schema::scalar_text(&meta["id"]))
+                };
+                HistoryEntry {
+                    path: rel_path,
+                    time,
+                    id,
+                }
+            })
+            .collect();
+        let lines = format_entries(&entries);
+
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&history_path)
+            .with_context(|| format!("Failed to open {:?}", history_path))?;
+        file.write_all(lines.as_bytes())
+            .with_context(|| format!("Failed to write {:?}", history_path))?;
+        drop(file);
+
+        // Remove old entries
+        let entries = read_entries(&history_path);
+        if entries.len() >= MAX_ENTRIES * 2 {
+            let text = format_entries(&entries[entries.len() - MAX_ENTRIES..]);
+            doc::write_atomic(&history_path, text.as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the distinct paths in the history, the most recently opened
+    /// first.
+    pub fn recent(&self) -> Vec<&Path> {
+        let mut seen = HashSet::new();
+        self.entries
+            .iter()
+            .rev()
+            .map(|entry| &*entry.path)
+            .filter(|path| seen.insert(*path))
+            .collect()
+    }
+
+    /// Calculate the frecency of each document, a score combining how often
+    /// and how recently it was opened. See [`frecency_weight`].
+    pub fn frecency(&self, now: i64) -> HashMap<&Path, u32> {
+        let mut scores: HashMap<&Path, u32> = HashMap::new();
+        for entry in &self.entries {
+            let score = scores.entry(&entry.path).or_default();
+            *score = (*score + frecency_weight(now - entry.time)).min(MAX_FRECENCY);
+        }
+        scores
+    }
+}
+
+/// Get the current time in seconds since the Unix epoch.
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+fn parse_entries(text: &str) -> Vec<HistoryEntry> {
+    text.lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+fn read_entries(path: &Path) -> Vec<HistoryEntry> {
+    parse_entries(&std::fs::read_to_string(path).unwrap_or_default())
+}
+
+fn format_entries(entries: &[HistoryEntry]) -> String {
+    let mut text = String::new();
+    for entry in entries {
+        text += &serde_json::to_string(entry).unwrap();
+        text.push('\n');
+    }
+    text
+}
+
+/// Update the paths of the entries for which `is_lost` returns `true` with
+/// the documents having the same `id`. The entries that can't be found lose
+/// their `id` so that they aren't looked up again.
+fn relocate_entries(
+    entries: &mut [HistoryEntry],
+    is_lost: impl Fn(&HistoryEntry) -> bool,
+    by_id: &HashMap<String, PathBuf>,
+) {
+    for entry in entries.iter_mut().filter(|entry| is_lost(entry)) {
+        match entry.id.as_ref().and_then(|id| by_id.get(id)) {
+            Some(new_path) => {
+                log::debug!("History: {:?} has moved to {:?}", entry.path, new_path);
+                entry.path = new_path.clone();
+            }
+            None => entry.id = None,
+        }
+    }
+}
+
+/// The contribution of an access made `age` seconds ago to the frecency
+fn frecency_weight(age: i64) -> u32 {
+    const DAY: i64 = 24 * 60 * 60;
+    match age {
+        _ if age < 4 * DAY => 4,
+        _ if age < 14 * DAY => 3,
+        _ if age < 31 * DAY => 2,
+        _ if age < 90 * DAY => 1,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frecency() {
+        let history = History {
+            entries: parse_entries(
+                "{\"path\":\"a.md\",\"time\":0}\n\
+                 garbage\n\
+                 {\"path\":\"a.md\",\"time\":1000000}\n\
+                 {\"path\":\"b.md\",\"time\":1000000}\n\
+                 {\"path\":\"b.md\",\"time\":1000000}\n",
+            ),
+        };
+        assert_eq!(history.entries.len(), 4);
+        let scores = history.frecency(1000000);
+        assert_eq!(scores[Path::new("a.md")], 3 + 4);
+        assert_eq!(scores[Path::new("b.md")], 8);
+    }
+
+    #[test]
+    fn test_recent() {
+        let history = History {
+            entries: parse_entries(
+                "{\"path\":\"a.md\",\"time\":0}\n\
+                 {\"path\":\"b.md\",\"time\":1}\n\
+                 {\"path\":\"a.md\",\"time\":2}\n\
+                 {\"path\":\"c.md\",\"time\":3}\n",
+            ),
+        };
+        assert_eq!(history.recent(), ["c.md", "a.md", "b.md"]);
+    }
+
+    #[test]
+    fn test_relocate_entries() {
+        let mut entries = parse_entries(
+            "{\"path\":\"a.md\",\"time\":0,\"id\":\"x\"}\n\
+             {\"path\":\"b.md\",\"time\":0,\"hash\":\"0123\"}\n\
+             {\"path\":\"c.md\",\"time\":0,\"id\":\"y\"}\n\
+             {\"path\":\"d.md\",\"time\":0,\"id\":\"z\"}\n",
+        );
+        let by_id = [("x", "x/a.md"), ("z", "x/d.md")]
+            .iter()
+            .map(|&(id, path)| (id.to_owned(), PathBuf::from(path)))
+            .collect();
+        relocate_entries(
+            &mut entries,
+            |entry| entry.id.is_some() && entry.path != Path::new("d.md"),
+            &by_id,
+        );
+        let paths: Vec<&Path> = entries.iter().map(|entry| &*entry.path).collect();
+        assert_eq!(paths, ["x/a.md", "b.md", "c.md", "d.md"]);
+        assert_eq!(entries[2].id, None);
+        assert_eq!(entries[3].id.as_deref(), Some("z"));
+    }
+}
@@ -0,0 +1,103 @@
+//! Per-directory ignore files (`.veiskuignore`)
+use ignore::gitignore::Gitignore;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// The name of the ignore files, which use `gitignore`'s syntax. The patterns
+/// are relative to the directory containing the file.
+pub const IGNORE_FILE_NAME: &str = ".veiskuignore";
+
+/// Determines whether files are excluded by the ignore files in their
+/// ancestor directories (up to the document root). The ignore files are
+/// loaded lazily and cached.
+#[derive(Debug)]
+pub struct IgnoreFiles {
+    root_path: PathBuf,
+    /// The ignore file of each directory, if any
+    cache: HashMap<PathBuf, Option<Gitignore>>,
+}
+
+impl IgnoreFiles {
+    pub fn new(root_path: &Path) -> Self {
+        Self {
+            root_path: root_path.to_owned(),
+            cache: HashMap::new(),
+        }
+    }
+
+    /// Check if the file at `path` is ignored.
+    pub fn is_ignored(&mut self, path: &Path) -> bool {
+        let dirs: Vec<PathBuf> = path
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(&self.root_path))
+            .map(Path::to_owned)
+            .collect();
+        for dir in &dirs {
+            self.cache.entry(dir.clone()).or_insert_with(|| load(dir));
+        }
+        is_ignored_by(dirs.iter().filter_map(|dir| self.cache[dir].as_ref()), path)
+    }
+}
+
+fn load(dir: &Path) -> Option<Gitignore> {
+    let path = dir.join(IGNORE_FILE_NAME);
+    if !path.is_file() {
+        return None;
+    }
+    let (gitignore, error) = Gitignore::new(&path);
+    if let Some(e) = error {
+        log::warn!("{:?}: {}", path, e);
+    }
+    Some(gitignore)
+}
+
+/// Check if `path` is ignored by `matchers`, which are ordered from the
+/// deepest directory. A deeper ignore file takes precedence, so it can
+/// re-include (`!PATTERN`) a file excluded by a shallower one.
+fn is_ignored_by<'a>(matchers: impl IntoIterator<Item = &'a Gitignore>, path: &Path) -> bool {
+    for matcher in matchers {
+        let m = matcher.matched_path_or_any_parents(path, false);
+        if m.is_ignore() {
+            return true;
+        } else if m.is_whitelist() {
+            return false;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ignore::gitignore::GitignoreBuilder;
+
+    fn gitignore(dir: &str, lines: &[&str]) -> Gitignore {
+        let mut builder = GitignoreBuilder::new(dir);
+        for line in lines {
+            builder.add_line(None, line).unwrap();
+        }
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn test_is_ignored_by() {
+        let root = gitignore("/r", &["drafts/", "*.tmp.md"]);
+        let sub = gitignore("/r/drafts", &["!keep.md"]);
+        let is_ignored = |path: &str| {
+            let path = Path::new(path);
+            if path.starts_with("/r/drafts") {
+                is_ignored_by(vec![&sub, &root], path)
+            } else {
+                is_ignored_by(vec![&root], path)
+            }
+        };
+        assert!(!is_ignored("/r/a.md"));
+        assert!(is_ignored("/r/a.tmp.md"));
+        assert!(is_ignored("/r/drafts/b.md"));
+        assert!(!is_ignored("/r/drafts/keep.md"));
+        assert!(is_ignored("/r/notes/deep/c.tmp.md"));
+    }
+}
@@ -0,0 +1,389 @@
+//! The journal of mutating operations, stored in `.veisku/state/journal`, which
+//! `v undo` uses to revert them
+//!
+//! Each operation is recorded in `.veisku/state/journal/index.jsonl`. The contents
+//! of rewritten files before and after the operation are stored in
+//! `.veisku/state/journal/ID/`.
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Mapping;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    doc, history,
+    lock::FileLock,
+    root::DocRoot,
+    state::{Component, StateDir},
+    trash::{Trash, TrashEntry},
+};
+
+/// The maximum number of operations kept in the journal
+const MAX_OPERATIONS: usize = 100;
+
+/// A recorded operation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Operation {
+    /// The name of the directory containing the file contents
+    pub id: String,
+    /// The time of the operation in seconds since the Unix epoch
+    pub time: i64,
+    /// A human-readable description (e.g., `v mv`)
+    pub description: String,
+    /// The changes in the order they were made. Paths are relative to the
+    /// document root.
+    pub changes: Vec<Change>,
+}
+
+/// A change made by an [`Operation`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+    /// A file was rewritten. The old and new contents are stored in
+    /// `N.before` and `N.after`, where `N` is `index`.
+    Write { path: PathBuf, index: usize },
+    /// A file was created. Its contents are stored in `N.after`, where `N`
+    /// is `index`.
+    Create { path: PathBuf, index: usize },
+    /// A file was renamed.
+    Move { from: PathBuf, to: PathBuf },
+    /// A file was moved to the trash.
+    Trash { entry: TrashEntry },
+}
+
+/// The journal of a document root
+#[derive(Debug)]
+pub struct Journal {
+    state: StateDir,
+    root_path: PathBuf,
+    /// `false` if the configuration directory doesn't exist, in which case
+    /// nothing is recorded (creating it would change the document root)
+    enabled: bool,
+}
+
+impl Journal {
+    pub fn new(root: &DocRoot) -> Self {
+        Self {
+            state: root.state_dir(),
+            root_path: root.path.clone(),
+            enabled: root.cfg_dir_path.is_dir(),
+        }
+    }
+
+    fn dir(&self) -> PathBuf {
+        self.state.component_path(Component::Journal)
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.dir().join("index.jsonl")
+    }
+
+    /// Get the time when an operation was last recorded or reverted. Returns
+    /// `None` if the journal is empty or disabled.
+    pub fn last_modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(self.index_path())
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    /// Get the recorded operations, oldest first. Malformed lines are
+    /// ignored.
+    pub fn operations(&self) -> Result<Vec<Operation>> {
+        let index_path = self.index_path();
+        let text = match std::fs::read_to_string(&index_path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", index_path)),
+        };
+        Ok(text
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+
+    /// Start recording an operation. The changes must be made through the
+    /// returned [`Recorder`].
+    pub fn begin(&self, description: impl Into<String>) -> Recorder<'_> {
+        Recorder {
+            journal: self,
+            op: Operation {
+                id: String::new(),
+                time: history::now(),
+                description: description.into(),
+                changes: Vec::new(),
+            },
+            contents: Vec::new(),
+        }
+    }
+
+    fn rewrite_index(&self, ops: &[Operation]) -> Result<()> {
+        let mut text = String::new();
+        for op in ops {
+            text += &serde_json::to_string(op).unwrap();
+            text.push('\n');
+        }
+        doc::write_atomic(&self.index_path(), text.as_bytes())
+    }
+
+    fn append(&self, mut op: Operation, contents: &[(Vec<u8>, Vec<u8>)]) -> Result<()> {
+        self.state.prepare()?;
+        let dir = self.dir();
+        std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {:?}", dir))?;
+        let _lock = FileLock::acquire(&self.index_path())?;
+
+        let (id, op_dir) = (1..)
+            .map(|i| {
+                let id = format!("{}-{}", op.time, i);
+                let op_dir = dir.join(&id);
+                (id, op_dir)
+            })
+            .find(|(_, op_dir)| !op_dir.exists())
+            .unwrap();
+        op.id = id;
+
+        if !contents.is_empty() {
+            std::fs::create_dir(&op_dir)
+                .with_context(|| format!("Failed to create {:?}", op_dir))?;
+        }
+        for (i, (before, after)) in contents.iter().enumerate() {
+            for (name, bytes) in [
+                (format!("{}.before", i), before),
+                (format!("{}.after", i), after),
+            ] {
+                // The copies may come from private documents
+                doc::write_new(&op_dir.join(name), bytes, 0o600)?;
+            }
+        }
+
+        let index_path = self.index_path();
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&index_path)
+            .with_context(|| format!("Failed to open {:?}", index_path))?;
+        writeln!(file, "{}", serde_json::to_string(&op).unwrap())
+            .with_context(|| format!("Failed to write {:?}", index_path))?;
+        drop(file);
+
+        // Remove old operations
+        let ops = self.operations()?;
+        if ops.len() > MAX_OPERATIONS {
+            let (old, new) = ops.split_at(ops.len() - MAX_OPERATIONS);
+            self.rewrite_index(new)?;
+            for op in old {
+                let _ = std::fs::remove_dir_all(dir.join(&op.id));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Revert the most recent operation and remove it from the journal.
+    /// Fails without changing anything if any of the affected files were
+    /// modified after the operation. Returns `None` if the journal is empty.
+    pub fn undo(&self, trash: &Trash) -> Result<Option<Operation>> {
+        if !self.dir().is_dir() {
+            return Ok(None);
+        }
+        self.state.prepare()?;
+        let _lock = FileLock::acquire(&self.index_path())?;
+        let mut ops = self.operations()?;
+        let op = match ops.pop() {
+            Some(op) => op,
+            None => return Ok(None),
+        };
+        let op_dir = self.dir().join(&op.id);
+        let read =
+            |path: &Path| std::fs::read(path).with_context(|| format!("Failed to read {:?}", path));
+
+        // Check that the operation can be reverted cleanly. The changes are
+        // examined in the reverse order because a later change may move a
+        // file written by an earlier one.
+        let mut renames: Vec<(&Path, &Path)> = Vec::new();
+        for change in op.changes.iter().rev() {
+            match change {
+                Change::Write { path, index } | Change::Create { path, index } => {
+                    let current_path = renames
+                        .iter()
+                        .find(|(from, _)| from == path)
+                        .map_or(&**path, |(_, to)| to);
+                    let current = read(&self.root_path.join(current_path))?;
+                    let after = read(&op_dir.join(format!("{}.after", index)))?;
+                    if current != after {
+                        bail!(
+                            "{:?} was modified after the operation; refusing to undo it",
+                            current_path
+                        );
+                    }
+                }
+                Change::Move { from, to } => {
+                    if !self.root_path.join(to).exists() {
+                        bail!("{:?} no longer exists; refusing to undo the operation", to);
+                    }
+                    if self.root_path.join(from).exists() {
+                        bail!("{:?} already exists; refusing to undo the operation", from);
+                    }
+                    renames.push((from, to));
+                }
+                Change::Trash { entry } => {
+                    if self.root_path.join(&entry.path).exists() {
+                        bail!(
+                            "{:?} already exists; refusing to undo the operation",
+                            entry.path
+                        );
+                    }
+                }
+            }
+        }
+
+        for change in op.changes.iter().rev() {
+            match change {
+                Change::Write { path, index } => {
+                    let before = read(&op_dir.join(format!("{}.before", index)))?;
+                    doc::write_atomic(&self.root_path.join(path), &before)?;
+                }
+                Change::Create { path, .. } => {
+                    let path = self.root_path.join(path);
+                    std::fs::remove_file(&path)
+                        .with_context(|| format!("Failed to remove {:?}", path))?;
+                }
+                Change::Move { from, to } => {
+                    doc::move_file(&self.root_path.join(to), &self.root_path.join(from))?;
+                }
+                Change::Trash { entry } => {
+                    trash.restore(entry)?;
+                }
+            }
+        }
+
+        self.rewrite_index(&ops)?;
+        let _ = std::fs::remove_dir_all(&op_dir);
+
+        Ok(Some(op))
+    }
+}
+
+/// Records the changes made by an operation. See [`Journal::begin`].
+#[derive(Debug)]
+pub struct Recorder<'a> {
+    journal: &'a Journal,
+    op: Operation,
+    /// The old and new contents of the files in [`Change::Write`]
+    contents: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Recorder<'_> {
+    fn relative(&self, path: &Path) -> PathBuf {
+        path.strip_prefix(&self.journal.root_path)
+            .unwrap_or(path)
+            .to_owned()
+    }
+
+    fn record_write(&mut self, path: &Path, before: Vec<u8>, after: Vec<u8>) {
+        if before != after {
+            self.op.changes.push(Change::Write {
+                path: self.relative(path),
+                index: self.contents.len(),
+            });
+            self.contents.push((before, after));
+        }
+    }
+
+    /// Replace the contents of the file at `path` (see [`doc::write_atomic`]).
+    pub fn write_atomic(&mut self, path: &Path, bytes: &[u8]) -> Result<()> {
+        let before = std::fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+        doc::write_atomic(path, bytes)?;
+        self.record_write(path, before, bytes.to_owned());
+        Ok(())
+    }
+
+    /// Create a file with the contents `bytes`. Fails if the file already
+    /// exists.
+    pub fn create(&mut self, path: &Path, bytes: &[u8]) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+        }
+        std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(bytes))
+            .with_context(|| format!("Failed to create {:?}", path))?;
+        self.op.changes.push(Change::Create {
+            path: self.relative(path),
+            index: self.contents.len(),
+        });
+        self.contents.push((Vec::new(), bytes.to_owned()));
+        Ok(())
+    }
+
+    /// Rewrite the preamble of a document (see [`doc::update_meta`]).
+    pub fn update_meta(
+        &mut self,
+        path: &Path,
+        f: impl FnOnce(&mut Mapping) -> Result<()>,
+    ) -> Result<()> {
+        let before = std::fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+        doc::update_meta(path, f)?;
+        let after = std::fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+        self.record_write(path, before, after);
+        Ok(())
+    }
+
+    /// Rename a file (see [`doc::move_file`]).
+    pub fn move_file(&mut self, from: &Path, to: &Path) -> Result<()> {
+        doc::move_file(from, to)?;
+        self.op.changes.push(Change::Move {
+            from: self.relative(from),
+            to: self.relative(to),
+        });
+        Ok(())
+    }
+
+    /// Move a document to the trash (see [`Trash::put`]).
+    pub fn trash(&mut self, trash: &Trash, path: &Path) -> Result<TrashEntry> {
+        let entry = trash.put(path)?;
+        self.op.changes.push(Change::Trash {
+            entry: entry.clone(),
+        });
+        Ok(entry)
+    }
+
+    /// Save the operation in the journal. Nothing is saved if no changes
+    /// were made.
+    pub fn commit(mut self) -> Result<()> {
+        self.save()
+    }
+
+    fn save(&mut self) -> Result<()> {
+        let changes = std::mem::take(&mut self.op.changes);
+        let contents = std::mem::take(&mut self.contents);
+        if !self.journal.enabled || changes.is_empty() {
+            return Ok(());
+        }
+        let op = Operation {
+            changes,
+            ..self.op.clone()
+        };
+        self.journal
+            .append(op, &contents)
+            .context("Failed to record the operation in the journal")
+    }
+}
+
+impl Drop for Recorder<'_> {
+    /// Save the changes made so far if the operation failed partway (i.e.,
+    /// [`Recorder::commit`] wasn't called), so that `v undo` can revert them.
+    fn drop(&mut self) {
+        if self.op.changes.is_empty() {
+            return;
+        }
+        self.op.description += " (incomplete)";
+        if let Err(e) = self.save() {
+            log::warn!("{:?}", e);
+        }
+    }
+}
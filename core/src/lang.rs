@@ -0,0 +1,140 @@
+//! Language detection of document bodies (the `lang` pseudo-field)
+use anyhow::Result;
+use whatlang::Lang;
+
+use crate::{cache::FileCache, doc::DocRead, doctype::DocType, root::DocRoot};
+
+/// The name of the pseudo-field holding the detected language
+pub const FIELD: &str = "lang";
+
+/// Bodies shorter than this (in characters) are too short to be classified
+/// reliably
+const MIN_CHARS: usize = 20;
+
+/// Detect the language of `text`. Returns its ISO 639-1 code (e.g., `ja`),
+/// or `None` if the text is too short or the detection is unreliable.
+pub fn detect(text: &str) -> Option<&'static str> {
+    if text.chars().filter(|c| c.is_alphabetic()).count() < MIN_CHARS {
+        return None;
+    }
+    let info = whatlang::detect(text)?;
+    if !info.is_reliable() {
+        return None;
+    }
+    Some(iso639_1(info.lang()))
+}
+
+fn iso639_1(lang: Lang) -> &'static str {
+    match lang {
+        Lang::Afr => "af",
+        Lang::Aka => "ak",
+        Lang::Amh => "am",
+        Lang::Ara => "ar",
+        Lang::Aze => "az",
+        Lang::Bel => "be",
+        Lang::Ben => "bn",
+        Lang::Bul => "bg",
+        Lang::Cat => "ca",
+        Lang::Ces => "cs",
+        Lang::Cmn => "zh",
+        Lang::Dan => "da",
+        Lang::Deu => "de",
+        Lang::Ell => "el",
+        Lang::Eng => "en",
+        Lang::Epo => "eo",
+        Lang::Est => "et",
+        Lang::Fin => "fi",
+        Lang::Fra => "fr",
+        Lang::Guj => "gu",
+        Lang::Heb => "he",
+        Lang::Hin => "hi",
+        Lang::Hrv => "hr",
+        Lang::Hun => "hu",
+        Lang::Hye => "hy",
+        Lang::Ind => "id",
+        Lang::Ita => "it",
+        Lang::Jav => "jv",
+        Lang::Jpn => "ja",
+        Lang::Kan => "kn",
+        Lang::Kat => "ka",
+        Lang::Khm => "km",
+        Lang::Kor => "ko",
+        Lang::Lat => "la",
+        Lang::Lav => "lv",
+        Lang::Lit => "lt",
+        Lang::Mal => "ml",
+        Lang::Mar => "mr",
+        Lang::Mkd => "mk",
+        Lang::Mya => "my",
+        Lang::Nep => "ne",
+        Lang::Nld => "nl",
+        Lang::Nob => "nb",
+        Lang::Ori => "or",
+        Lang::Pan => "pa",
+        Lang::Pes => "fa",
+        Lang::Pol => "pl",
+        Lang::Por => "pt",
+        Lang::Ron => "ro",
+        Lang::Rus => "ru",
+        Lang::Sin => "si",
+        Lang::Slk => "sk",
+        Lang::Slv => "sl",
+        Lang::Sna => "sn",
+        Lang::Spa => "es",
+        Lang::Srp => "sr",
+        Lang::Swe => "sv",
+        Lang::Tam => "ta",
+        Lang::Tel => "te",
+        Lang::Tgl => "tl",
+        Lang::Tha => "th",
+        Lang::Tuk => "tk",
+        Lang::Tur => "tr",
+        Lang::Ukr => "uk",
+        Lang::Urd => "ur",
+        Lang::Uzb => "uz",
+        Lang::Vie => "vi",
+        Lang::Yid => "yi",
+        Lang::Zul => "zu",
+    }
+}
+
+/// A cache of detected languages stored in `.veisku/state/cache/lang.json`. See
+/// [`FileCache`].
+#[derive(Debug)]
+pub struct LangCache(FileCache<Option<String>>);
+
+impl LangCache {
+    pub fn load(root: &DocRoot) -> Self {
+        Self(FileCache::load(root, "lang.json"))
+    }
+
+    /// Get the language of a document body, detecting it if necessary.
+    /// Documents that aren't text (e.g., PDF files) have no language.
+    pub fn get(&self, doc: &DocRead) -> Result<Option<String>> {
+        if matches!(doc.doc_type(), DocType::Pdf | DocType::Image) {
+            return Ok(None);
+        }
+        self.0.get(doc.path(), || {
+            let body = doc.read_body()?;
+            Ok(detect(&String::from_utf8_lossy(&body)).map(str::to_owned))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            detect("The quick brown fox jumps over the lazy dog, again and again."),
+            Some("en")
+        );
+        assert_eq!(
+            detect("吾輩は猫である。名前はまだ無い。どこで生れたかとんと見当がつかぬ。"),
+            Some("ja")
+        );
+        assert_eq!(detect("ok"), None);
+    }
+}
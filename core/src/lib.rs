@@ -0,0 +1,44 @@
+//! The core of `veisku`: document roots, documents, and the query engine
+//!
+//! This crate doesn't depend on the command-line interface, so it can be
+//! embedded by other frontends.
+//!
+//! ```no_run
+//! use veisku_core::{cfg::Criterion, query, root::DocRoot};
+//!
+//! let root = DocRoot::current()?;
+//! let criteria: Vec<Criterion> = vec!["tags:draft".parse().unwrap()];
+//! let query = query::Query::new(&root, "default", &criteria)?;
+//! for doc in query::select_all(&root, &query) {
+//!     println!("{}", doc?);
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+pub mod cache;
+pub mod cfg;
+pub mod collate;
+pub mod complete;
+pub mod crypt;
+pub mod dates;
+pub mod diagnostics;
+pub mod doc;
+pub mod doctype;
+pub mod git;
+pub mod history;
+pub mod ignore_files;
+pub mod journal;
+pub mod lang;
+pub mod link;
+pub mod lock;
+pub mod normalize;
+pub mod query;
+pub mod query_cache;
+pub mod registry;
+pub mod root;
+pub mod schema;
+pub mod search;
+pub mod state;
+pub mod stats;
+pub mod tag;
+pub mod timing;
+pub mod trash;
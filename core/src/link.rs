@@ -0,0 +1,293 @@
+//! Links between documents
+use std::{
+    ops::Range,
+    path::{Component, Path, PathBuf},
+};
+
+/// A link found in a document
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub kind: LinkKind,
+    /// The byte range of the link target in the text, excluding an alias
+    /// (`[[target|alias]]`) or a fragment (`#heading`)
+    pub range: Range<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinkKind {
+    /// `[[target]]`
+    Wiki,
+    /// `[text](target)`
+    Markdown,
+}
+
+/// Find the links in a Markdown text.
+pub fn find_links(text: &str) -> Vec<Link> {
+    let mut links = Vec::new();
+
+    // Wikilinks
+    let mut i = 0;
+    while let Some(start) = text[i..].find("[[").map(|k| i + k + 2) {
+        let end = match text[start..].find("]]") {
+            Some(k) => start + k,
+            None => break,
+        };
+        let inner = &text[start..end];
+        let target_len = inner.find(&['|', '#'][..]).unwrap_or(inner.len());
+        if target_len > 0 && !inner.contains('\n') {
+            links.push(Link {
+                kind: LinkKind::Wiki,
+                range: start..start + target_len,
+            });
+        }
+        i = end + 2;
+    }
+
+    // Markdown links
+    let mut i = 0;
+    while let Some(start) = text[i..].find("](").map(|k| i + k + 2) {
+        let rest = &text[start..];
+        let (start, target) = if let Some(rest) = rest.strip_prefix('<') {
+            match rest.find(&['>', '\n'][..]) {
+                Some(k) if rest[k..].starts_with('>') => (start + 1, &rest[..k]),
+                _ => {
+                    i = start;
+                    continue;
+                }
+            }
+        } else {
+            let len = rest
+                .find(|c: char| c == ')' || c.is_whitespace())
+                .unwrap_or(rest.len());
+            (start, &rest[..len])
+        };
+        let target_len = target.find('#').unwrap_or(target.len());
+        if target_len > 0 {
+            links.push(Link {
+                kind: LinkKind::Markdown,
+                range: start..start + target_len,
+            });
+        }
+        i = start + target.len();
+    }
+
+    links.sort_by_key(|link| link.range.start);
+    links
+}
+
+/// Resolve the target of a Markdown link in the document at `doc_path`.
+/// Returns `None` if the target is not a local path (e.g., a URL).
+///
+/// A target starting with `/` is relative to `root_path`.
+pub fn resolve_markdown_target(root_path: &Path, doc_path: &Path, target: &str) -> Option<PathBuf> {
+    if target.contains("://") || target.starts_with("mailto:") {
+        return None;
+    }
+    let path = if let Some(target) = target.strip_prefix('/') {
+        root_path.join(target)
+    } else {
+        doc_path.parent()?.join(target)
+    };
+    Some(normalize(&path))
+}
+
+/// Lexically normalize a path, removing `.` and `..` components.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !out.pop() {
+                    out.push("..");
+                }
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Get the relative path from the directory `from_dir` to `to`. Both paths
+/// must be normalized.
+pub fn relative_path(from_dir: &Path, to: &Path) -> PathBuf {
+    let from: Vec<_> = from_dir.components().collect();
+    let to: Vec<_> = to.components().collect();
+    let common = from
+        .iter()
+        .zip(to.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut out: PathBuf = vec![Component::ParentDir; from.len() - common]
+        .into_iter()
+        .collect();
+    out.extend(to[common..].iter());
+    out
+}
+
+/// Get the relative path from the directory `from_dir` to `to` as a link
+/// target (using `/` as the separator). Both paths must be normalized.
+pub fn relative_target(from_dir: &Path, to: &Path) -> String {
+    relative_path(from_dir, to)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Rewrite the links in the document at `doc_path` pointing to `old_path` so
+/// that they point to `new_path`. Returns the new text and the number of
+/// rewritten links.
+///
+/// Wikilinks are matched by the file stem (or the file name) of `old_path`.
+/// All paths must be normalized.
+pub fn rewrite_links(
+    text: &str,
+    root_path: &Path,
+    doc_path: &Path,
+    old_path: &Path,
+    new_path: &Path,
+) -> (String, usize) {
+    let file_stem = |path: &Path| path.file_stem().map(|s| s.to_string_lossy().into_owned());
+    let file_name = |path: &Path| path.file_name().map(|s| s.to_string_lossy().into_owned());
+    let (old_stem, new_stem) = (file_stem(old_path), file_stem(new_path));
+    let (old_name, new_name) = (file_name(old_path), file_name(new_path));
+
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    let mut count = 0;
+    for link in find_links(text) {
+        let target = &text[link.range.clone()];
+        let replacement = match link.kind {
+            LinkKind::Wiki => {
+                if Some(target) == old_stem.as_deref() {
+                    new_stem.clone()
+                } else if Some(target) == old_name.as_deref() {
+                    new_name.clone()
+                } else {
+                    None
+                }
+            }
+            LinkKind::Markdown => match resolve_markdown_target(root_path, doc_path, target) {
+                Some(path) if path == old_path => {
+                    if target.starts_with('/') {
+                        Some(format!("/{}", relative_target(root_path, new_path)))
+                    } else {
+                        doc_path.parent().map(|dir| relative_target(dir, new_path))
+                    }
+                }
+                _ => None,
+            },
+        };
+
+        if let Some(replacement) = replacement {
+            out.push_str(&text[last..link.range.start]);
+            out.push_str(&replacement);
+            last = link.range.end;
+            count += 1;
+        }
+    }
+    out.push_str(&text[last..]);
+
+    (out, count)
+}
+
+/// Rewrite the relative Markdown links in the document moved from
+/// `old_doc_path` to `new_doc_path` so that they keep pointing to the same
+/// files. Returns the new text and the number of rewritten links. All paths
+/// must be normalized.
+pub fn rebase_links(
+    text: &str,
+    root_path: &Path,
+    old_doc_path: &Path,
+    new_doc_path: &Path,
+) -> (String, usize) {
+    let new_dir = match new_doc_path.parent() {
+        Some(dir) => dir,
+        None => return (text.to_owned(), 0),
+    };
+
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    let mut count = 0;
+    for link in find_links(text) {
+        let target = &text[link.range.clone()];
+        if link.kind != LinkKind::Markdown || target.starts_with('/') {
+            continue;
+        }
+        let path = match resolve_markdown_target(root_path, old_doc_path, target) {
+            // A link to the document itself moves with it
+            Some(path) if path == old_doc_path => new_doc_path.to_owned(),
+            Some(path) => path,
+            None => continue,
+        };
+        let replacement = relative_target(new_dir, &path);
+        if replacement != target {
+            out.push_str(&text[last..link.range.start]);
+            out.push_str(&replacement);
+            last = link.range.end;
+            count += 1;
+        }
+    }
+    out.push_str(&text[last..]);
+
+    (out, count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_links() {
+        let text = "[[a]] [[b|B]] [c](c.md#x) [d](<d e.md>) [e](#top)";
+        let targets: Vec<_> = find_links(text)
+            .into_iter()
+            .map(|link| (link.kind, &text[link.range]))
+            .collect();
+        assert_eq!(
+            targets,
+            [
+                (LinkKind::Wiki, "a"),
+                (LinkKind::Wiki, "b"),
+                (LinkKind::Markdown, "c.md"),
+                (LinkKind::Markdown, "d e.md"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rewrite_links() {
+        let root = Path::new("/r");
+        let (text, count) = rewrite_links(
+            "[[old]] [[old|Old]] [[other]] [x](../notes/old.md#h) [y](/notes/old.md)",
+            root,
+            Path::new("/r/misc/doc.md"),
+            Path::new("/r/notes/old.md"),
+            Path::new("/r/archive/new.md"),
+        );
+        assert_eq!(
+            text,
+            "[[new]] [[new|Old]] [[other]] [x](../archive/new.md#h) [y](/archive/new.md)"
+        );
+        assert_eq!(count, 4);
+    }
+
+    #[test]
+    fn test_rebase_links() {
+        let root = Path::new("/r");
+        let (text, count) = rebase_links(
+            "[a](a.md) [b](../b.md#h) [c](/c.md) [d](https://x/d.md) [e](doc.md) [[f]]",
+            root,
+            Path::new("/r/notes/doc.md"),
+            Path::new("/r/archive/2020/doc.md"),
+        );
+        assert_eq!(
+            text,
+            "[a](../../notes/a.md) [b](../../b.md#h) [c](/c.md) [d](https://x/d.md) \
+            [e](doc.md) [[f]]"
+        );
+        assert_eq!(count, 2);
+    }
+}
@@ -0,0 +1,84 @@
+//! Advisory locks protecting the state files in `.veisku` from concurrent
+//! `v` processes
+//!
+//! A lock is an OS file lock (`flock` on Unix) on a file named `FILE.lock`
+//! next to the protected file. The OS releases it when the holding process
+//! exits, so a crashed process can't leave a stale lock behind. On Unix, the
+//! lock file is removed on release.
+use anyhow::{Context, Result};
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+/// A held lock, which is released when dropped
+#[derive(Debug)]
+pub struct FileLock {
+    path: PathBuf,
+    file: File,
+}
+
+impl FileLock {
+    /// Acquire the lock protecting the file at `path`, waiting for other
+    /// processes to release it. The parent directory must exist.
+    pub fn acquire(path: &Path) -> Result<Self> {
+        let mut lock_name = path
+            .file_name()
+            .with_context(|| format!("{:?} does not have a file name", path))?
+            .to_owned();
+        lock_name.push(".lock");
+        let lock_path = path.with_file_name(lock_name);
+
+        loop {
+            let file = std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&lock_path)
+                .with_context(|| format!("Failed to open {:?}", lock_path))?;
+            file.lock()
+                .with_context(|| format!("Failed to lock {:?}", lock_path))?;
+
+            // The previous holder may have removed the lock file after we
+            // opened it, in which case we locked a file no one else can see
+            if is_linked(&file, &lock_path) {
+                log::trace!("Acquired {:?}", lock_path);
+                return Ok(Self {
+                    path: lock_path,
+                    file,
+                });
+            }
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // Remove the lock file while still holding the lock. Processes
+        // waiting for it will notice that it was removed and retry.
+        #[cfg(unix)]
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            log::warn!("Failed to remove the lock {:?}: {:?}", self.path, e);
+        }
+        if let Err(e) = self.file.unlock() {
+            log::warn!("Failed to release the lock {:?}: {:?}", self.path, e);
+        }
+    }
+}
+
+/// Check that `path` still refers to `file`.
+#[cfg(unix)]
+fn is_linked(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(a), Ok(b)) => (a.dev(), a.ino()) == (b.dev(), b.ino()),
+        _ => false,
+    }
+}
+
+/// Lock files aren't removed on this platform, so they are never unlinked.
+#[cfg(not(unix))]
+fn is_linked(_: &File, _: &Path) -> bool {
+    true
+}
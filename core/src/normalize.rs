@@ -0,0 +1,105 @@
+//! Unicode normalization of names and metadata for matching
+//!
+//! File names created on macOS are often in NFD (decomposed) while text typed
+//! on other platforms is usually in NFC (composed), so both sides of a
+//! comparison are normalized to NFC.
+use std::borrow::Cow;
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+
+use crate::{cfg::Cfg, collate};
+
+/// Normalize `s` to NFC. Doesn't allocate if `s` is already in NFC.
+pub fn nfc(s: &str) -> Cow<'_, str> {
+    match is_nfc_quick(s.chars()) {
+        IsNormalized::Yes => Cow::Borrowed(s),
+        _ => Cow::Owned(s.nfc().collect()),
+    }
+}
+
+/// Replace accented Latin letters with their ASCII transliterations (see
+/// [`collate::transliterate`]). Other characters are preserved.
+pub fn transliterate(s: &str) -> Cow<'_, str> {
+    if s.is_ascii() {
+        return Cow::Borrowed(s);
+    }
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match collate::transliterate(ch) {
+            Some(ascii) => out.push_str(ascii),
+            None => out.push(ch),
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Normalizes both sides of the comparisons made by search criteria
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Normalizer {
+    /// Transliterate to ASCII and ignore case (`ascii_search`)
+    pub ascii: bool,
+    /// Ignore case without transliterating (used by `--rank`). Doesn't
+    /// affect [`Self::regex_pattern`].
+    pub ignore_case: bool,
+}
+
+impl Normalizer {
+    pub fn new(cfg: &Cfg) -> Self {
+        Self {
+            ascii: cfg.ascii_search,
+            ignore_case: false,
+        }
+    }
+
+    /// Normalize a name, a metadata value, or a non-regex pattern.
+    pub fn text<'a>(&self, s: &'a str) -> Cow<'a, str> {
+        let s = nfc(s);
+        if self.ascii {
+            if s.is_ascii() && !s.bytes().any(|b| b.is_ascii_uppercase()) {
+                return s;
+            }
+            return Cow::Owned(transliterate(&s).to_lowercase());
+        }
+        if self.ignore_case && s.chars().any(char::is_uppercase) {
+            return Cow::Owned(s.to_lowercase());
+        }
+        s
+    }
+
+    /// Normalize a regex pattern. Case is preserved because lowering escape
+    /// sequences such as `\D` changes their meaning, so regexes must be
+    /// compiled case-insensitively if [`Self::ascii`] is set.
+    pub fn regex_pattern<'a>(&self, s: &'a str) -> Cow<'a, str> {
+        let s = nfc(s);
+        if !self.ascii {
+            return s;
+        }
+        match transliterate(&s) {
+            Cow::Borrowed(_) => s,
+            Cow::Owned(t) => Cow::Owned(t),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nfc() {
+        assert_eq!(nfc("Zu\u{308}rich"), "Z\u{fc}rich");
+        assert!(matches!(nfc("Z\u{fc}rich"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_ascii() {
+        let norm = Normalizer {
+            ascii: true,
+            ..Normalizer::default()
+        };
+        assert_eq!(norm.text("Zu\u{308}rich"), "zurich");
+        assert_eq!(norm.text("Straße"), "strasse");
+        assert!(matches!(norm.text("zurich"), Cow::Borrowed(_)));
+        assert_eq!(norm.regex_pattern(r"^Zür\D"), r"^Zur\D");
+        assert_eq!(Normalizer::default().text("Zürich"), "Zürich");
+    }
+}
@@ -0,0 +1,2117 @@
+//! Document queries
+use crate::{
+    cfg::{
+        CmpOp, ContentsCfg, Criterion, FieldCfg, FieldType, RegexCriterion, SetOp, SimpleCriterion,
+        SmartPhase,
+    },
+    collate::Collator,
+    dates::{GitDates, GIT_FIELDS},
+    diagnostics::{self, SkipReason},
+    doc::DocRead,
+    lang::{self, LangCache},
+    normalize::Normalizer,
+    root::DocRoot,
+    schema,
+    search::SavedSearches,
+    stats::{self, StatsCache},
+    tag,
+    timing::{self, Phase},
+};
+use anyhow::{Context, Error, Result};
+use either::{Left, Right};
+use serde_yaml::Value;
+use std::{
+    borrow::Cow, cmp::Ordering, collections::HashSet, fmt, ops::Range, path::PathBuf,
+    time::SystemTime,
+};
+
+/// The prefix of a key referring to a metadata field even if the name is
+/// reserved for a special criterion or a pseudo-field (e.g., `meta.name:x`
+/// matches the field `name` rather than the file name)
+pub const META_PREFIX: &str = "meta.";
+
+/// Compiled document query
+#[derive(Debug)]
+pub struct Query {
+    smart_name: Option<String>,
+    matchers: Vec<Box<dyn Matcher>>,
+    highlights: Highlights,
+    norm: Normalizer,
+    /// The documents to examine instead of all documents in the root, along
+    /// with their metadata if it's already known
+    files: Option<Vec<(PathBuf, Option<Value>)>>,
+    guard: ContentGuard,
+}
+
+/// The patterns of the criteria matched against the names and titles of
+/// documents, which are highlighted in listings to show why documents
+/// matched
+#[derive(Debug, Default)]
+pub struct Highlights {
+    name: Vec<regex::Regex>,
+    title: Vec<regex::Regex>,
+    contents: Vec<regex::Regex>,
+}
+
+impl Highlights {
+    /// Get the byte ranges to highlight in a document name.
+    pub fn name_ranges(&self, name: &str) -> Vec<Range<usize>> {
+        match_ranges(&self.name, name)
+    }
+
+    /// Get the byte ranges to highlight in a document title.
+    pub fn title_ranges(&self, title: &str) -> Vec<Range<usize>> {
+        match_ranges(&self.title, title)
+    }
+}
+
+/// Find the matches of `regexes` in `text`, merging overlapping ones.
+fn match_ranges(regexes: &[regex::Regex], text: &str) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = regexes
+        .iter()
+        .flat_map(|regex| regex.find_iter(text))
+        .map(|m| m.range())
+        .filter(|range| !range.is_empty())
+        .collect();
+    ranges.sort_by_key(|range| range.start);
+
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// A predicate on documents. All matchers in a [`Query`] must be satisfied
+/// for a document to be selected.
+pub trait Matcher: std::fmt::Debug + Send + Sync {
+    fn matches(&self, doc: &mut DocRead) -> Result<bool>;
+
+    /// The cost of evaluating this matcher. Matchers are evaluated in the
+    /// increasing order of cost so that cheap matchers can reject documents
+    /// before expensive ones read them.
+    fn cost(&self) -> Cost {
+        Cost::Meta
+    }
+}
+
+/// The cost of evaluating a [`Matcher`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Cost {
+    /// Only examines the path
+    Path,
+    /// Reads the metadata
+    Meta,
+    /// Reads the whole document body
+    Body,
+}
+
+impl Query {
+    /// Compile a query from a preset name and search criteria.
+    pub fn new(root: &DocRoot, preset: &str, criteria: &[Criterion]) -> Result<Self> {
+        let norm = Normalizer::new(&root.cfg);
+        let mut query = Query {
+            smart_name: None,
+            matchers: Vec::new(),
+            highlights: Highlights::default(),
+            norm,
+            files: None,
+            guard: ContentGuard::new(&root.cfg.contents),
+        };
+
+        // Look up the preset in the saved searches. The `default` preset is
+        // optional.
+        let preset_criteria = if preset.is_empty() {
+            Vec::new()
+        } else {
+            match SavedSearches::load(root)?.criteria(preset) {
+                Some(criteria) => criteria?,
+                None if preset == "default" => Vec::new(),
+                None => anyhow::bail!("Unknown query preset: '{}'", preset),
+            }
+        };
+
+        let mut smart_tokens: Vec<String> = Vec::new();
+        for criterion in preset_criteria.iter().chain(criteria.iter()) {
+            match criterion {
+                Criterion::NameSmart(smart_name) => {
+                    smart_tokens.push(norm.text(smart_name).into_owned());
+                }
+                Criterion::Simple {
+                    negate,
+                    simple_criterion,
+                } => {
+                    let mut matcher: Box<dyn Matcher> = match simple_criterion {
+                        SimpleCriterion::NameRegex(regex) => {
+                            let regex = compile_regex(root, regex, root.cfg.regex.anchored)?;
+                            if !negate {
+                                query.highlights.name.push(regex.clone());
+                            }
+                            Box::new(NameRegex { regex, norm })
+                        }
+                        SimpleCriterion::NameExact(name) => {
+                            let name = norm.text(name).into_owned();
+                            if !negate {
+                                query.highlights.name.push(
+                                    regex::Regex::new(&format!("^{}$", regex::escape(&name)))
+                                        .unwrap(),
+                                );
+                            }
+                            Box::new(NameExact { name, norm })
+                        }
+                        SimpleCriterion::Tag(tag) => Box::new(Tag {
+                            tag: norm.text(tag).into_owned(),
+                            norm,
+                        }),
+                        SimpleCriterion::MetaEq(key, value) if key == "changed" => {
+                            let paths = if value == "worktree" {
+                                crate::git::status(&root.path)?
+                                    .into_iter()
+                                    .map(|e| e.path)
+                                    .collect()
+                            } else {
+                                crate::git::changed_since(&root.path, value)?
+                            };
+                            Box::new(Changed { paths })
+                        }
+                        SimpleCriterion::MetaEq(key, value) if key == "errors" => {
+                            let expected = match &**value {
+                                "true" => true,
+                                "false" => false,
+                                _ => anyhow::bail!(
+                                    "`errors:` expects `true` or `false`, got '{}'",
+                                    value
+                                ),
+                            };
+                            Box::new(MetaErrors { expected })
+                        }
+                        SimpleCriterion::MetaEq(key, text) if key == "contents" => {
+                            let regex = RegexCriterion {
+                                pattern: regex::escape(text),
+                                flags: "i".to_owned(),
+                            };
+                            let regex = compile_regex(root, &regex, false)?;
+                            if !negate {
+                                query.highlights.contents.push(regex.clone());
+                            }
+                            Box::new(Contents {
+                                regex,
+                                norm,
+                                guard: query.guard,
+                            })
+                        }
+                        SimpleCriterion::MetaEq(key, dir) if key == "in" => {
+                            let dir = root.path.join(dir.trim_start_matches('/'));
+                            Box::new(InDir {
+                                dir: crate::link::normalize(&dir),
+                            })
+                        }
+                        SimpleCriterion::MetaEq(key, glob) if key == "name" => {
+                            let glob = norm.text(glob);
+                            let regex = glob_to_regex(glob.strip_prefix('/').unwrap_or(&glob));
+                            Box::new(NameGlob {
+                                regex: regex::Regex::new(&regex).with_context(|| {
+                                    format!("Failed to compile the glob '{}'", glob)
+                                })?,
+                                root_path: root.path.clone(),
+                                match_path: glob.contains('/'),
+                                norm,
+                            })
+                        }
+                        SimpleCriterion::MetaEq(key, value) => {
+                            meta_matcher(root, key, MetaOp::Eq(norm.text(value).into_owned()))
+                        }
+                        SimpleCriterion::MetaRegex(key, regex) if key == "contents" => {
+                            let regex = compile_regex(root, regex, false)?;
+                            if !negate {
+                                query.highlights.contents.push(regex.clone());
+                            }
+                            Box::new(Contents {
+                                regex,
+                                norm,
+                                guard: query.guard,
+                            })
+                        }
+                        SimpleCriterion::MetaRegex(key, regex) => {
+                            let regex = compile_regex(root, regex, false)?;
+                            if key == "title" && !negate {
+                                query.highlights.title.push(regex.clone());
+                            }
+                            meta_matcher(root, key, MetaOp::Regex(regex))
+                        }
+                        SimpleCriterion::MetaCmp(key, op, value) => {
+                            meta_matcher(root, key, MetaOp::Cmp(*op, norm.text(value).into_owned()))
+                        }
+                        SimpleCriterion::MetaRange(key, low, high) => {
+                            let field_cfg = root
+                                .cfg
+                                .schema
+                                .get(key.strip_prefix(META_PREFIX).unwrap_or(key));
+                            let op = if schema::is_range_bound(field_cfg, low)
+                                && schema::is_range_bound(field_cfg, high)
+                            {
+                                MetaOp::Range(
+                                    norm.text(low).into_owned(),
+                                    norm.text(high).into_owned(),
+                                )
+                            } else {
+                                // Not a range of the field's type (e.g.,
+                                // `title:a..b`)
+                                MetaOp::Eq(norm.text(&format!("{}..{}", low, high)).into_owned())
+                            };
+                            meta_matcher(root, key, op)
+                        }
+                        SimpleCriterion::MetaSet(key, op, values) => meta_matcher(
+                            root,
+                            key,
+                            MetaOp::Set(
+                                *op,
+                                values
+                                    .iter()
+                                    .map(|value| norm.text(value).into_owned())
+                                    .collect(),
+                            ),
+                        ),
+                    };
+
+                    if *negate {
+                        matcher = Box::new(Negate(matcher));
+                    }
+
+                    query.matchers.push(matcher);
+                }
+            }
+        }
+
+        if let [smart_name] = &mut smart_tokens[..] {
+            query
+                .highlights
+                .name
+                .push(regex::Regex::new(&format!("^{}", regex::escape(smart_name))).unwrap());
+            query.smart_name = Some(std::mem::take(smart_name));
+        } else {
+            // Multiple bare words (`v ls meeting notes`) must all appear in
+            // the name or the title, in any order
+            for token in smart_tokens {
+                let regex = regex::Regex::new(&format!("(?i){}", regex::escape(&token))).unwrap();
+                query.highlights.name.push(regex.clone());
+                query.highlights.title.push(regex);
+                query
+                    .matchers
+                    .push(Box::new(NameOrTitleContains { token, norm }));
+            }
+        }
+
+        // Evaluate cheap matchers first. Since all matchers must be satisfied,
+        // this doesn't change the result (except for errors, which may be
+        // avoided by rejecting documents early).
+        query.matchers.sort_by_key(|matcher| matcher.cost());
+
+        log::debug!("compiled query = {:?}", query);
+
+        Ok(query)
+    }
+
+    /// Examine only the documents at `files` instead of enumerating the
+    /// documents in the document root. The paths must be inside the root.
+    pub fn restrict_to_files(&mut self, files: Vec<PathBuf>) {
+        // The list may reach a file through more than one path, which
+        // `DocRoot::doc_files` would yield only once
+        let mut seen = HashSet::new();
+        self.files = Some(
+            files
+                .into_iter()
+                .filter(|path| crate::root::file_id(path).is_none_or(|id| seen.insert(id)))
+                .map(|path| (path, None))
+                .collect(),
+        );
+    }
+
+    /// Like [`Self::restrict_to_files`], but the metadata of the documents
+    /// is given instead of being read from the files (e.g., by `v daemon`,
+    /// which keeps it in memory).
+    pub fn restrict_to_docs(&mut self, docs: Vec<(PathBuf, Option<Value>)>) {
+        self.files = Some(docs);
+    }
+
+    /// Iterate over the documents to examine.
+    fn candidates<'a>(&'a self, root: &'a DocRoot) -> impl Iterator<Item = Result<DocRead>> + 'a {
+        match &self.files {
+            Some(files) => Left(files.iter().map(move |(path, meta)| {
+                let doc = root.doc_at(path.clone());
+                Ok(match meta {
+                    Some(meta) => doc.with_meta(meta.clone()),
+                    None => doc,
+                })
+            })),
+            None => Right(root.docs()),
+        }
+    }
+
+    /// Get the patterns to highlight in listings.
+    pub fn highlights(&self) -> &Highlights {
+        &self.highlights
+    }
+
+    /// Require documents to match at least one of `queries` (each of which
+    /// is a conjunction of criteria). The highlights of `queries` are merged
+    /// into this query.
+    pub fn push_any(&mut self, queries: Vec<Query>) {
+        for query in &queries {
+            self.highlights
+                .name
+                .extend(query.highlights.name.iter().cloned());
+            self.highlights
+                .title
+                .extend(query.highlights.title.iter().cloned());
+            self.highlights
+                .contents
+                .extend(query.highlights.contents.iter().cloned());
+        }
+        self.push_matcher(Box::new(Any { queries }));
+    }
+
+    /// Check if a document matches this query on its own. Unlike
+    /// [`select_all`], the smart name matches the exact name, the ID, or a
+    /// prefix of the name regardless of whether other documents match more
+    /// precisely.
+    fn matches(&self, doc: &mut DocRead) -> Result<bool> {
+        if let Some(pattern) = &self.smart_name {
+            let pattern: &str = pattern;
+            let norm = self.norm;
+            if !(SmartNamePrefix { pattern, norm }).matches(doc)?
+                && !(SmartId { pattern, norm }).matches(doc)?
+            {
+                return Ok(false);
+            }
+        }
+        for matcher in &self.matchers {
+            if !matcher.matches(doc)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn cost(&self) -> Cost {
+        let smart_name_cost = self.smart_name.as_ref().map(|_| Cost::Meta);
+        self.matchers
+            .iter()
+            .map(|matcher| matcher.cost())
+            .chain(smart_name_cost)
+            .max()
+            .unwrap_or(Cost::Path)
+    }
+
+    /// Find the lines of a document matched by the `contents:` criteria of
+    /// this query, at most one match per line, in the order of appearance.
+    /// Line numbers count the preamble.
+    pub fn content_matches(&self, doc: &DocRead) -> Result<Vec<ContentMatch>> {
+        if self.highlights.contents.is_empty() {
+            return Ok(Vec::new());
+        }
+        let contents = match self.guard.read(doc, false)? {
+            Some(contents) => contents,
+            None => return Ok(Vec::new()),
+        };
+        Ok(find_content_matches(
+            &self.highlights.contents,
+            &contents,
+            self.norm,
+        ))
+    }
+
+    /// Replace the matches of the `contents:` criteria of this query in
+    /// `text` with `replacement`, which can refer to capture groups (e.g.,
+    /// `$1` or `${name}`). Returns the new text and the number of replaced
+    /// matches.
+    ///
+    /// Criteria are matched against normalized text (see [`Normalizer`]), but
+    /// the replacement must be made in `text` itself. Returns `None` if the
+    /// matches in `text` differ from those in the normalized text (e.g., `cafe`
+    /// matches `café` only with `ascii_search`).
+    pub fn replace_content_matches(
+        &self,
+        text: &str,
+        replacement: &str,
+    ) -> Option<(String, usize)> {
+        let mut text = text.to_owned();
+        let mut count = 0;
+        for regex in self.highlights.contents.iter() {
+            let normalized = self.norm.text(&text);
+            let expected = regex.find_iter(&normalized).map(|m| m.as_str());
+            let found: Vec<Cow<'_, str>> = regex
+                .find_iter(&text)
+                .map(|m| self.norm.text(m.as_str()))
+                .collect();
+            if !expected.eq(found.iter().map(|m| &**m)) {
+                return None;
+            }
+            count += found.len();
+            text = regex.replace_all(&text, replacement).into_owned();
+        }
+        Some((text, count))
+    }
+
+    /// Add a custom matcher.
+    pub fn push_matcher(&mut self, matcher: Box<dyn Matcher>) {
+        let i = self
+            .matchers
+            .partition_point(|other| other.cost() <= matcher.cost());
+        self.matchers.insert(i, matcher);
+    }
+}
+
+/// The implementation of [`Query::content_matches`]
+fn find_content_matches(
+    regexes: &[regex::Regex],
+    contents: &[u8],
+    norm: Normalizer,
+) -> Vec<ContentMatch> {
+    let body = crate::doc::split_md_preamble(contents).1;
+    let first_line = 1 + contents[..contents.len() - body.len()]
+        .iter()
+        .filter(|&&b| b == b'\n')
+        .count();
+    let body = String::from_utf8_lossy(body);
+    let body = norm.text(&body);
+
+    let mut starts: Vec<usize> = regexes
+        .iter()
+        .flat_map(|regex| regex.find_iter(&body))
+        .map(|m| m.start())
+        .collect();
+    starts.sort_unstable();
+
+    let mut matches: Vec<ContentMatch> = Vec::new();
+    for start in starts {
+        let line_start = body[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line = first_line + body[..line_start].matches('\n').count();
+        if matches.last().is_some_and(|m| m.line == line) {
+            continue;
+        }
+        let line_end = body[start..].find('\n').map_or(body.len(), |i| start + i);
+        matches.push(ContentMatch {
+            line,
+            column: body[line_start..start].chars().count() + 1,
+            text: body[line_start..line_end].trim_end_matches('\r').to_owned(),
+        });
+    }
+    matches
+}
+
+/// A line matched by a `contents:` criterion (see [`Query::content_matches`])
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContentMatch {
+    /// The one-based line number in the file
+    pub line: usize,
+    /// The one-based column number of the start of the match, in characters
+    pub column: usize,
+    /// The matched line (normalized if `ascii_search` is enabled)
+    pub text: String,
+}
+
+/// Construct a matcher for a metadata field or a pseudo-field computed from
+/// document bodies.
+fn meta_matcher(root: &DocRoot, key: &str, op: MetaOp) -> Box<dyn Matcher> {
+    if let Some(key) = key.strip_prefix(META_PREFIX) {
+        Box::new(Meta {
+            key: key.to_owned(),
+            explicit: true,
+            field_cfg: root.cfg.schema.get(key).cloned(),
+            op,
+            norm: Normalizer::new(&root.cfg),
+        })
+    } else if key == lang::FIELD {
+        Box::new(Language {
+            op,
+            cache: LangCache::load(root),
+        })
+    } else if stats::PSEUDO_FIELDS.contains(&key) {
+        Box::new(Stat {
+            key: key.to_owned(),
+            op,
+            cache: StatsCache::load(root),
+        })
+    } else if GIT_FIELDS.contains(&key) {
+        Box::new(GitDate {
+            key: key.to_owned(),
+            field_cfg: root.cfg.schema.get(key).cloned(),
+            op,
+            norm: Normalizer::new(&root.cfg),
+            dates: GitDates::load(root),
+        })
+    } else {
+        Box::new(Meta {
+            key: key.to_owned(),
+            explicit: false,
+            field_cfg: root.cfg.schema.get(key).cloned(),
+            op,
+            norm: Normalizer::new(&root.cfg),
+        })
+    }
+}
+
+/// Compile a `/REGEX/FLAGS` criterion. `anchored` is the default anchoring
+/// mode, which the `a` flag overrides.
+fn compile_regex(root: &DocRoot, regex: &RegexCriterion, anchored: bool) -> Result<regex::Regex> {
+    let norm = Normalizer::new(&root.cfg);
+    let pattern = norm.regex_pattern(&regex.pattern);
+    let pattern = if anchored || regex.has_flag('a') {
+        // A line break terminates a `#` comment in the `x` mode
+        let eol = if regex.has_flag('x') { "\n" } else { "" };
+        format!("^(?:{}{})$", pattern, eol)
+    } else {
+        pattern.into_owned()
+    };
+    let case_insensitive = regex.has_flag('i')
+        || norm.ascii
+        || (root.cfg.regex.case_insensitive && !regex.has_flag('c'));
+    regex::RegexBuilder::new(&pattern)
+        .case_insensitive(case_insensitive)
+        .ignore_whitespace(regex.has_flag('x'))
+        .dot_matches_new_line(regex.has_flag('s'))
+        .multi_line(regex.has_flag('m'))
+        .build()
+        .with_context(|| format!("Failed to comple the regex '{}'", regex.pattern))
+}
+
+#[derive(Debug)]
+struct Always;
+
+impl Matcher for Always {
+    fn matches(&self, _doc: &mut DocRead) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn cost(&self) -> Cost {
+        Cost::Path
+    }
+}
+
+#[derive(Debug)]
+struct Never;
+
+impl Matcher for Never {
+    fn matches(&self, _doc: &mut DocRead) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn cost(&self) -> Cost {
+        Cost::Path
+    }
+}
+
+#[derive(Debug)]
+struct Negate(Box<dyn Matcher>);
+
+impl Matcher for Negate {
+    fn matches(&self, doc: &mut DocRead) -> Result<bool> {
+        Ok(!self.0.matches(doc)?)
+    }
+
+    fn cost(&self) -> Cost {
+        self.0.cost()
+    }
+}
+
+/// The matcher that selects documents matching any of the sub-queries
+/// (`--or`).
+#[derive(Debug)]
+struct Any {
+    queries: Vec<Query>,
+}
+
+impl Matcher for Any {
+    fn matches(&self, doc: &mut DocRead) -> Result<bool> {
+        for query in &self.queries {
+            if query.matches(doc)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn cost(&self) -> Cost {
+        self.queries
+            .iter()
+            .map(Query::cost)
+            .max()
+            .unwrap_or(Cost::Path)
+    }
+}
+
+/// Reads files for the criteria searching contents, skipping the ones
+/// excluded by [`ContentsCfg`]
+#[derive(Debug, Clone, Copy)]
+struct ContentGuard {
+    max_size: u64,
+    binary: bool,
+}
+
+/// The number of leading bytes examined to detect binary files
+const BINARY_SNIFF_LEN: usize = 8000;
+
+impl ContentGuard {
+    fn new(cfg: &ContentsCfg) -> Self {
+        Self {
+            max_size: cfg.max_size,
+            binary: cfg.binary,
+        }
+    }
+
+    /// Read the whole contents (or only the body if `body` is set) of a
+    /// document. Returns `None` if the document is skipped.
+    fn read(&self, doc: &DocRead, body: bool) -> Result<Option<Vec<u8>>> {
+        if self.max_size > 0 {
+            let len = std::fs::metadata(doc.path())
+                .with_context(|| format!("Failed to get the metadata of {:?}", doc.path()))?
+                .len();
+            if len > self.max_size {
+                diagnostics::record_skipped(doc.path(), SkipReason::TooLarge);
+                return Ok(None);
+            }
+        }
+        let bytes = if body {
+            doc.read_body()
+        } else {
+            doc.read_contents()
+        }
+        .with_context(|| format!("Failed to read {:?}", doc.path()))?;
+        if !self.binary && is_binary(&bytes) {
+            diagnostics::record_skipped(doc.path(), SkipReason::Binary);
+            return Ok(None);
+        }
+        Ok(Some(bytes))
+    }
+}
+
+/// Check if `bytes` looks like the contents of a binary file, i.e., has a NUL
+/// byte near the start.
+fn is_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(BINARY_SNIFF_LEN)].contains(&0)
+}
+
+/// The matcher that applies regex on document bodies (`contents:`). Binary
+/// files and files larger than `contents.max_size` never match.
+#[derive(Debug)]
+struct Contents {
+    regex: regex::Regex,
+    norm: Normalizer,
+    guard: ContentGuard,
+}
+
+impl Matcher for Contents {
+    fn matches(&self, doc: &mut DocRead) -> Result<bool> {
+        let body = match self.guard.read(doc, true)? {
+            Some(body) => body,
+            None => return Ok(false),
+        };
+        Ok(self
+            .regex
+            .is_match(&self.norm.text(&String::from_utf8_lossy(&body))))
+    }
+
+    fn cost(&self) -> Cost {
+        Cost::Body
+    }
+}
+
+/// The matcher that applies regex on document names.
+#[derive(Debug)]
+struct NameRegex {
+    regex: regex::Regex,
+    norm: Normalizer,
+}
+
+impl Matcher for NameRegex {
+    fn matches(&self, doc: &mut DocRead) -> Result<bool> {
+        if let Some(stem) = doc.path().file_stem().and_then(|s| s.to_str()) {
+            Ok(self.regex.is_match(&self.norm.text(stem)))
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn cost(&self) -> Cost {
+        Cost::Path
+    }
+}
+
+/// The matcher that selects documents by their exact base names or file
+/// names (`name=VALUE`).
+#[derive(Debug)]
+struct NameExact {
+    name: String,
+    norm: Normalizer,
+}
+
+impl Matcher for NameExact {
+    fn matches(&self, doc: &mut DocRead) -> Result<bool> {
+        let path = doc.path();
+        let matches = |name: Option<&std::ffi::OsStr>| {
+            name.and_then(|name| name.to_str())
+                .is_some_and(|name| self.norm.text(name) == self.name)
+        };
+        Ok(matches(path.file_stem()) || matches(path.file_name()))
+    }
+
+    fn cost(&self) -> Cost {
+        Cost::Path
+    }
+}
+
+/// The matcher that selects documents having a tag or its descendants
+/// (`#TAG`)
+#[derive(Debug)]
+struct Tag {
+    tag: String,
+    norm: Normalizer,
+}
+
+impl Matcher for Tag {
+    fn matches(&self, doc: &mut DocRead) -> Result<bool> {
+        let path = doc.path().to_owned();
+        let meta = doc
+            .ensure_meta()
+            .with_context(|| format!("Failed to read the metadata of {:?}", path))?;
+        let tags = match &meta["tags"] {
+            Value::Sequence(tags) => Left(tags.iter()),
+            value => Right(std::iter::once(value)),
+        };
+        Ok(tags
+            .filter_map(schema::scalar_text)
+            .any(|tag| tag::is_within(&self.norm.text(&tag), &self.tag)))
+    }
+}
+
+/// The matcher that selects documents under a directory (`in:DIR`). Paths
+/// are compared component-wise, so `in:notes` doesn't match `notes-old/a.md`.
+#[derive(Debug)]
+struct InDir {
+    dir: PathBuf,
+}
+
+impl Matcher for InDir {
+    fn matches(&self, doc: &mut DocRead) -> Result<bool> {
+        Ok(crate::link::normalize(doc.path()).starts_with(&self.dir))
+    }
+
+    fn cost(&self) -> Cost {
+        Cost::Path
+    }
+}
+
+/// The matcher that applies a glob on document names (`name:GLOB`). A glob
+/// containing `/` is matched against the path relative to the document root.
+/// Either way, the file extension may be omitted from the glob.
+#[derive(Debug)]
+struct NameGlob {
+    regex: regex::Regex,
+    root_path: PathBuf,
+    match_path: bool,
+    norm: Normalizer,
+}
+
+impl Matcher for NameGlob {
+    fn matches(&self, doc: &mut DocRead) -> Result<bool> {
+        let path = if self.match_path {
+            match doc.path().strip_prefix(&self.root_path) {
+                Ok(path) => path,
+                Err(_) => return Ok(false),
+            }
+        } else {
+            match doc.path().file_name() {
+                Some(name) => std::path::Path::new(name),
+                None => return Ok(false),
+            }
+        };
+        let with_ext = self.norm.text(&path.to_string_lossy()).replace('\\', "/");
+        let without_ext = (self.norm)
+            .text(&path.with_extension("").to_string_lossy())
+            .replace('\\', "/");
+        Ok(self.regex.is_match(&with_ext) || self.regex.is_match(&without_ext))
+    }
+
+    fn cost(&self) -> Cost {
+        Cost::Path
+    }
+}
+
+/// Convert a gitignore-style glob to an anchored regex.
+fn glob_to_regex(glob: &str) -> String {
+    let mut out = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    out.push_str("(?:.*/)?");
+                } else {
+                    out.push_str(".*");
+                }
+            }
+            '*' => out.push_str("[^/]*"),
+            '?' => out.push_str("[^/]"),
+            '[' => {
+                let class: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                out.push('[');
+                let class = if let Some(class) = class.strip_prefix('!') {
+                    out.push('^');
+                    class
+                } else {
+                    &class
+                };
+                for c in class.chars() {
+                    if c != '-' && c.is_ascii_punctuation() {
+                        out.push('\\');
+                    }
+                    out.push(c);
+                }
+                out.push(']');
+            }
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    out.push_str(&regex::escape(&c.to_string()));
+                }
+            }
+            c => out.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    out.push('$');
+    out
+}
+
+#[derive(Debug)]
+struct SmartNameExact<'a> {
+    pattern: &'a str,
+    norm: Normalizer,
+}
+
+impl Matcher for SmartNameExact<'_> {
+    fn matches(&self, doc: &mut DocRead) -> Result<bool> {
+        if let Some(stem) = doc.path().file_stem().and_then(|s| s.to_str()) {
+            Ok(self.norm.text(stem) == self.pattern)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn cost(&self) -> Cost {
+        Cost::Path
+    }
+}
+
+#[derive(Debug)]
+struct SmartNamePrefix<'a> {
+    pattern: &'a str,
+    norm: Normalizer,
+}
+
+impl Matcher for SmartNamePrefix<'_> {
+    fn matches(&self, doc: &mut DocRead) -> Result<bool> {
+        if let Some(stem) = doc.path().file_stem().and_then(|s| s.to_str()) {
+            Ok(self.norm.text(stem).starts_with(self.pattern))
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn cost(&self) -> Cost {
+        Cost::Path
+    }
+}
+
+#[derive(Debug)]
+struct SmartNameSubstring<'a> {
+    pattern: &'a str,
+    norm: Normalizer,
+}
+
+impl Matcher for SmartNameSubstring<'_> {
+    fn matches(&self, doc: &mut DocRead) -> Result<bool> {
+        if let Some(stem) = doc.path().file_stem().and_then(|s| s.to_str()) {
+            Ok(self.norm.text(stem).contains(self.pattern))
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn cost(&self) -> Cost {
+        Cost::Path
+    }
+}
+
+#[derive(Debug)]
+struct SmartNameFuzzy<'a> {
+    pattern: &'a str,
+    norm: Normalizer,
+}
+
+impl Matcher for SmartNameFuzzy<'_> {
+    fn matches(&self, doc: &mut DocRead) -> Result<bool> {
+        if let Some(stem) = doc.path().file_stem().and_then(|s| s.to_str()) {
+            Ok(is_fuzzy_match(self.pattern, &self.norm.text(stem)))
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn cost(&self) -> Cost {
+        Cost::Path
+    }
+}
+
+/// The matcher that selects documents whose titles equal or contain the
+/// smart name, ignoring case.
+#[derive(Debug)]
+struct SmartTitle<'a> {
+    pattern: &'a str,
+    norm: Normalizer,
+    exact: bool,
+}
+
+impl Matcher for SmartTitle<'_> {
+    fn matches(&self, doc: &mut DocRead) -> Result<bool> {
+        Ok(if self.exact {
+            title_equals(doc, self.pattern, self.norm)
+        } else {
+            title_contains(doc, self.pattern, self.norm)
+        })
+    }
+}
+
+/// The matcher that selects documents whose names or titles contain one of
+/// multiple smart name words (e.g., `notes` in `v ls meeting notes`),
+/// ignoring case.
+#[derive(Debug)]
+struct NameOrTitleContains {
+    token: String,
+    norm: Normalizer,
+}
+
+impl Matcher for NameOrTitleContains {
+    fn matches(&self, doc: &mut DocRead) -> Result<bool> {
+        if let Some(stem) = doc.path().file_stem().and_then(|s| s.to_str()) {
+            if (self.norm.text(stem).to_lowercase()).contains(&self.token.to_lowercase()) {
+                return Ok(true);
+            }
+        }
+        Ok(title_contains(doc, &self.token, self.norm))
+    }
+}
+
+/// The matcher that compares the smart name with document IDs (the `id`
+/// metadata field).
+#[derive(Debug)]
+struct SmartId<'a> {
+    pattern: &'a str,
+    norm: Normalizer,
+}
+
+impl Matcher for SmartId<'_> {
+    fn matches(&self, doc: &mut DocRead) -> Result<bool> {
+        // Documents with unreadable metadata are not an error here because
+        // this matcher is merely a fallback
+        let meta = match doc.ensure_meta() {
+            Ok(meta) => meta,
+            Err(_) => return Ok(false),
+        };
+        Ok(match &meta["id"] {
+            Value::String(st) => self.norm.text(st) == self.pattern,
+            Value::Number(n) => n.to_string() == self.pattern,
+            _ => false,
+        })
+    }
+}
+
+/// The matcher that compares the smart name with the alternative names of
+/// documents (the `aliases` metadata field), ignoring case.
+#[derive(Debug)]
+struct SmartAlias<'a> {
+    pattern: &'a str,
+    norm: Normalizer,
+}
+
+impl Matcher for SmartAlias<'_> {
+    fn matches(&self, doc: &mut DocRead) -> Result<bool> {
+        Ok(aliases(doc)
+            .iter()
+            .any(|alias| self.norm.text(alias).to_lowercase() == self.pattern.to_lowercase()))
+    }
+}
+
+/// Get the alternative names of a document (the `aliases` metadata field,
+/// either a sequence or a single string). Unreadable metadata is treated as
+/// having no aliases.
+pub fn aliases(doc: &mut DocRead) -> Vec<String> {
+    match doc.ensure_meta().map(|meta| &meta["aliases"]) {
+        Ok(Value::Sequence(aliases)) => aliases.iter().filter_map(schema::scalar_text).collect(),
+        Ok(value) => schema::scalar_text(value).into_iter().collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// The matcher that selects documents changed in a Git working tree.
+#[derive(Debug)]
+struct Changed {
+    paths: HashSet<PathBuf>,
+}
+
+impl Matcher for Changed {
+    fn matches(&self, doc: &mut DocRead) -> Result<bool> {
+        Ok(self.paths.contains(doc.path()))
+    }
+
+    fn cost(&self) -> Cost {
+        Cost::Path
+    }
+}
+
+/// The matcher that selects documents whose metadata can't be read
+/// (`errors:true`) or can be read (`errors:false`).
+#[derive(Debug)]
+struct MetaErrors {
+    expected: bool,
+}
+
+impl Matcher for MetaErrors {
+    fn matches(&self, doc: &mut DocRead) -> Result<bool> {
+        Ok(doc.has_meta_error() == self.expected)
+    }
+}
+
+/// The matcher that selects documents by their file modification times
+/// (`--since` and `--until`).
+#[derive(Debug)]
+pub struct MtimeRange {
+    /// The inclusive lower bound
+    pub since: Option<SystemTime>,
+    /// The exclusive upper bound
+    pub until: Option<SystemTime>,
+}
+
+impl Matcher for MtimeRange {
+    fn matches(&self, doc: &mut DocRead) -> Result<bool> {
+        let mtime = std::fs::metadata(doc.path())
+            .and_then(|metadata| metadata.modified())
+            .with_context(|| format!("Failed to get the modification time of {:?}", doc.path()))?;
+        Ok(self.since.is_none_or(|since| mtime >= since)
+            && self.until.is_none_or(|until| mtime < until))
+    }
+
+    fn cost(&self) -> Cost {
+        Cost::Path
+    }
+}
+
+/// The matcher that tries to equate or compare field values.
+#[derive(Debug)]
+struct Meta {
+    key: String,
+    /// Set if the key was given with [`META_PREFIX`], in which case `path`
+    /// and `type` refer to the metadata fields
+    explicit: bool,
+    /// The declared type of the field, which determines how values are
+    /// compared
+    field_cfg: Option<FieldCfg>,
+    op: MetaOp,
+    norm: Normalizer,
+}
+
+#[derive(Debug)]
+enum MetaOp {
+    Eq(String),
+    Regex(regex::Regex),
+    Cmp(CmpOp, String),
+    /// An inclusive range
+    Range(String, String),
+    /// Checks which of the values are among the elements of a sequence (or
+    /// equal to a scalar)
+    Set(SetOp, Vec<String>),
+}
+
+impl Matcher for Meta {
+    fn matches(&self, doc: &mut DocRead) -> Result<bool> {
+        let meta_path;
+        let meta = if self.explicit {
+            &doc.ensure_meta()?[&*self.key]
+        } else if self.key == "path" {
+            meta_path = Value::String(doc.path().to_string_lossy().into_owned());
+            &meta_path
+        } else if self.key == "type" {
+            meta_path = match explicit_field(doc, "type")? {
+                Some(value) => value,
+                None => Value::String(doc.doc_type().as_str().to_owned()),
+            };
+            &meta_path
+        } else {
+            &doc.ensure_meta()?[&*self.key]
+        };
+        match self.op.matches(meta, self.field_cfg.as_ref(), self.norm) {
+            Some(x) => Ok(x),
+            None if diagnostics::is_strict() => anyhow::bail!(
+                "The field '{}' of document '{}' contains an object of an uncomparable type",
+                self.key,
+                doc
+            ),
+            None => {
+                diagnostics::record_uncomparable(&self.key, doc.path());
+                Ok(false)
+            }
+        }
+    }
+
+    fn cost(&self) -> Cost {
+        match &*self.key {
+            "path" if !self.explicit => Cost::Path,
+            _ => Cost::Meta,
+        }
+    }
+}
+
+/// The matcher for the pseudo-fields computed from document bodies (see
+/// [`stats::PSEUDO_FIELDS`]).
+#[derive(Debug)]
+struct Stat {
+    key: String,
+    op: MetaOp,
+    cache: StatsCache,
+}
+
+impl Matcher for Stat {
+    fn matches(&self, doc: &mut DocRead) -> Result<bool> {
+        let value = match explicit_field(doc, &self.key)? {
+            Some(value) => value,
+            None => Value::from(self.cache.get(doc)?.field(&self.key).unwrap() as u64),
+        };
+        Ok(self
+            .op
+            .matches(&value, None, Normalizer::default())
+            .unwrap_or(false))
+    }
+
+    fn cost(&self) -> Cost {
+        Cost::Body
+    }
+}
+
+/// Get the value of the field `key` in the metadata of `doc`. A field present
+/// in the metadata takes precedence over the pseudo-field of the same name.
+/// Returns `None` if the field is absent (or null).
+fn explicit_field(doc: &mut DocRead, key: &str) -> Result<Option<Value>> {
+    let value = &doc.ensure_meta()?[key];
+    Ok((!value.is_null()).then(|| value.clone()))
+}
+
+/// The matcher for the `lang` pseudo-field (see [`lang::detect`])
+#[derive(Debug)]
+struct Language {
+    op: MetaOp,
+    cache: LangCache,
+}
+
+impl Matcher for Language {
+    fn matches(&self, doc: &mut DocRead) -> Result<bool> {
+        let value = match explicit_field(doc, lang::FIELD)? {
+            Some(value) => value,
+            None => self.cache.get(doc)?.map_or(Value::Null, Value::String),
+        };
+        Ok(self
+            .op
+            .matches(&value, None, Normalizer::default())
+            .unwrap_or(false))
+    }
+
+    fn cost(&self) -> Cost {
+        Cost::Body
+    }
+}
+
+/// The matcher for the fields derived from the Git history (see
+/// [`GIT_FIELDS`])
+#[derive(Debug)]
+struct GitDate {
+    key: String,
+    field_cfg: Option<FieldCfg>,
+    op: MetaOp,
+    norm: Normalizer,
+    dates: GitDates,
+}
+
+impl Matcher for GitDate {
+    fn matches(&self, doc: &mut DocRead) -> Result<bool> {
+        let path = doc.path().to_owned();
+        let value = self.dates.field_value(&path, doc.ensure_meta()?, &self.key);
+        Ok((self.op)
+            .matches(&value, self.field_cfg.as_ref(), self.norm)
+            .unwrap_or(false))
+    }
+}
+
+impl MetaOp {
+    fn in_range(field_cfg: Option<&FieldCfg>, yaml: &Value, low: &str, high: &str) -> Option<bool> {
+        Some(
+            CmpOp::Ge.test(schema::compare(field_cfg, yaml, low)?)
+                && CmpOp::Le.test(schema::compare(field_cfg, yaml, high)?),
+        )
+    }
+
+    fn matches(
+        &self,
+        yaml: &Value,
+        field_cfg: Option<&FieldCfg>,
+        norm: Normalizer,
+    ) -> Option<bool> {
+        if let Self::Set(op, values) = self {
+            let elements = match yaml {
+                Value::Sequence(array) => &array[..],
+                Value::Null => &[],
+                _ => std::slice::from_ref(yaml),
+            };
+            let mut is_present = values.iter().map(|value| {
+                let eq = Self::Eq(value.clone());
+                elements
+                    .iter()
+                    .any(|e| eq.matches(e, field_cfg, norm) == Some(true))
+            });
+            return Some(match op {
+                SetOp::All => is_present.all(|x| x),
+                SetOp::Any => is_present.any(|x| x),
+                SetOp::None => !is_present.any(|x| x),
+            });
+        }
+
+        match yaml {
+            Value::String(st) => {
+                let normalized;
+                let (st, yaml) = match norm.text(st) {
+                    Cow::Borrowed(st) => (st, yaml),
+                    Cow::Owned(st) => {
+                        normalized = Value::String(st);
+                        (normalized.as_str().unwrap(), &normalized)
+                    }
+                };
+                match self {
+                    Self::Eq(rhs) => Some(st == rhs),
+                    Self::Regex(regex) => Some(regex.is_match(st)),
+                    Self::Cmp(op, rhs) => Some(op.test(schema::compare(field_cfg, yaml, rhs)?)),
+                    Self::Range(low, high) => Self::in_range(field_cfg, yaml, low, high),
+                    Self::Set(..) => unreachable!(),
+                }
+            }
+            Value::Number(_) | Value::Bool(_) => match self {
+                Self::Eq(rhs) => Some(schema::compare(field_cfg, yaml, rhs)?.is_eq()),
+                Self::Regex(_) => None,
+                Self::Cmp(op, rhs) => Some(op.test(schema::compare(field_cfg, yaml, rhs)?)),
+                Self::Range(low, high) => Self::in_range(field_cfg, yaml, low, high),
+                Self::Set(..) => unreachable!(),
+            },
+            Value::Sequence(array) => {
+                if array.is_empty() {
+                    Some(false)
+                } else {
+                    array
+                        .iter()
+                        .map(|e| self.matches(e, field_cfg, norm))
+                        // Take the maximum value based on the ordering:
+                        // `Some(true) > Some(false) > None`, producing the following
+                        // properties:
+                        //
+                        //  - If any element matches, `yaml_eq` returns `Some(true)`
+                        //
+                        //  - If the above is not the case but at least one element
+                        //    is comparable, `yaml_eq` returns `Some(false)`.
+                        //
+                        //  - If none of the elements are comparable, `yaml_eq`
+                        //    returns `None`.
+                        //
+                        .fold(None, |acc, x| match (acc, x) {
+                            (Some(true), _) | (_, Some(true)) => Some(true),
+                            (Some(false), _) | (_, Some(false)) => Some(false),
+                            (None, None) => None,
+                        })
+                }
+            }
+            Value::Null => Some(false),
+            _ => {
+                // Uncomparable
+                None
+            }
+        }
+    }
+}
+
+/// Select the matching documents. A document yielded more than once (e.g.,
+/// through symbolic links or a repeated path in [`Query::restrict_to_files`])
+/// is included only once.
+pub fn select_all<'a>(
+    root: &'a DocRoot,
+    query: &'a Query,
+) -> impl Iterator<Item = Result<DocRead, Error>> + 'a {
+    let mut smart_name_matchers: std::vec::IntoIter<Box<dyn Matcher + 'a>> = match &query.smart_name
+    {
+        Some(pattern) => (root.cfg.smart_phases.iter())
+            .map(|&phase| smart_phase_matcher(phase, pattern, query.norm))
+            .collect::<Vec<_>>()
+            .into_iter(),
+        None => vec![Box::new(Always) as Box<dyn Matcher>].into_iter(),
+    };
+
+    loop {
+        let smart_name_matcher = smart_name_matchers
+            .next()
+            .unwrap_or_else(|| Box::new(Never));
+        let is_last_phase = smart_name_matchers.len() == 0;
+
+        fn apply_matcher(
+            acc: Option<Result<DocRead, Error>>,
+            matcher: &dyn Matcher,
+        ) -> Option<Result<DocRead, Error>> {
+            match acc {
+                Some(Ok(mut doc)) => match matcher.matches(&mut doc) {
+                    Ok(true) => Some(Ok(doc)),
+                    Ok(false) => None,
+                    Err(e) => Some(Err(e)),
+                },
+                x => x,
+            }
+        }
+
+        // Evaluate the smart name matcher in the order of cost as well
+        let (cheap_matchers, other_matchers) = query.matchers.split_at(
+            query
+                .matchers
+                .partition_point(|matcher| matcher.cost() <= smart_name_matcher.cost()),
+        );
+
+        let mut iterator = query
+            .candidates(root)
+            .filter_map(move |doc_or_err| {
+                timing::measure(Phase::Matching, || {
+                    cheap_matchers
+                        .iter()
+                        .map(|matcher| &**matcher)
+                        .chain(std::iter::once(&*smart_name_matcher))
+                        .chain(other_matchers.iter().map(|matcher| &**matcher))
+                        .fold(Some(doc_or_err), apply_matcher)
+                })
+            })
+            .peekable();
+
+        if iterator.peek().is_some() || is_last_phase {
+            return iterator;
+        }
+
+        // If the iterator returned no element, proceed to the next phase
+    }
+}
+
+/// Construct the matcher implementing a phase of smart name resolution.
+fn smart_phase_matcher(
+    phase: SmartPhase,
+    pattern: &str,
+    norm: Normalizer,
+) -> Box<dyn Matcher + '_> {
+    match phase {
+        SmartPhase::Exact => Box::new(SmartNameExact { pattern, norm }),
+        SmartPhase::Id => Box::new(SmartId { pattern, norm }),
+        SmartPhase::Alias => Box::new(SmartAlias { pattern, norm }),
+        SmartPhase::Prefix => Box::new(SmartNamePrefix { pattern, norm }),
+        SmartPhase::Substring => Box::new(SmartNameSubstring { pattern, norm }),
+        SmartPhase::Fuzzy => Box::new(SmartNameFuzzy { pattern, norm }),
+        SmartPhase::TitleExact => Box::new(SmartTitle {
+            pattern,
+            norm,
+            exact: true,
+        }),
+        SmartPhase::Title => Box::new(SmartTitle {
+            pattern,
+            norm,
+            exact: false,
+        }),
+    }
+}
+
+pub enum SelectOneError {
+    Empty,
+    Ambiguous {
+        /// Some of the matching documents (up to `max_candidates` in
+        /// `config.toml`)
+        candidates: Vec<DocRead>,
+        /// The number of the matching documents
+        total: usize,
+    },
+    Misc(Error),
+}
+
+impl SelectOneError {
+    /// Construct [`Self::Ambiguous`] from all matching documents, keeping
+    /// only the first `max_candidates` of them as candidates.
+    pub fn ambiguous(docs: impl IntoIterator<Item = DocRead>, max_candidates: usize) -> Self {
+        let mut total = 0;
+        let mut candidates = Vec::new();
+        for doc in docs {
+            if candidates.len() < max_candidates {
+                candidates.push(doc);
+            }
+            total += 1;
+        }
+        Self::Ambiguous { candidates, total }
+    }
+}
+
+impl fmt::Display for SelectOneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Empty => f.write_str("Did not match anything"),
+            Self::Ambiguous { candidates, total } => {
+                write!(
+                    f,
+                    "Ambigous document selection ({} documents matched). Candidates:",
+                    total
+                )?;
+                for (i, doc) in candidates.iter().enumerate() {
+                    write!(f, "\n {:2}. {}", i + 1, doc)?;
+                }
+                if *total > candidates.len() {
+                    write!(f, "\n     ({} more)", total - candidates.len())?;
+                }
+                Ok(())
+            }
+            Self::Misc(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl fmt::Debug for SelectOneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Empty => f.write_str("Did not match anything"),
+            Self::Ambiguous { .. } => write!(f, "{}", self),
+            Self::Misc(e) => write!(f, "{:?}", e),
+        }
+    }
+}
+
+impl std::error::Error for SelectOneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        if let Self::Misc(e) = self {
+            e.source()
+        } else {
+            None
+        }
+    }
+}
+
+/// Select exactly one document. Fails if no documents or more than one
+/// document matched.
+pub fn select_one(root: &DocRoot, query: &Query) -> Result<DocRead, SelectOneError> {
+    let mut docs = select_all_sorted(root, query)?;
+
+    match docs.len() {
+        0 => Err(SelectOneError::Empty),
+        1 => Ok(docs.pop().unwrap()),
+        // Report an error, presenting a few candidates to the user
+        _ => Err(SelectOneError::ambiguous(docs, root.cfg.max_candidates)),
+    }
+}
+
+/// Select the `index`-th (zero-based) document of the matching documents
+/// sorted by path. This is the same order as the candidates of
+/// [`SelectOneError::Ambiguous`].
+pub fn select_nth(root: &DocRoot, query: &Query, index: usize) -> Result<DocRead, SelectOneError> {
+    take_nth(select_all_sorted(root, query)?, index)
+}
+
+fn take_nth(mut docs: Vec<DocRead>, index: usize) -> Result<DocRead, SelectOneError> {
+    if docs.is_empty() {
+        Err(SelectOneError::Empty)
+    } else if index >= docs.len() {
+        Err(SelectOneError::Misc(anyhow::anyhow!(
+            "Index {} is out of range; only {} documents matched",
+            index + 1,
+            docs.len()
+        )))
+    } else {
+        Ok(docs.swap_remove(index))
+    }
+}
+
+/// Sort documents by a metadata field or a pseudo-field (`name`, `path`,
+/// `type`, `mtime`, `words`, `reading_time`, or `lang`). `reverse` reverses
+/// the order. The documents are returned with their sort keys, which
+/// [`SortOrder`] compares to merge the documents of several document roots.
+///
+/// Text is compared by the collation rules of `locale` in `config.toml`, and
+/// fields declared in the schema are compared according to their types.
+/// Documents lacking the field are placed last even if `reverse` is set. The
+/// sort is stable.
+pub fn sort_docs(
+    root: &DocRoot,
+    docs: Vec<DocRead>,
+    key: &str,
+    reverse: bool,
+) -> Result<Vec<(Value, DocRead)>> {
+    let sorter = Sorter::new(root, key, reverse);
+
+    let mut keyed = docs
+        .into_iter()
+        .map(|mut doc| Ok((sorter.sort_key(&mut doc)?, doc)))
+        .collect::<Result<Vec<_>>>()?;
+
+    keyed.sort_by(|(a, _), (b, _)| sorter.order.compare(a, b));
+
+    Ok(keyed)
+}
+
+/// Select the first `limit` matching documents in the order of `key` (see
+/// [`sort_docs`]), breaking ties by path.
+///
+/// This produces the same result as sorting all matching documents and
+/// truncating the result, but it only holds up to `2 * limit` documents at
+/// once, so it stays fast and memory-light on a large document root.
+pub fn select_top_k(
+    root: &DocRoot,
+    query: &Query,
+    key: &str,
+    reverse: bool,
+    limit: usize,
+) -> Result<Vec<(Value, DocRead)>> {
+    if limit == 0 {
+        return Ok(Vec::new());
+    }
+
+    let sorter = Sorter::new(root, key, reverse);
+    let compare = |(a_key, a_doc): &(Value, DocRead), (b_key, b_doc): &(Value, DocRead)| {
+        sorter
+            .order
+            .compare(a_key, b_key)
+            .then_with(|| a_doc.path().cmp(b_doc.path()))
+    };
+
+    let mut keyed: Vec<(Value, DocRead)> = Vec::with_capacity(limit * 2);
+    for doc in select_all(root, query) {
+        let mut doc = doc?;
+        keyed.push((sorter.sort_key(&mut doc)?, doc));
+
+        if keyed.len() >= limit * 2 {
+            // Discard all but the first `limit` documents
+            keyed.select_nth_unstable_by(limit - 1, compare);
+            keyed.truncate(limit);
+        }
+    }
+
+    keyed.sort_by(compare);
+    keyed.truncate(limit);
+
+    Ok(keyed)
+}
+
+/// The order of the sort keys produced by [`sort_docs`] and [`select_top_k`]
+pub struct SortOrder<'a> {
+    collator: Collator,
+    field_cfg: Option<&'a FieldCfg>,
+    reverse: bool,
+}
+
+impl<'a> SortOrder<'a> {
+    pub fn new(root: &'a DocRoot, key: &str, reverse: bool) -> Self {
+        Self {
+            collator: Collator::new(&root.cfg.locale),
+            field_cfg: root
+                .cfg
+                .schema
+                .get(key.strip_prefix(META_PREFIX).unwrap_or(key)),
+            reverse,
+        }
+    }
+
+    pub fn compare(&self, a: &Value, b: &Value) -> Ordering {
+        compare_sort_keys(&self.collator, self.field_cfg, self.reverse, a, b)
+    }
+}
+
+/// Extracts and compares sort keys
+struct Sorter<'a> {
+    key: &'a str,
+    order: SortOrder<'a>,
+    stats_cache: StatsCache,
+    /// Set if `key` is `lang`
+    lang_cache: Option<LangCache>,
+    /// Set if `key` is in [`GIT_FIELDS`]
+    git_dates: Option<GitDates>,
+}
+
+impl<'a> Sorter<'a> {
+    fn new(root: &'a DocRoot, key: &'a str, reverse: bool) -> Self {
+        Self {
+            key,
+            order: SortOrder::new(root, key, reverse),
+            stats_cache: StatsCache::load(root),
+            lang_cache: (key == lang::FIELD).then(|| LangCache::load(root)),
+            git_dates: GIT_FIELDS.contains(&key).then(|| GitDates::load(root)),
+        }
+    }
+
+    fn sort_key(&self, doc: &mut DocRead) -> Result<Value> {
+        if let Some(key) = self.key.strip_prefix(META_PREFIX) {
+            let path = doc.path().to_owned();
+            let meta = doc
+                .ensure_meta()
+                .with_context(|| format!("Failed to read the metadata of {:?}", path))?;
+            return Ok(meta[key].clone());
+        }
+        Ok(match self.key {
+            "name" => Value::String(
+                doc.path()
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+            ),
+            "path" => Value::String(doc.path().to_string_lossy().into_owned()),
+            "type" => match explicit_field(doc, "type")? {
+                Some(value) => value,
+                None => Value::String(doc.doc_type().as_str().to_owned()),
+            },
+            "mtime" => match explicit_field(doc, "mtime")? {
+                Some(value) => value,
+                None => std::fs::metadata(doc.path())
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+                    .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map_or(Value::Null, |time| Value::from(time.as_nanos() as u64)),
+            },
+            lang::FIELD => match explicit_field(doc, lang::FIELD)? {
+                Some(value) => value,
+                None => {
+                    let cache = self.lang_cache.as_ref().unwrap();
+                    cache.get(doc)?.map_or(Value::Null, Value::String)
+                }
+            },
+            key if stats::PSEUDO_FIELDS.contains(&key) => match explicit_field(doc, key)? {
+                Some(value) => value,
+                None => Value::from(self.stats_cache.get(doc)?.field(key).unwrap() as u64),
+            },
+            key => {
+                let path = doc.path().to_owned();
+                let meta = doc
+                    .ensure_meta()
+                    .with_context(|| format!("Failed to read the metadata of {:?}", path))?;
+                match &self.git_dates {
+                    Some(dates) => dates.field_value(&path, meta, key),
+                    None => meta[key].clone(),
+                }
+            }
+        })
+    }
+}
+
+fn compare_sort_keys(
+    collator: &Collator,
+    field_cfg: Option<&FieldCfg>,
+    reverse: bool,
+    a: &Value,
+    b: &Value,
+) -> Ordering {
+    // Sequences are represented by their first elements
+    let sort_text = |value: &Value| match value {
+        Value::Sequence(array) => array.first().and_then(schema::scalar_text),
+        _ => schema::scalar_text(value),
+    };
+    let (a_text, b_text) = match (sort_text(a), sort_text(b)) {
+        (None, None) => return Ordering::Equal,
+        (None, Some(_)) => return Ordering::Greater,
+        (Some(_), None) => return Ordering::Less,
+        (Some(x), Some(y)) => (x, y),
+    };
+
+    let typed = match field_cfg.and_then(|f| f.ty) {
+        Some(FieldType::String) | Some(FieldType::List) | Some(FieldType::ListString) => None,
+        Some(_) => schema::compare(field_cfg, a, &b_text),
+        None => match (a.as_f64(), b.as_f64()) {
+            (Some(x), Some(y)) => x.partial_cmp(&y),
+            _ => None,
+        },
+    };
+    let ordering = typed.unwrap_or_else(|| collator.compare(&a_text, &b_text));
+    if reverse {
+        ordering.reverse()
+    } else {
+        ordering
+    }
+}
+
+/// Collect the matching documents sorted by path.
+pub fn select_all_sorted(root: &DocRoot, query: &Query) -> Result<Vec<DocRead>, SelectOneError> {
+    let mut docs: Vec<DocRead> = select_all(root, query)
+        .collect::<Result<_>>()
+        .map_err(SelectOneError::Misc)?;
+    docs.sort_by(|a, b| a.path().cmp(b.path()));
+    Ok(docs)
+}
+
+/// A document selected by [`select_ranked`]
+pub struct RankedDoc {
+    pub doc: DocRead,
+    /// How well the smart name matched: 3 (the exact name or ID), 2 (a prefix
+    /// of the name), 1 (a fuzzy match), or 0 (no smart name was given)
+    pub tier: u32,
+    /// The relevance score, which is the tier boosted by a title match and
+    /// the frecency (see [`crate::history::History::frecency`]). The boosts
+    /// never exceed the difference between tiers.
+    pub score: u32,
+}
+
+/// The boost given to documents whose titles contain the smart name
+const TITLE_MATCH_BOOST: u32 = 50;
+
+/// Select the matching documents ordered by relevance (and then by path).
+///
+/// Unlike [`select_all`], where the smart name matches only prefixes of
+/// names if no document has the exact name or ID, this includes all
+/// documents matched by the smart name, including fuzzy matches (the
+/// characters of the smart name appear in order in the name). The smart name
+/// is compared ignoring case in every tier.
+pub fn select_ranked(root: &DocRoot, query: &Query) -> Result<Vec<RankedDoc>, SelectOneError> {
+    let history = crate::history::History::load(root);
+    let frecency = history.frecency(crate::history::now());
+
+    // The phases of smart name resolution making up each tier. Unlike
+    // `select_all`, all of them ignore case.
+    let norm = Normalizer {
+        ignore_case: true,
+        ..query.norm
+    };
+    let pattern = (query.smart_name.as_ref()).map(|pattern| norm.text(pattern).into_owned());
+    let tiers: Vec<(u32, Box<dyn Matcher + '_>)> = match &pattern {
+        Some(pattern) => [
+            (3, SmartPhase::Exact),
+            (3, SmartPhase::Id),
+            (3, SmartPhase::Alias),
+            (2, SmartPhase::Prefix),
+            (1, SmartPhase::Fuzzy),
+        ]
+        .iter()
+        .map(|&(tier, phase)| (tier, smart_phase_matcher(phase, pattern, norm)))
+        .collect(),
+        None => Vec::new(),
+    };
+
+    let mut ranked = Vec::new();
+    for doc in query.candidates(root) {
+        let mut doc = doc.map_err(SelectOneError::Misc)?;
+
+        let matched = timing::measure(Phase::Matching, || {
+            let (tier, title_match) = match &pattern {
+                Some(pattern) => {
+                    let tier = tiers.iter().find_map(|(tier, matcher)| {
+                        matcher.matches(&mut doc).unwrap_or(false).then_some(*tier)
+                    });
+                    match tier {
+                        Some(tier) => (tier, title_contains(&mut doc, pattern, norm)),
+                        None => return Ok(None),
+                    }
+                }
+                None => (0, false),
+            };
+            for matcher in &query.matchers {
+                if !matcher.matches(&mut doc)? {
+                    return Ok(None);
+                }
+            }
+            Ok(Some((tier, title_match)))
+        });
+        let (tier, title_match) = match matched.map_err(SelectOneError::Misc)? {
+            Some(x) => x,
+            None => continue,
+        };
+
+        let rel_path = doc.path().strip_prefix(&root.path).unwrap_or(doc.path());
+        let score = tier * 100
+            + if title_match { TITLE_MATCH_BOOST } else { 0 }
+            + frecency.get(rel_path).copied().unwrap_or(0);
+        ranked.push(RankedDoc { doc, tier, score });
+    }
+
+    ranked.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then_with(|| a.doc.path().cmp(b.doc.path()))
+    });
+    Ok(ranked)
+}
+
+/// Select the most relevant document (see [`select_ranked`]). Fails if no
+/// documents matched or more than one document matched in the best tier.
+pub fn select_one_ranked(root: &DocRoot, query: &Query) -> Result<DocRead, SelectOneError> {
+    let ranked = select_ranked(root, query)?;
+    match &ranked[..] {
+        [] => Err(SelectOneError::Empty),
+        [_] => Ok(ranked.into_iter().next().unwrap().doc),
+        [first, second, ..] if first.tier > second.tier => {
+            Ok(ranked.into_iter().next().unwrap().doc)
+        }
+        _ => Err(SelectOneError::ambiguous(
+            ranked.into_iter().map(|r| r.doc),
+            root.cfg.max_candidates,
+        )),
+    }
+}
+
+/// Select the `index`-th (zero-based) document in the order of relevance
+/// (see [`select_ranked`]).
+pub fn select_nth_ranked(
+    root: &DocRoot,
+    query: &Query,
+    index: usize,
+) -> Result<DocRead, SelectOneError> {
+    let docs = select_ranked(root, query)?
+        .into_iter()
+        .map(|r| r.doc)
+        .collect();
+    take_nth(docs, index)
+}
+
+/// Check if the characters of `pattern` appear in `text` in order, ignoring
+/// case.
+fn is_fuzzy_match(pattern: &str, text: &str) -> bool {
+    let mut text = text.chars().flat_map(char::to_lowercase);
+    pattern
+        .chars()
+        .flat_map(char::to_lowercase)
+        .all(|c| text.any(|x| x == c))
+}
+
+/// Check if the title of a document equals `pattern`, ignoring case.
+fn title_equals(doc: &mut DocRead, pattern: &str, norm: Normalizer) -> bool {
+    match doc.ensure_meta().map(|meta| &meta["title"]) {
+        Ok(Value::String(title)) => norm.text(title).to_lowercase() == pattern.to_lowercase(),
+        _ => false,
+    }
+}
+
+/// Check if the title of a document contains `pattern`, ignoring case.
+fn title_contains(doc: &mut DocRead, pattern: &str, norm: Normalizer) -> bool {
+    match doc.ensure_meta().map(|meta| &meta["title"]) {
+        Ok(Value::String(title)) => {
+            (norm.text(title).to_lowercase()).contains(&pattern.to_lowercase())
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matcher_order() {
+        let meta = |key: &str| Meta {
+            key: key.to_owned(),
+            explicit: false,
+            field_cfg: None,
+            op: MetaOp::Eq(String::new()),
+            norm: Normalizer::default(),
+        };
+        let mut query = Query {
+            smart_name: None,
+            matchers: Vec::new(),
+            highlights: Highlights::default(),
+            norm: Normalizer::default(),
+            files: None,
+            guard: ContentGuard::new(&ContentsCfg::default()),
+        };
+        query.push_matcher(Box::new(meta("status")));
+        query.push_matcher(Box::new(Negate(Box::new(meta("path")))));
+        query.push_matcher(Box::new(Never));
+        query.push_matcher(Box::new(meta("title")));
+        let costs: Vec<_> = query.matchers.iter().map(|m| m.cost()).collect();
+        assert_eq!(costs, [Cost::Path, Cost::Path, Cost::Meta, Cost::Meta]);
+    }
+
+    #[test]
+    fn test_meta_set() {
+        let matches = |criterion: &str, yaml: &str| {
+            let op = match criterion.parse::<Criterion>() {
+                Ok(Criterion::Simple {
+                    simple_criterion: SimpleCriterion::MetaSet(_, op, values),
+                    ..
+                }) => MetaOp::Set(op, values),
+                x => panic!("{:?}", x),
+            };
+            op.matches(
+                &serde_yaml::from_str(yaml).unwrap(),
+                None,
+                Normalizer::default(),
+            )
+            .unwrap()
+        };
+
+        assert!(matches("tags:all(work, urgent)", "[urgent, x, work]"));
+        assert!(!matches("tags:all(work,urgent)", "[work]"));
+        assert!(matches("tags:any(a,b,c)", "[x, c]"));
+        assert!(matches("tags:any(a,b,c)", "b"));
+        assert!(!matches("tags:any(a,b,c)", "~"));
+        assert!(matches("tags:none(x)", "[a, b]"));
+        assert!(matches("tags:none(x)", "~"));
+        assert!(!matches("tags:none(x,y)", "[a, y]"));
+        assert!(matches("priority:any(1,2)", "[2]"));
+        assert!(matches!(
+            "path:any(a)".parse::<Criterion>(),
+            Ok(Criterion::Simple {
+                simple_criterion: SimpleCriterion::MetaEq(..),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_meta_range() {
+        let range = |s: &str| match s.parse::<Criterion>() {
+            Ok(Criterion::Simple {
+                simple_criterion: SimpleCriterion::MetaRange(_, low, high),
+                ..
+            }) => MetaOp::Range(low, high),
+            x => panic!("{:?}", x),
+        };
+        let matches = |op: &MetaOp, yaml: &str| {
+            op.matches(
+                &serde_yaml::from_str(yaml).unwrap(),
+                None,
+                Normalizer::default(),
+            )
+        };
+
+        let op = range("priority:2..10");
+        assert_eq!(matches(&op, "2"), Some(true));
+        assert_eq!(matches(&op, "10"), Some(true));
+        assert_eq!(matches(&op, "11"), Some(false));
+        assert_eq!(matches(&op, "[1, 5]"), Some(true));
+
+        let op = range("due:2024-01-01..2024-01-31");
+        assert_eq!(matches(&op, "2024-01-31"), Some(true));
+        assert_eq!(matches(&op, "2024-02-01"), Some(false));
+
+        assert!(matches!(
+            "changed:main..topic".parse::<Criterion>(),
+            Ok(Criterion::Simple {
+                simple_criterion: SimpleCriterion::MetaEq(..),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_criterion_display() {
+        for s in [
+            "foo",
+            "/^a.c$/i",
+            "!#project/alpha",
+            "name=index",
+            "status:draft",
+            "!title:/x/a",
+            "priority:<=3",
+            "date:2024-01..2024-06",
+            "tags:any(a,b)",
+            "in:all(x)",
+        ] {
+            assert_eq!(s.parse::<Criterion>().unwrap().to_string(), s);
+        }
+    }
+
+    #[test]
+    fn test_match_ranges() {
+        let regexes = [
+            regex::Regex::new("ab").unwrap(),
+            regex::Regex::new("bc").unwrap(),
+            regex::Regex::new("x*").unwrap(),
+        ];
+        assert_eq!(match_ranges(&regexes, "abc-ab"), [0..3, 4..6]);
+        assert_eq!(match_ranges(&regexes, "zzz"), []);
+    }
+
+    #[test]
+    fn test_is_fuzzy_match() {
+        assert!(is_fuzzy_match("mtg", "2021-Meeting"));
+        assert!(is_fuzzy_match("", "a"));
+        assert!(!is_fuzzy_match("gm", "meeting"));
+    }
+
+    #[test]
+    fn test_glob_to_regex() {
+        let is_match = |glob: &str, name: &str| {
+            regex::Regex::new(&glob_to_regex(glob))
+                .unwrap()
+                .is_match(name)
+        };
+        assert!(is_match("2024-*-meeting", "2024-03-meeting"));
+        assert!(!is_match("2024-*-meeting", "2024-03-meeting-notes"));
+        assert!(!is_match("*.md", "notes/a.md"));
+        assert!(is_match("notes/**/*.md", "notes/a.md"));
+        assert!(is_match("notes/**/*.md", "notes/2024/a.md"));
+        assert!(is_match("draft-[0-9]?", "draft-1a"));
+        assert!(!is_match("draft-[!0-9]", "draft-1"));
+        assert!(is_match("a.b", "a.b"));
+        assert!(!is_match("a.b", "axb"));
+    }
+
+    #[test]
+    fn test_find_content_matches() {
+        let regexes = [
+            regex::Regex::new("(?i)call").unwrap(),
+            regex::Regex::new("dentist").unwrap(),
+        ];
+        let contents = b"---\ntitle: call\n---\nTodo\r\n- Call the dentist\n- call\n";
+        let matches = find_content_matches(&regexes, contents, Normalizer::default());
+        assert_eq!(
+            matches,
+            [
+                ContentMatch {
+                    line: 5,
+                    column: 3,
+                    text: "- Call the dentist".to_owned(),
+                },
+                ContentMatch {
+                    line: 6,
+                    column: 3,
+                    text: "- call".to_owned(),
+                },
+            ]
+        );
+        assert!(find_content_matches(&regexes, b"nothing", Normalizer::default()).is_empty());
+    }
+
+    #[test]
+    fn test_is_binary() {
+        assert!(!is_binary(b""));
+        assert!(!is_binary("# Café\n".as_bytes()));
+        assert!(is_binary(b"%PDF-1.7\n\x00\x01"));
+        let mut late_nul = vec![b'a'; BINARY_SNIFF_LEN];
+        late_nul.push(0);
+        assert!(!is_binary(&late_nul));
+    }
+
+    #[test]
+    fn test_compare_sort_keys() {
+        let collator = Collator::new("en");
+        let keys = [Value::Null, Value::from(2), Value::from(10)];
+        for &(reverse, expected) in &[(false, [2, 10]), (true, [10, 2])] {
+            let mut sorted = keys.to_vec();
+            sorted.sort_by(|a, b| compare_sort_keys(&collator, None, reverse, a, b));
+            assert_eq!(
+                sorted,
+                [
+                    Value::from(expected[0]),
+                    Value::from(expected[1]),
+                    Value::Null
+                ]
+            );
+        }
+    }
+}
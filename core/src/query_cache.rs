@@ -0,0 +1,255 @@
+//! A short-lived cache of query results, stored in
+//! `.veisku/state/cache/queries.json`
+//!
+//! Unlike [`crate::cache::FileCache`], the entries can't be validated without
+//! scanning the document root. Before the cached results are used, they are
+//! refreshed by a quick walk of the document root, which finds the documents
+//! modified since the query and the directories whose modification times
+//! changed (i.e., where files were added, removed, or renamed). Only these
+//! documents need to be examined again. If the walk takes longer than
+//! [`REFRESH_BUDGET`], the entries are trusted as they are until they
+//! expire.
+//!
+//! The entries are also invalidated by the operations recorded in the journal
+//! (e.g., `v mv`) and by configuration changes.
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{doc, journal::Journal, lock::FileLock, root::DocRoot, state::StateDir};
+
+/// Entries older than this are removed regardless of the TTL requested
+const MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// How long refreshing the cached results may take
+pub const REFRESH_BUDGET: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    /// The time of the query in seconds since the Unix epoch
+    time: u64,
+    /// See [`generation`]
+    generation: u64,
+    /// The matching documents
+    paths: Vec<PathBuf>,
+    /// The modification times of the directories containing documents, in
+    /// nanoseconds since the Unix epoch
+    #[serde(default)]
+    dirs: HashMap<PathBuf, u64>,
+}
+
+/// The cached results of a query returned by [`QueryCache::get`]
+#[derive(Debug)]
+pub struct CachedResults {
+    /// The matching documents. If `refreshed` is set, this also includes the
+    /// documents that may have changed since the query, which must be
+    /// examined again.
+    pub paths: Vec<PathBuf>,
+    /// Whether the results were refreshed. If so, they should be stored again
+    /// by [`QueryCache::put`] after being examined.
+    pub refreshed: bool,
+}
+
+/// The cached results of a query
+#[derive(Debug)]
+pub struct QueryCache {
+    /// `None` if the cache is not persisted
+    path: Option<PathBuf>,
+    state_dir: StateDir,
+    key: String,
+    generation: u64,
+    /// The time when this handle was created, which is recorded as the time
+    /// of the query
+    time: u64,
+    /// The directory modification times found by [`Self::get`]
+    dirs: Option<HashMap<PathBuf, u64>>,
+}
+
+impl QueryCache {
+    /// Construct a handle of the results of the query identified by
+    /// `query_key` (e.g., the search criteria). The configuration of `root`
+    /// is included in the key.
+    pub fn new(root: &DocRoot, query_key: &str) -> Self {
+        // Don't create the configuration directory, which would change the
+        // document root
+        let path = if root.cfg_dir_path.is_dir() {
+            Some(root.state_dir_path.join("cache").join("queries.json"))
+        } else {
+            None
+        };
+        let cfg = toml::to_string(&root.cfg).unwrap_or_default();
+        Self {
+            path,
+            state_dir: root.state_dir(),
+            key: doc::content_digest(
+                format!("{}\n{}\n{}", root.path.display(), cfg, query_key).as_bytes(),
+            ),
+            generation: generation(root),
+            time: now(),
+            dirs: None,
+        }
+    }
+
+    /// Get the cached results if they are younger than `ttl`, refreshing them
+    /// if possible.
+    pub fn get(&mut self, root: &DocRoot, ttl: Duration) -> Option<CachedResults> {
+        let mut entries = self.load();
+        let entry = entries.remove(&self.key)?;
+        let age = now().saturating_sub(entry.time);
+        if entry.generation != self.generation || age >= ttl.as_secs() {
+            return None;
+        }
+
+        match scan(root, Some(&entry)) {
+            Some(scan) => {
+                let mut paths: Vec<PathBuf> = entry
+                    .paths
+                    .into_iter()
+                    .filter(|path| scan.docs.contains(path))
+                    .collect();
+                log::debug!(
+                    "Refreshing the cached results with {} changed document(s)",
+                    scan.changed.len()
+                );
+                let cached: HashSet<PathBuf> = paths.iter().cloned().collect();
+                paths.extend(
+                    scan.changed
+                        .into_iter()
+                        .filter(|path| !cached.contains(path)),
+                );
+                self.dirs = Some(scan.dirs);
+                Some(CachedResults {
+                    paths,
+                    refreshed: true,
+                })
+            }
+            None => {
+                log::debug!("Refreshing the cached results took too long; using them as they are");
+                Some(CachedResults {
+                    paths: entry.paths,
+                    refreshed: false,
+                })
+            }
+        }
+    }
+
+    /// Store the results, removing expired entries.
+    pub fn put(&self, root: &DocRoot, paths: Vec<PathBuf>) -> Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        // Without the directory modification times, everything will be
+        // examined again on refresh, which is still correct
+        let dirs = match &self.dirs {
+            Some(dirs) => dirs.clone(),
+            None => scan(root, None).map_or_else(HashMap::new, |scan| scan.dirs),
+        };
+        self.state_dir.prepare()?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+        }
+
+        let _lock = FileLock::acquire(path)?;
+        let now = now();
+        let mut entries = self.load();
+        entries.retain(|_, entry| now.saturating_sub(entry.time) < MAX_AGE.as_secs());
+        entries.insert(
+            self.key.clone(),
+            Entry {
+                time: self.time,
+                generation: self.generation,
+                paths,
+                dirs,
+            },
+        );
+        doc::write_atomic(path, &serde_json::to_vec(&entries).unwrap())
+    }
+
+    fn load(&self) -> HashMap<String, Entry> {
+        self.path
+            .as_ref()
+            .and_then(|path| std::fs::read(path).ok())
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// The result of [`scan`]
+struct Scan {
+    /// All documents in the document root
+    docs: HashSet<PathBuf>,
+    /// The documents that may have changed since the query of the entry
+    changed: Vec<PathBuf>,
+    /// See [`Entry::dirs`]
+    dirs: HashMap<PathBuf, u64>,
+}
+
+/// Walk the document root to find the documents that may have changed since
+/// the query of `entry`. Returns `None` if it takes longer than
+/// [`REFRESH_BUDGET`].
+fn scan(root: &DocRoot, entry: Option<&Entry>) -> Option<Scan> {
+    let start = Instant::now();
+    let mut scan = Scan {
+        docs: HashSet::new(),
+        changed: Vec::new(),
+        dirs: HashMap::new(),
+    };
+    for doc_file in root.doc_files() {
+        if start.elapsed() > REFRESH_BUDGET {
+            return None;
+        }
+        let doc_file = match doc_file {
+            Ok(x) => x,
+            // Let the query report the error
+            Err(_) => return None,
+        };
+        let path = doc_file.path().to_owned();
+        let dir = path.parent().unwrap_or(&root.path).to_owned();
+        let dir_mtime = *scan
+            .dirs
+            .entry(dir.clone())
+            .or_insert_with(|| std::fs::metadata(&dir).map_or(0, |m| mtime_nanos(&m)));
+
+        if let Some(entry) = entry {
+            // A document is examined again if it was modified during or after
+            // the query, or if it may have been moved into its directory
+            let dir_changed = entry.dirs.get(&dir) != Some(&dir_mtime);
+            let modified = doc_file
+                .metadata()
+                .map_or(true, |m| mtime_nanos(&m) / 1_000_000_000 >= entry.time);
+            if dir_changed || modified {
+                scan.changed.push(path.clone());
+            }
+        }
+        scan.docs.insert(path);
+    }
+    Some(scan)
+}
+
+fn mtime_nanos(metadata: &std::fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |time| time.as_nanos() as u64)
+}
+
+/// Get a number that changes whenever `v` modifies documents, i.e., the time
+/// of the last change to the journal in nanoseconds.
+fn generation(root: &DocRoot) -> u64 {
+    Journal::new(root)
+        .last_modified()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |time| time.as_nanos() as u64)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
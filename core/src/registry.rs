@@ -0,0 +1,76 @@
+//! The registry of named document roots
+//!
+//! The registry is a TOML file mapping root names to directories:
+//!
+//! ```toml
+//! work = "~/work/notes"
+//! personal = "/home/me/notes"
+//! ```
+//!
+//! It's read from `$VEISKU_ROOTS` if set, or `veisku/roots.toml` in the user's
+//! configuration directory (`$XDG_CONFIG_HOME` or `~/.config`) otherwise.
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::root::DocRoot;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Registry {
+    /// The directories of document roots, keyed by names
+    pub roots: BTreeMap<String, String>,
+}
+
+impl Registry {
+    /// Get the path of the registry file.
+    pub fn file_path() -> Option<PathBuf> {
+        if let Some(path) = std::env::var_os("VEISKU_ROOTS") {
+            return Some(path.into());
+        }
+        let cfg_dir = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| Some(home_dir()?.join(".config")))?;
+        Some(cfg_dir.join("veisku/roots.toml"))
+    }
+
+    /// Load the registry. Returns an empty registry if the file doesn't exist.
+    pub fn load() -> Result<Self> {
+        let path = match Self::file_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(Self::default()),
+        };
+        let text =
+            std::fs::read_to_string(&path).with_context(|| format!("Failed to read {:?}", path))?;
+        toml::de::from_str(&text).with_context(|| format!("Failed to parse {:?}", path))
+    }
+
+    /// Open all registered document roots, ordered by name.
+    pub fn open_all(&self) -> Result<Vec<(String, DocRoot)>> {
+        self.roots
+            .iter()
+            .map(|(name, dir)| {
+                let root = DocRoot::open(&expand_tilde(dir))
+                    .with_context(|| format!("Failed to open the document root '{}'", name))?;
+                Ok((name.clone(), root))
+            })
+            .collect()
+    }
+}
+
+fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+/// Expand a leading `~/` to the home directory.
+fn expand_tilde(dir: &str) -> PathBuf {
+    match (dir.strip_prefix("~/"), home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => Path::new(dir).to_owned(),
+    }
+}
@@ -0,0 +1,757 @@
+//! Document root discovery and configuration retrieval
+use anyhow::{bail, Context, Error, Result};
+use either::{Left, Right};
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{
+    cfg::Cfg,
+    doc::{self, DocRead},
+    ignore_files::IgnoreFiles,
+    state,
+    timing::{self, Counter, Phase},
+};
+
+/// Contains the configuration data of a document root.
+#[derive(Debug)]
+pub struct DocRoot {
+    pub path: PathBuf,
+    /// The configuration directory (`.veisku`). Might not exist.
+    pub cfg_dir_path: PathBuf,
+    /// The directory containing machine-generated state (see [`state`]).
+    /// Might not exist. Use [`Self::state_dir`] to access it.
+    pub state_dir_path: PathBuf,
+    pub cfg: Cfg,
+}
+
+impl DocRoot {
+    /// Locate the doocument root based on the current working directory and
+    /// return the corresponding `DocRoot` object.
+    pub fn current() -> Result<Self> {
+        Self::current_with_overrides(&[])
+    }
+
+    /// Like [`Self::current`], but overrides the configuration values as
+    /// specified by `overrides`.
+    pub fn current_with_overrides(overrides: &[CfgOverride]) -> Result<Self> {
+        Self::open_with_overrides(&Self::locate_current()?, overrides)
+    }
+
+    /// Locate the directory containing the configuration directory based on
+    /// the current working directory without reading the configuration.
+    pub fn locate_current() -> Result<PathBuf> {
+        let current_dir =
+            std::env::current_dir().context("Failed to determine the current directory")?;
+        let inherited_base_path = std::env::var_os("V_CONFIG")
+            .and_then(|path| Some(Path::new(&path).parent()?.parent()?.to_owned()));
+        let mut doc_root_path: &Path = &current_dir;
+        if let Some(path) = &inherited_base_path {
+            // We are invoked by a script run by another instance of this
+            // program. Use the same document root.
+            log::debug!(
+                "Using the document root inherited through `V_CONFIG`: {:?}",
+                path
+            );
+            doc_root_path = path;
+        } else {
+            let mut dir: &Path = &current_dir;
+            while {
+                log::trace!("Checking if {:?} contains a configuration directory", dir);
+                let cfg_dir_path = cfg_dir_path_for_doc_root_path(dir);
+                if cfg_dir_path.is_dir() {
+                    log::trace!(
+                        "Found the directory {:?}; using {:?} as the document root",
+                        cfg_dir_path,
+                        dir
+                    );
+                    doc_root_path = dir;
+                    false
+                } else if let Some(next_dir) = dir.parent() {
+                    dir = next_dir;
+                    true
+                } else {
+                    log::debug!(
+                        "Could not locate a configuration directory; using {:?} as the document root",
+                        current_dir
+                    );
+                    false
+                }
+            } {}
+        }
+
+        Ok(doc_root_path.to_owned())
+    }
+
+    /// Open the document root at the specified directory (which may or may
+    /// not contain a configuration directory).
+    pub fn open(doc_root_path: &Path) -> Result<Self> {
+        Self::open_with_overrides(doc_root_path, &[])
+    }
+
+    /// Like [`Self::open`], but overrides the configuration values as
+    /// specified by `overrides`.
+    pub fn open_with_overrides(doc_root_path: &Path, overrides: &[CfgOverride]) -> Result<Self> {
+        // Read the configuration
+        let cfg_path = cfg_file_path_for_doc_root_path(doc_root_path);
+        let cfg_toml = if cfg_path.exists() {
+            log::trace!("Reading configuration from {:?}", cfg_path);
+            std::fs::read_to_string(&cfg_path).context("Failed to read `config.toml`")?
+        } else {
+            log::trace!(
+                "{:?} doesn't exist; using the default configuration",
+                cfg_path
+            );
+            String::new()
+        };
+        let cfg: Cfg = if overrides.is_empty() {
+            parse_cfg_toml(&cfg_path, &cfg_toml)?
+        } else {
+            // Deserialize `Cfg` after applying the overrides. This loses the
+            // locations of errors, so it's only done when necessary.
+            let mut table: toml::value::Table = parse_cfg_toml(&cfg_path, &cfg_toml)?;
+            for cfg_override in overrides {
+                log::debug!("Applying {:?}", cfg_override);
+                cfg_override.apply(&mut table)?;
+            }
+            toml::Value::Table(table)
+                .try_into()
+                .map_err(
+                    |e: toml::de::Error| match UnknownField::parse(&e.to_string()) {
+                        Some(unknown) => Error::msg(unknown.describe(None)),
+                        None => Error::new(e),
+                    },
+                )
+                .context("Failed to apply the configuration overrides")?
+        };
+
+        // Decide the final document root
+        let cfg_dir_path = cfg_dir_path_for_doc_root_path(doc_root_path);
+        let doc_root_path = doc_root_path.join(&*portable_path(&cfg.root));
+        let doc_root_path = doc_root_path.canonicalize().with_context(|| {
+            format!(
+                "Failed to canonicalize the document root {:?}",
+                doc_root_path
+            )
+        })?;
+        let doc_root_path = strip_verbatim_prefix(doc_root_path);
+
+        let state_dir_path = state::resolve(&cfg_dir_path, &doc_root_path, cfg.state_dir);
+        state::StateDir::new(&cfg_dir_path, &state_dir_path).check_version();
+
+        Ok(DocRoot {
+            path: doc_root_path,
+            cfg_dir_path,
+            state_dir_path,
+            cfg,
+        })
+    }
+
+    /// Get the state directory, which must be set up by
+    /// [`state::StateDir::prepare`] before writing to it.
+    pub fn state_dir(&self) -> state::StateDir {
+        state::StateDir::new(&self.cfg_dir_path, &self.state_dir_path)
+    }
+
+    pub fn script_dir_path(&self) -> PathBuf {
+        self.path.join("bin")
+    }
+
+    /// Get the path of the configuration file. The file might not exist.
+    pub fn cfg_file_path(&self) -> PathBuf {
+        self.cfg_dir_path.join("config.toml")
+    }
+
+    /// Get the directory containing document templates for `v new`.
+    pub fn template_dir_path(&self) -> PathBuf {
+        self.cfg_dir_path.join("templates")
+    }
+
+    /// Fail if the document root is in read-only mode (`read_only = true`),
+    /// which refuses all modifications including the creation of documents.
+    pub fn ensure_not_read_only(&self) -> Result<()> {
+        if self.cfg.read_only {
+            bail!(
+                "The document root is in read-only mode (`--read-only` or \
+                `read_only = true` in `config.toml`)"
+            );
+        }
+        Ok(())
+    }
+
+    /// Fail if the configuration doesn't allow modifying documents.
+    pub fn ensure_writable(&self) -> Result<()> {
+        self.ensure_not_read_only()?;
+        if self.cfg.writable {
+            Ok(())
+        } else {
+            Err(Error::msg(
+                "This operation modifies documents, which requires `writable = true` \
+                in `config.toml`",
+            ))
+        }
+    }
+}
+
+/// The error context indicating that the configuration file at `path` is
+/// malformed.
+#[derive(Debug, thiserror::Error)]
+#[error("Failed to parse `config.toml`")]
+pub struct CfgParseError {
+    pub path: PathBuf,
+    /// The zero-based line number of the error location, if known.
+    pub line: Option<usize>,
+    /// The zero-based column number of the error location, if known.
+    pub column: Option<usize>,
+    /// The key probably intended by an unknown key, if any.
+    pub suggestion: Option<String>,
+}
+
+/// An override of a configuration value (`--config KEY=VALUE`)
+#[derive(Debug, Clone)]
+pub struct CfgOverride {
+    /// The components of a dotted key (e.g., `new.name`)
+    pub key: Vec<String>,
+    pub value: toml::Value,
+}
+
+impl FromStr for CfgOverride {
+    type Err = &'static str;
+
+    /// Parse `KEY=VALUE`. See [`CfgOverride::new`].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Find the first `=` outside quotes
+        let mut quote = None;
+        let i = s
+            .char_indices()
+            .find(|&(_, c)| match quote {
+                Some(q) => {
+                    if c == q {
+                        quote = None;
+                    }
+                    false
+                }
+                None if c == '"' || c == '\'' => {
+                    quote = Some(c);
+                    false
+                }
+                None => c == '=',
+            })
+            .ok_or("Expected `KEY=VALUE`")?
+            .0;
+
+        Self::new(&s[..i], &s[i + 1..])
+    }
+}
+
+impl CfgOverride {
+    /// Construct `Self` from a TOML dotted key, which may contain quoted parts
+    /// (e.g., `openers."image/*"`), and a value, which is parsed as a TOML
+    /// value if possible and treated as a string otherwise.
+    pub fn new(key: &str, value: &str) -> Result<Self, &'static str> {
+        Ok(Self {
+            key: parse_dotted_key(key)?,
+            value: parse_value(value),
+        })
+    }
+
+    /// Apply the override to the contents of a configuration file, creating
+    /// tables as needed.
+    pub fn apply(&self, cfg: &mut toml::value::Table) -> Result<()> {
+        let (last, parents) = self.key.split_last().unwrap();
+        let mut table = cfg;
+        for (i, key) in parents.iter().enumerate() {
+            let value = table
+                .entry(key.clone())
+                .or_insert_with(|| toml::Value::Table(Default::default()));
+            table = match value {
+                toml::Value::Table(table) => table,
+                _ => bail!("`{}` is not a table", parents[..=i].join(".")),
+            };
+        }
+        table.insert(last.clone(), self.value.clone());
+        Ok(())
+    }
+}
+
+/// Parse a TOML dotted key into its components.
+pub fn parse_dotted_key(key: &str) -> Result<Vec<String>, &'static str> {
+    // Let the TOML parser handle quoted parts
+    let mut components = Vec::new();
+    let mut value =
+        toml::de::from_str::<toml::Value>(&format!("{} = 0", key)).map_err(|_| "Invalid key")?;
+    while let toml::Value::Table(table) = value {
+        let (name, inner) = table.into_iter().next().ok_or("Invalid key")?;
+        components.push(name);
+        value = inner;
+    }
+    if components.iter().any(String::is_empty) {
+        return Err("The key must not be empty");
+    }
+    Ok(components)
+}
+
+/// Parse a TOML value, falling back to a string.
+fn parse_value(value: &str) -> toml::Value {
+    toml::de::from_str::<toml::value::Table>(&format!("value = {}", value))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_owned()))
+}
+
+/// Set a configuration value in the configuration file at `cfg_path`,
+/// preserving the formatting and comments of the rest of the file. The file is
+/// left unmodified if the resulting configuration is invalid.
+pub fn set_cfg_value(cfg_path: &Path, cfg_override: &CfgOverride) -> Result<()> {
+    let cfg_toml = match std::fs::read_to_string(cfg_path) {
+        Ok(cfg_toml) => cfg_toml,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", cfg_path)),
+    };
+
+    let cfg_toml = edit_cfg_toml(&cfg_toml, cfg_override)
+        .with_context(|| format!("Failed to edit {:?}", cfg_path))?;
+
+    if let Some(dir) = cfg_path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+    }
+    doc::write_atomic(cfg_path, cfg_toml.as_bytes())
+}
+
+/// Apply `cfg_override` to the text of a configuration file. See
+/// [`set_cfg_value`].
+fn edit_cfg_toml(cfg_toml: &str, cfg_override: &CfgOverride) -> Result<String> {
+    // Validate the new configuration
+    let mut table: toml::value::Table = toml::de::from_str(cfg_toml)?;
+    cfg_override.apply(&mut table)?;
+    toml::Value::Table(table)
+        .try_into::<Cfg>()
+        .context("The new configuration is invalid")?;
+
+    // Rewrite only the specified value
+    let mut document: toml_edit::DocumentMut = cfg_toml.parse()?;
+    let (last, parents) = cfg_override.key.split_last().unwrap();
+    let mut table: &mut dyn toml_edit::TableLike = document.as_table_mut();
+    for (i, key) in parents.iter().enumerate() {
+        table = table
+            .entry(key)
+            .or_insert_with(|| {
+                let mut table = toml_edit::Table::new();
+                table.set_implicit(true);
+                toml_edit::Item::Table(table)
+            })
+            .as_table_like_mut()
+            .with_context(|| format!("`{}` is not a table", parents[..=i].join(".")))?;
+    }
+    let mut value = toml_edit_value(&cfg_override.value);
+    match table.get_mut(last) {
+        // Keep the key and the comments around the old value
+        Some(item) => {
+            if let Some(old_value) = item.as_value() {
+                *value.decor_mut() = old_value.decor().clone();
+            }
+            *item = toml_edit::Item::Value(value);
+        }
+        None => {
+            table.insert(last, toml_edit::Item::Value(value));
+        }
+    }
+
+    Ok(document.to_string())
+}
+
+fn toml_edit_value(value: &toml::Value) -> toml_edit::Value {
+    match value {
+        toml::Value::String(x) => x.into(),
+        toml::Value::Integer(x) => (*x).into(),
+        toml::Value::Float(x) => (*x).into(),
+        toml::Value::Boolean(x) => (*x).into(),
+        toml::Value::Datetime(x) => x
+            .to_string()
+            .parse()
+            .unwrap_or_else(|_| x.to_string().into()),
+        toml::Value::Array(array) => array
+            .iter()
+            .map(toml_edit_value)
+            .collect::<toml_edit::Array>()
+            .into(),
+        toml::Value::Table(table) => table
+            .iter()
+            .map(|(k, v)| (k.clone(), toml_edit_value(v)))
+            .collect::<toml_edit::InlineTable>()
+            .into(),
+    }
+}
+
+/// Get the configuration directory path for the specified document root.
+/// Parse the text of the configuration file at `cfg_path`. Errors are
+/// reported with their locations by [`CfgParseError`].
+pub fn parse_cfg_toml<T: serde::de::DeserializeOwned>(
+    cfg_path: &Path,
+    cfg_toml: &str,
+) -> Result<T> {
+    toml::de::from_str(cfg_toml).map_err(|e: toml::de::Error| {
+        let message = e.to_string();
+        let (error, line_col, suggestion) = match UnknownField::parse(&message) {
+            // `toml` reports the location of the enclosing table (at best), so
+            // find the key by ourselves
+            Some(unknown) => {
+                let line_col = unknown.locate(cfg_toml).or_else(|| e.line_col());
+                (
+                    Error::msg(unknown.describe(line_col)),
+                    line_col,
+                    unknown.suggestion().map(str::to_owned),
+                )
+            }
+            None => {
+                let line_col = e.line_col();
+                (Error::new(e), line_col, None)
+            }
+        };
+        let (line, column) = line_col.map_or((None, None), |(l, c)| (Some(l), Some(c)));
+        error.context(CfgParseError {
+            path: cfg_path.to_owned(),
+            line,
+            column,
+            suggestion,
+        })
+    })
+}
+
+/// An unknown key rejected by `#[serde(deny_unknown_fields)]`
+#[derive(Debug, PartialEq)]
+struct UnknownField<'a> {
+    /// The dotted key of the table containing the key. Empty for the
+    /// top-level table.
+    table: Vec<&'a str>,
+    key: &'a str,
+    /// The known keys of the table
+    expected: Vec<&'a str>,
+}
+
+impl<'a> UnknownField<'a> {
+    /// Parse an error message of `toml`, such as "unknown field `fgg`,
+    /// expected one of `fg`, `bg` for key `theme.tag_default` at line 1
+    /// column 1".
+    fn parse(message: &'a str) -> Option<Self> {
+        let rest = message.strip_prefix("unknown field `")?;
+        let (key, rest) = rest.split_once("`, expected ")?;
+        let (expected, table) = match rest.split_once(" for key `") {
+            Some((expected, rest)) => (expected, rest.split_once('`')?.0.split('.').collect()),
+            None => (
+                rest.split_once(" at line ")
+                    .map_or(rest, |(expected, _)| expected),
+                Vec::new(),
+            ),
+        };
+        Some(Self {
+            table,
+            key,
+            // The odd-numbered parts are enclosed by backquotes
+            expected: expected.split('`').skip(1).step_by(2).collect(),
+        })
+    }
+
+    /// Get the known key most similar to the unknown key, if any is similar
+    /// enough.
+    fn suggestion(&self) -> Option<&'a str> {
+        self.expected
+            .iter()
+            .map(|&candidate| (strsim::jaro_winkler(self.key, candidate), candidate))
+            .filter(|&(similarity, _)| similarity > 0.8)
+            .max_by(|(s1, _), (s2, _)| s1.partial_cmp(s2).unwrap())
+            .map(|(_, candidate)| candidate)
+    }
+
+    /// Find the zero-based line and column numbers of the unknown key in
+    /// `cfg_toml`.
+    fn locate(&self, cfg_toml: &str) -> Option<(usize, usize)> {
+        let document = toml_edit::ImDocument::parse(cfg_toml).ok()?;
+        let mut table = document.as_item().as_table_like()?;
+        for key in self.table.iter() {
+            table = table.get(key)?.as_table_like()?;
+        }
+        let (key, _) = table.get_key_value(self.key)?;
+        let before = &cfg_toml[..key.span()?.start];
+        let line = before.matches('\n').count();
+        let column = before.rsplit('\n').next().unwrap_or("").chars().count();
+        Some((line, column))
+    }
+
+    /// Format an error message. `line_col` is the zero-based location of the
+    /// key, if known.
+    fn describe(&self, line_col: Option<(usize, usize)>) -> String {
+        let mut message = format!("unknown key `{}`", self.key);
+        if !self.table.is_empty() {
+            message += &format!(" in `[{}]`", self.table.join("."));
+        }
+        if let Some((line, column)) = line_col {
+            message += &format!(" at line {} column {}", line + 1, column + 1);
+        }
+        if let Some(suggestion) = self.suggestion() {
+            message += &format!("; did you mean `{}`?", suggestion);
+        } else if !self.expected.is_empty() {
+            let expected: Vec<String> = self.expected.iter().map(|k| format!("`{}`", k)).collect();
+            message += &format!("; expected one of {}", expected.join(", "));
+        }
+        message
+    }
+}
+
+/// Convert a path in the configuration to the native form. Backslashes are
+/// treated as path separators on all platforms, so the same configuration
+/// written on Windows works elsewhere.
+fn portable_path(path: &str) -> Cow<'_, str> {
+    if cfg!(windows) || !path.contains('\\') {
+        Cow::Borrowed(path)
+    } else {
+        Cow::Owned(path.replace('\\', "/"))
+    }
+}
+
+/// Translate a pattern in `files` to the syntax of [`::globwalk`], which uses
+/// `/` as the path separator and `\` as the escape character on Unix.
+/// Backslashes are treated as path separators on all platforms; use a
+/// character class (e.g., `[*]`) to match a metacharacter literally.
+pub fn glob_pattern(pattern: &str) -> Cow<'_, str> {
+    if pattern.contains('\\') {
+        Cow::Owned(pattern.replace('\\', "/"))
+    } else {
+        Cow::Borrowed(pattern)
+    }
+}
+
+/// Remove the verbatim prefix (`\\?\`) that [`Path::canonicalize`] adds on
+/// Windows. Many programs (including editors launched on documents) don't
+/// understand verbatim paths, and they don't compare equal to the paths users
+/// type. The prefix is kept if the path isn't expressible without it.
+fn strip_verbatim_prefix(path: PathBuf) -> PathBuf {
+    if !cfg!(windows) {
+        return path;
+    }
+    match path.to_str().and_then(strip_verbatim_prefix_str) {
+        Some(stripped) => stripped.into(),
+        None => path,
+    }
+}
+
+fn strip_verbatim_prefix_str(path: &str) -> Option<String> {
+    if let Some(rest) = path.strip_prefix(r"\\?\UNC\") {
+        // `\\?\UNC\server\share\dir` → `\\server\share\dir`
+        Some(format!(r"\\{}", rest))
+    } else {
+        let rest = path.strip_prefix(r"\\?\")?;
+        // Only drive paths such as `C:\dir` can be expressed without the prefix
+        let bytes = rest.as_bytes();
+        let is_drive_path = bytes.len() >= 2
+            && bytes[0].is_ascii_alphabetic()
+            && bytes[1] == b':'
+            && matches!(bytes.get(2), None | Some(b'\\'));
+        // Verbatim paths may contain components that are invalid otherwise
+        let reserved = rest.split('\\').any(|c| c.ends_with(['.', ' ']));
+        (is_drive_path && !reserved && rest.len() < 260).then(|| rest.to_owned())
+    }
+}
+
+/// Identifies a file regardless of the path it was found through: the device
+/// and inode numbers on Unix, or the canonical path elsewhere
+#[cfg(unix)]
+pub(crate) type FileId = (u64, u64);
+#[cfg(not(unix))]
+pub(crate) type FileId = PathBuf;
+
+/// Get the [`FileId`] of the file at `path`, following symbolic links.
+#[cfg(unix)]
+pub(crate) fn file_id(path: &Path) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn file_id(path: &Path) -> Option<FileId> {
+    std::fs::canonicalize(path).ok()
+}
+
+/// Get the [`FileId`] of the file a walker entry refers to, following
+/// symbolic links.
+#[cfg(unix)]
+fn entry_file_id(entry: &globwalk::DirEntry) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+    if entry.file_type().is_symlink() {
+        // A symbolic link the walker didn't follow
+        return file_id(entry.path());
+    }
+    let metadata = entry.metadata().ok()?;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn entry_file_id(entry: &globwalk::DirEntry) -> Option<FileId> {
+    file_id(entry.path())
+}
+
+fn cfg_dir_path_for_doc_root_path(doc_root_path: &Path) -> PathBuf {
+    doc_root_path.join(".veisku")
+}
+
+/// Get the configuration path for the specified document root.
+pub fn cfg_file_path_for_doc_root_path(doc_root_path: &Path) -> PathBuf {
+    doc_root_path.join(".veisku/config.toml")
+}
+
+impl DocRoot {
+    /// Return an iterator over the document files in the document root.
+    /// Files excluded by `.veiskuignore` files are skipped.
+    ///
+    /// Each file is yielded only for the first path found to reach it (e.g.,
+    /// through a symbolic link). If `follow_symlinks` is enabled, symbolic
+    /// links forming a loop are skipped.
+    pub fn doc_files(&self) -> impl Iterator<Item = Result<globwalk::DirEntry, Error>> {
+        let mut ignore_files = IgnoreFiles::new(&self.path);
+        // The files yielded so far
+        let mut visited = HashSet::new();
+        let patterns: Vec<Cow<'_, str>> = self.cfg.files.iter().map(|p| glob_pattern(p)).collect();
+        let mut builder = globwalk::GlobWalkerBuilder::from_patterns(&self.path, &patterns)
+            .follow_links(self.cfg.follow_symlinks);
+        if let Some(max_depth) = self.cfg.max_depth {
+            // `walkdir` counts the files directly in the root as depth 1
+            builder = builder.max_depth(max_depth.saturating_add(1));
+        }
+        match builder.build() {
+            Ok(mut it) => Left(
+                std::iter::from_fn(move || {
+                    timing::measure(Phase::Walk, || {
+                        it.by_ref().find(|e| match e {
+                            Ok(e) => {
+                                !ignore_files.is_ignored(e.path())
+                                    && entry_file_id(e).is_none_or(|id| visited.insert(id))
+                            }
+                            Err(e) if e.loop_ancestor().is_some() => {
+                                log::warn!(
+                                    "Skipping {:?}, which is a symbolic link to its ancestor",
+                                    e.path().unwrap_or(Path::new(""))
+                                );
+                                false
+                            }
+                            Err(_) => true,
+                        })
+                    })
+                })
+                .inspect(|_| timing::count(Counter::FilesScanned))
+                .map(|e| e.map_err(Into::into)),
+            ),
+            Err(e) => Right(std::iter::once(Err(e.into()))),
+        }
+    }
+
+    /// Return an iterator over the `DocRead` objects representing the document
+    /// files in the document root.
+    pub fn docs(&self) -> impl Iterator<Item = Result<DocRead, Error>> + '_ {
+        self.doc_files()
+            .map(move |entry_or_err| entry_or_err.map(|entry| self.doc_at(entry.into_path())))
+    }
+
+    /// Construct a `DocRead` object for the document file at `path`.
+    pub fn doc_at(&self, path: PathBuf) -> DocRead {
+        let decrypt_cmd = self.cfg.encryption_for(&path).map(|e| e.decrypt.clone());
+        DocRead::new(path)
+            .with_decrypt_cmd(decrypt_cmd)
+            .with_skip_errors(self.cfg.skip_errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cfg_override() {
+        let mut table: toml::value::Table = toml::de::from_str("[new]\nid = \"uuid\"").unwrap();
+        for s in &["new.name={slug}.md", "files=[\"*.txt\"]", "writable=true"] {
+            s.parse::<CfgOverride>().unwrap().apply(&mut table).unwrap();
+        }
+        let cfg: Cfg = toml::Value::Table(table).try_into().unwrap();
+        assert_eq!(cfg.new.name, "{slug}.md");
+        assert_eq!(cfg.files, ["*.txt"]);
+        assert!(cfg.writable);
+
+        let mut table = toml::value::Table::new();
+        table.insert("files".to_owned(), toml::Value::Array(vec![]));
+        let cfg_override: CfgOverride = "files.x=1".parse().unwrap();
+        assert!(cfg_override.apply(&mut table).is_err());
+
+        let cfg_override: CfgOverride = r#"openers."a=b/*" = ["x"]"#.parse().unwrap();
+        assert_eq!(cfg_override.key, ["openers", "a=b/*"]);
+        assert!("=1".parse::<CfgOverride>().is_err());
+    }
+
+    #[test]
+    fn test_unknown_field() {
+        let cfg_toml = "writable = true\n\n[theme]\nhighlight = { bold = true }\n\n  tag_defualt.fg = \"red\"\n";
+        let e = parse_cfg_toml::<Cfg>(Path::new("config.toml"), cfg_toml).unwrap_err();
+        let cfg_e = e.downcast_ref::<CfgParseError>().unwrap();
+        assert_eq!((cfg_e.line, cfg_e.column), (Some(5), Some(2)));
+        assert_eq!(cfg_e.suggestion.as_deref(), Some("tag_default"));
+        assert_eq!(
+            e.root_cause().to_string(),
+            "unknown key `tag_defualt` in `[theme]` at line 6 column 3; did you mean `tag_default`?"
+        );
+
+        let unknown = UnknownField::parse("unknown field `xyz`, expected `a` or `b`").unwrap();
+        assert_eq!(
+            unknown,
+            UnknownField {
+                table: vec![],
+                key: "xyz",
+                expected: vec!["a", "b"]
+            }
+        );
+        assert_eq!(unknown.suggestion(), None);
+        assert_eq!(
+            unknown.describe(None),
+            "unknown key `xyz`; expected one of `a`, `b`"
+        );
+        assert_eq!(UnknownField::parse("invalid type: string"), None);
+    }
+
+    #[test]
+    fn test_edit_cfg_toml() {
+        let cfg_toml = "# Comment\nwritable = true # why\n\n[new]\nid = \"uuid\"\n";
+        let edit = |s: &str| edit_cfg_toml(cfg_toml, &s.parse().unwrap());
+        assert_eq!(
+            edit("writable=false").unwrap(),
+            "# Comment\nwritable = false # why\n\n[new]\nid = \"uuid\"\n"
+        );
+        assert_eq!(
+            edit("theme.tag_default.fg=#ff8800").unwrap(),
+            format!("{}\n[theme.tag_default]\nfg = \"#ff8800\"\n", cfg_toml)
+        );
+        assert!(edit("new.id=bogus").is_err());
+        assert!(edit("writable.x=1").is_err());
+    }
+
+    #[test]
+    fn test_glob_pattern() {
+        assert_eq!(glob_pattern("*.md"), "*.md");
+        assert_eq!(glob_pattern(r"!journal\**\*.tmp"), "!journal/**/*.tmp");
+    }
+
+    #[test]
+    fn test_strip_verbatim_prefix_str() {
+        let strip = |s| strip_verbatim_prefix_str(s);
+        assert_eq!(strip(r"\\?\C:\notes").as_deref(), Some(r"C:\notes"));
+        assert_eq!(strip(r"\\?\D:").as_deref(), Some("D:"));
+        assert_eq!(
+            strip(r"\\?\UNC\server\share\vault").as_deref(),
+            Some(r"\\server\share\vault")
+        );
+        assert_eq!(strip(r"C:\notes"), None);
+        assert_eq!(strip(r"\\?\C:\notes\trailing."), None);
+        assert_eq!(strip(r"\\?\Volume{1234}\notes"), None);
+        assert_eq!(strip("/home/user/notes"), None);
+    }
+}
@@ -0,0 +1,269 @@
+//! Metadata schema validation (see [`crate::cfg::FieldCfg`])
+use serde_yaml::Value;
+use std::{cmp::Ordering, collections::BTreeMap};
+
+use crate::cfg::{FieldCfg, FieldType};
+
+/// A violation of the metadata schema
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub field: String,
+    pub message: String,
+}
+
+/// Check the metadata of a document against `schema`.
+pub fn validate(schema: &BTreeMap<String, FieldCfg>, meta: &Value) -> Vec<Violation> {
+    let mut violations = Vec::new();
+
+    for (field, field_cfg) in schema.iter() {
+        let mut report = |message: String| {
+            violations.push(Violation {
+                field: field.clone(),
+                message,
+            })
+        };
+
+        let value = match &meta[&**field] {
+            Value::Null => {
+                if field_cfg.required {
+                    report("The field is required but missing".to_owned());
+                }
+                continue;
+            }
+            value => value,
+        };
+
+        if let Some(ty) = field_cfg.ty {
+            if !has_type(value, ty) {
+                report(format!(
+                    "Expected {}, found {}",
+                    type_name(ty),
+                    describe(value)
+                ));
+                continue;
+            }
+        }
+
+        if let Some(allowed) = &field_cfg.values {
+            let elements = match value {
+                Value::Sequence(array) => &array[..],
+                _ => std::slice::from_ref(value),
+            };
+            for e in elements.iter() {
+                let text = scalar_text(e);
+                if !text.as_ref().is_some_and(|text| allowed.contains(text)) {
+                    report(format!(
+                        "{} is not one of the allowed values ({})",
+                        describe(e),
+                        allowed.join(", ")
+                    ));
+                }
+            }
+        }
+    }
+
+    violations
+}
+
+fn has_type(value: &Value, ty: FieldType) -> bool {
+    match ty {
+        FieldType::String => value.is_string(),
+        FieldType::Int => value.is_i64() || value.is_u64(),
+        FieldType::Number => value.is_number(),
+        FieldType::Bool => value.is_bool(),
+        FieldType::Date => value.as_str().is_some_and(is_date),
+        // The values are checked separately
+        FieldType::Enum => scalar_text(value).is_some(),
+        FieldType::List => value
+            .as_sequence()
+            .is_some_and(|array| array.iter().all(|e| !e.is_sequence() && !e.is_mapping())),
+        FieldType::ListString => value
+            .as_sequence()
+            .is_some_and(|array| array.iter().all(Value::is_string)),
+    }
+}
+
+fn type_name(ty: FieldType) -> &'static str {
+    match ty {
+        FieldType::String => "a string",
+        FieldType::Int => "an integer",
+        FieldType::Number => "a number",
+        FieldType::Bool => "a boolean",
+        FieldType::Date => "a date (YYYY-MM-DD)",
+        FieldType::Enum => "a scalar value",
+        FieldType::List => "a list",
+        FieldType::ListString => "a list of strings",
+    }
+}
+
+/// Describe a value for an error message.
+fn describe(value: &Value) -> String {
+    match value {
+        Value::Sequence(_) => "a sequence".to_owned(),
+        Value::Mapping(_) => "a mapping".to_owned(),
+        _ => serde_json::to_string(value).unwrap_or_else(|_| "?".to_owned()),
+    }
+}
+
+/// Compare a scalar field value with `operand` (a criterion value)
+/// according to the declared type of the field. Returns `None` if they are
+/// uncomparable.
+pub fn compare(field_cfg: Option<&FieldCfg>, value: &Value, operand: &str) -> Option<Ordering> {
+    let text = scalar_text(value)?;
+    let as_number = |st: &str| st.trim().parse::<f64>().ok();
+
+    match field_cfg.and_then(|f| f.ty) {
+        Some(FieldType::Int) | Some(FieldType::Number) => value
+            .as_f64()
+            .or_else(|| as_number(&text))?
+            .partial_cmp(&as_number(operand)?),
+        Some(FieldType::Bool) => {
+            let as_bool = |st: &str| st.parse::<bool>().ok();
+            Some(as_bool(&text)?.cmp(&as_bool(operand)?))
+        }
+        Some(FieldType::Date) => {
+            if is_date(&text) && is_date(operand) {
+                Some(text.as_str().cmp(operand))
+            } else {
+                None
+            }
+        }
+        Some(FieldType::Enum) => {
+            let values = field_cfg?.values.as_ref()?;
+            let index = |st: &str| values.iter().position(|x| x == st);
+            Some(index(&text)?.cmp(&index(operand)?))
+        }
+        Some(FieldType::String) | Some(FieldType::List) | Some(FieldType::ListString) => {
+            Some(text.as_str().cmp(operand))
+        }
+        None => match (as_number(&text), as_number(operand)) {
+            (Some(x), Some(y)) => x.partial_cmp(&y),
+            _ => Some(text.as_str().cmp(operand)),
+        },
+    }
+}
+
+/// Check if `operand` is a valid bound of a range (`KEY:LOW..HIGH`) on a
+/// field. Only fields declared as ordered types (`int`, `number`, `date`, and
+/// `enum`) have ranges, so that values like `title:a..b` stay literal.
+pub fn is_range_bound(field_cfg: Option<&FieldCfg>, operand: &str) -> bool {
+    match field_cfg.and_then(|f| f.ty) {
+        Some(FieldType::Int) | Some(FieldType::Number) => operand.trim().parse::<f64>().is_ok(),
+        Some(FieldType::Date) => is_date(operand),
+        Some(FieldType::Enum) => field_cfg
+            .and_then(|f| f.values.as_ref())
+            .is_some_and(|values| values.iter().any(|x| x == operand)),
+        _ => false,
+    }
+}
+
+/// Get the textual representation of a scalar value.
+pub fn scalar_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(st) => Some(st.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Check if `s` starts with a valid `YYYY-MM-DD` date, followed by nothing or
+/// a time (`T...` or ` ...`).
+pub fn is_date(s: &str) -> bool {
+    let date_len = s.len().min(10);
+    if !s.is_char_boundary(date_len) {
+        return false;
+    }
+    let (date, rest) = s.split_at(date_len);
+    if !(rest.is_empty() || rest.starts_with('T') || rest.starts_with(' ')) {
+        return false;
+    }
+
+    let bytes = date.as_bytes();
+    if bytes.len() != 10 || bytes[4] != b'-' || bytes[7] != b'-' {
+        return false;
+    }
+    let (year, month, day) = match (
+        date[..4].parse::<u32>(),
+        date[5..7].parse::<u32>(),
+        date[8..].parse::<u32>(),
+    ) {
+        (Ok(y), Ok(m), Ok(d)) => (y, m, d),
+        _ => return false,
+    };
+
+    let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+    let days_in_month = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return false,
+    };
+    (1..=days_in_month).contains(&day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_date() {
+        assert!(is_date("2020-11-20"));
+        assert!(is_date("2020-02-29T12:00:00Z"));
+        assert!(!is_date("2021-02-29"));
+        assert!(!is_date("2020-13-01"));
+        assert!(!is_date("2020-1-1"));
+        assert!(!is_date("2020-11-20x"));
+        assert!(!is_date("日本語日本語日本語"));
+    }
+
+    #[test]
+    fn test_compare() {
+        let int: FieldCfg = toml::de::from_str(r#"type = "int""#).unwrap();
+        let priority: FieldCfg =
+            toml::de::from_str("type = \"enum\"\nvalues = [\"low\", \"high\"]").unwrap();
+        let value = |st: &str| serde_yaml::from_str::<Value>(st).unwrap();
+
+        assert_eq!(
+            compare(Some(&int), &value("10"), "9"),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(compare(None, &value("10"), "9"), Some(Ordering::Greater));
+        assert_eq!(compare(None, &value("b"), "ab"), Some(Ordering::Greater));
+        assert_eq!(
+            compare(Some(&priority), &value("low"), "high"),
+            Some(Ordering::Less)
+        );
+        assert_eq!(compare(Some(&priority), &value("mid"), "high"), None);
+    }
+
+    #[test]
+    fn test_is_range_bound() {
+        let date: FieldCfg = toml::de::from_str(r#"type = "date""#).unwrap();
+        let string: FieldCfg = toml::de::from_str(r#"type = "string""#).unwrap();
+
+        assert!(is_range_bound(Some(&date), "2024-01-31"));
+        assert!(!is_range_bound(Some(&date), "a"));
+        assert!(!is_range_bound(Some(&string), "a"));
+        assert!(!is_range_bound(None, "1"));
+    }
+
+    #[test]
+    fn test_validate() {
+        let schema: BTreeMap<String, FieldCfg> = toml::de::from_str(
+            r#"
+            status = { required = true, values = ["todo", "done"] }
+            due = { type = "date" }
+            tags = { type = "list", values = ["a", "b"] }
+            "#,
+        )
+        .unwrap();
+        let meta: Value = serde_yaml::from_str("due: tomorrow\ntags: [a, c]").unwrap();
+        let fields: Vec<_> = validate(&schema, &meta)
+            .into_iter()
+            .map(|v| v.field)
+            .collect();
+        assert_eq!(fields, ["due", "status", "tags"]);
+    }
+}
@@ -0,0 +1,76 @@
+//! Saved searches (`.veisku/searches.toml`)
+//!
+//! ```toml
+//! work = ["tags:work", "!status:done"]
+//! ```
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::PathBuf};
+
+use crate::{cfg::Criterion, doc::write_atomic, lock::FileLock, root::DocRoot};
+
+/// The saved searches of a document root, keyed by name. Each search is
+/// a list of criteria in the command-line syntax.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SavedSearches {
+    pub searches: BTreeMap<String, Vec<String>>,
+}
+
+impl SavedSearches {
+    /// Read the saved searches of the specified document root. Returns an
+    /// empty set if the file doesn't exist.
+    pub fn load(root: &DocRoot) -> Result<Self> {
+        let path = file_path(root);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text =
+            std::fs::read_to_string(&path).with_context(|| format!("Failed to read {:?}", path))?;
+        toml::de::from_str(&text).with_context(|| format!("Failed to parse {:?}", path))
+    }
+
+    /// Write the saved searches to `.veisku/searches.toml`.
+    pub fn save(&self, root: &DocRoot) -> Result<()> {
+        let path = file_path(root);
+        std::fs::create_dir_all(&root.cfg_dir_path)
+            .with_context(|| format!("Failed to create {:?}", root.cfg_dir_path))?;
+        let text = toml::ser::to_string(self).context("Failed to serialize the saved searches")?;
+        write_atomic(&path, text.as_bytes())
+    }
+
+    /// Modify the saved searches by applying `f`. Other processes are
+    /// prevented from modifying them in the meantime.
+    pub fn update(root: &DocRoot, f: impl FnOnce(&mut Self) -> Result<()>) -> Result<()> {
+        std::fs::create_dir_all(&root.cfg_dir_path)
+            .with_context(|| format!("Failed to create {:?}", root.cfg_dir_path))?;
+        let _lock = FileLock::acquire(&file_path(root))?;
+        let mut searches = Self::load(root)?;
+        f(&mut searches)?;
+        searches.save(root)
+    }
+
+    /// Get the parsed criteria of the specified saved search.
+    pub fn criteria(&self, name: &str) -> Option<Result<Vec<Criterion>>> {
+        let criteria = self.searches.get(name)?;
+        Some(
+            criteria
+                .iter()
+                .map(|st| {
+                    st.parse().map_err(|e| {
+                        anyhow::anyhow!(
+                            "Invalid criterion {:?} in saved search '{}': {}",
+                            st,
+                            name,
+                            e
+                        )
+                    })
+                })
+                .collect(),
+        )
+    }
+}
+
+fn file_path(root: &DocRoot) -> PathBuf {
+    root.cfg_dir_path.join("searches.toml")
+}
@@ -0,0 +1,280 @@
+//! The layout of machine-generated state in `.veisku/state`
+//!
+//! Everything veisku writes on its own (as opposed to the configuration
+//! written by users) lives in `.veisku/state`:
+//!
+//!  - `cache/` - caches, which can be removed at any time
+//!  - `history.jsonl` - the documents opened recently
+//!  - `journal/` - the operations that can be reverted by `v undo`
+//!  - `trash/` - the documents removed by `v rm`
+//!  - `daemon.sock` - the socket of `v daemon`
+//!
+//! The directory contains a `.gitignore` ignoring all of its contents, so it
+//! stays out of version control even if `.veisku` is committed. `VERSION`
+//! records the version of the layout, which is incremented on incompatible
+//! changes. The directory is created the first time veisku writes to it.
+//!
+//! The state can be stored in the user's cache directory instead, which is
+//! necessary if the document root is on a read-only mount (see
+//! [`StateDirCfg`]).
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+use crate::{cfg::StateDirCfg, doc, lock::FileLock};
+
+/// The current version of the layout
+pub const VERSION: u32 = 1;
+
+/// A component of the state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Component {
+    Cache,
+    History,
+    Journal,
+    Trash,
+}
+
+impl Component {
+    pub const ALL: [Self; 4] = [Self::Cache, Self::History, Self::Journal, Self::Trash];
+
+    /// The name of the file or directory in `.veisku/state`. Before the layout
+    /// was versioned, the component was stored directly in `.veisku` under the
+    /// same name.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Self::Cache => "cache",
+            Self::History => "history.jsonl",
+            Self::Journal => "journal",
+            Self::Trash => "trash",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Cache => "cache",
+            Self::History => "history",
+            Self::Journal => "journal",
+            Self::Trash => "trash",
+        }
+    }
+}
+
+impl std::str::FromStr for Component {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|c| c.name() == s)
+            .ok_or("Expected `cache`, `history`, `journal`, or `trash`")
+    }
+}
+
+/// Get the state directory in the configuration directory `cfg_dir_path`.
+pub fn dir_path(cfg_dir_path: &Path) -> PathBuf {
+    cfg_dir_path.join("state")
+}
+
+/// Get the state directory of the document root at `doc_root_path` in the
+/// user's cache directory. Returns `None` if the home directory is unknown.
+pub fn user_dir_path(doc_root_path: &Path) -> Option<PathBuf> {
+    let cache_dir = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+            Some(Path::new(&home).join(".cache"))
+        })?;
+    let digest = doc::content_digest(doc_root_path.to_string_lossy().as_bytes());
+    Some(cache_dir.join("veisku").join(&digest[..16]))
+}
+
+/// Decide the state directory of a document root.
+pub fn resolve(cfg_dir_path: &Path, doc_root_path: &Path, location: StateDirCfg) -> PathBuf {
+    let in_root = dir_path(cfg_dir_path);
+    let use_user_dir = match location {
+        StateDirCfg::Root => false,
+        StateDirCfg::User => true,
+        StateDirCfg::Auto => {
+            // The state directory itself might be read-only even if
+            // `.veisku` isn't
+            let dir = if in_root.is_dir() {
+                &in_root
+            } else {
+                cfg_dir_path
+            };
+            let read_only = dir.is_dir() && !is_writable(dir);
+            if read_only {
+                log::debug!(
+                    "{:?} is not writable; using the user's cache directory",
+                    dir
+                );
+            }
+            read_only
+        }
+    };
+    if use_user_dir {
+        match user_dir_path(doc_root_path) {
+            Some(path) => return path,
+            None => log::warn!(
+                "Could not determine the user's cache directory; storing the state in {:?}",
+                in_root
+            ),
+        }
+    }
+    in_root
+}
+
+fn is_writable(dir: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        let path = match std::ffi::CString::new(dir.as_os_str().as_bytes()) {
+            Ok(x) => x,
+            Err(_) => return false,
+        };
+        // Unlike the permission bits, this also detects read-only mounts
+        unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
+    }
+    #[cfg(not(unix))]
+    {
+        std::fs::metadata(dir).map_or(false, |m| !m.permissions().readonly())
+    }
+}
+
+/// The state directory of a document root
+///
+/// The directory is set up by [`Self::prepare`] the first time something is
+/// written to it, so commands that only read don't modify the document root.
+#[derive(Debug, Clone)]
+pub struct StateDir {
+    cfg_dir_path: PathBuf,
+    path: PathBuf,
+}
+
+impl StateDir {
+    pub fn new(cfg_dir_path: &Path, path: &Path) -> Self {
+        Self {
+            cfg_dir_path: cfg_dir_path.to_owned(),
+            path: path.to_owned(),
+        }
+    }
+
+    /// Get the path of the state directory. Might not exist.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the path of `component` for reading. Until the state directory is
+    /// set up, this is where older versions stored it, if it exists there.
+    pub fn component_path(&self, component: Component) -> PathBuf {
+        let path = self.path.join(component.file_name());
+        if self.path == dir_path(&self.cfg_dir_path) && !self.version_path().exists() {
+            let old_path = self.cfg_dir_path.join(component.file_name());
+            if old_path.exists() {
+                return old_path;
+            }
+        }
+        path
+    }
+
+    fn version_path(&self) -> PathBuf {
+        self.path.join("VERSION")
+    }
+
+    /// Warn if the state directory was written by a newer version.
+    pub fn check_version(&self) {
+        let text = match std::fs::read_to_string(self.version_path()) {
+            Ok(text) => text,
+            Err(_) => return,
+        };
+        match text.trim().parse::<u32>() {
+            Ok(version) if version <= VERSION => {}
+            _ => log::warn!(
+                "{:?} was written by a newer version of veisku (version {:?}); \
+                run `v state clear` if it causes problems",
+                self.path,
+                text.trim()
+            ),
+        }
+    }
+
+    /// Set up the state directory before writing to it. Does nothing if the
+    /// configuration directory doesn't exist. If the directory is in the
+    /// configuration directory, the state stored there by older versions is
+    /// moved into it.
+    pub fn prepare(&self) -> Result<()> {
+        let (cfg_dir_path, dir) = (&self.cfg_dir_path, &self.path);
+        if !cfg_dir_path.is_dir() {
+            // Creating the configuration directory would change the document root
+            return Ok(());
+        }
+
+        let version_path = self.version_path();
+        if version_path.exists() {
+            return Ok(());
+        }
+
+        if let Some(parent) = dir.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {:?}", parent))?;
+        }
+        let _lock = FileLock::acquire(dir)?;
+        // Another process might have set it up before the lock was acquired
+        if version_path.exists() {
+            return Ok(());
+        }
+
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+
+        if *dir == dir_path(cfg_dir_path) {
+            let gitignore_path = dir.join(".gitignore");
+            std::fs::write(&gitignore_path, "*\n")
+                .with_context(|| format!("Failed to write {:?}", gitignore_path))?;
+
+            for component in Component::ALL.iter() {
+                let old_path = cfg_dir_path.join(component.file_name());
+                let new_path = dir.join(component.file_name());
+                if old_path.exists() && !new_path.exists() {
+                    log::info!("Moving {:?} to {:?}", old_path, new_path);
+                    std::fs::rename(&old_path, &new_path).with_context(|| {
+                        format!("Failed to move {:?} to {:?}", old_path, new_path)
+                    })?;
+                }
+            }
+        }
+
+        std::fs::write(&version_path, format!("{}\n", VERSION))
+            .with_context(|| format!("Failed to write {:?}", version_path))?;
+        Ok(())
+    }
+}
+
+/// Remove a component of the state in the state directory `dir`. Returns
+/// `false` if it didn't exist.
+pub fn clear(dir: &Path, component: Component) -> Result<bool> {
+    let path = dir.join(component.file_name());
+    let result = if path.is_dir() {
+        std::fs::remove_dir_all(&path)
+    } else {
+        std::fs::remove_file(&path)
+    };
+    match result {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e).with_context(|| format!("Failed to remove {:?}", path)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_component_from_str() {
+        for component in Component::ALL.iter() {
+            assert_eq!(component.name().parse(), Ok(*component));
+        }
+        assert!("config".parse::<Component>().is_err());
+    }
+}
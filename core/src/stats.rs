@@ -0,0 +1,87 @@
+//! Text statistics of documents (word counts) and their cache
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{cache::FileCache, doc::DocRead, root::DocRoot};
+
+/// The reading speed assumed by the `reading_time` pseudo-field, in words
+/// per minute
+pub const WORDS_PER_MINUTE: usize = 200;
+
+/// The names of the pseudo-fields computed from document bodies. Fields of
+/// the same names in the metadata take precedence over them.
+pub const PSEUDO_FIELDS: &[&str] = &["words", "reading_time"];
+
+/// Text statistics of a document body
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TextStats {
+    /// The number of whitespace-separated words containing at least one
+    /// alphanumeric character (so that Markdown markers like `-` and `#`
+    /// aren't counted)
+    pub words: usize,
+    /// The number of characters
+    pub chars: usize,
+}
+
+impl TextStats {
+    pub fn of(text: &str) -> Self {
+        Self {
+            words: text
+                .split_whitespace()
+                .filter(|word| word.chars().any(char::is_alphanumeric))
+                .count(),
+            chars: text.chars().count(),
+        }
+    }
+
+    /// The estimated reading time in minutes (rounded up).
+    pub fn reading_time(&self) -> usize {
+        self.words.div_ceil(WORDS_PER_MINUTE)
+    }
+
+    /// Get the value of a pseudo-field (see [`PSEUDO_FIELDS`]).
+    pub fn field(&self, name: &str) -> Option<usize> {
+        match name {
+            "words" => Some(self.words),
+            "reading_time" => Some(self.reading_time()),
+            _ => None,
+        }
+    }
+}
+
+/// A cache of [`TextStats`] stored in `.veisku/state/cache/stats.json`. See
+/// [`FileCache`].
+#[derive(Debug)]
+pub struct StatsCache(FileCache<TextStats>);
+
+impl StatsCache {
+    pub fn load(root: &DocRoot) -> Self {
+        Self(FileCache::load(root, "stats.json"))
+    }
+
+    /// Get the statistics of a document, computing them if necessary.
+    pub fn get(&self, doc: &DocRead) -> Result<TextStats> {
+        self.0.get(doc.path(), || {
+            let body = doc.read_body()?;
+            Ok(TextStats::of(&String::from_utf8_lossy(&body)))
+        })
+    }
+
+    /// Write the cache back if it was modified.
+    pub fn save(&self) -> Result<()> {
+        self.0.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_stats() {
+        let stats = TextStats::of("# Title\n\n - one, two\n - three\n");
+        assert_eq!(stats.words, 4);
+        assert_eq!(stats.reading_time(), 1);
+        assert_eq!(TextStats::of("").reading_time(), 0);
+    }
+}
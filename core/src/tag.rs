@@ -0,0 +1,43 @@
+//! Hierarchical tags
+//!
+//! Tags containing `/` form a hierarchy: `project/alpha` is a descendant of
+//! `project`.
+
+/// The separator of the components of a hierarchical tag
+pub const SEPARATOR: char = '/';
+
+/// Check if `tag` is `ancestor` or its descendant.
+pub fn is_within(tag: &str, ancestor: &str) -> bool {
+    match tag.strip_prefix(ancestor) {
+        Some(rest) => rest.is_empty() || rest.starts_with(SEPARATOR),
+        None => false,
+    }
+}
+
+/// Iterate over `tag` and its ancestors, from the longest to the shortest
+/// (e.g., `a/b/c`, `a/b`, `a`).
+pub fn ancestors(tag: &str) -> impl Iterator<Item = &str> {
+    std::iter::successors(Some(tag), |tag| tag.rfind(SEPARATOR).map(|i| &tag[..i]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_within() {
+        assert!(is_within("project", "project"));
+        assert!(is_within("project/alpha", "project"));
+        assert!(!is_within("projects", "project"));
+        assert!(!is_within("project", "project/alpha"));
+    }
+
+    #[test]
+    fn test_ancestors() {
+        assert_eq!(
+            ancestors("a/b/c").collect::<Vec<_>>(),
+            ["a/b/c", "a/b", "a"]
+        );
+        assert_eq!(ancestors("a").collect::<Vec<_>>(), ["a"]);
+    }
+}
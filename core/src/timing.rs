@@ -0,0 +1,108 @@
+//! Phase timings and counters reported by `--timing`
+//!
+//! Measurements are recorded only after [`enable`] is called, so they cost
+//! nothing otherwise.
+use std::{
+    fmt::Write as _,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy)]
+pub enum Phase {
+    RootDiscovery,
+    /// Enumerating document files
+    Walk,
+    /// Evaluating search criteria, including the metadata reads they cause
+    Matching,
+    /// Reading preambles, in whichever phase it happens
+    MetaRead,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Counter {
+    FilesScanned,
+    PreamblesParsed,
+    CacheHits,
+    CacheMisses,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU64 = AtomicU64::new(0);
+static PHASE_NANOS: [AtomicU64; 4] = [ZERO; 4];
+static COUNTERS: [AtomicU64; 4] = [ZERO; 4];
+
+/// Start recording measurements.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Call `f`, adding the time it takes to `phase`.
+pub fn measure<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    if !is_enabled() {
+        return f();
+    }
+    let start = Instant::now();
+    let result = f();
+    let nanos = start.elapsed().as_nanos() as u64;
+    PHASE_NANOS[phase as usize].fetch_add(nanos, Ordering::Relaxed);
+    result
+}
+
+/// Increment `counter`.
+pub fn count(counter: Counter) {
+    if is_enabled() {
+        COUNTERS[counter as usize].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn phase_time(phase: Phase) -> Duration {
+    Duration::from_nanos(PHASE_NANOS[phase as usize].load(Ordering::Relaxed))
+}
+
+fn counter(counter: Counter) -> u64 {
+    COUNTERS[counter as usize].load(Ordering::Relaxed)
+}
+
+/// Format the measurements. `total` is the wall-clock time of the whole
+/// command, and the time not attributed to any phase is reported as
+/// rendering (which includes waiting for the pager).
+pub fn report(total: Duration) -> String {
+    let ms = |d: Duration| format!("{:>10.3} ms", d.as_secs_f64() * 1000.0);
+    let root_discovery = phase_time(Phase::RootDiscovery);
+    let walk = phase_time(Phase::Walk);
+    let matching = phase_time(Phase::Matching);
+    let rest = total
+        .checked_sub(root_discovery + walk + matching)
+        .unwrap_or_default();
+
+    let mut out = String::new();
+    writeln!(out, "root discovery     {}", ms(root_discovery)).unwrap();
+    writeln!(out, "file walk          {}", ms(walk)).unwrap();
+    writeln!(out, "matching           {}", ms(matching)).unwrap();
+    writeln!(out, "rendering, other   {}", ms(rest)).unwrap();
+    writeln!(out, "total              {}", ms(total)).unwrap();
+    // Metadata is read lazily, so this overlaps matching and rendering
+    writeln!(
+        out,
+        "metadata reads     {} (included above)",
+        ms(phase_time(Phase::MetaRead))
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "files scanned: {}, preambles parsed: {}, cache hits: {}, cache misses: {}",
+        counter(Counter::FilesScanned),
+        counter(Counter::PreamblesParsed),
+        counter(Counter::CacheHits),
+        counter(Counter::CacheMisses),
+    )
+    .unwrap();
+    out
+}
@@ -0,0 +1,138 @@
+//! The trash of removed documents, stored in `.veisku/state/trash`
+//!
+//! Each removed document is moved to `.veisku/state/trash/ID` (without an
+//! extension, so that it's not recognized as a document) and recorded in
+//! `.veisku/state/trash/index.jsonl` along with its original path.
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    doc, history,
+    lock::FileLock,
+    root::DocRoot,
+    state::{Component, StateDir},
+};
+
+/// A document in the trash
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrashEntry {
+    /// The name of the file in `.veisku/state/trash`
+    pub id: String,
+    /// The original path of the document relative to the document root
+    pub path: PathBuf,
+    /// The time when the document was removed, in seconds since the Unix
+    /// epoch
+    pub time: i64,
+}
+
+/// The trash of a document root
+#[derive(Debug)]
+pub struct Trash {
+    state: StateDir,
+    root_path: PathBuf,
+}
+
+impl Trash {
+    pub fn new(root: &DocRoot) -> Self {
+        Self {
+            state: root.state_dir(),
+            root_path: root.path.clone(),
+        }
+    }
+
+    fn dir(&self) -> PathBuf {
+        self.state.component_path(Component::Trash)
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.dir().join("index.jsonl")
+    }
+
+    /// Get the documents in the trash in the order of removal. Malformed
+    /// lines are ignored.
+    pub fn entries(&self) -> Result<Vec<TrashEntry>> {
+        let index_path = self.index_path();
+        let text = match std::fs::read_to_string(&index_path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", index_path)),
+        };
+        Ok(parse_entries(&text))
+    }
+
+    /// Move the document at `path` to the trash.
+    pub fn put(&self, path: &Path) -> Result<TrashEntry> {
+        self.state.prepare()?;
+        let dir = self.dir();
+        std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {:?}", dir))?;
+
+        let _lock = FileLock::acquire(&self.index_path())?;
+        let time = history::now();
+        let (id, trash_path) = (1..)
+            .map(|i| {
+                let id = format!("{}-{}", time, i);
+                let trash_path = dir.join(&id);
+                (id, trash_path)
+            })
+            .find(|(_, trash_path)| !trash_path.exists())
+            .unwrap();
+        let entry = TrashEntry {
+            id,
+            path: path
+                .strip_prefix(&self.root_path)
+                .unwrap_or(path)
+                .to_owned(),
+            time,
+        };
+
+        // Record the entry first so that a document is never lost without
+        // a trace
+        let index_path = self.index_path();
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&index_path)
+            .with_context(|| format!("Failed to open {:?}", index_path))?;
+        writeln!(file, "{}", serde_json::to_string(&entry).unwrap())
+            .with_context(|| format!("Failed to write {:?}", index_path))?;
+        drop(file);
+
+        doc::move_file(path, &trash_path)?;
+        Ok(entry)
+    }
+
+    /// Move a document in the trash back to its original path, which is
+    /// returned.
+    pub fn restore(&self, entry: &TrashEntry) -> Result<PathBuf> {
+        self.state.prepare()?;
+        let _lock = FileLock::acquire(&self.index_path())?;
+        let path = self.root_path.join(&entry.path);
+        if path.exists() {
+            bail!("{:?} already exists", path);
+        }
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+        }
+        doc::move_file(&self.dir().join(&entry.id), &path)?;
+
+        let mut text = String::new();
+        for other in self.entries()? {
+            if other.id != entry.id {
+                text += &serde_json::to_string(&other).unwrap();
+                text.push('\n');
+            }
+        }
+        doc::write_atomic(&self.index_path(), text.as_bytes())?;
+        Ok(path)
+    }
+}
+
+fn parse_entries(text: &str) -> Vec<TrashEntry> {
+    text.lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
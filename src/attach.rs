@@ -0,0 +1,147 @@
+//! Attachment management (`v attach`)
+use anyhow::{bail, Context, Result};
+use serde_yaml::Value;
+use std::{convert::Infallible, path::PathBuf};
+use veisku_core::{doctype::DocType, journal::Journal};
+
+use crate::{cfg, doc::DocRead, root::DocRoot};
+
+/// The metadata field listing the attachments of a document.
+const ATTACHMENTS_KEY: &str = "attachments";
+
+pub fn verb_attach(root: &DocRoot, sc: &cfg::Attach) -> Result<()> {
+    match &sc.subcmd {
+        cfg::AttachSubcommand::Add(sc) => verb_attach_add(root, sc),
+        cfg::AttachSubcommand::Ls(sc) => verb_attach_ls(root, sc),
+        cfg::AttachSubcommand::Open(sc) => verb_attach_open(root, sc).map(|x| match x {}),
+    }
+}
+
+fn verb_attach_add(root: &DocRoot, sc: &cfg::AttachAdd) -> Result<()> {
+    root.ensure_writable()?;
+
+    let doc = sc.query.select_one(root)?;
+
+    let dir = attachment_dir_path(root, &doc);
+
+    let mut attachments = Vec::with_capacity(sc.files.len());
+    for src in sc.files.iter() {
+        let file_name = src
+            .file_name()
+            .with_context(|| format!("{:?} does not have a file name", src))?;
+        let dst = dir.join(file_name);
+        if dst.exists() || attachments.iter().any(|(_, other)| *other == dst) {
+            bail!("{:?} already exists", dst);
+        }
+        attachments.push((src, dst));
+    }
+
+    // Prepare the new contents of the document before touching any files so
+    // that a malformed preamble doesn't leave unreferenced attachments behind
+    let doc_type = DocType::detect(doc.path());
+    if !doc_type.has_preamble() {
+        bail!(
+            "Editing the metadata of {} documents is not supported: {:?}",
+            doc_type,
+            doc.path()
+        );
+    }
+    let bytes =
+        std::fs::read(doc.path()).with_context(|| format!("Failed to read {:?}", doc.path()))?;
+    let new_entries = attachments
+        .iter()
+        .map(|(_, dst)| {
+            Value::String(
+                dst.strip_prefix(&root.path)
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned(),
+            )
+        })
+        .collect();
+    let new_bytes = crate::doc::rewrite_meta(&bytes, |meta| {
+        let key = Value::String(ATTACHMENTS_KEY.to_owned());
+        match meta.get_mut(&key) {
+            Some(Value::Sequence(array)) => array.extend(new_entries),
+            Some(Value::Null) | None => {
+                meta.insert(key, Value::Sequence(new_entries));
+            }
+            Some(_) => bail!("The field '{}' is not a sequence", ATTACHMENTS_KEY),
+        }
+        Ok(())
+    })
+    .with_context(|| format!("Failed to record the attachments in {:?}", doc.path()))?;
+
+    // If this fails partway, the changes made so far can be reverted by
+    // `v undo`
+    let journal = Journal::new(root);
+    let mut recorder = journal.begin(format!("attach add ({} file(s))", attachments.len()));
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {:?}", dir))?;
+    for (src, dst) in attachments.iter() {
+        if sc.copy {
+            let contents =
+                std::fs::read(src).with_context(|| format!("Failed to read {:?}", src))?;
+            recorder.create(dst, &contents)?;
+        } else {
+            recorder.move_file(src, dst)?;
+        }
+        log::info!("Attached {:?} as {:?}", src, dst);
+    }
+    recorder
+        .write_atomic(doc.path(), &new_bytes)
+        .with_context(|| format!("Failed to record the attachments in {:?}", doc.path()))?;
+    recorder.commit()
+}
+
+fn verb_attach_ls(root: &DocRoot, sc: &cfg::Query) -> Result<()> {
+    let mut doc = sc.select_one(root)?;
+
+    for path in attachment_paths(root, &mut doc)? {
+        println!("{}", path.display());
+    }
+
+    Ok(())
+}
+
+fn verb_attach_open(root: &DocRoot, sc: &cfg::AttachOpen) -> Result<Infallible> {
+    let mut doc = sc.query.select_one(root)?;
+
+    let mut paths = attachment_paths(root, &mut doc)?;
+    if let Some(name) = &sc.name {
+        paths.retain(|path| path.file_name().is_some_and(|x| x == &**name));
+    }
+
+    match paths.len() {
+        0 => bail!("{} has no matching attachments", doc),
+        1 => crate::open_path(root, &paths[0], sc.cmd.as_deref(), sc.preserve_pwd, || {
+            crate::OpenMode::Open.default_cmd_for(root, &[&paths[0]])
+        }),
+        _ => {
+            let mut msg = String::from("Ambigous attachment selection. Candidates:");
+            for path in paths.iter() {
+                msg += &format!("\n - {}", path.display());
+            }
+            bail!("{}", msg)
+        }
+    }
+}
+
+/// Get the directory where the attachments of `doc` are stored.
+fn attachment_dir_path(root: &DocRoot, doc: &DocRead) -> PathBuf {
+    root.path
+        .join("attachments")
+        .join(doc.path().file_stem().unwrap())
+}
+
+/// Get the paths of the attachments recorded in the metadata of `doc`.
+fn attachment_paths(root: &DocRoot, doc: &mut DocRead) -> Result<Vec<PathBuf>> {
+    Ok(match &doc.ensure_meta()?[ATTACHMENTS_KEY] {
+        Value::Sequence(array) => array
+            .iter()
+            .filter_map(|e| e.as_str())
+            .map(|e| root.path.join(e))
+            .collect(),
+        Value::String(st) => vec![root.path.join(st)],
+        _ => Vec::new(),
+    })
+}
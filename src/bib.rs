@@ -0,0 +1,289 @@
+//! Bibliography export (`v bib` and `v export --to csl-json`)
+use anyhow::{Context, Result};
+use serde_yaml::Value;
+use std::{collections::BTreeMap, fmt::Write as _, io::Write};
+
+use crate::{cfg, query, root::DocRoot, schema};
+
+/// The BibTeX fields output by default, each read from the metadata field of
+/// the same name
+const DEFAULT_FIELDS: &[&str] = &[
+    "author",
+    "editor",
+    "title",
+    "booktitle",
+    "journal",
+    "publisher",
+    "school",
+    "year",
+    "month",
+    "volume",
+    "number",
+    "pages",
+    "edition",
+    "address",
+    "isbn",
+    "doi",
+    "url",
+    "note",
+];
+
+/// A bibliography entry extracted from a document
+pub struct BibEntry {
+    pub key: String,
+    /// The BibTeX entry type (e.g., `article`)
+    pub entry_type: String,
+    /// The BibTeX fields and their values
+    pub fields: Vec<(String, String)>,
+}
+
+/// Extract bibliography entries from the documents matching `query`.
+/// Documents without citation keys are skipped.
+pub fn entries(root: &DocRoot, query: &query::Query) -> Result<Vec<BibEntry>> {
+    let bib_cfg = &root.cfg.bib;
+
+    // BibTeX field -> metadata field
+    let mut fields: BTreeMap<&str, &str> = DEFAULT_FIELDS.iter().map(|&f| (f, f)).collect();
+    for (bib_field, meta_field) in &bib_cfg.fields {
+        if meta_field.is_empty() {
+            fields.remove(&**bib_field);
+        } else {
+            fields.insert(bib_field, meta_field);
+        }
+    }
+
+    let docs = query::select_all_sorted(root, query)
+        .context("An error occurred while enumerating matching documents")?;
+
+    let mut entries = Vec::new();
+    let mut num_skipped = 0;
+    for mut doc in docs {
+        let path = doc.path().to_owned();
+        let meta = doc
+            .ensure_meta()
+            .with_context(|| format!("Failed to read the metadata of {:?}", path))?;
+        let key = match schema::scalar_text(&meta[&*bib_cfg.key]) {
+            Some(key) => key,
+            None => {
+                log::debug!("Skipping {:?}, which has no `{}`", path, bib_cfg.key);
+                num_skipped += 1;
+                continue;
+            }
+        };
+        entries.push(BibEntry {
+            key,
+            entry_type: schema::scalar_text(&meta[&*bib_cfg.r#type])
+                .unwrap_or_else(|| "misc".to_owned()),
+            fields: fields
+                .iter()
+                .filter_map(|(&bib_field, &meta_field)| {
+                    Some((bib_field.to_owned(), bib_value(&meta[meta_field])?))
+                })
+                .collect(),
+        });
+    }
+
+    if num_skipped > 0 {
+        log::info!(
+            "Skipped {} document(s) without `{}`",
+            num_skipped,
+            bib_cfg.key
+        );
+    }
+
+    Ok(entries)
+}
+
+pub fn verb_bib(root: &DocRoot, sc: &cfg::Bib) -> Result<()> {
+    let query = sc.query.compile(root)?;
+    let entries = entries(root, &query)?;
+    let out = entries
+        .iter()
+        .map(render_entry)
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    match &sc.output {
+        Some(path) => {
+            std::fs::write(path, out).with_context(|| format!("Failed to write {:?}", path))?
+        }
+        None => std::io::stdout().write_all(out.as_bytes())?,
+    }
+    Ok(())
+}
+
+/// Convert a metadata value to a BibTeX field value. Sequences (e.g., lists
+/// of authors) are joined by ` and `.
+fn bib_value(value: &Value) -> Option<String> {
+    match value {
+        Value::Sequence(items) => {
+            let items: Vec<String> = items.iter().filter_map(schema::scalar_text).collect();
+            if items.is_empty() {
+                None
+            } else {
+                Some(items.join(" and "))
+            }
+        }
+        value => schema::scalar_text(value),
+    }
+}
+
+/// Render a BibTeX entry. Values are enclosed in braces, in which unbalanced
+/// braces would end the value, so all braces in values are escaped.
+fn render_entry(entry: &BibEntry) -> String {
+    let mut out = String::new();
+    writeln!(out, "@{}{{{},", entry.entry_type, entry.key).unwrap();
+    for (name, value) in &entry.fields {
+        let value = value.replace('{', "\\{").replace('}', "\\}");
+        writeln!(out, "  {} = {{{}}},", name, value).unwrap();
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Convert an entry to a CSL-JSON item (used by `v export --to csl-json`).
+pub fn to_csl(entry: &BibEntry) -> serde_json::Value {
+    let mut item = serde_json::Map::new();
+    item.insert("id".to_owned(), entry.key.clone().into());
+    item.insert("type".to_owned(), csl_type(&entry.entry_type).into());
+
+    let field = |name: &str| {
+        entry
+            .fields
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    };
+    for (name, value) in &entry.fields {
+        let (csl_name, csl_value) = match &**name {
+            "author" | "editor" => (name.as_str(), value.split(" and ").map(csl_name).collect()),
+            "journal" | "booktitle" => ("container-title", value.clone().into()),
+            "school" => ("publisher", value.clone().into()),
+            "address" => ("publisher-place", value.clone().into()),
+            "number" => ("issue", value.clone().into()),
+            "pages" => ("page", value.replace("--", "-").into()),
+            "isbn" => ("ISBN", value.clone().into()),
+            "doi" => ("DOI", value.clone().into()),
+            "url" => ("URL", value.clone().into()),
+            "year" | "month" => continue,
+            _ => (name.as_str(), value.clone().into()),
+        };
+        item.insert(csl_name.to_owned(), csl_value);
+    }
+
+    if let Some(year) = field("year").and_then(|year| year.trim().parse::<i32>().ok()) {
+        let mut parts = vec![year];
+        if let Some(month) = field("month").and_then(csl_month) {
+            parts.push(month);
+        }
+        item.insert(
+            "issued".to_owned(),
+            serde_json::json!({ "date-parts": [parts] }),
+        );
+    }
+
+    item.into()
+}
+
+/// Map a BibTeX entry type to a CSL item type.
+fn csl_type(entry_type: &str) -> &'static str {
+    match &*entry_type.to_lowercase() {
+        "article" => "article-journal",
+        "book" | "manual" => "book",
+        "inbook" | "incollection" => "chapter",
+        "inproceedings" | "conference" => "paper-conference",
+        "phdthesis" | "mastersthesis" | "thesis" => "thesis",
+        "techreport" | "report" => "report",
+        "online" | "electronic" => "webpage",
+        _ => "document",
+    }
+}
+
+/// Convert a name in the form `Given Family` or `Family, Given` to a CSL
+/// name.
+fn csl_name(name: &str) -> serde_json::Value {
+    let name = name.trim();
+    if let Some((family, given)) = name.split_once(',') {
+        serde_json::json!({ "family": family.trim(), "given": given.trim() })
+    } else if let Some((given, family)) = name.rsplit_once(' ') {
+        serde_json::json!({ "family": family.trim(), "given": given.trim() })
+    } else {
+        serde_json::json!({ "literal": name })
+    }
+}
+
+/// Parse a BibTeX month (a number or an English month name or abbreviation).
+fn csl_month(month: &str) -> Option<i32> {
+    const NAMES: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    let month = month.trim().to_lowercase();
+    if let Ok(n) = month.parse::<i32>() {
+        return Some(n).filter(|n| (1..=12).contains(n));
+    }
+    NAMES
+        .iter()
+        .position(|name| month.starts_with(name))
+        .map(|i| i as i32 + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_entry() {
+        let meta: Value =
+            serde_yaml::from_str("author: [Ada Lovelace, Charles Babbage]\nyear: 1843\n").unwrap();
+        let entry = BibEntry {
+            key: "lovelace1843".to_owned(),
+            entry_type: "article".to_owned(),
+            fields: vec![
+                ("author".to_owned(), bib_value(&meta["author"]).unwrap()),
+                ("year".to_owned(), bib_value(&meta["year"]).unwrap()),
+                ("title".to_owned(), "Notes {draft}".to_owned()),
+            ],
+        };
+        assert_eq!(
+            render_entry(&entry),
+            "@article{lovelace1843,\n  \
+             author = {Ada Lovelace and Charles Babbage},\n  \
+             year = {1843},\n  \
+             title = {Notes \\{draft\\}},\n\
+             }\n"
+        );
+        assert_eq!(bib_value(&meta["missing"]), None);
+    }
+
+    #[test]
+    fn test_to_csl() {
+        let entry = BibEntry {
+            key: "turing1950".to_owned(),
+            entry_type: "article".to_owned(),
+            fields: vec![
+                (
+                    "author".to_owned(),
+                    "Alan M. Turing and Church, Alonzo".to_owned(),
+                ),
+                ("journal".to_owned(), "Mind".to_owned()),
+                ("month".to_owned(), "October".to_owned()),
+                ("pages".to_owned(), "433--460".to_owned()),
+                ("year".to_owned(), "1950".to_owned()),
+            ],
+        };
+        assert_eq!(
+            to_csl(&entry),
+            serde_json::json!({
+                "id": "turing1950",
+                "type": "article-journal",
+                "author": [
+                    { "family": "Turing", "given": "Alan M." },
+                    { "family": "Church", "given": "Alonzo" },
+                ],
+                "container-title": "Mind",
+                "page": "433-460",
+                "issued": { "date-parts": [[1950, 10]] },
+            })
+        );
+    }
+}
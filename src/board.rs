@@ -0,0 +1,340 @@
+//! Kanban-style board view (`v board`)
+use ansi_term::Style;
+use anyhow::{bail, Context, Result};
+use console::{Key, Term};
+use serde_yaml::Value;
+use std::{collections::BTreeMap, io::Write, path::PathBuf};
+use unicode_width::UnicodeWidthStr;
+use veisku_core::journal::Journal;
+
+use crate::{browse::AltScreen, cfg, query, render, root::DocRoot, schema, OpenMode};
+
+pub fn verb_board(root: &DocRoot, opts: &cfg::Opts, sc: &cfg::Board) -> Result<()> {
+    let board = Board::load(root, sc)?;
+
+    if sc.interactive {
+        return interact(root, sc, board);
+    }
+
+    let term = Term::stdout();
+    let width = if term.features().is_attended() {
+        term.size().1 as usize
+    } else {
+        80
+    };
+
+    let mut out = render::Pager::new(opts);
+    for line in board.render(width, usize::MAX, 0, None) {
+        writeln!(out, "{}", line)?;
+    }
+    out.finish()?;
+    Ok(())
+}
+
+/// The separator between columns
+const COLUMN_SEPARATOR: &str = " │ ";
+
+/// The minimum width of a column
+const MIN_COLUMN_WIDTH: usize = 8;
+
+struct Board {
+    columns: Vec<Column>,
+}
+
+struct Column {
+    /// The field value represented by this column. `None` for documents
+    /// lacking the field.
+    value: Option<Value>,
+    label: String,
+    cards: Vec<Card>,
+}
+
+struct Card {
+    path: PathBuf,
+    title: String,
+    /// Whether the field value is a sequence, in which case the card is
+    /// displayed in multiple columns and can't be moved
+    multi: bool,
+}
+
+impl Board {
+    /// Group the matching documents by the values of the field `sc.by`. The
+    /// columns are ordered by the allowed values declared in the schema
+    /// (which are displayed even if empty), followed by other values in
+    /// ascending order.
+    fn load(root: &DocRoot, sc: &cfg::Board) -> Result<Self> {
+        let query = sc.query.compile(root)?;
+        let docs = query::select_all_sorted(root, &query)
+            .context("An error occurred while enumerating matching documents")?;
+
+        let declared = root
+            .cfg
+            .schema
+            .get(&sc.by)
+            .and_then(|field_cfg| field_cfg.values.as_ref());
+        let mut columns: Vec<Column> = declared
+            .into_iter()
+            .flatten()
+            .map(|value| Column::new(Some(Value::String(value.clone()))))
+            .collect();
+        let num_declared = columns.len();
+        let mut none_column = Column::new(None);
+        let mut others: BTreeMap<String, Column> = BTreeMap::new();
+
+        for mut doc in docs {
+            let path = doc.path().to_owned();
+            let meta = doc
+                .ensure_meta()
+                .with_context(|| format!("Failed to read the metadata of {:?}", path))?;
+            let (values, multi): (Vec<&Value>, bool) = match &meta[&*sc.by] {
+                Value::Sequence(array) => (array.iter().collect(), true),
+                Value::Null => (Vec::new(), false),
+                value => (vec![value], false),
+            };
+            let title = match &meta["title"] {
+                Value::String(st) => st.clone(),
+                _ => path.file_stem().unwrap().to_string_lossy().into_owned(),
+            };
+            let card = || Card {
+                path: path.clone(),
+                title: title.clone(),
+                multi,
+            };
+
+            if values.is_empty() {
+                none_column.cards.push(card());
+            }
+            for value in values {
+                let label = match schema::scalar_text(value) {
+                    Some(label) => label,
+                    None => continue,
+                };
+                let column = match columns[..num_declared]
+                    .iter_mut()
+                    .find(|column| column.label == label)
+                {
+                    Some(column) => column,
+                    None => others
+                        .entry(label)
+                        .or_insert_with(|| Column::new(Some(value.clone()))),
+                };
+                column.cards.push(card());
+            }
+        }
+
+        columns.extend(others.into_values());
+        if !none_column.cards.is_empty() {
+            columns.push(none_column);
+        }
+        Ok(Self { columns })
+    }
+
+    /// Render the board into lines fitting in `width` columns. The column
+    /// headers are followed by at most `height` rows of cards, starting from
+    /// the `offset`-th card.
+    fn render(
+        &self,
+        width: usize,
+        height: usize,
+        offset: usize,
+        selection: Option<(usize, usize)>,
+    ) -> Vec<String> {
+        let num_columns = self.columns.len().max(1);
+        let column_width = (width.saturating_sub((num_columns - 1) * COLUMN_SEPARATOR.width())
+            / num_columns)
+            .max(MIN_COLUMN_WIDTH);
+        let num_rows = self
+            .columns
+            .iter()
+            .map(|column| column.cards.len())
+            .max()
+            .unwrap_or(0);
+
+        let mut lines = Vec::new();
+        let header = self
+            .columns
+            .iter()
+            .map(|column| {
+                let header = format!("{} ({})", column.label, column.cards.len());
+                Style::new()
+                    .bold()
+                    .paint(render::fit_to_width(&header, column_width))
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        lines.push(header.join(COLUMN_SEPARATOR));
+        lines.push(
+            vec!["─".repeat(column_width); num_columns].join(&COLUMN_SEPARATOR.replace(' ', "─")),
+        );
+
+        for row in (offset..num_rows).take(height) {
+            let cells = self
+                .columns
+                .iter()
+                .enumerate()
+                .map(|(i, column)| {
+                    let title = column.cards.get(row).map_or("", |card| &card.title);
+                    let cell = render::fit_to_width(title, column_width);
+                    if selection == Some((i, row)) {
+                        Style::new().reverse().paint(cell).to_string()
+                    } else {
+                        cell
+                    }
+                })
+                .collect::<Vec<_>>();
+            lines.push(cells.join(COLUMN_SEPARATOR).trim_end().to_owned());
+        }
+
+        lines
+    }
+}
+
+impl Column {
+    fn new(value: Option<Value>) -> Self {
+        Self {
+            label: match &value {
+                Some(value) => schema::scalar_text(value).unwrap_or_default(),
+                None => "(none)".to_owned(),
+            },
+            value,
+            cards: Vec::new(),
+        }
+    }
+}
+
+/// Display the board in full screen and let the user move the cards between
+/// columns.
+fn interact(root: &DocRoot, sc: &cfg::Board, mut board: Board) -> Result<()> {
+    let term = Term::stdout();
+    if !term.features().is_attended() {
+        bail!("`v board --interactive` requires a terminal");
+    }
+    root.ensure_writable()?;
+
+    let mut column = 0;
+    let mut row = 0;
+    let mut offset = 0;
+    let mut message = String::new();
+
+    let _screen = AltScreen::enter(&term)?;
+    term.hide_cursor()?;
+    loop {
+        // Clamp the selection
+        column = column.min(board.columns.len().saturating_sub(1));
+        let num_cards = board.columns.get(column).map_or(0, |c| c.cards.len());
+        row = row.min(num_cards.saturating_sub(1));
+
+        // Draw the board, scrolling it to make the selection visible
+        let (height, width) = term.size();
+        let body_height = (height as usize).saturating_sub(3).max(1);
+        if row < offset {
+            offset = row;
+        } else if row >= offset + body_height {
+            offset = row + 1 - body_height;
+        }
+        let mut lines = board.render(width as usize, body_height, offset, Some((column, row)));
+        lines.resize((height as usize).saturating_sub(1), String::new());
+        let status = if message.is_empty() {
+            "←/→/↑/↓: select  </>: move  Enter: edit  q: quit"
+        } else {
+            &message
+        };
+        lines.push(Style::new().reverse().paint(status).to_string());
+        let mut frame = String::from("\x1b[H");
+        frame.push_str(&lines.join("\x1b[K\r\n"));
+        frame.push_str("\x1b[K\x1b[J");
+        term.write_str(&frame)?;
+        term.flush()?;
+
+        let key = match term.read_key() {
+            Ok(key) => key,
+            // Ctrl-C
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => break,
+            Err(e) => return Err(e).context("Failed to read a key"),
+        };
+        message.clear();
+        match key {
+            Key::Escape | Key::Char('q') => break,
+            Key::ArrowLeft | Key::Char('h') => column = column.saturating_sub(1),
+            Key::ArrowRight | Key::Char('l') => column += 1,
+            Key::ArrowUp | Key::Char('k') => row = row.saturating_sub(1),
+            Key::ArrowDown | Key::Char('j') => row += 1,
+            Key::Char(c @ '<') | Key::Char(c @ '>') | Key::Char(c @ 'H') | Key::Char(c @ 'L') => {
+                let target = if c == '<' || c == 'H' {
+                    column.checked_sub(1)
+                } else {
+                    Some(column + 1).filter(|&i| i < board.columns.len())
+                };
+                let (target, card) = match (target, board.columns[column].cards.get(row)) {
+                    (Some(target), Some(card)) => (target, card),
+                    _ => continue,
+                };
+                match move_card(root, &sc.by, card, &board.columns[target]) {
+                    Ok(()) => {
+                        let path = card.path.clone();
+                        board = Board::load(root, sc)?;
+                        column = target;
+                        row = board.columns[column]
+                            .cards
+                            .iter()
+                            .position(|card| card.path == path)
+                            .unwrap_or(0);
+                    }
+                    Err(e) => message = format!("Error: {:#}", e),
+                }
+            }
+            Key::Enter => {
+                let card = match board.columns.get(column).and_then(|c| c.cards.get(row)) {
+                    Some(card) => card,
+                    None => continue,
+                };
+                if root.cfg.encryption_for(&card.path).is_some() {
+                    message = "Encrypted documents can't be edited from the board".to_owned();
+                    continue;
+                }
+                let mut cmd = crate::open_command(root, &card.path, None, false, || {
+                    OpenMode::Edit.default_cmd()
+                });
+                log::debug!("Spawning {:?}", cmd);
+                term.write_str("\x1b[?1049l")?;
+                term.show_cursor()?;
+                let result = cmd.status();
+                term.write_str("\x1b[?1049h")?;
+                term.hide_cursor()?;
+                match result {
+                    Ok(_) => board = Board::load(root, sc)?,
+                    Err(e) => message = format!("Failed to execute {:?}: {}", cmd, e),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Rewrite the field `key` of the document represented by `card` so that it
+/// belongs to `column`.
+fn move_card(root: &DocRoot, key: &str, card: &Card, column: &Column) -> Result<()> {
+    if card.multi {
+        bail!("`{}` of {:?} is a sequence", key, card.path);
+    }
+    if root.cfg.encryption_for(&card.path).is_some() {
+        bail!("Editing the metadata of encrypted documents is not supported");
+    }
+    let journal = Journal::new(root);
+    let mut recorder = journal.begin(format!("set {} of {}", key, card.path.display()));
+    recorder.update_meta(&card.path, |meta| {
+        let key = Value::String(key.to_owned());
+        match &column.value {
+            Some(value) => {
+                meta.insert(key, value.clone());
+            }
+            None => {
+                meta.remove(&key);
+            }
+        }
+        Ok(())
+    })?;
+    recorder.commit()
+}
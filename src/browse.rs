@@ -0,0 +1,383 @@
+//! Full-screen interactive browser (`v browse`)
+use anyhow::{bail, Context, Result};
+use console::{Key, Term};
+use serde_yaml::Value;
+use std::path::Path;
+
+use veisku_core::{doctype::DocType, journal::Journal};
+
+use crate::{cfg, cfg::Criterion, doc::DocRead, query, render, root::DocRoot, OpenMode};
+
+pub fn verb_browse(root: &DocRoot, sc: &cfg::Browse) -> Result<()> {
+    let term = Term::stdout();
+    if !term.features().is_attended() {
+        bail!("`v browse` requires a terminal");
+    }
+
+    let mut browser = Browser {
+        root,
+        term: &term,
+        preset: &sc.preset,
+        query_text: sc.criteria.join(" "),
+        docs: Vec::new(),
+        selected: 0,
+        scroll: 0,
+        preview: None,
+        status: Status::None,
+        tag_input: None,
+    };
+    browser.update_results();
+
+    let _screen = AltScreen::enter(&term)?;
+    loop {
+        browser.draw().context("Failed to draw the screen")?;
+        let key = match term.read_key() {
+            Ok(key) => key,
+            // Ctrl-C
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => break,
+            Err(e) => return Err(e).context("Failed to read a key"),
+        };
+        if !browser.handle_key(key)? {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Switches to the alternate screen while this object is alive.
+pub struct AltScreen<'a>(&'a Term);
+
+impl<'a> AltScreen<'a> {
+    pub fn enter(term: &'a Term) -> Result<Self> {
+        term.write_str("\x1b[?1049h")
+            .context("Failed to initialize the screen")?;
+        Ok(Self(term))
+    }
+}
+
+impl Drop for AltScreen<'_> {
+    fn drop(&mut self) {
+        let _ = self.0.show_cursor();
+        let _ = self.0.write_str("\x1b[?1049l");
+    }
+}
+
+struct Browser<'a> {
+    root: &'a DocRoot,
+    term: &'a Term,
+    preset: &'a str,
+    query_text: String,
+    /// The matching documents, sorted by path
+    docs: Vec<DocRead>,
+    selected: usize,
+    /// The index of the first visible document
+    scroll: usize,
+    /// The index of the previewed document and the lines of its preview
+    preview: Option<(usize, Vec<String>)>,
+    status: Status,
+    /// The tag being entered (Ctrl-T)
+    tag_input: Option<String>,
+}
+
+enum Status {
+    None,
+    Message(String),
+    Error(String),
+}
+
+impl Browser<'_> {
+    /// Re-evaluate the query. Keeps the previous results if the query is
+    /// invalid.
+    fn update_results(&mut self) {
+        match self.select() {
+            Ok(docs) => {
+                self.docs = docs;
+                self.status = Status::None;
+            }
+            Err(e) => {
+                self.status = Status::Error(format!("{:#}", e));
+                return;
+            }
+        }
+        self.selected = self.selected.min(self.docs.len().saturating_sub(1));
+        self.scroll = self.scroll.min(self.selected);
+        self.preview = None;
+    }
+
+    fn select(&self) -> Result<Vec<DocRead>> {
+        let criteria = self
+            .query_text
+            .split_whitespace()
+            .map(|s| {
+                s.parse::<Criterion>()
+                    .map_err(|e| anyhow::anyhow!("'{}': {}", s, e))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let query = query::Query::new(self.root, self.preset, &criteria)?;
+        Ok(query::select_all_sorted(self.root, &query)?)
+    }
+
+    /// The number of rows of the result list
+    fn list_height(&self) -> usize {
+        let (height, _) = self.term.size();
+        ((height as usize).saturating_sub(2) / 2).max(1)
+    }
+
+    /// Handle a key press. Returns `false` to quit.
+    fn handle_key(&mut self, key: Key) -> Result<bool> {
+        if let Some(tag) = &mut self.tag_input {
+            match key {
+                Key::Enter => {
+                    let tag = self.tag_input.take().unwrap();
+                    if !tag.is_empty() {
+                        self.add_tag(&tag);
+                    }
+                }
+                Key::Escape => self.tag_input = None,
+                Key::Backspace => {
+                    tag.pop();
+                }
+                Key::Char(c) if !c.is_control() && !c.is_whitespace() => tag.push(c),
+                _ => {}
+            }
+            return Ok(true);
+        }
+
+        let list_height = self.list_height();
+        match key {
+            Key::Escape => return Ok(false),
+            Key::ArrowUp | Key::Char('\x10') => self.move_selection(-1),
+            Key::ArrowDown | Key::Char('\x0e') => self.move_selection(1),
+            Key::PageUp => self.move_selection(-(list_height as isize)),
+            Key::PageDown => self.move_selection(list_height as isize),
+            Key::Enter | Key::Char('\x05') => self.open(OpenMode::Edit)?,
+            Key::Char('\x0f') => self.open(OpenMode::Open)?,
+            Key::Char('\x14') if !self.docs.is_empty() => self.tag_input = Some(String::new()),
+            Key::Char('\x15') => {
+                self.query_text.clear();
+                self.update_results();
+            }
+            Key::Backspace => {
+                self.query_text.pop();
+                self.update_results();
+            }
+            Key::Char(c) if !c.is_control() => {
+                self.query_text.push(c);
+                self.update_results();
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let max = self.docs.len().saturating_sub(1) as isize;
+        self.selected = (self.selected as isize + delta).max(0).min(max) as usize;
+    }
+
+    /// Open the selected document, suspending the browser until the command
+    /// exits.
+    fn open(&mut self, mode: OpenMode) -> Result<()> {
+        let doc = match self.docs.get(self.selected) {
+            Some(doc) => doc,
+            None => return Ok(()),
+        };
+        if self.root.cfg.encryption_for(doc.path()).is_some() {
+            self.status = Status::Error(
+                "Encrypted documents can't be opened from the browser; use `v edit`".to_owned(),
+            );
+            return Ok(());
+        }
+
+        let mut cmd = crate::open_command(self.root, doc.path(), None, false, || {
+            mode.default_cmd_for(self.root, &[doc.path()])
+        });
+        log::debug!("Spawning {:?}", cmd);
+
+        let _ = self.term.show_cursor();
+        self.term.write_str("\x1b[?1049l")?;
+        let result = cmd.status();
+        self.term.write_str("\x1b[?1049h")?;
+
+        match result {
+            Ok(status) if status.success() => {
+                // The document might have been modified
+                self.update_results();
+            }
+            Ok(status) => {
+                self.status = Status::Error(format!("The command exited with {}", status))
+            }
+            Err(e) => self.status = Status::Error(format!("Failed to execute {:?}: {}", cmd, e)),
+        }
+        Ok(())
+    }
+
+    fn add_tag(&mut self, tag: &str) {
+        let path = self.docs[self.selected].path().to_owned();
+        let result = add_tag(self.root, &path, tag);
+
+        match result {
+            Ok(()) => {
+                self.update_results();
+                self.status = Status::Message(format!("Added the tag '{}'", tag));
+            }
+            Err(e) => self.status = Status::Error(format!("{:#}", e)),
+        }
+    }
+
+    fn draw(&mut self) -> Result<()> {
+        let (height, width) = self.term.size();
+        let (height, width) = (height as usize, width as usize);
+        let list_height = self.list_height();
+        let theme = &self.root.cfg.theme;
+
+        // Keep the selection visible
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + list_height {
+            self.scroll = self.selected + 1 - list_height;
+        }
+
+        let mut lines: Vec<String> = Vec::with_capacity(height);
+
+        // Query box
+        let prompt = match &self.tag_input {
+            Some(tag) => format!("Tag: {}", tag),
+            None => format!("> {}", self.query_text),
+        };
+        lines.push(prompt.clone());
+
+        // Results
+        for i in self.scroll..self.scroll + list_height {
+            let doc = match self.docs.get_mut(i) {
+                Some(doc) => doc,
+                None => {
+                    lines.push(String::new());
+                    continue;
+                }
+            };
+            let mut line = Vec::new();
+            line.extend_from_slice(if i == self.selected { b"> " } else { b"  " });
+            let path = doc.path().to_owned();
+            match doc.ensure_meta() {
+                Ok(meta) => render::write_doc_summary(&mut line, theme, &path, meta)?,
+                Err(_) => render::write_doc_summary(&mut line, theme, &path, &Value::Null)?,
+            }
+            let line = String::from_utf8_lossy(&line).into_owned();
+            let line = if i == self.selected {
+                console::style(line).bold().to_string()
+            } else {
+                line
+            };
+            lines.push(line);
+        }
+
+        // Status bar
+        let status = match &self.status {
+            Status::None => format!(
+                " {} document(s) | Enter: edit  ^O: open  ^T: tag  ^U: clear  Esc: quit",
+                self.docs.len()
+            ),
+            Status::Message(message) => format!(" {}", message),
+            Status::Error(message) => format!(" Error: {}", message),
+        };
+        let status = console::pad_str(&status, width, console::Alignment::Left, None);
+        lines.push(console::style(status).reverse().to_string());
+
+        // Preview
+        let preview_height = height.saturating_sub(lines.len());
+        lines.extend(self.preview_lines().iter().take(preview_height).cloned());
+
+        let mut frame = String::from("\x1b[H");
+        for (i, line) in lines.iter().take(height).enumerate() {
+            if i > 0 {
+                frame.push_str("\r\n");
+            }
+            // `truncate_str` also shortens a line exactly as wide as `width`
+            if console::measure_text_width(line) > width {
+                frame.push_str(&console::truncate_str(line, width, "…"));
+            } else {
+                frame.push_str(line);
+            }
+            frame.push_str("\x1b[K");
+        }
+        frame.push_str("\x1b[J");
+        self.term.write_str(&frame)?;
+
+        let cursor_x = console::measure_text_width(&prompt).min(width.saturating_sub(1));
+        self.term.move_cursor_to(cursor_x, 0)?;
+        self.term.show_cursor()?;
+        self.term.flush()?;
+        Ok(())
+    }
+
+    /// Get the preview of the selected document.
+    fn preview_lines(&mut self) -> &[String] {
+        let selected = self.selected;
+        if self.preview.as_ref().map(|(i, _)| *i) != Some(selected) {
+            let lines = match self.docs.get(selected) {
+                Some(doc) => preview(self.root, doc),
+                None => Vec::new(),
+            };
+            self.preview = Some((selected, lines));
+        }
+        &self.preview.as_ref().unwrap().1
+    }
+}
+
+/// The maximum number of lines included in a preview
+const PREVIEW_MAX_LINES: usize = 200;
+
+/// Get the lines of the body of `doc` to preview.
+pub fn preview(root: &DocRoot, doc: &DocRead) -> Vec<String> {
+    if root.cfg.encryption_for(doc.path()).is_some() {
+        return vec!["(encrypted)".to_owned()];
+    }
+    match doc.doc_type() {
+        doc_type @ DocType::Pdf | doc_type @ DocType::Image => {
+            return vec![format!("({} document)", doc_type)];
+        }
+        _ => {}
+    }
+
+    match doc.read_body() {
+        Ok(body) => String::from_utf8_lossy(&body)
+            .lines()
+            .take(PREVIEW_MAX_LINES)
+            .map(|line| {
+                line.replace('\t', "    ")
+                    .replace(|c: char| c.is_control(), "")
+            })
+            .collect(),
+        Err(e) => vec![format!("Error: {:#}", e)],
+    }
+}
+
+/// Add `tag` to the `tags` field of the document at `path` unless it's already
+/// there.
+pub fn add_tag(root: &DocRoot, path: &Path, tag: &str) -> Result<()> {
+    root.ensure_writable()?;
+    if root.cfg.encryption_for(path).is_some() {
+        bail!("Editing the metadata of encrypted documents is not supported");
+    }
+    let journal = Journal::new(root);
+    let mut recorder = journal.begin(format!("add the tag '{}' to {}", tag, path.display()));
+    recorder.update_meta(path, |meta| {
+        let tags_key = Value::String("tags".to_owned());
+        let new_tag = Value::String(tag.to_owned());
+        match meta.get_mut(&tags_key) {
+            Some(Value::Sequence(array)) => {
+                if !array.contains(&new_tag) {
+                    array.push(new_tag);
+                }
+            }
+            Some(Value::Null) | None => {
+                meta.insert(tags_key, Value::Sequence(vec![new_tag]));
+            }
+            Some(_) => bail!("`tags` is not a sequence"),
+        }
+        Ok(())
+    })?;
+    recorder.commit()
+}
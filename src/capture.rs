@@ -0,0 +1,65 @@
+//! Appending entries to the inbox document (`v capture`)
+use anyhow::{anyhow, bail, Context, Result};
+use std::{fmt::Write as _, io::Read};
+use veisku_core::journal::Journal;
+
+use crate::{cfg, new, root::DocRoot, template};
+
+pub fn verb_capture(root: &DocRoot, sc: &cfg::Capture) -> Result<()> {
+    root.ensure_writable()?;
+
+    let text = if sc.text.is_empty() {
+        let mut text = String::new();
+        std::io::stdin()
+            .read_to_string(&mut text)
+            .context("Failed to read the standard input")?;
+        text
+    } else {
+        sc.text.join(" ")
+    };
+    let text = text.trim_end();
+    if text.trim().is_empty() {
+        bail!("Nothing to capture");
+    }
+
+    let capture_cfg = &root.cfg.capture;
+    let mut prefix = String::new();
+    write!(
+        prefix,
+        "{}",
+        chrono::Local::now().format(&capture_cfg.prefix)
+    )
+    .map_err(|_| anyhow!("Invalid `capture.prefix`: {:?}", capture_cfg.prefix))?;
+    let path = root.path.join(&capture_cfg.path);
+    if !path.exists() {
+        if let Some(name) = &capture_cfg.template {
+            let title = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let vars = template::Vars::new(std::iter::once(("title".to_owned(), title)).collect());
+            new::create_from_template(root, &path, name, vars)?;
+        } else {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)
+                    .with_context(|| format!("Failed to create {:?}", dir))?;
+            }
+            std::fs::write(&path, b"").with_context(|| format!("Failed to create {:?}", path))?;
+        }
+        log::info!("Created {:?}", path);
+    }
+
+    let mut contents =
+        std::fs::read(&path).with_context(|| format!("Failed to read {:?}", path))?;
+    if !contents.is_empty() && !contents.ends_with(b"\n") {
+        contents.push(b'\n');
+    }
+    contents.extend_from_slice(format!("{}{}\n", prefix, text).as_bytes());
+
+    let journal = Journal::new(root);
+    let mut recorder = journal.begin(format!("capture {}", capture_cfg.path));
+    recorder.write_atomic(&path, &contents)?;
+    recorder.commit()?;
+
+    Ok(())
+}
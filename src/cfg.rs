@@ -1,87 +1,1809 @@
-use clap::Clap;
-use serde::Deserialize;
-use std::{collections::HashMap, ffi::OsString, str::FromStr};
+use clap::{AppSettings, Clap};
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::SystemTime,
+};
+
+use veisku_core::{
+    doc::DocRead,
+    link, query,
+    root::{CfgOverride, DocRoot},
+};
+
+// The document root configuration is defined by the core library
+pub use veisku_core::cfg::*;
 
 // Command-line options
 // --------------------------------------------------------------------
 
-/// Personal file-oriented document manager
+/// Personal file-oriented document manager
+#[derive(Debug, Clap)]
+pub struct Opts {
+    /// The command to invoke a pager.
+    ///
+    /// If stdout is a terminal, the default is `$VEISKU_PAGER`, `$PAGER`, or
+    /// `less --RAW-CONTROL-CHARS`. An empty value disables the use of a pager.
+    #[clap(long = "pager", multiple = true, require_delimiter = true)]
+    pub pager: Option<Vec<OsString>>,
+
+    /// Run as if started in `DIR` instead of the current directory. This
+    /// affects the document root discovery and the working directories of
+    /// child processes.
+    #[clap(short = 'C', value_name = "DIR")]
+    pub directory: Option<PathBuf>,
+
+    /// Print more log messages (`-vv` for even more). `RUST_LOG` takes
+    /// precedence if set.
+    #[clap(short = 'v', long = "verbose", parse(from_occurrences))]
+    pub verbose: u64,
+
+    /// Override a value in `config.toml` in the form `KEY=VALUE` (e.g.,
+    /// `files=["*.txt"]` or `new.name="{slug}.md"`). `VALUE` is parsed as a
+    /// TOML value, or treated as a string if it isn't valid TOML.
+    #[clap(
+        long = "config",
+        value_name = "KEY=VALUE",
+        multiple = true,
+        number_of_values = 1
+    )]
+    pub config: Vec<CfgOverride>,
+
+    /// Treat the metadata of documents with malformed preambles as empty
+    /// (logging the errors) instead of failing. `skip_errors = true` in
+    /// `config.toml` makes this the default. `errors:true` finds such
+    /// documents.
+    #[clap(long = "skip-errors")]
+    pub skip_errors: bool,
+
+    /// Refuse every operation creating, modifying, or removing documents or
+    /// changing the configuration. `read_only = true` in `config.toml` makes
+    /// this the default. Scripts run by `v` inherit this mode through
+    /// `V_READ_ONLY`.
+    #[clap(long = "read-only")]
+    pub read_only: bool,
+
+    /// Search the contents of binary files (files containing NUL bytes) for
+    /// `contents:` and `v grep`, which skip them by default.
+    /// `contents.binary = true` in `config.toml` makes this the default.
+    #[clap(long = "binary")]
+    pub binary: bool,
+
+    /// Recognize the files matching `PATTERN` as documents instead of the
+    /// ones matching `files` in `config.toml` (e.g., `--files '*.txt'`). The
+    /// syntax is the same as `files`. Can be given multiple times.
+    #[clap(
+        long = "files",
+        value_name = "PATTERN",
+        multiple = true,
+        number_of_values = 1
+    )]
+    pub files: Vec<String>,
+
+    /// Like `--files`, but adds `PATTERN` to the configured patterns (e.g.,
+    /// `--add-files '*.txt'` or `--add-files '!archive/'`).
+    #[clap(
+        long = "add-files",
+        value_name = "PATTERN",
+        multiple = true,
+        number_of_values = 1
+    )]
+    pub add_files: Vec<String>,
+
+    /// Print only errors, suppressing warnings and progress messages.
+    #[clap(short = 'q', long = "quiet", conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// The format of error messages (`human` or `json`).
+    ///
+    /// `json` emits a JSON object on stderr, which includes the candidates of
+    /// an ambiguous selection and the locations of parse errors.
+    #[clap(
+        long = "error-format",
+        default_value = "human",
+        possible_values = &["human", "json"]
+    )]
+    pub error_format: ErrorFormat,
+
+    /// Print the time spent in each phase (root discovery, file walk,
+    /// matching, metadata reads, and rendering) and counters (files scanned,
+    /// preambles parsed, and cache hits) to stderr on exit.
+    #[clap(long = "timing")]
+    pub timing: bool,
+
+    /// Fail instead of warning when a document can't be evaluated normally,
+    /// e.g., when a metadata field has a value that can't be compared with a
+    /// search criterion. Without this, such conditions are summarized at the
+    /// end of the command.
+    #[clap(long = "strict")]
+    pub strict: bool,
+
+    #[clap(subcommand)]
+    pub subcmd: Option<Subcommand>,
+
+    /// The script to execute (if it doesn't match any builtin subcommand).
+    ///
+    /// Given a command `NAME ARGS...`, the program will check the following
+    /// locations to find the script to run: (1) `v-NAME` in `PATH` (2)
+    /// `NAME` in `$root/.veisku/bin`.
+    pub cmd: Vec<OsString>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorFormat {
+    Human,
+    Json,
+}
+
+impl FromStr for ErrorFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            _ => Err("Expected `human` or `json`"),
+        }
+    }
+}
+
+#[derive(Debug, Clap)]
+pub enum Subcommand {
+    Which(Which),
+    Edit(Open),
+    Open(Open),
+    Show(Open),
+    Ls(List),
+    Run(Run),
+    Dedupe(Dedupe),
+    Grep(Grep),
+    Attach(Attach),
+    Serve(Serve),
+    Daemon(Daemon),
+    Watch(Watch),
+    Export(Export),
+    Snapshot(Snapshot),
+    Bib(Bib),
+    Import(Import),
+    Status(Status),
+    Meta(Meta),
+    New(New),
+    Capture(Capture),
+    Today(Today),
+    Periodic(Periodic),
+    Generate(Generate),
+    Commands(Commands),
+    Search(Search),
+    Validate(Validate),
+    ParseErrors(ParseErrors),
+    Id(Id),
+    Mv(Mv),
+    Split(Split),
+    Tags(Tags),
+    RenameTag(RenameTag),
+    Rm(Rm),
+    Trash(Trash),
+    Undo(Undo),
+    Sync(Sync),
+    Wc(Wc),
+    Hash(Hash),
+    Browse(Browse),
+    Board(Board),
+    Inbox(Inbox),
+    Root(Root),
+    Config(Config),
+    Theme(Theme),
+    State(State),
+    ShellInit(ShellInit),
+    Schema(Schema),
+    #[clap(name = "__complete", setting = AppSettings::Hidden)]
+    Complete(Complete),
+}
+
+/// Print completion candidates, one per line
+///
+/// This is intended to be called by shell completion scripts and editor
+/// plugins. The metadata of documents is cached in `.veisku/state/cache`.
+/// Encrypted documents are excluded.
+#[derive(Debug, Clap)]
+pub struct Complete {
+    /// The kind of the candidates: `tags`, `fields`, `names`, `commands`
+    /// (custom subcommands), or `flags` (the flags of the custom subcommand
+    /// specified by `--command`).
+    pub kind: CompleteKind,
+    /// Only print the candidates starting with this string.
+    pub prefix: Option<String>,
+    /// The custom subcommand whose flags are printed by `flags`.
+    #[clap(long = "command", value_name = "NAME", required_if_eq("kind", "flags"))]
+    pub command: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompleteKind {
+    Tags,
+    Fields,
+    Names,
+    Commands,
+    Flags,
+}
+
+impl FromStr for CompleteKind {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tags" => Ok(Self::Tags),
+            "fields" => Ok(Self::Fields),
+            "names" => Ok(Self::Names),
+            "commands" => Ok(Self::Commands),
+            "flags" => Ok(Self::Flags),
+            _ => Err("Expected `tags`, `fields`, `names`, `commands`, or `flags`"),
+        }
+    }
+}
+
+/// Print the shell integration code
+///
+/// The code defines `vcd [CRITERIA]`, which changes the current directory to
+/// the directory of the selected document (or to the document root if no
+/// criteria are given), and binds Ctrl-X Ctrl-V to insert `$(v which )` into
+/// the command line. Add the following line to the shell's startup file:
+///
+///     eval "$(v shell-init bash)"        # ~/.bashrc
+///     eval "$(v shell-init zsh)"         # ~/.zshrc
+///     v shell-init fish | source         # ~/.config/fish/config.fish
+#[derive(Debug, Clap)]
+pub struct ShellInit {
+    /// The shell: `bash`, `zsh`, or `fish`.
+    pub shell: Shell,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl FromStr for Shell {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bash" => Ok(Self::Bash),
+            "zsh" => Ok(Self::Zsh),
+            "fish" => Ok(Self::Fish),
+            _ => Err("Expected `bash`, `zsh`, or `fish`"),
+        }
+    }
+}
+
+/// Work with the theme (`theme` in `config.toml`)
+#[derive(Debug, Clap)]
+pub struct Theme {
+    #[clap(subcommand)]
+    pub subcmd: ThemeSubcommand,
+}
+
+#[derive(Debug, Clap)]
+pub enum ThemeSubcommand {
+    Preview(ThemePreview),
+}
+
+/// Manage the machine-generated state in `.veisku/state`
+///
+/// The state directory holds the caches, the history of opened documents, the
+/// journal of `v undo`, and the trash of `v rm`. It contains a `.gitignore`
+/// ignoring its contents, so it stays out of version control even if the rest
+/// of `.veisku` is committed.
+#[derive(Debug, Clap)]
+pub struct State {
+    #[clap(subcommand)]
+    pub subcmd: StateSubcommand,
+}
+
+#[derive(Debug, Clap)]
+pub enum StateSubcommand {
+    Clear(StateClear),
+}
+
+/// Remove components of the state
+///
+/// Only the caches are removed unless components are specified. Removing
+/// `trash` permanently deletes the documents in the trash and requires
+/// `writable = true`.
+#[derive(Debug, Clap)]
+pub struct StateClear {
+    /// Remove all components.
+    #[clap(long = "all", conflicts_with = "components")]
+    pub all: bool,
+    /// Display the components to remove without removing them.
+    #[clap(short = 'n', long = "dry-run")]
+    pub dry_run: bool,
+    /// The components to remove: `cache`, `history`, `journal`, or `trash`.
+    pub components: Vec<veisku_core::state::Component>,
+}
+
+/// Render sample listings in the current theme
+///
+/// The samples include the tags styled by `theme.tags`, so the theme can be
+/// tweaked without running real queries.
+#[derive(Debug, Clap)]
+pub struct ThemePreview {
+    /// Preview this built-in theme (`default`, `light`, `dark`, or
+    /// `solarized`) instead of `theme.base`. The styles in `config.toml`
+    /// are still layered on top.
+    pub base: Option<ThemeBase>,
+}
+
+/// Print a JSON Schema
+///
+/// `config` describes `.veisku/config.toml`, and `list-output` describes the
+/// output of `v ls --json`. Editors can use them to validate the
+/// configuration, and other programs can generate types from them.
+#[derive(Debug, Clap)]
+pub struct Schema {
+    /// The described format: `config` or `list-output`.
+    pub kind: SchemaKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SchemaKind {
+    Config,
+    ListOutput,
+}
+
+impl FromStr for SchemaKind {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "config" => Ok(Self::Config),
+            "list-output" => Ok(Self::ListOutput),
+            _ => Err("Expected `config` or `list-output`"),
+        }
+    }
+}
+
+/// Read and write the configuration (`.veisku/config.toml`)
+#[derive(Debug, Clap)]
+pub struct Config {
+    #[clap(subcommand)]
+    pub subcmd: ConfigSubcommand,
+}
+
+#[derive(Debug, Clap)]
+pub enum ConfigSubcommand {
+    Get(ConfigGet),
+    Set(ConfigSet),
+    Edit(ConfigEdit),
+    Check(ConfigCheck),
+}
+
+/// Print a configuration value
+///
+/// The effective value is printed, including the default value and
+/// `--config` overrides. Strings are printed as they are, and other values
+/// are printed in the TOML syntax.
+#[derive(Debug, Clap)]
+pub struct ConfigGet {
+    /// The dotted key (e.g., `theme.tag_default.fg`).
+    pub key: String,
+}
+
+/// Set a configuration value
+///
+/// Only the specified value is rewritten, and the rest of the file (such as
+/// comments) is preserved. The file is left unmodified if the new
+/// configuration is invalid.
+#[derive(Debug, Clap)]
+pub struct ConfigSet {
+    /// The dotted key (e.g., `theme.tag_default.fg`).
+    pub key: String,
+    /// The new value in the TOML syntax (e.g., `true` or `["*.md"]`). Values
+    /// that can't be parsed as TOML are treated as strings.
+    pub value: String,
+}
+
+/// Validate the configuration without running a command
+///
+/// Unknown keys are rejected with a suggestion of the intended key, and the
+/// location of an error is shown with the offending line. `--config` is
+/// applied after the configuration file is validated.
+#[derive(Debug, Clap)]
+pub struct ConfigCheck {}
+
+/// Edit the configuration file in the editor
+///
+/// The configuration file is copied to a temporary file, which is opened in
+/// `$EDITOR`. The configuration file is updated only if the edited
+/// configuration is valid. Otherwise, the errors are reported, and the
+/// temporary file can be edited again.
+///
+/// This works even if the current configuration is broken. `--config` is
+/// ignored.
+#[derive(Debug, Clap)]
+pub struct ConfigEdit {
+    /// The command to edit the configuration.
+    ///
+    /// If the value contains at least one `{}`, they will be replaced with the
+    /// temporary file's path. Otherwise, the path will be appended to the
+    /// command line.
+    #[clap(
+        short = 'c',
+        long = "command",
+        multiple = true,
+        min_values = 1,
+        require_delimiter = true
+    )]
+    pub cmd: Option<Vec<OsString>>,
+}
+
+/// Print the path of the document root
+///
+/// Useful for scripts (e.g., `cd "$(v root)"`) and for checking which
+/// document root and configuration are in effect.
+#[derive(Debug, Clap)]
+pub struct Root {
+    /// Display the configuration file path, the `files` patterns, and the
+    /// effective configuration values (including the defaults and
+    /// `--config` overrides) as JSON.
+    #[clap(short = 'j', long = "json")]
+    pub json: bool,
+}
+
+/// Print the path of a document
+#[derive(Debug, Clap)]
+pub struct Which {
+    #[clap(flatten)]
+    pub query: Query,
+    /// Copy the path to the clipboard.
+    #[clap(long = "copy")]
+    pub copy: bool,
+    /// Print nothing and report the result by the exit status: 0 if exactly
+    /// one document matched, 1 if none matched, 2 if more than one matched,
+    /// and 3 if an error occurred.
+    #[clap(short = 'q', long = "quiet", conflicts_with_all = &["copy", "all"])]
+    pub quiet: bool,
+    /// Print the paths of all matching documents (sorted by path) instead of
+    /// requiring exactly one match.
+    #[clap(short = 'a', long = "all")]
+    pub all: bool,
+    /// Separate the paths by NUL characters instead of line breaks. Requires
+    /// `--all`.
+    #[clap(short = '0', long = "null", requires = "all")]
+    pub null: bool,
+    /// Quote the paths for a shell (`shell`, which produces words safe for
+    /// `eval` and `xargs`) or as JSON strings (`json`).
+    #[clap(long = "quote", possible_values = &["shell", "json"])]
+    pub quote: Option<Quote>,
+    #[clap(flatten)]
+    pub path_format: PathFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quote {
+    Shell,
+    Json,
+}
+
+impl FromStr for Quote {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "shell" => Ok(Self::Shell),
+            "json" => Ok(Self::Json),
+            _ => Err("Expected `shell` or `json`"),
+        }
+    }
+}
+
+impl Quote {
+    /// Quote `s`. Shell words are left unquoted if they consist only of
+    /// characters that are never special, and single-quoted otherwise.
+    pub fn apply(self, s: &str) -> String {
+        match self {
+            Self::Json => serde_json::to_string(s).unwrap(),
+            Self::Shell
+                if !s.is_empty()
+                    && s.chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "_-+=./:,@%".contains(c)) =>
+            {
+                s.to_owned()
+            }
+            Self::Shell => format!("'{}'", s.replace('\'', "'\\''")),
+        }
+    }
+}
+
+// Specifies how paths are printed. (Not a doc comment because it would
+// override the descriptions of the subcommands flattening this struct.)
+#[derive(Debug, Default, Clap)]
+pub struct PathFormat {
+    /// Print absolute paths (the default).
+    #[clap(long = "absolute", group = "path-format")]
+    pub absolute: bool,
+    /// Print paths relative to the current directory.
+    #[clap(long = "relative", group = "path-format")]
+    pub relative: bool,
+    /// Print paths relative to the document root.
+    #[clap(long = "root-relative", group = "path-format")]
+    pub root_relative: bool,
+}
+
+impl PathFormat {
+    /// Format the path of a document as specified.
+    pub fn apply(&self, root: &DocRoot, path: &Path) -> PathBuf {
+        let path = link::normalize(path);
+        if self.absolute {
+            path
+        } else if self.relative {
+            // Canonicalize both sides so that they agree on symbolic links
+            // (e.g., a document root reached through one). The document's
+            // own name is kept even if it's a symbolic link.
+            let canonical_path = match (path.parent(), path.file_name()) {
+                (Some(parent), Some(name)) => parent
+                    .canonicalize()
+                    .map(|parent| parent.join(name))
+                    .unwrap_or_else(|_| path.clone()),
+                _ => path.clone(),
+            };
+            match std::env::current_dir().and_then(|dir| dir.canonicalize()) {
+                Ok(dir) => link::relative_path(&dir, &canonical_path),
+                Err(e) => {
+                    log::warn!("Failed to get the current directory: {}", e);
+                    path
+                }
+            }
+        } else if self.root_relative {
+            link::relative_path(&root.path, &path)
+        } else {
+            path
+        }
+    }
+}
+
+/// List documents
+#[derive(Debug, Clap)]
+pub struct List {
+    #[clap(flatten)]
+    pub query: Query,
+    /// Display only full paths
+    #[clap(short = '1', long = "simple", group = "mode")]
+    pub simple: bool,
+    /// Display the result in JSON
+    ///
+    /// Paths that aren't valid Unicode are also emitted as `raw_path` (an
+    /// array of bytes on Unix or UTF-16 code units on Windows) in addition
+    /// to the lossy `path`.
+    #[clap(short = 'j', long = "json", group = "mode")]
+    pub json: bool,
+    /// Display the result as an aligned table with a header row. The columns
+    /// are chosen by `--fields`.
+    #[clap(short = 't', long = "table", group = "mode")]
+    pub table: bool,
+    /// Display each document as a block of its path, metadata fields, size,
+    /// and modification time.
+    #[clap(short = 'l', long = "long", group = "mode")]
+    pub long: bool,
+    /// Print a line `PATH:LINE:COLUMN:TEXT` for each match of the `contents`
+    /// criteria, or `PATH:1:1:TITLE` for documents without such matches,
+    /// which can be loaded into Vim's quickfix list (e.g.,
+    /// `:cexpr system('v ls --vimgrep contents:TEXT')`) or parsed by problem
+    /// matchers of other editors. Columns are counted in characters.
+    #[clap(long = "vimgrep", group = "mode")]
+    pub vimgrep: bool,
+    /// Soft-wrap long titles at the terminal width, indenting the
+    /// continuation lines to align with the title. Has no effect unless the
+    /// output is a terminal.
+    #[clap(
+        short = 'w',
+        long = "wrap",
+        conflicts_with_all = &["simple", "json", "table", "long", "vimgrep", "group-by", "duplicates-by", "filter"]
+    )]
+    pub wrap: bool,
+    /// Draw a ruler and a vertical bar at the right edge of each line to
+    /// check if the terminal displays characters in the expected widths. The
+    /// bars on the lines containing characters displayed in other widths are
+    /// misaligned. See `ambiguous_width` in `config.toml`.
+    #[clap(
+        long = "align-debug",
+        conflicts_with_all = &["simple", "json", "vimgrep", "group-by", "duplicates-by", "filter"]
+    )]
+    pub align_debug: bool,
+    /// Summarize the documents by directory (`dir` is the only supported
+    /// key): print each directory once with the number of documents and
+    /// their most common tags. Documents below `--depth` are counted in their
+    /// ancestor directories.
+    #[clap(
+        long = "group-by",
+        value_name = "KEY",
+        possible_values = &["dir"],
+        group = "mode",
+        conflicts_with_all = &["all-roots", "rank"]
+    )]
+    pub group_by: Option<String>,
+    /// The depth of the directories listed by `--group-by dir` (1 for the
+    /// top-level directories). Defaults to 1.
+    #[clap(long = "depth", value_name = "N", requires = "group-by")]
+    pub depth: Option<usize>,
+    /// Report the documents sharing the same value of a metadata field or
+    /// `name` (the file stem), e.g., to catch colliding IDs or titles. Only
+    /// the values shared by more than one document are printed. Each element
+    /// of a sequence (e.g., `aliases`) counts as a value.
+    #[clap(
+        long = "duplicates-by",
+        value_name = "KEY",
+        group = "mode",
+        conflicts_with_all = &["all-roots", "rank"]
+    )]
+    pub duplicates_by: Option<String>,
+    /// Act as a filter in a pipeline: read paths from the standard input (one
+    /// per line) and print the ones naming documents that match the search
+    /// criteria, as they were given and in the same order.
+    #[clap(
+        long = "filter-stdin",
+        group = "mode",
+        conflicts_with_all = &["files-from", "all-roots", "sort", "rank"]
+    )]
+    pub filter: bool,
+    /// The columns of `--table`: metadata fields, `name` (the file stem),
+    /// `path`, `type`, `created`, or `modified` (see the search criteria).
+    /// Defaults to `name,title,tags`.
+    #[clap(
+        long = "fields",
+        value_name = "KEYS",
+        multiple = true,
+        require_delimiter = true,
+        requires = "table"
+    )]
+    pub fields: Option<Vec<String>>,
+    /// Include an excerpt of the body (the first 200 characters or so) of
+    /// each document. Requires `--json`.
+    #[clap(long = "excerpt", requires = "json")]
+    pub excerpt: bool,
+    /// Include the full body (excluding the preamble) of each document.
+    /// Requires `--json`.
+    #[clap(long = "with-body", requires = "json")]
+    pub with_body: bool,
+    /// Copy the paths to the clipboard. Requires `--simple`.
+    #[clap(long = "copy", requires = "simple")]
+    pub copy: bool,
+    #[clap(flatten)]
+    pub path_format: PathFormat,
+    /// Search all document roots in the named-roots registry
+    /// (`~/.config/veisku/roots.toml` or `$VEISKU_ROOTS`). The results are
+    /// prefixed with the root names and ordered by root name and path, or
+    /// by `--sort` across all roots.
+    #[clap(long = "all-roots")]
+    pub all_roots: bool,
+    /// Sort the documents by a metadata field, `name` (the file stem),
+    /// `path`, `type`, `mtime` (the modification time), `words`,
+    /// `reading_time`, `lang`, `created`, or `modified` (see the search
+    /// criteria). Metadata fields take precedence over the computed ones of
+    /// the same names (e.g., a `mtime` field is used instead of the
+    /// modification time). `meta.KEY` always refers to the metadata field
+    /// `KEY` (e.g., `meta.name`).
+    /// Text is ordered according to `locale` in
+    /// `config.toml`.
+    #[clap(short = 's', long = "sort", value_name = "KEY")]
+    pub sort: Option<String>,
+    /// Reverse the order. Documents lacking the field are still placed last.
+    /// Requires `--sort`.
+    #[clap(short = 'r', long = "reverse", requires = "sort")]
+    pub reverse: bool,
+    /// List at most `N` documents. Combined with `--sort`, the first `N`
+    /// documents are found without sorting all matching documents.
+    #[clap(short = 'n', long = "limit", value_name = "N")]
+    pub limit: Option<usize>,
+    /// Reuse the results of an identical query made within the last `SECS`
+    /// seconds instead of scanning the document root, e.g., for a shell
+    /// prompt running the same query repeatedly. The results are invalidated
+    /// by configuration changes and the operations recorded for `v undo`.
+    /// Other changes (e.g., new documents) are found by a quick walk of the
+    /// document root, and only the changed documents are examined again. If
+    /// the walk is too slow, the changes are noticed only after `SECS`
+    /// seconds.
+    #[clap(
+        long = "cache-ttl",
+        value_name = "SECS",
+        conflicts_with_all = &["all-roots", "files-from", "filter", "group-by", "duplicates-by"]
+    )]
+    pub cache_ttl: Option<u64>,
+    /// Query the index kept by `v daemon` instead of scanning the document
+    /// root. The document root is scanned as usual if the daemon isn't
+    /// running. The daemon refreshes the index before answering, but if that
+    /// is too slow, documents that started matching since its last scan (up
+    /// to `v daemon --interval` seconds ago) are missing from the results.
+    #[clap(
+        long = "daemon",
+        conflicts_with_all = &["all-roots", "files-from", "filter", "cache-ttl"]
+    )]
+    pub daemon: bool,
+}
+
+/// Open a document
+///
+/// The search criteria must select exactly one document, or the operation will
+/// fail.
+///
+/// There are variations of this subcommand: edit, open, show. The only
+/// differences between them are the default commands they use.
+#[derive(Debug, Clap)]
+pub struct Open {
+    /// The command to open or edit a document.
+    ///
+    /// If the value contains at least one `{}`, they will be replaced with the
+    /// document's path. Otherwise, the path will be appended to the command
+    /// line.
+    #[clap(
+        short = 'c',
+        long = "command",
+        multiple = true,
+        min_values = 1,
+        require_delimiter = true
+    )]
+    pub cmd: Option<Vec<OsString>>,
+    #[clap(flatten)]
+    pub query: Query,
+    /// Preserves the current working directory (does not cd to the document
+    /// root).
+    #[clap(short = 'p', long = "preserve-pwd")]
+    pub preserve_pwd: bool,
+    /// Run the command as a child process and wait for it to exit instead of
+    /// replacing the `v` process, so that `open.after` in `config.toml` can
+    /// be executed afterwards. Implied by `open.spawn = true`.
+    #[clap(long = "spawn")]
+    pub spawn: bool,
+    /// Launch the command in the background, detached from the terminal
+    /// (in a new session on Unix), with its standard input and output
+    /// redirected to the null device. `v` exits without waiting for it, so
+    /// `open.after` is not executed. Useful for GUI applications.
+    #[clap(long = "detach", conflicts_with = "spawn")]
+    pub detach: bool,
+    /// Allow up to `N` matching documents, which are passed to the command at
+    /// once (e.g., `vim -p FILE1 FILE2`). A `{}` in the command is replaced
+    /// with all of the paths.
+    #[clap(
+        short = 'n',
+        long = "limit",
+        value_name = "N",
+        conflicts_with_all = &["first", "index", "pick"]
+    )]
+    pub limit: Option<usize>,
+    /// Select the `N`-th most recently opened document (`1` for the last
+    /// one) recorded in `.veisku/state/history.jsonl`. If search criteria are
+    /// given, only the documents matching them are counted.
+    #[clap(
+        long = "nth-recent",
+        value_name = "N",
+        conflicts_with_all = &["limit", "first", "index", "pick", "rank", "files-from"]
+    )]
+    pub nth_recent: Option<usize>,
+    /// Set a metadata field of the selected documents before opening them,
+    /// in the form `KEY=VALUE` (see `v meta set`), e.g.,
+    /// `--meta status=in-progress`. Can be given more than once. Requires
+    /// `writable = true`.
+    #[clap(
+        long = "meta",
+        value_name = "KEY=VALUE",
+        multiple = true,
+        number_of_values = 1
+    )]
+    pub meta: Vec<Assignment>,
+    /// Print the absolute paths of the selected documents (one per line)
+    /// instead of opening them, so that file managers (e.g., lf, ranger, and
+    /// nnn) can use `v` as a selector. The paths are written to file
+    /// descriptor 3 if it's open and to the standard output otherwise.
+    #[clap(long = "print-only", conflicts_with_all = &["cmd", "spawn", "detach", "meta"])]
+    pub print_only: bool,
+    /// Like `--print-only`, but writes the paths to `PATH` (e.g., a named
+    /// pipe).
+    #[clap(
+        long = "print-to",
+        value_name = "PATH",
+        conflicts_with_all = &["print-only", "cmd", "spawn", "detach", "meta"]
+    )]
+    pub print_to: Option<PathBuf>,
+}
+
+/// Find documents having identical bodies
+///
+/// Preambles are ignored in the comparison. By default, differences in
+/// whitespace and letter case are ignored as well, so that near-identical
+/// copies (e.g., ones with different line endings) are detected.
+#[derive(Debug, Clap)]
+pub struct Dedupe {
+    #[clap(flatten)]
+    pub query: Query,
+    /// Compare document bodies byte by byte.
+    #[clap(long = "exact")]
+    pub exact: bool,
+}
+
+/// Search the bodies of documents
+///
+/// The matching lines are printed as `PATH:LINE:TEXT`. Only the documents
+/// matching the search criteria are searched, so this is equivalent to
+/// `v ls 'contents:/PATTERN/' CRITERIA...` but shows where the bodies matched.
+///
+/// With `--replace`, the matches in the bodies (never the preambles) are
+/// replaced instead. The changes to each document are displayed as a diff
+/// and applied only if confirmed. Requires `writable = true` unless
+/// `--dry-run` is given.
+#[derive(Debug, Clap)]
+pub struct Grep {
+    /// The regex to search for.
+    pub pattern: String,
+    #[clap(flatten)]
+    pub query: Query,
+    /// Ignore case.
+    #[clap(short = 'i', long = "ignore-case")]
+    pub ignore_case: bool,
+    /// Choose one of the matching lines using the picker (`picker.command`
+    /// in `config.toml`) and open the editor at it. The location is passed
+    /// to the editor according to `open.goto` (`+{line}` by default).
+    #[clap(long = "edit")]
+    pub edit: bool,
+    /// Print the matching lines as `PATH:LINE:COLUMN:TEXT`, which can be
+    /// loaded into Vim's quickfix list or parsed by problem matchers of
+    /// other editors. Columns are counted in characters.
+    #[clap(long = "vimgrep", conflicts_with = "edit")]
+    pub vimgrep: bool,
+    /// Replace the matches with `REPLACEMENT`, which can refer to capture
+    /// groups as `$1` or `${name}` (`$$` for a literal `$`).
+    #[clap(
+        long = "replace",
+        value_name = "REPLACEMENT",
+        conflicts_with_all = &["edit", "vimgrep"]
+    )]
+    pub replace: Option<String>,
+    /// Display the diffs of `--replace` without modifying anything.
+    #[clap(short = 'n', long = "dry-run", requires = "replace")]
+    pub dry_run: bool,
+    /// Search all document roots in the named-roots registry (see
+    /// `v ls --all-roots`). The lines are prefixed with the root names and
+    /// ordered by root name and path.
+    #[clap(long = "all-roots", conflicts_with_all = &["vimgrep", "replace"])]
+    pub all_roots: bool,
+    #[clap(flatten)]
+    pub path_format: PathFormat,
+}
+
+/// Print the SHA-256 digests of matching documents
+///
+/// Each line has the form `DIGEST  PATH`, which is compatible with
+/// `sha256sum -c` when the whole files are hashed.
+#[derive(Debug, Clap)]
+pub struct Hash {
+    /// Hash only the bodies, excluding the metadata (the bodies of encrypted
+    /// documents are decrypted first). The digests don't change when only
+    /// the metadata is edited.
+    #[clap(short = 'b', long = "body")]
+    pub body: bool,
+    #[clap(flatten)]
+    pub query: Query,
+    #[clap(flatten)]
+    pub path_format: PathFormat,
+}
+
+/// Manage the files attached to a document
+///
+/// Attachments are stored in `attachments/DOC` (where `DOC` is the base name of
+/// the document) in the document root and recorded in the document's
+/// `attachments` field.
+#[derive(Debug, Clap)]
+pub struct Attach {
+    #[clap(subcommand)]
+    pub subcmd: AttachSubcommand,
+}
+
+#[derive(Debug, Clap)]
+pub enum AttachSubcommand {
+    Add(AttachAdd),
+    /// Print the paths of the files attached to a document
+    Ls(Query),
+    Open(AttachOpen),
+}
+
+/// Attach files to a document
+///
+/// The search criteria must select exactly one document, or the operation will
+/// fail.
+#[derive(Debug, Clap)]
+pub struct AttachAdd {
+    /// The files to attach.
+    #[clap(
+        short = 'a',
+        long = "file",
+        required = true,
+        multiple = true,
+        number_of_values = 1,
+        parse(from_os_str)
+    )]
+    pub files: Vec<PathBuf>,
+    /// Copy the files instead of moving them.
+    #[clap(long = "copy")]
+    pub copy: bool,
+    #[clap(flatten)]
+    pub query: Query,
+}
+
+/// Open a file attached to a document
+///
+/// The search criteria must select exactly one document, or the operation will
+/// fail.
+#[derive(Debug, Clap)]
+pub struct AttachOpen {
+    /// The file name of the attachment to open. Can be omitted if the document
+    /// has exactly one attachment.
+    #[clap(short = 'n', long = "name")]
+    pub name: Option<String>,
+    /// The command to open an attachment.
+    ///
+    /// If the value contains at least one `{}`, they will be replaced with the
+    /// attachment's path. Otherwise, the path will be appended to the command
+    /// line.
+    #[clap(
+        short = 'c',
+        long = "command",
+        multiple = true,
+        min_values = 1,
+        require_delimiter = true
+    )]
+    pub cmd: Option<Vec<OsString>>,
+    #[clap(flatten)]
+    pub query: Query,
+    /// Preserves the current working directory (does not cd to the document
+    /// root).
+    #[clap(short = 'p', long = "preserve-pwd")]
+    pub preserve_pwd: bool,
+}
+
+/// Serve the document query engine over HTTP
+///
+/// `GET /docs?q=CRITERIA` returns the matching documents' paths and metadata in
+/// JSON. `CRITERIA` uses the common search query syntax (separated by spaces).
+/// `GET /docs/NAME` returns the path, metadata, and body of the document
+/// selected by a smart name search.
+#[derive(Debug, Clap)]
+pub struct Serve {
+    /// The address to listen on.
+    #[clap(short = 'b', long = "bind", default_value = "127.0.0.1:8375")]
+    pub bind: String,
+}
+
+/// Keep the document index in memory and answer queries over a socket
+///
+/// The daemon polls the document root and keeps the paths and metadata of
+/// the documents in memory, so `v ls --daemon` can skip reading the
+/// documents. Before answering a query, the daemon quickly rescans the
+/// document root to pick up recent changes. The socket is `.veisku/state/daemon.sock` by default. Only available on
+/// Unix.
+#[derive(Debug, Clap)]
+pub struct Daemon {
+    /// The path of the Unix domain socket to listen on.
+    #[clap(short = 's', long = "socket", value_name = "PATH")]
+    pub socket: Option<PathBuf>,
+    /// The interval between scans of the document root, in seconds.
+    #[clap(short = 'n', long = "interval", default_value = "1")]
+    pub interval: f64,
+}
+
+/// Watch for changes to matching documents
+///
+/// The document root is polled periodically, and a line `EVENT PATH` is
+/// printed whenever a matching document is created, modified, or deleted
+/// (`EVENT` is `created`, `modified`, or `deleted`). An existing document that
+/// starts or stops matching the search criteria is reported as `matched` or
+/// `unmatched`.
+#[derive(Debug, Clap)]
+pub struct Watch {
+    /// The command to execute on each change instead of printing it.
+    ///
+    /// If the value contains at least one `{}`, they will be replaced with the
+    /// document's path. Otherwise, the path will be appended to the command
+    /// line. `V_EVENT` is set to the kind of the change (`created`,
+    /// `modified`, `deleted`, or `matched` and `unmatched` for existing
+    /// documents that started or stopped matching the query). Note that
+    /// changes made by the command are reported as well.
+    #[clap(
+        short = 'x',
+        long = "exec",
+        multiple = true,
+        min_values = 1,
+        require_delimiter = true
+    )]
+    pub cmd: Option<Vec<OsString>>,
+    /// The polling interval in seconds.
+    #[clap(short = 'n', long = "interval", default_value = "1")]
+    pub interval: f64,
+    #[clap(flatten)]
+    pub query: Query,
+}
+
+/// Convert documents using a configured converter
+///
+/// The output files are placed in the output directory, preserving the
+/// directory layout relative to the document root. See `ExportCfg` in
+/// `core/src/cfg.rs` for how to configure converters.
+///
+/// The following formats are built in unless overridden by `config.toml`:
+///
+///  - `csl-json`: Writes the documents having citation keys (see `v bib`) as
+///    a CSL-JSON array to the output file or the standard output, which can
+///    be imported into Zotero or used by pandoc-citeproc.
+#[derive(Debug, Clap)]
+pub struct Export {
+    /// The target format, which must be defined in `config.toml`'s `export`
+    /// table or built in.
+    #[clap(
+        short = 't',
+        long = "to",
+        alias = "format",
+        required_unless_present_any = &["site", "ics"]
+    )]
+    pub format: Option<String>,
+    /// The output directory (the output file for single-file formats).
+    #[clap(short = 'o', long = "output", parse(from_os_str))]
+    pub output: Option<PathBuf>,
+    /// Render a static HTML site in this directory instead: an index page
+    /// listing the documents grouped by tag and a page for each document,
+    /// with links between documents pointing to their pages.
+    #[clap(
+        long = "site",
+        value_name = "DIR",
+        parse(from_os_str),
+        conflicts_with_all = &["format", "output", "ics"]
+    )]
+    pub site: Option<PathBuf>,
+    /// Write the documents having `due` or `event_date` fields as an
+    /// iCalendar file (to the output file or the standard output) instead.
+    /// `due` produces a to-do, and `event_date` an event.
+    #[clap(long = "ics", conflicts_with = "format")]
+    pub ics: bool,
+    #[clap(flatten)]
+    pub query: Query,
+}
+
+/// Copy matching documents to a directory
+///
+/// The documents are copied as-is (encrypted documents stay encrypted),
+/// preserving the directory layout relative to the document root, so that a
+/// subset of the document root can be handed to someone else. The document
+/// root is never modified.
+#[derive(Debug, Clap)]
+pub struct Snapshot {
+    /// The output directory, which must be outside the document root.
+    #[clap(short = 'o', long = "output", parse(from_os_str))]
+    pub output: PathBuf,
+    /// Create hard links instead of copies. The output directory must be on
+    /// the same file system as the document root, and editing the linked
+    /// files modifies the documents.
+    #[clap(long = "hardlink")]
+    pub hardlink: bool,
+    /// Overwrite existing files in the output directory.
+    #[clap(long = "force")]
+    pub force: bool,
+    /// Display the files to be copied without copying anything.
+    #[clap(short = 'n', long = "dry-run")]
+    pub dry_run: bool,
+    #[clap(flatten)]
+    pub query: Query,
+}
+
+/// Export documents as BibTeX entries
+///
+/// The citation key is read from the `citekey` field, and the entry type from
+/// the `bibtype` field (`misc` by default). Documents without a citation key
+/// are skipped. The mapping between BibTeX fields and metadata fields is
+/// configured by the `bib` table in `config.toml`.
+#[derive(Debug, Clap)]
+pub struct Bib {
+    #[clap(flatten)]
+    pub query: Query,
+    /// Write the entries to this file instead of the standard output.
+    #[clap(short = 'o', long = "output", parse(from_os_str))]
+    pub output: Option<PathBuf>,
+}
+
+/// Copy external files into the document root as documents
+///
+/// The new documents are named according to `import.name` in `config.toml`.
+/// `title`, `date`, and `tags` fields are added to the preambles of the
+/// imported documents.
+#[derive(Debug, Clap)]
+pub struct Import {
+    /// The files to import.
+    #[clap(required = true, parse(from_os_str))]
+    pub files: Vec<PathBuf>,
+    /// Move the files instead of copying them.
+    #[clap(long = "move")]
+    pub move_files: bool,
+    /// The tags to add to the imported documents.
+    #[clap(short = 't', long = "tags", multiple = true, require_delimiter = true)]
+    pub tags: Vec<String>,
+    /// Prompt for the tags of each imported document.
+    #[clap(short = 'i', long = "interactive")]
+    pub interactive: bool,
+}
+
+/// List documents modified in the Git working tree
+#[derive(Debug, Clap)]
+pub struct Status {
+    #[clap(flatten)]
+    pub query: Query,
+}
+
+/// Manipulate document metadata
+#[derive(Debug, Clap)]
+pub struct Meta {
+    #[clap(subcommand)]
+    pub subcmd: MetaSubcommand,
+}
+
+#[derive(Debug, Clap)]
+pub enum MetaSubcommand {
+    Show(MetaShow),
+    Edit(MetaEdit),
+    Set(MetaSet),
+    Diff(MetaDiff),
+}
+
+/// Print the metadata of a document
+///
+/// The search criteria must select exactly one document, or the operation will
+/// fail.
+#[derive(Debug, Clap)]
+pub struct MetaShow {
+    /// The output format (`yaml`, `json`, or `toml`). TOML can't represent
+    /// null values, so fields having them are omitted.
+    #[clap(
+        long = "format",
+        default_value = "yaml",
+        possible_values = &["yaml", "json", "toml"]
+    )]
+    pub format: MetaFormat,
+    #[clap(flatten)]
+    pub query: Query,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MetaFormat {
+    Yaml,
+    Json,
+    Toml,
+}
+
+impl FromStr for MetaFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "yaml" => Ok(Self::Yaml),
+            "json" => Ok(Self::Json),
+            "toml" => Ok(Self::Toml),
+            _ => Err("Expected `yaml`, `json`, or `toml`"),
+        }
+    }
+}
+
+/// Edit the preamble of a document in the editor
+///
+/// The preamble is extracted to a temporary file, which is opened in
+/// `$EDITOR`. The document is updated only if the edited preamble is valid
+/// YAML. Requires `writable = true`.
+///
+/// The search criteria must select exactly one document, or the operation will
+/// fail.
+#[derive(Debug, Clap)]
+pub struct MetaEdit {
+    /// The command to edit the preamble.
+    ///
+    /// If the value contains at least one `{}`, they will be replaced with the
+    /// temporary file's path. Otherwise, the path will be appended to the
+    /// command line.
+    #[clap(
+        short = 'c',
+        long = "command",
+        multiple = true,
+        min_values = 1,
+        require_delimiter = true
+    )]
+    pub cmd: Option<Vec<OsString>>,
+    #[clap(flatten)]
+    pub query: Query,
+}
+
+/// Set a metadata field of documents
+///
+/// Without `--all`, the search criteria must select exactly one document, or
+/// the operation will fail. With `--all`, the field is set in every matching
+/// document after displaying a summary of the changes and asking for
+/// confirmation. Requires `writable = true`.
+#[derive(Debug, Clap)]
+pub struct MetaSet {
+    /// Apply the change to all matching documents.
+    #[clap(short = 'a', long = "all")]
+    pub all: bool,
+    /// Don't ask for confirmation.
+    #[clap(short = 'y', long = "yes")]
+    pub yes: bool,
+    /// Display a unified diff of each document to be updated instead of the
+    /// summary of the changes. Combine with `--all` to review the diffs
+    /// before confirming; answering no leaves the documents untouched.
+    #[clap(long = "diff")]
+    pub diff: bool,
+    /// The field to set, in the form `KEY=VALUE`.
+    ///
+    /// `VALUE` is parsed as YAML (e.g., `[a, b]` produces a sequence). An
+    /// empty `VALUE` removes the field.
+    pub assignment: Assignment,
+    #[clap(flatten)]
+    pub query: Query,
+}
+
+/// Compare the metadata of two documents
+///
+/// Each of `A` and `B` is a search criterion (usually a document name) that
+/// must select exactly one document.
+#[derive(Debug, Clap)]
+pub struct MetaDiff {
+    /// The first document.
+    #[clap(value_name = "A")]
+    pub a: Criterion,
+    /// The second document.
+    #[clap(value_name = "B")]
+    pub b: Criterion,
+    /// Also display the fields having identical values.
+    #[clap(long = "all")]
+    pub all: bool,
+}
+
+#[derive(Debug)]
+pub struct Assignment {
+    pub key: String,
+    /// `None` indicates the removal of the field.
+    pub value: Option<serde_yaml::Value>,
+}
+
+impl FromStr for Assignment {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let i = s.find('=').ok_or("Expected `KEY=VALUE`")?;
+        let key = &s[..i];
+        let value = &s[i + 1..];
+        if key.is_empty() {
+            return Err("The key must not be empty");
+        }
+
+        let value = if value.is_empty() {
+            None
+        } else {
+            Some(serde_yaml::from_str(value).map_err(|_| "The value is not valid YAML")?)
+        };
+
+        Ok(Self {
+            key: key.to_owned(),
+            value,
+        })
+    }
+}
+
+/// Create a document from a template
+///
+/// Templates are read from `.veisku/templates`. In a template, `{{NAME}}` and
+/// `{{NAME:ARG}}` are replaced with the values of the following variables:
+/// `title`, `date` (`{{date:%Y-%m-%d}}` specifies the format), `uuid`,
+/// `clipboard`, and the ones defined by `--var`. A `title` field is added to
+/// the preamble if the template doesn't define one.
+///
+/// The new document is named according to `new.name` and `slug` in
+/// `config.toml`. If the name is already taken, a suffix (`-2`, `-3`, ...) is
+/// appended to it.
+#[derive(Debug, Clap)]
+pub struct New {
+    /// The template to use, which is the name of a file in
+    /// `.veisku/templates` with or without its extension. Defaults to the
+    /// class's template or `default`.
+    #[clap(short = 't', long = "template")]
+    pub template: Option<String>,
+    /// The class of the new document, defined by `new.classes.NAME` in
+    /// `config.toml`, which determines the default template, the directory,
+    /// and the file name.
+    #[clap(long = "class", value_name = "NAME")]
+    pub class: Option<String>,
+    /// Define a template variable in the form `KEY=VALUE`.
+    #[clap(long = "var", multiple = true, number_of_values = 1)]
+    pub vars: Vec<TemplateVar>,
+    /// Open the new document in the editor.
+    #[clap(short = 'e', long = "edit")]
+    pub edit: bool,
+    /// The title of the new document.
+    pub title: String,
+}
+
+#[derive(Debug)]
+pub struct TemplateVar {
+    pub key: String,
+    pub value: String,
+}
+
+impl FromStr for TemplateVar {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let i = s.find('=').ok_or("Expected `KEY=VALUE`")?;
+        Ok(Self {
+            key: s[..i].to_owned(),
+            value: s[i + 1..].to_owned(),
+        })
+    }
+}
+
+/// List custom subcommands
+///
+/// Lists the executables in `$root/bin` and `v-*` programs in `PATH`, along
+/// with their descriptions. Scripts mentioning `veisku-describe` are run with
+/// `--veisku-describe` and must print a JSON object in the form
+/// `{"description": TEXT, "flags": [{"name": "--flag", "help": TEXT, "value":
+/// PLACEHOLDER}]}`. The descriptions of other scripts are taken from their
+/// header comments.
+#[derive(Debug, Clap)]
+pub struct Commands {
+    /// Also list the flags reported by the scripts.
+    #[clap(short = 'l', long = "long")]
+    pub long: bool,
+}
+
+/// Manage saved searches
+///
+/// Saved searches are stored in `.veisku/searches.toml`. They can also be
+/// used by `-f NAME` in any verb accepting search criteria.
+#[derive(Debug, Clap)]
+pub struct Search {
+    #[clap(subcommand)]
+    pub subcmd: SearchSubcommand,
+}
+
+#[derive(Debug, Clap)]
+pub enum SearchSubcommand {
+    Save(SearchSave),
+    Run(SearchRun),
+    Ls(SearchList),
+    Rm(SearchRemove),
+}
+
+/// Save search criteria under a name
+#[derive(Debug, Clap)]
+pub struct SearchSave {
+    /// Overwrite an existing saved search.
+    #[clap(long = "force")]
+    pub force: bool,
+    /// The name of the saved search.
+    pub name: String,
+    /// The search criteria (see `v ls --help` for the syntax).
+    pub criteria: Vec<String>,
+}
+
+/// List the documents matching a saved search
+#[derive(Debug, Clap)]
+pub struct SearchRun {
+    /// The name of the saved search.
+    pub name: String,
+    /// Additional search criteria.
+    pub criteria: Vec<Criterion>,
+}
+
+/// List saved searches
 #[derive(Debug, Clap)]
-pub struct Opts {
-    /// The command to invoke a pager.
-    ///
-    /// An empty value disables the use of a pager.
-    #[clap(long = "pager", multiple = true, require_delimiter = true)]
-    pub pager: Option<Vec<OsString>>,
+pub struct SearchList {}
+
+/// Remove a saved search
+#[derive(Debug, Clap)]
+pub struct SearchRemove {
+    /// The name of the saved search.
+    pub name: String,
+}
+
+/// Check documents against the metadata schema
+///
+/// The schema is defined by the `schema` table in `config.toml`. Violations
+/// are reported with the paths of the documents, and the exit status is
+/// non-zero if any document violates the schema.
+#[derive(Debug, Clap)]
+pub struct Validate {
+    #[clap(flatten)]
+    pub query: Query,
+}
+
+/// List documents whose metadata can't be parsed
+///
+/// Each problem is reported as `PATH:LINE:COLUMN: MESSAGE` (or `PATH: MESSAGE`
+/// if the location is unknown), and the exit status is non-zero if any
+/// document is broken.
+#[derive(Debug, Clap)]
+pub struct ParseErrors {
+    #[clap(flatten)]
+    pub query: Query,
+    /// Open the broken documents in `$EDITOR` one by one. A document is
+    /// opened again if it's still broken after the editor exits.
+    #[clap(long = "fix")]
+    pub fix: bool,
+}
+
+/// Append an entry to the inbox document
+///
+/// The entry is prefixed with a timestamp and appended to the document
+/// configured by `capture.path` in `config.toml` (`inbox.md` by default),
+/// which is created from `capture.template` if it doesn't exist. Requires
+/// `writable = true`.
+///
+/// Example: `echo "call dentist" | v capture`
+#[derive(Debug, Clap)]
+pub struct Capture {
+    /// The text to append. The standard input is read if omitted.
+    pub text: Vec<String>,
+}
+
+/// Open the daily note
+///
+/// Equivalent to `v periodic daily`. The note is located by
+/// `periodic.daily.path` in `config.toml` (`journal/%Y-%m-%d.md` by default)
+/// and created from `periodic.daily.template` if it doesn't exist.
+#[derive(Debug, Clap)]
+pub struct Today {
+    #[clap(flatten)]
+    pub period: PeriodArgs,
+}
+
+/// Open a periodic note
+///
+/// The note of the current period is located by `periodic.CADENCE.path` in
+/// `config.toml` and created from `periodic.CADENCE.template` if it doesn't
+/// exist. The default paths are `journal/%Y-%m-%d.md` (daily),
+/// `journal/%G-W%V.md` (weekly), and `journal/%Y-%m.md` (monthly).
+#[derive(Debug, Clap)]
+pub struct Periodic {
+    /// The cadence: `daily`, `weekly`, or `monthly`.
+    pub cadence: Cadence,
+    #[clap(flatten)]
+    pub period: PeriodArgs,
+}
+
+/// Create recurring documents that are due but missing
+///
+/// Recurring documents are defined by `generate.NAME` in `config.toml` with a
+/// cron-like `schedule` (`DAY MONTH WEEKDAY`, e.g., `* * fri` for every
+/// Friday), a `strftime`-style `path` formatted with the due date, and an
+/// optional `template`. The document of the most recent due date is created
+/// if it doesn't exist, so this can be run from a login script or a timer.
+/// The paths of the created documents are printed.
+#[derive(Debug, Clap)]
+pub struct Generate {
+    /// Also create the documents of all due dates in the last `DAYS` days.
+    #[clap(long = "backfill", value_name = "DAYS")]
+    pub backfill: Option<u32>,
+    /// Print the paths of the documents to create without creating them.
+    #[clap(short = 'n', long = "dry-run")]
+    pub dry_run: bool,
+    /// Only create the recurring documents of these names.
+    pub names: Vec<String>,
+}
+
+// Selects a period. (Not a doc comment because it would override the
+// descriptions of the subcommands flattening this struct.)
+#[derive(Debug, Clap)]
+pub struct PeriodArgs {
+    /// Open the note of the period containing this date (e.g., `yesterday`,
+    /// `2024-05-01`, or `3d` for three days ago).
+    #[clap(long = "date")]
+    pub date: Option<TimeArg>,
+    /// Open the note of the `N`-th next period (or previous if negative),
+    /// e.g., `--offset -1` for the previous one.
+    #[clap(long = "offset", value_name = "N", allow_hyphen_values = true)]
+    pub offset: Option<i32>,
+    /// Print the path of the note instead of opening it.
+    #[clap(short = 'p', long = "print")]
+    pub print: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cadence {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl FromStr for Cadence {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "daily" => Ok(Self::Daily),
+            "weekly" => Ok(Self::Weekly),
+            "monthly" => Ok(Self::Monthly),
+            _ => Err("Expected `daily`, `weekly`, or `monthly`"),
+        }
+    }
+}
 
+/// Manage document IDs
+#[derive(Debug, Clap)]
+pub struct Id {
     #[clap(subcommand)]
-    pub subcmd: Option<Subcommand>,
+    pub subcmd: IdSubcommand,
+}
 
-    /// The script to execute (if it doesn't match any builtin subcommand).
-    ///
-    /// Given a command `NAME ARGS...`, the program will check the following
-    /// locations to find the script to run: (1) `v-NAME` in `PATH` (2)
-    /// `NAME` in `$root/.veisku/bin`.
-    pub cmd: Vec<OsString>,
+#[derive(Debug, Clap)]
+pub enum IdSubcommand {
+    Assign(IdAssign),
 }
 
+/// Assign IDs to documents lacking one
+///
+/// The IDs are stored in the `id` metadata field and generated according to
+/// `new.id` in `config.toml` (`timestamp` by default). Displays the documents
+/// to be updated and asks for confirmation. Requires `writable = true`.
 #[derive(Debug, Clap)]
-pub enum Subcommand {
-    /// Print the path of a document
-    Which(Query),
-    Edit(Open),
-    Open(Open),
-    Show(Open),
-    Ls(List),
-    Run(Run),
+pub struct IdAssign {
+    /// The ID scheme to use, overriding `new.id`.
+    #[clap(long = "scheme", possible_values = &["timestamp", "uuid"])]
+    pub scheme: Option<IdScheme>,
+    /// Don't ask for confirmation.
+    #[clap(short = 'y', long = "yes")]
+    pub yes: bool,
+    #[clap(flatten)]
+    pub query: Query,
 }
 
-/// List documents
+/// Rename a document, updating the links pointing to it
+///
+/// `[[wikilinks]]` naming the document and relative Markdown links to the
+/// document in other documents are rewritten. Encrypted documents are not
+/// updated. Requires `writable = true` unless `--dry-run` is given.
+///
+/// The search criteria must select exactly one document, or the operation will
+/// fail.
 #[derive(Debug, Clap)]
-pub struct List {
+pub struct Mv {
+    /// The new name of the document. A name without `/` keeps the document in
+    /// its directory; otherwise, the name is relative to the document root.
+    /// The extension is preserved if omitted.
+    #[clap(short = 't', long = "to")]
+    pub to: String,
+    /// Display the changes without modifying anything.
+    #[clap(short = 'n', long = "dry-run")]
+    pub dry_run: bool,
     #[clap(flatten)]
     pub query: Query,
-    /// Display only full paths
-    #[clap(short = '1', long = "simple", group = "mode")]
-    pub simple: bool,
-    /// Display the result in JSON
-    #[clap(short = 'j', long = "json", group = "mode")]
-    pub json: bool,
 }
 
-/// Open a document
+/// Split a document into multiple documents at its headings
+///
+/// Each section under a heading of the chosen level becomes a new document in
+/// the same directory, titled and named after the heading (see `slug` in
+/// `config.toml`). The new documents inherit the metadata of the original
+/// except `title`, `id`, and `aliases`, and start with a link back to the
+/// original, in which the sections are replaced with a list of links to the
+/// new documents. Only Markdown documents are supported. Requires
+/// `writable = true` unless `--dry-run` is given.
 ///
 /// The search criteria must select exactly one document, or the operation will
 /// fail.
+#[derive(Debug, Clap)]
+pub struct Split {
+    /// The level of the headings to split at (1 for `#`, 2 for `##`, and so
+    /// on). Defaults to the shallowest level having more than one heading.
+    #[clap(long = "level", value_name = "N")]
+    pub level: Option<usize>,
+    /// Display the documents to be created without modifying anything.
+    #[clap(short = 'n', long = "dry-run")]
+    pub dry_run: bool,
+    #[clap(flatten)]
+    pub query: Query,
+}
+
+/// List the tags of matching documents
 ///
-/// There are variations of this subcommand: edit, open, show. The only
-/// differences between them are the default commands they use.
+/// Each line has the number of documents having a tag, followed by the tag.
 #[derive(Debug, Clap)]
-pub struct Open {
-    /// The command to open or edit a document.
-    ///
-    /// If the value contains at least one `{}`, they will be replaced with the
-    /// document's path. Otherwise, the path will be appended to the command
-    /// line.
-    #[clap(
-        short = 'c',
-        long = "command",
-        multiple = true,
-        min_values = 1,
-        require_delimiter = true
-    )]
-    pub cmd: Option<Vec<OsString>>,
+pub struct Tags {
+    /// Display hierarchical tags (e.g., `project/alpha`) as a tree. The count
+    /// of a tag includes the documents having its descendants.
+    #[clap(long = "tree")]
+    pub tree: bool,
+    #[clap(flatten)]
+    pub query: Query,
+}
+
+/// Rename a tag in all documents
+///
+/// The tag is replaced in the `tags` field of every document. With
+/// `--inline`, `#OLD` in document bodies is also replaced. Encrypted documents
+/// are not updated. Requires `writable = true` unless `--dry-run` is given.
+#[derive(Debug, Clap)]
+pub struct RenameTag {
+    /// The tag to rename.
+    pub old: String,
+    /// The new name of the tag. Documents already having it are left with
+    /// one copy.
+    pub new: String,
+    /// Also replace inline `#OLD` tags in document bodies.
+    #[clap(long = "inline")]
+    pub inline: bool,
+    /// Display the changes without modifying anything.
+    #[clap(short = 'n', long = "dry-run")]
+    pub dry_run: bool,
+    /// Display a unified diff of each document to be updated instead of the
+    /// list of the documents.
+    #[clap(long = "diff")]
+    pub diff: bool,
+}
+
+/// Remove documents
+///
+/// The documents are moved to `.veisku/state/trash`, from which they can be
+/// restored by `v trash restore`. Displays the documents to be removed and
+/// asks for confirmation. Requires `writable = true`.
+#[derive(Debug, Clap)]
+pub struct Rm {
+    /// Delete the documents permanently instead of moving them to the trash.
+    #[clap(long = "permanent")]
+    pub permanent: bool,
+    /// Don't ask for confirmation.
+    #[clap(short = 'y', long = "yes")]
+    pub yes: bool,
+    #[clap(flatten)]
+    pub query: Query,
+}
+
+/// Manage removed documents
+#[derive(Debug, Clap)]
+pub struct Trash {
+    #[clap(subcommand)]
+    pub subcmd: TrashSubcommand,
+}
+
+#[derive(Debug, Clap)]
+pub enum TrashSubcommand {
+    #[clap(alias = "list")]
+    Ls(TrashList),
+    Restore(TrashRestore),
+}
+
+/// List the documents in the trash
+#[derive(Debug, Clap)]
+pub struct TrashList {}
+
+/// Restore documents from the trash to their original paths
+///
+/// Requires `writable = true`.
+#[derive(Debug, Clap)]
+pub struct TrashRestore {
+    /// The IDs (as displayed by `v trash ls`) or the original paths (relative
+    /// to the document root) of the documents. The most recently removed
+    /// document is restored if more than one document had the same path.
+    #[clap(required = true)]
+    pub docs: Vec<String>,
+}
+
+/// Revert the last operation
+///
+/// `v mv`, `v rename-tag`, `v rm`, `v meta set`, `v meta edit`,
+/// `v edit --meta`, `v id assign`, `v split`, `v attach add`,
+/// `v grep --replace`, and the edits made in `v browse`, `v board`, and
+/// `v inbox` are recorded in
+/// `.veisku/state/journal` (the last 100 of them). The operation is not
+/// reverted if any of the affected files were modified after it. Requires
+/// `writable = true` unless `--dry-run` is given.
+#[derive(Debug, Clap)]
+pub struct Undo {
+    /// Display the operation without reverting it.
+    #[clap(short = 'n', long = "dry-run")]
+    pub dry_run: bool,
+}
+
+/// Synchronize the document root
+///
+/// Executes the commands configured by `sync.steps` in `config.toml` in the
+/// document root, stopping at the first failing command.
+#[derive(Debug, Clap)]
+pub struct Sync {
+    /// Display the commands without executing them.
+    #[clap(short = 'n', long = "dry-run")]
+    pub dry_run: bool,
+}
+
+/// Count the words and characters in documents
+///
+/// Preambles are excluded from the counts. The counts are cached in
+/// `.veisku/state/cache`.
+#[derive(Debug, Clap)]
+pub struct Wc {
+    /// Aggregate the counts by the values of a metadata field (e.g., `tags`).
+    /// A document is counted in each group of a sequence value.
+    #[clap(long = "by", value_name = "KEY")]
+    pub by: Option<String>,
+    #[clap(flatten)]
+    pub query: Query,
+}
+
+/// Browse documents interactively
+///
+/// Type search criteria (the same syntax as other subcommands) to narrow
+/// down the list. Keys: Up/Down (Ctrl-P/Ctrl-N) and PageUp/PageDown move the
+/// selection, Enter (Ctrl-E) edits the selected document, Ctrl-O opens it,
+/// Ctrl-T adds a tag to it, Ctrl-U clears the query, and Esc quits.
+#[derive(Debug, Clap)]
+pub struct Browse {
+    /// Specifies a pre-defined filter (see `v ls --help`).
+    #[clap(short = 'f', long = "filter", default_value = "default")]
+    pub preset: String,
+    /// The initial search criteria.
+    pub criteria: Vec<String>,
+}
+
+/// Display matching documents as a Kanban-style board
+///
+/// The documents are grouped into columns by the values of a metadata field.
+/// The columns are ordered by the allowed values declared in the `schema`
+/// table of `config.toml`, followed by other values and `(none)`.
+#[derive(Debug, Clap)]
+pub struct Board {
+    /// The metadata field to group the documents by.
+    #[clap(long = "by", value_name = "KEY", default_value = "status")]
+    pub by: String,
+    /// Display the board in full screen and allow moving the documents
+    /// between columns (`<`/`>`), which rewrites the field. Requires
+    /// `writable = true`.
+    #[clap(short = 'i', long = "interactive")]
+    pub interactive: bool,
+    #[clap(flatten)]
+    pub query: Query,
+}
+
+/// Triage documents one by one
+///
+/// The documents matching a saved search (`untriaged` by default) are
+/// displayed in turn with their metadata and the beginning of their bodies.
+/// Single keypresses act on the current document: `t` adds a tag, `s` sets
+/// `status`, `a` moves it to `inbox.archive_dir` (rewriting the links to it),
+/// `o` opens it in the editor, `n` (or Space) skips it, and `q` quits.
+/// Requires `writable = true`.
+#[derive(Debug, Clap)]
+pub struct Inbox {
+    /// The saved search selecting the documents to triage.
+    #[clap(long = "search", value_name = "NAME", default_value = "untriaged")]
+    pub search: String,
+    /// The number of lines of the body to display.
+    #[clap(short = 'n', long = "lines", default_value = "10")]
+    pub lines: usize,
     #[clap(flatten)]
     pub query: Query,
-    /// Preserves the current working directory (does not cd to the document
-    /// root).
-    #[clap(short = 'p', long = "preserve-pwd")]
-    pub preserve_pwd: bool,
 }
 
 /// Execute a command in the document root
+///
+/// With `--with CRITERION`, the matching documents are passed to the
+/// command: an argument `{}` is replaced with their paths, and the
+/// environment variables `V_DOC_COUNT` (the number of documents) and
+/// `V_DOC_LIST` (a file listing their paths, one per line) are set, e.g.,
+/// `v run --with tags:todo --with status:open -- wc -l {}`.
 #[derive(Debug, Clap)]
 pub struct Run {
+    /// A search criterion selecting the documents to pass to the command.
+    /// Can be given multiple times. Fails if no documents match.
+    #[clap(
+        long = "with",
+        value_name = "CRITERION",
+        multiple = true,
+        number_of_values = 1
+    )]
+    pub with: Vec<Criterion>,
     /// The command to execute.
     #[clap(required = true)]
     pub cmd: Vec<OsString>,
@@ -89,260 +1811,452 @@ pub struct Run {
 
 #[derive(Debug, Clap)]
 pub struct Query {
-    /// Specifies a pre-defined filter. An empty string disables the default
-    /// filter.
+    /// Specifies a pre-defined filter, which is a saved search (see
+    /// `v search`) whose criteria are added to the query. An empty string
+    /// disables the default filter (the saved search named `default`, if any).
     #[clap(short = 'f', long = "filter", default_value = "default")]
     pub preset: String,
 
     /// Conjunctive search criteria
     ///
-    ///  - `STRING` performs a smart name search (can be used only once in a
-    ///    single query). First, it looks for documents with an exactly matching
-    ///    base name. If none was found, then it looks for documents whose base
-    ///    names start with `STRING`.
+    ///  - `STRING` performs a smart name search. First, it looks for documents with an exactly matching
+    ///    base name, then for the ones with a matching ID or alias (one of the
+    ///    alternative names in the `aliases` field). If none was found, then
+    ///    it looks for documents whose base names start with `STRING`, and finally for documents whose titles
+    ///    are or contain `STRING` (ignoring case). `smart_phases` in
+    ///    `config.toml` changes the steps (e.g.,
+    ///    `["exact", "prefix", "substring", "fuzzy"]`). If there are multiple
+    ///    `STRING`s (e.g., `meeting notes`), each of them must appear in the
+    ///    base name or the title, in any order.
     ///
     ///  - `/REGEX/` matches documents whose base names match the specified
     ///    regex.
     ///
+    ///  - `name=VALUE` matches documents whose base names (or file names) are
+    ///    exactly `VALUE`. Unlike a smart search, this never falls back to
+    ///    prefixes or IDs and can be negated (e.g., `!name=index`).
+    ///
+    ///  - `#TAG` matches documents whose `tags` include `TAG` or its
+    ///    descendants, treating `/` as a hierarchy separator (e.g., `#project`
+    ///    matches `project/alpha`). Quote it in the shell (`'#project'`).
+    ///
     ///  - `KEY:VALUE` matches a metadata field having the name `KEY` and value
     ///    `VALUE`.
     ///
     ///      - `path:VALUE` matches the full path of a document.
     ///
+    ///      - `type:TYPE` matches the type of a document (`markdown`, `org`,
+    ///        `pdf`, `image`, or `plain`), detected by the file extension and
+    ///        contents. The `type` field of a document is used instead if
+    ///        present.
+    ///
+    ///      - `words:N` and `reading_time:N` (in minutes, assuming 200 words
+    ///        per minute) are computed from document bodies, e.g.,
+    ///        `words:>2000`, unless the document has a field of the same name.
+    ///        The results are cached in `.veisku/state/cache`.
+    ///
+    ///      - `lang:CODE` matches the language of document bodies, given as an
+    ///        ISO 639-1 code (e.g., `lang:ja` or `!lang:en`). The `lang` field
+    ///        of a document is used if present, and otherwise the language is
+    ///        detected automatically. Documents too short to be classified
+    ///        have no language. The results are cached in
+    ///        `.veisku/state/cache`.
+    ///
+    ///      - `in:DIR` matches documents under the directory `DIR` (relative
+    ///        to the document root), e.g., `in:projects/` or `!in:archive`.
+    ///
+    ///      - `name:GLOB` matches documents whose file names match the
+    ///        gitignore-style glob `GLOB` (e.g., `name:2024-*-meeting`). A glob
+    ///        containing `/` is matched against the path relative to the
+    ///        document root. The file extension may be omitted.
+    ///
+    ///      - `created` and `modified` are the author dates of the first and
+    ///        last commits touching a document (e.g., `modified:>=2024-05-01`)
+    ///        if the document root is in a Git repository and the metadata
+    ///        doesn't have these fields. They are cached in `.veisku/state/cache`.
+    ///
+    ///      - `contents:TEXT` matches documents whose bodies contain `TEXT`
+    ///        (case-insensitive), and `contents:/REGEX/` matches documents
+    ///        whose bodies match `REGEX`. With `open.goto` in `config.toml`,
+    ///        `v edit` opens the document at the first match.
+    ///
+    ///      - `errors:true` matches documents whose metadata can't be read
+    ///        (e.g., because of malformed YAML), and `errors:false` matches
+    ///        the others.
+    ///
+    ///      - `changed:worktree` matches documents modified in the Git working
+    ///        tree (including untracked ones). `changed:REV` matches
+    ///        documents that differ from the revision `REV`.
+    ///
+    ///      - `meta.KEY:VALUE` (and the other forms below) matches the
+    ///        metadata field `KEY` itself. The names `path`, `in`, `name`,
+    ///        `contents`, `errors`, and `changed` are reserved for the
+    ///        criteria above, so this is the only way to query fields with
+    ///        these names (e.g., `meta.name:Alice`).
+    ///
     ///  - `KEY:/VALUE/` matches a metadata field having the name `KEY` and
     ///    a value matching the regex `VALUE`.
     ///
-    ///  - The `!` prefix negates the criterion. Illegal for a smart search.
+    ///  - A regex can be followed by flags, e.g., `/VALUE/ix`: `i`
+    ///    (case-insensitive), `c` (case-sensitive), `x` (verbose), `s` (`.`
+    ///    matches `\n`), `m` (multi-line), and `a` (must match the whole name
+    ///    or value). `regex.case_insensitive` and `regex.anchored` in
+    ///    `config.toml` change the defaults.
     ///
-    /// # Unimplemented syntax
+    ///  - `id:ID` matches the document having the ID `ID` (the `id` metadata
+    ///    field). A smart search also matches document IDs if no document
+    ///    name matches exactly, so that references by ID survive renames.
     ///
-    ///  - `contents:TEXT` - please use ripgrep for now
+    ///  - `KEY:<VALUE`, `KEY:>VALUE`, `KEY:<=VALUE`, `KEY:>=VALUE`, and
+    ///    `KEY:<>VALUE` compare a metadata field with `VALUE`. Values are
+    ///    compared according to the field type declared in the `schema` table
+    ///    of `config.toml`. Undeclared fields are compared numerically if both
+    ///    sides are numbers and lexicographically otherwise.
     ///
-    ///  - `KEY:<VALUE`, `KEY:>VALUE`, `KEY:<=VALUE`, `KEY:>=VALUE`, `KEY:<>VALUE`
+    ///  - `KEY:LOW..HIGH` checks if a metadata field is between `LOW` and
+    ///    `HIGH` (inclusive), comparing values in the same way as above, e.g.,
+    ///    `due:2024-01-01..2024-01-31`. This only applies to the fields
+    ///    declared as `int`, `number`, `date`, or `enum` in the `schema` table
+    ///    when both bounds are valid values of the type. Otherwise, the value
+    ///    is matched literally (e.g., `title:a..b`).
+    ///
+    ///  - `KEY:all(A,B,...)`, `KEY:any(A,B,...)`, and `KEY:none(A,B,...)`
+    ///    check how many of the values are elements of a sequence field (or
+    ///    equal to a scalar field), e.g., `tags:all(work,urgent)`.
+    ///
+    ///  - The `!` prefix negates the criterion. Illegal for a smart search.
+    ///
+    /// # Unimplemented syntax
     ///
     ///  - `=EXPRESSION`
     ///
     pub criteria: Vec<Criterion>,
-}
 
-#[derive(Debug)]
-pub enum Criterion {
-    NameSmart(String),
-    Simple {
-        negate: bool,
-        simple_criterion: SimpleCriterion,
-    },
+    /// Select the first of the matching documents (sorted by path, or by
+    /// relevance with `--rank`) instead of failing if more than one document
+    /// matches. Only affects the verbs operating on a single document.
+    #[clap(long = "first", conflicts_with = "index")]
+    pub first: bool,
+
+    /// Select the `N`-th (one-based) of the matching documents (sorted by
+    /// path, or by relevance with `--rank`), as numbered in the error message
+    /// of an ambiguous selection. Only affects the verbs operating on a single
+    /// document.
+    #[clap(long = "index", value_name = "N")]
+    pub index: Option<usize>,
+
+    /// Ask which document to use if more than one document matches, using
+    /// the picker command configured by `picker.command` if any. Only affects
+    /// the verbs operating on a single document.
+    #[clap(long = "pick", conflicts_with_all = &["first", "index"])]
+    pub pick: bool,
+
+    /// Order the matching documents by relevance. A smart name matches
+    /// documents having the exact name or ID, names starting with it, and
+    /// names containing its characters in order (fuzzy matches), which are
+    /// ranked in this order. Documents whose titles contain the smart name
+    /// and documents opened often and recently are ranked higher. A single
+    /// document is selected if it's the only one in the best of these three
+    /// tiers. Only affects `v ls` and the verbs operating on a single
+    /// document. `rank = true` in `config.toml` makes this the default.
+    #[clap(long = "rank")]
+    pub rank: bool,
+
+    /// Select only the documents matching at least one of the given groups
+    /// of criteria, in addition to the other criteria. Each group is a
+    /// whitespace-separated list of criteria, all of which must be satisfied
+    /// (e.g., `-e 'tags:work status:open' -e tags:urgent`). A smart name in a
+    /// group matches the exact name, the ID, or a prefix of the name.
+    #[clap(
+        short = 'e',
+        long = "or",
+        value_name = "CRITERIA",
+        multiple = true,
+        number_of_values = 1
+    )]
+    pub or: Vec<String>,
+
+    /// Select only the documents modified at or after `TIME`, which is a
+    /// date (`2024-05-01`), a date and time (`2024-05-01T09:00`), `today`,
+    /// `yesterday`, or a duration ago (`30min`, `12h`, `3d`, or `2w`).
+    #[clap(long = "since", value_name = "TIME")]
+    pub since: Option<TimeArg>,
+
+    /// Select only the documents modified before `TIME` (see `--since`). A
+    /// date includes the whole day, e.g., `--until yesterday` excludes
+    /// only the documents modified today.
+    #[clap(long = "until", value_name = "TIME")]
+    pub until: Option<TimeArg>,
+
+    /// Select only the documents that differ from the Git revision `REV`
+    /// (e.g., `--changed-since main` to review the notes changed in a
+    /// branch). Equivalent to the criterion `changed:REV`.
+    #[clap(long = "changed-since", value_name = "REV")]
+    pub changed_since: Option<String>,
+
+    /// Examine only the documents listed in `FILE` (one path per line,
+    /// relative to the current directory) instead of all documents in the
+    /// document root. `-` reads the list from the standard input, e.g.,
+    /// `git diff --name-only | v ls --files-from - tags:todo`. Paths outside
+    /// the document root are ignored with a warning.
+    #[clap(long = "files-from", value_name = "FILE", parse(from_os_str))]
+    pub files_from: Option<PathBuf>,
 }
 
-#[derive(Debug)]
-pub enum SimpleCriterion {
-    NameRegex(String),
-    MetaEq(String, String),
-    MetaRegex(String, String),
+/// A point in time given to `--since` or `--until`
+#[derive(Debug, Clone, Copy)]
+pub struct TimeArg {
+    /// The start of the specified period
+    pub start: SystemTime,
+    /// The end of the specified period, which is one day after `start` for
+    /// dates and equal to `start` otherwise
+    pub end: SystemTime,
 }
 
-impl FromStr for Criterion {
-    type Err = &'static str;
+impl FromStr for TimeArg {
+    type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (negate, s) = if let Some(s) = s.strip_prefix("!") {
-            (true, s)
-        } else {
-            (false, s)
-        };
+        use chrono::{Duration, Local, NaiveDate, NaiveDateTime, TimeZone};
 
-        if let Some(s) = s.strip_prefix("/").and_then(|s| s.strip_suffix("/")) {
-            Ok(Self::Simple {
-                negate,
-                simple_criterion: SimpleCriterion::NameRegex(s.to_owned()),
-            })
-        } else if s.starts_with("=") {
-            Err("`=EXPRESSION` syntax is not implemented")
-        } else if let Some(i) = s.find(":") {
-            let key = &s[..i];
-            let value = &s[i + 1..];
-            if value.starts_with("<") || value.starts_with(">") {
-                Err("Unimplemented syntax")
-            } else if let Some(s) = value.strip_prefix("/").and_then(|s| s.strip_suffix("/")) {
-                Ok(Self::Simple {
-                    negate,
-                    simple_criterion: SimpleCriterion::MetaRegex(key.to_owned(), s.to_owned()),
-                })
-            } else {
-                Ok(Self::Simple {
-                    negate,
-                    simple_criterion: SimpleCriterion::MetaEq(key.to_owned(), value.to_owned()),
-                })
-            }
+        let now = Local::now();
+        let date = match s {
+            "today" => Some(now.date_naive()),
+            "yesterday" => Some(now.date_naive() - Duration::days(1)),
+            _ => NaiveDate::parse_from_str(s, "%Y-%m-%d").ok(),
+        };
+        let (start, len) = if let Some(date) = date {
+            (date.and_hms_opt(0, 0, 0).unwrap(), Duration::days(1))
+        } else if let Some(ago) = parse_duration(s) {
+            let start = now
+                .checked_sub_signed(ago)
+                .ok_or_else(|| format!("Time out of range '{}'", s))?;
+            (start.naive_local(), Duration::zero())
         } else {
-            // Smart name search
-            if negate {
-                Err("Smart name search cannot be used with negation")
-            } else {
-                Ok(Self::NameSmart(s.to_owned()))
-            }
-        }
+            let datetime = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"]
+                .iter()
+                .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+                .ok_or_else(|| format!("Unrecognized time '{}'", s))?;
+            (datetime, Duration::zero())
+        };
+        let local = |t: NaiveDateTime| {
+            Local
+                .from_local_datetime(&t)
+                .earliest()
+                .map(SystemTime::from)
+                .ok_or_else(|| format!("Nonexistent local time '{}'", s))
+        };
+        let end = start
+            .checked_add_signed(len)
+            .ok_or_else(|| format!("Time out of range '{}'", s))?;
+        Ok(Self {
+            start: local(start)?,
+            end: local(end)?,
+        })
     }
 }
 
-// Document root configuration
-// --------------------------------------------------------------------
+/// Read the list of document paths given to `--files-from`.
+fn read_file_list(root: &DocRoot, list_path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    use anyhow::Context;
 
-/// Document root configuration (`.veisku/config.toml`)
-#[derive(Debug, Deserialize)]
-pub struct Cfg {
-    /// Modifies the document root.
-    #[serde(default)]
-    pub root: String,
-
-    /// Allows the modification of document metadata, even though it might lose
-    /// non-semantic information (such as comments). Currently unused.
-    #[serde(default)]
-    pub writable: bool,
-
-    /// The patterns of file names to recognize as documents. The patterns are
-    /// processed by [`::globwalk`], which supports `gitignore`'s syntax.
-    /// The paths are relative to the document root.
-    #[serde(default = "files_default")]
-    pub files: Vec<String>,
+    let text;
+    let text = if list_path == Path::new("-") {
+        read_stdin_once()?
+    } else {
+        text = std::fs::read_to_string(list_path)
+            .with_context(|| format!("Failed to read {:?}", list_path))?;
+        &text
+    };
 
-    /// Specifies the text styles applied to various elements
-    #[serde(default)]
-    pub theme: ThemeCfg,
+    let mut seen = std::collections::HashSet::new();
+    Ok(resolve_path_list(root, text)?
+        .into_iter()
+        .map(|(_, path)| path)
+        .filter(|path| seen.insert(path.clone()))
+        .collect())
 }
 
-fn files_default() -> Vec<String> {
-    ["*.md", "*.mdown", "!*.swp", "!.git/", "!.svn/"]
-        .iter()
-        .cloned()
-        .map(String::from)
-        .collect()
-}
+/// Read the whole standard input. The standard input is read only once even
+/// if this function is called more than once (e.g., when a query is compiled
+/// more than once).
+pub fn read_stdin_once() -> anyhow::Result<&'static str> {
+    use anyhow::Context;
+    use std::io::Read;
 
-#[derive(Debug, Deserialize)]
-pub struct ThemeCfg {
-    /// The mapping between tags and text styles.
-    #[serde(default)]
-    pub tags: HashMap<String, StyleCfg>,
-    #[serde(default = "default_tag_default")]
-    pub tag_default: StyleCfg,
+    static STDIN: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+    if STDIN.get().is_none() {
+        let mut text = String::new();
+        std::io::stdin()
+            .read_to_string(&mut text)
+            .context("Failed to read the standard input")?;
+        let _ = STDIN.set(text);
+    }
+    Ok(STDIN.get().unwrap())
 }
 
-impl Default for ThemeCfg {
-    fn default() -> Self {
-        Self {
-            tags: HashMap::new(),
-            tag_default: default_tag_default(),
+/// Resolve a list of paths (one per line, relative to the current directory)
+/// to the documents in the document root. Returns the lines along with the
+/// resolved paths. Lines that don't name a file in the document root are
+/// ignored with a warning.
+pub fn resolve_path_list<'a>(
+    root: &DocRoot,
+    text: &'a str,
+) -> anyhow::Result<Vec<(&'a str, PathBuf)>> {
+    use anyhow::Context;
+
+    let root_path = root
+        .path
+        .canonicalize()
+        .with_context(|| format!("Failed to canonicalize {:?}", root.path))?;
+    let mut files = Vec::new();
+    for line in text.lines().filter(|line| !line.is_empty()) {
+        let path = match Path::new(line).canonicalize() {
+            Ok(path) if path.is_file() => path,
+            Ok(_) => {
+                log::warn!("Ignoring {:?}, which isn't a file", line);
+                continue;
+            }
+            Err(e) => {
+                log::warn!("Ignoring {:?}: {}", line, e);
+                continue;
+            }
+        };
+        // Keep the root's own spelling of its path
+        match path.strip_prefix(&root_path) {
+            Ok(rel_path) if !rel_path.starts_with(".veisku") => {
+                files.push((line, root.path.join(rel_path)))
+            }
+            _ => log::warn!("Ignoring {:?}, which is outside the document root", line),
         }
     }
+    Ok(files)
 }
 
-fn default_tag_default() -> StyleCfg {
-    StyleCfg {
-        fg: Some(ColorCfg {
-            ansi_term_color: ansi_term::Color::Green,
-        }),
-        bg: Some(ColorCfg {
-            ansi_term_color: ansi_term::Color::RGB(64, 64, 64),
-        }),
-        bold: false,
-        italic: false,
+/// Parse a duration like `3d`. Returns `None` if the duration is malformed or
+/// out of range.
+fn parse_duration(s: &str) -> Option<chrono::Duration> {
+    let i = s.find(|c: char| !c.is_ascii_digit())?;
+    let n: i64 = s[..i].parse().ok()?;
+    match &s[i..] {
+        "min" => chrono::Duration::try_minutes(n),
+        "h" => chrono::Duration::try_hours(n),
+        "d" => chrono::Duration::try_days(n),
+        "w" => chrono::Duration::try_weeks(n),
+        _ => None,
     }
 }
 
-/// Text style
-#[derive(Debug, Default, Deserialize)]
-pub struct StyleCfg {
-    /// The foreground color
-    #[serde(default)]
-    fg: Option<ColorCfg>,
-
-    /// The background color
-    #[serde(default)]
-    bg: Option<ColorCfg>,
-
-    #[serde(default)]
-    bold: bool,
-
-    #[serde(default)]
-    italic: bool,
-}
+impl Query {
+    /// Compile the query for the specified document root.
+    pub fn compile(&self, root: &DocRoot) -> anyhow::Result<query::Query> {
+        self.compile_with(root, &[])
+    }
 
-impl StyleCfg {
-    pub fn ansi_term_style(&self) -> ansi_term::Style {
-        ansi_term::Style {
-            background: self.bg.map(|c| c.ansi_term_color),
-            foreground: self.fg.map(|c| c.ansi_term_color),
-            is_bold: self.bold,
-            is_italic: self.italic,
-            ..Default::default()
+    /// Compile the query with additional criteria.
+    pub fn compile_with(
+        &self,
+        root: &DocRoot,
+        extra_criteria: &[Criterion],
+    ) -> anyhow::Result<query::Query> {
+        let mut criteria = [&self.criteria[..], extra_criteria].concat();
+        if let Some(rev) = &self.changed_since {
+            criteria.push(Criterion::Simple {
+                negate: false,
+                simple_criterion: SimpleCriterion::MetaEq("changed".to_owned(), rev.clone()),
+            });
+        }
+        let mut query = query::Query::new(root, &self.preset, &criteria)?;
+        if !self.or.is_empty() {
+            let queries = self
+                .or
+                .iter()
+                .map(|group| {
+                    let criteria = group
+                        .split_whitespace()
+                        .map(|s| {
+                            s.parse::<Criterion>()
+                                .map_err(|e| anyhow::anyhow!("'{}': {}", s, e))
+                        })
+                        .collect::<anyhow::Result<Vec<_>>>()?;
+                    query::Query::new(root, "", &criteria)
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            query.push_any(queries);
+        }
+        if self.since.is_some() || self.until.is_some() {
+            query.push_matcher(Box::new(query::MtimeRange {
+                since: self.since.map(|t| t.start),
+                until: self.until.map(|t| t.end),
+            }));
         }
+        if let Some(list_path) = &self.files_from {
+            query.restrict_to_files(read_file_list(root, list_path)?);
+        }
+        Ok(query)
     }
-}
 
-#[derive(Debug, Copy, Clone)]
-pub struct ColorCfg {
-    ansi_term_color: ansi_term::Color,
-}
-
-impl<'de> Deserialize<'de> for ColorCfg {
-    fn deserialize<D>(de: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        use serde::de::Error;
-        let st = String::deserialize(de)?;
-
-        let ansi_term_color = match &*st {
-            "black" => ansi_term::Color::Black,
-            "red" => ansi_term::Color::Red,
-            "green" => ansi_term::Color::Green,
-            "yellow" => ansi_term::Color::Yellow,
-            "blue" => ansi_term::Color::Blue,
-            "purple" => ansi_term::Color::Purple,
-            "cyan" => ansi_term::Color::Cyan,
-            "white" => ansi_term::Color::White,
-            _ => {
-                if let Some([r, g, b]) = parse_hex_color(&st) {
-                    ansi_term::Color::RGB(r, g, b)
-                } else {
-                    return Err(D::Error::custom(format_args!(
-                        "invalid hexadecimal color specification: '{}'",
-                        st
-                    )));
-                }
+    /// Select exactly one document, taking `--first`, `--index`, and `--pick`
+    /// into account.
+    pub fn select_one(&self, root: &DocRoot) -> Result<DocRead, query::SelectOneError> {
+        let query = self.compile(root).map_err(query::SelectOneError::Misc)?;
+        let rank = self.is_ranked(root);
+        let select_nth = |index| {
+            if rank {
+                query::select_nth_ranked(root, &query, index)
+            } else {
+                query::select_nth(root, &query, index)
             }
         };
+        match (self.first, self.index) {
+            (true, _) => select_nth(0),
+            (false, Some(0)) => Err(query::SelectOneError::Misc(anyhow::anyhow!(
+                "`--index` is one-based"
+            ))),
+            (false, Some(index)) => select_nth(index - 1),
+            (false, None) if self.pick => {
+                let docs = if rank {
+                    query::select_ranked(root, &query)?
+                        .into_iter()
+                        .map(|ranked| ranked.doc)
+                        .collect()
+                } else {
+                    query::select_all_sorted(root, &query)?
+                };
+                crate::pick::pick(root, docs)
+            }
+            (false, None) if rank => query::select_one_ranked(root, &query),
+            (false, None) => query::select_one(root, &query),
+        }
+    }
 
-        Ok(Self { ansi_term_color })
+    /// Whether the matching documents are ordered by relevance (`--rank` or
+    /// `rank` in `config.toml`).
+    pub fn is_ranked(&self, root: &DocRoot) -> bool {
+        self.rank || root.cfg.rank
     }
 }
 
-#[allow(unstable_name_collisions)] // `[_; T]::map` is compatible with `array:Array3::map`
-fn parse_hex_color(s: &str) -> Option<[u8; 3]> {
-    use array::Array3;
-    let bytes = s.as_bytes();
-    if bytes[0] == b'#' {
-        if bytes.len() == 4 {
-            if let [Ok(r), Ok(g), Ok(b)] =
-                [&s[1..], &s[2..], &s[3..]].map(|x| u8::from_str_radix(&x[..1], 16))
-            {
-                Some([r * 0x11, g * 0x11, b * 0x11])
-            } else {
-                None
-            }
-        } else if bytes.len() == 7 {
-            if let [Ok(r), Ok(g), Ok(b)] =
-                [&s[1..], &s[3..], &s[5..]].map(|x| u8::from_str_radix(&x[..2], 16))
-            {
-                Some([r, g, b])
-            } else {
-                None
-            }
-        } else {
-            None
-        }
-    } else {
-        None
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("3d"), Some(chrono::Duration::days(3)));
+        assert_eq!(parse_duration("30min"), Some(chrono::Duration::minutes(30)));
+        assert_eq!(parse_duration("d"), None);
+        assert_eq!(parse_duration("3y"), None);
+        assert_eq!(parse_duration("12"), None);
+        assert_eq!(parse_duration("999999999999999d"), None);
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(Quote::Shell.apply("/notes/a-b.md"), "/notes/a-b.md");
+        assert_eq!(Quote::Shell.apply("it's new.md"), "'it'\\''s new.md'");
+        assert_eq!(Quote::Shell.apply("a\nb"), "'a\nb'");
+        assert_eq!(Quote::Shell.apply(""), "''");
+        assert_eq!(Quote::Json.apply("say \"hi\".md"), "\"say \\\"hi\\\".md\"");
     }
 }
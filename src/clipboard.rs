@@ -0,0 +1,151 @@
+//! System clipboard access
+use anyhow::{bail, Context, Result};
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// Put `text` onto the system clipboard.
+///
+/// This tries platform clipboard utilities first and falls back to the OSC 52
+/// escape sequence, which is supported by many terminal emulators (even over
+/// SSH).
+pub fn copy(text: &str) -> Result<()> {
+    let candidates: &[&[&str]] = if cfg!(target_os = "macos") {
+        &[&["pbcopy"]]
+    } else if cfg!(windows) {
+        &[&["clip"]]
+    } else {
+        &[
+            &["wl-copy"],
+            &["xclip", "-selection", "clipboard"],
+            &["xsel", "--clipboard", "--input"],
+        ]
+    };
+
+    for argv in candidates.iter() {
+        match copy_by_command(argv, text) {
+            Ok(()) => {
+                log::debug!("Copied to the clipboard using {:?}", argv[0]);
+                return Ok(());
+            }
+            Err(e) => log::debug!("Could not copy using {:?}: {:?}", argv[0], e),
+        }
+    }
+
+    log::debug!("Falling back to OSC 52");
+    copy_by_osc52(text)
+}
+
+/// Get the text contents of the system clipboard.
+pub fn paste() -> Result<String> {
+    let candidates: &[&[&str]] = if cfg!(target_os = "macos") {
+        &[&["pbpaste"]]
+    } else if cfg!(windows) {
+        &[&["powershell", "-NoProfile", "-Command", "Get-Clipboard"]]
+    } else {
+        &[
+            &["wl-paste", "--no-newline"],
+            &["xclip", "-selection", "clipboard", "-o"],
+            &["xsel", "--clipboard", "--output"],
+        ]
+    };
+
+    for argv in candidates.iter() {
+        match paste_by_command(argv) {
+            Ok(text) => {
+                log::debug!("Read the clipboard using {:?}", argv[0]);
+                return Ok(text);
+            }
+            Err(e) => log::debug!("Could not paste using {:?}: {:?}", argv[0], e),
+        }
+    }
+
+    bail!("Could not find a usable clipboard utility")
+}
+
+fn paste_by_command(argv: &[&str]) -> Result<String> {
+    let output = Command::new(argv[0])
+        .args(&argv[1..])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .context("Failed to run the process")?;
+    if !output.status.success() {
+        bail!("The process exited with {}", output.status);
+    }
+    String::from_utf8(output.stdout).context("The clipboard contents are not valid UTF-8")
+}
+
+fn copy_by_command(argv: &[&str], text: &str) -> Result<()> {
+    let mut child = Command::new(argv[0])
+        .args(&argv[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Failed to spawn the process")?;
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(text.as_bytes())
+        .context("Failed to write to the process")?;
+
+    let status = child.wait().context("Failed to wait for the process")?;
+    if !status.success() {
+        bail!("The process exited with {}", status);
+    }
+    Ok(())
+}
+
+fn copy_by_osc52(text: &str) -> Result<()> {
+    let seq = format!("\x1b]52;c;{}\x07", base64_encode(text.as_bytes()));
+
+    // Prefer the controlling terminal so that the sequence doesn't end up in
+    // redirected output
+    let mut tty = std::fs::OpenOptions::new()
+        .write(true)
+        .open("/dev/tty")
+        .context("Failed to open the terminal")?;
+    tty.write_all(seq.as_bytes())
+        .context("Failed to write to the terminal")?;
+    Ok(())
+}
+
+fn base64_encode(bytes: &[u8]) -> String {
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).cloned().unwrap_or(0),
+            chunk.get(2).cloned().unwrap_or(0),
+        ];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(TABLE[((n >> (18 - 6 * i)) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64_encode() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+        assert_eq!(base64_encode(b"foobar"), "Zm9vYmFy");
+    }
+}
@@ -0,0 +1,351 @@
+//! Listing custom subcommands (`v commands`)
+//!
+//! # Describe handshake
+//!
+//! A script mentioning `veisku-describe` in its first 4096 bytes is run with
+//! the single argument `--veisku-describe` to obtain a description in the
+//! following JSON format:
+//!
+//! ```json
+//! {
+//!   "description": "Sync the notes",
+//!   "flags": [
+//!     {"name": "--dry-run", "help": "Don't push"},
+//!     {"name": "--remote", "help": "The remote to push to", "value": "NAME"}
+//!   ]
+//! }
+//! ```
+//!
+//! Other scripts are never executed; their descriptions are taken from their
+//! header comments.
+use ansi_term::Color;
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use crate::{cfg, root::DocRoot};
+
+/// The prefix of custom subcommand programs in `PATH`.
+const PATH_PREFIX: &str = "v-";
+
+/// The argument passed to a script to request its description
+const DESCRIBE_ARG: &str = "--veisku-describe";
+
+/// How long to wait for a script to respond to the describe handshake
+const DESCRIBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The description of a custom subcommand
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScriptDescription {
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub flags: Vec<FlagDescription>,
+}
+
+/// A flag accepted by a custom subcommand
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FlagDescription {
+    /// The flag including the leading hyphens (e.g., `--dry-run`)
+    pub name: String,
+    #[serde(default)]
+    pub help: Option<String>,
+    /// The placeholder of the value if the flag takes one
+    #[serde(default)]
+    pub value: Option<String>,
+}
+
+pub fn verb_commands(root: &DocRoot, sc: &cfg::Commands) -> Result<()> {
+    let commands = find_commands(root)?;
+
+    let name_width = commands.keys().map(|name| name.len()).max().unwrap_or(0);
+    for (name, path) in commands.iter() {
+        let desc = describe(path);
+        println!(
+            "{:width$}  {}",
+            name,
+            Color::Fixed(245).paint(desc.description.as_deref().unwrap_or("")),
+            width = name_width
+        );
+        if sc.long {
+            let flags: Vec<(String, &str)> = desc
+                .flags
+                .iter()
+                .map(|flag| {
+                    let text = match &flag.value {
+                        Some(value) => format!("{} {}", flag.name, value),
+                        None => flag.name.clone(),
+                    };
+                    (text, flag.help.as_deref().unwrap_or(""))
+                })
+                .collect();
+            let flag_width = flags.iter().map(|(text, _)| text.len()).max().unwrap_or(0);
+            for (text, help) in flags.iter() {
+                println!(
+                    "    {:width$}  {}",
+                    text,
+                    Color::Fixed(245).paint(*help),
+                    width = flag_width
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the custom subcommands in the format of the subcommand list of
+/// `--help`.
+pub fn print_help_section(root: &DocRoot) -> Result<()> {
+    let commands = find_commands(root)?;
+    if commands.is_empty() {
+        return Ok(());
+    }
+
+    let name_width = commands.keys().map(|name| name.len()).max().unwrap_or(0);
+    println!("\nCUSTOM SUBCOMMANDS:");
+    for (name, path) in commands.iter() {
+        let desc = describe(path);
+        println!(
+            "    {:width$}    {}",
+            name,
+            desc.description.as_deref().unwrap_or(""),
+            width = name_width
+        );
+    }
+    Ok(())
+}
+
+/// Find the custom subcommands. Returns a map from the subcommand names to the
+/// paths of the programs.
+pub fn find_commands(root: &DocRoot) -> Result<BTreeMap<String, PathBuf>> {
+    // Later entries don't override earlier ones, mirroring the lookup order of
+    // `verb_run_script`
+    let mut commands: BTreeMap<String, PathBuf> = BTreeMap::new();
+
+    let script_dir = root.script_dir_path();
+    for (name, path) in list_executables(&script_dir)? {
+        commands.entry(name).or_insert(path);
+    }
+
+    if let Some(paths) = std::env::var_os("PATH") {
+        for dir in std::env::split_paths(&paths) {
+            for (name, path) in list_executables(&dir).unwrap_or_default() {
+                if let Some(name) = name.strip_prefix(PATH_PREFIX) {
+                    let name = strip_exe_extension(name);
+                    commands.entry(name.to_owned()).or_insert(path);
+                }
+            }
+        }
+    }
+
+    Ok(commands)
+}
+
+/// Get the description of a custom subcommand, using the describe handshake
+/// if the script supports it and the header comment otherwise. Errors are
+/// logged and result in an empty description.
+pub fn describe(path: &Path) -> ScriptDescription {
+    let header = match read_header(path) {
+        Ok(header) => header,
+        Err(e) => {
+            log::debug!("Could not read the header of {:?}: {:?}", path, e);
+            return ScriptDescription::default();
+        }
+    };
+
+    if header.contains("veisku-describe") {
+        match run_describe(path) {
+            Ok(mut desc) => {
+                if desc.description.is_none() {
+                    desc.description = extract_description(&header);
+                }
+                return desc;
+            }
+            Err(e) => log::warn!("The describe handshake of {:?} failed: {:#}", path, e),
+        }
+    }
+
+    ScriptDescription {
+        description: extract_description(&header),
+        flags: Vec::new(),
+    }
+}
+
+/// Run `path --veisku-describe` and parse the output.
+fn run_describe(path: &Path) -> Result<ScriptDescription> {
+    let mut child = Command::new(path)
+        .arg(DESCRIBE_ARG)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to execute {:?}", path))?;
+
+    // Read the output in a separate thread so that a script that doesn't exit
+    // can be killed
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = std::thread::spawn(move || {
+        let mut output = Vec::new();
+        stdout.read_to_end(&mut output).map(|_| output)
+    });
+
+    let deadline = Instant::now() + DESCRIBE_TIMEOUT;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!("Timed out after {:?}", DESCRIBE_TIMEOUT);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    if !status.success() {
+        bail!("The script exited with {}", status);
+    }
+
+    let output = reader.join().unwrap()?;
+    parse_description(&output)
+}
+
+fn parse_description(output: &[u8]) -> Result<ScriptDescription> {
+    let desc: ScriptDescription =
+        serde_json::from_slice(output).context("Malformed describe output")?;
+    if let Some(flag) = desc.flags.iter().find(|flag| !flag.name.starts_with('-')) {
+        bail!("Flag names must start with `-`: {:?}", flag.name);
+    }
+    Ok(desc)
+}
+
+/// List the executable files in the specified directory. Returns an empty list
+/// if the directory doesn't exist.
+fn list_executables(dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut out = Vec::new();
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Failed to read {:?}", dir))? {
+        let entry = entry.with_context(|| format!("Failed to read {:?}", dir))?;
+        let path = entry.path();
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+        if is_executable(&path) {
+            out.push((name, path));
+        }
+    }
+    Ok(out)
+}
+
+fn is_executable(path: &Path) -> bool {
+    let metadata = match std::fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return false,
+    };
+    if !metadata.is_file() {
+        return false;
+    }
+
+    match () {
+        #[cfg(unix)]
+        () => {
+            use std::os::unix::fs::PermissionsExt;
+            metadata.permissions().mode() & 0o111 != 0
+        }
+        #[cfg(not(unix))]
+        () => true,
+    }
+}
+
+fn strip_exe_extension(name: &str) -> &str {
+    if cfg!(windows) {
+        for ext in [".exe", ".bat", ".cmd"].iter() {
+            if let Some(stem) = name.strip_suffix(ext) {
+                return stem;
+            }
+        }
+    }
+    name
+}
+
+/// Read the first 4096 bytes of a script.
+fn read_header(path: &Path) -> Result<String> {
+    let mut header = Vec::new();
+    std::fs::File::open(path)?
+        .take(4096)
+        .read_to_end(&mut header)?;
+    Ok(String::from_utf8_lossy(&header).into_owned())
+}
+
+/// Extract the first non-empty comment line, skipping a shebang line.
+fn extract_description(header: &str) -> Option<String> {
+    for (i, line) in header.lines().enumerate() {
+        let line = line.trim();
+        if i == 0 && line.starts_with("#!") {
+            continue;
+        }
+
+        let text = ["#", "//", "--", "REM ", "::"]
+            .iter()
+            .find_map(|prefix| line.strip_prefix(prefix))?;
+        let text = text.trim_start_matches(['#', '/', '-']);
+        let text = text.trim();
+        if !text.is_empty() {
+            return Some(text.to_owned());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_description() {
+        assert_eq!(
+            extract_description("#!/bin/sh\n#\n# Sync the notes\nset -e\n").as_deref(),
+            Some("Sync the notes")
+        );
+        assert_eq!(
+            extract_description("#!/usr/bin/env node\n// List drafts\n").as_deref(),
+            Some("List drafts")
+        );
+        assert_eq!(extract_description("#!/bin/sh\nexit 0\n"), None);
+        assert_eq!(extract_description("\x7fELF\x02\x01"), None);
+    }
+
+    #[test]
+    fn test_parse_description() {
+        let desc = parse_description(
+            br#"{"description": "Sync", "flags": [{"name": "--remote", "value": "NAME"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            desc,
+            ScriptDescription {
+                description: Some("Sync".to_owned()),
+                flags: vec![FlagDescription {
+                    name: "--remote".to_owned(),
+                    help: None,
+                    value: Some("NAME".to_owned()),
+                }],
+            }
+        );
+        assert!(parse_description(b"{}").is_ok());
+        assert!(parse_description(br#"{"flags": [{"name": "remote"}]}"#).is_err());
+        assert!(parse_description(b"usage: ...").is_err());
+    }
+}
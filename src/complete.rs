@@ -0,0 +1,40 @@
+//! Completion candidates for shell completion scripts (`v __complete`)
+use anyhow::{bail, Result};
+use std::io::Write;
+
+use veisku_core::complete;
+
+use crate::{cfg, commands, root::DocRoot};
+
+pub fn verb_complete(root: &DocRoot, sc: &cfg::Complete) -> Result<()> {
+    let candidates = match sc.kind {
+        cfg::CompleteKind::Tags => complete::tags(root)?,
+        cfg::CompleteKind::Fields => complete::fields(root)?,
+        cfg::CompleteKind::Names => complete::names(root)?,
+        cfg::CompleteKind::Commands => commands::find_commands(root)?.into_keys().collect(),
+        cfg::CompleteKind::Flags => {
+            let name = sc.command.as_deref().unwrap();
+            let path = match commands::find_commands(root)?.remove(name) {
+                Some(path) => path,
+                None => bail!("Unknown custom subcommand: '{}'", name),
+            };
+            commands::describe(&path)
+                .flags
+                .into_iter()
+                .map(|flag| flag.name)
+                .collect()
+        }
+    };
+
+    let stdout = std::io::stdout();
+    let mut out = std::io::BufWriter::new(stdout.lock());
+    let prefix = sc.prefix.as_deref().unwrap_or("");
+    for candidate in candidates.range(prefix.to_owned()..) {
+        if !candidate.starts_with(prefix) {
+            break;
+        }
+        writeln!(out, "{}", candidate)?;
+    }
+    out.flush()?;
+    Ok(())
+}
@@ -0,0 +1,138 @@
+//! Configuration manipulation (`v config`)
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::{
+    cfg,
+    meta::confirm,
+    root::{self, CfgOverride, CfgParseError, DocRoot},
+    temp,
+};
+
+pub fn verb_config(root: &DocRoot, sc: &cfg::Config) -> Result<()> {
+    match &sc.subcmd {
+        cfg::ConfigSubcommand::Get(sc) => verb_config_get(root, sc),
+        cfg::ConfigSubcommand::Set(sc) => verb_config_set(root, sc),
+        // Handled by `run` without loading the configuration
+        cfg::ConfigSubcommand::Edit(sc) => verb_config_edit(sc),
+        // Handled by `run` before loading the configuration
+        cfg::ConfigSubcommand::Check(_) => unreachable!(),
+    }
+}
+
+fn verb_config_get(root: &DocRoot, sc: &cfg::ConfigGet) -> Result<()> {
+    let key = root::parse_dotted_key(&sc.key).map_err(|e| anyhow!("'{}': {}", sc.key, e))?;
+    match root.cfg.get_value(&key) {
+        Some(toml::Value::String(st)) => println!("{}", st),
+        Some(value) => println!("{}", value),
+        None => bail!("`{}` is unknown or not set", sc.key),
+    }
+    Ok(())
+}
+
+fn verb_config_set(root: &DocRoot, sc: &cfg::ConfigSet) -> Result<()> {
+    let cfg_override =
+        CfgOverride::new(&sc.key, &sc.value).map_err(|e| anyhow!("'{}': {}", sc.key, e))?;
+    let cfg_path = root.cfg_file_path();
+    root::set_cfg_value(&cfg_path, &cfg_override)
+        .with_context(|| format!("Failed to update {:?}", cfg_path))?;
+    log::debug!("Updated `{}` in {:?}", sc.key, cfg_path);
+    Ok(())
+}
+
+pub fn verb_config_edit(sc: &cfg::ConfigEdit) -> Result<()> {
+    let doc_root_path = DocRoot::locate_current()?;
+    let cfg_path = root::cfg_file_path_for_doc_root_path(&doc_root_path);
+    let cfg_toml = match std::fs::read_to_string(&cfg_path) {
+        Ok(cfg_toml) => Some(cfg_toml),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", cfg_path)),
+    };
+
+    let temp_dir = temp::PrivateTempDir::new()?;
+    let temp_path = temp_dir.path().join("config.toml");
+    std::fs::write(&temp_path, cfg_toml.as_deref().unwrap_or(""))
+        .with_context(|| format!("Failed to write {:?}", temp_path))?;
+
+    // Let the user edit the configuration until it's valid
+    let new_cfg_toml = loop {
+        let argv = crate::open_argv(&[&temp_path], sc.cmd.as_deref(), crate::default_editor);
+        let mut cmd = crate::new_command(&argv);
+        log::debug!("Spawning {:?}", cmd);
+        let status = cmd
+            .status()
+            .with_context(|| format!("Failed to execute {:?}", cmd))?;
+        if !status.success() {
+            bail!("The editor exited with {}; discarding the changes", status);
+        }
+
+        let new_cfg_toml = std::fs::read_to_string(&temp_path)
+            .with_context(|| format!("Failed to read {:?}", temp_path))?;
+        match root::parse_cfg_toml::<cfg::Cfg>(&cfg_path, &new_cfg_toml) {
+            Ok(_) => break new_cfg_toml,
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                if !confirm("Edit again?", true)? {
+                    bail!("Discarded the changes");
+                }
+            }
+        }
+    };
+
+    if Some(&new_cfg_toml) == cfg_toml.as_ref() {
+        log::info!("The configuration was not modified");
+        return Ok(());
+    }
+
+    // Make sure the file wasn't modified in the meantime
+    let current_cfg_toml = std::fs::read_to_string(&cfg_path).ok();
+    if current_cfg_toml != cfg_toml {
+        bail!(
+            "{:?} was modified while editing it; discarding the changes",
+            cfg_path
+        );
+    }
+
+    if let Some(dir) = cfg_path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+    }
+    crate::doc::write_atomic(&cfg_path, new_cfg_toml.as_bytes())?;
+    log::info!("Updated {:?}", cfg_path);
+
+    Ok(())
+}
+
+pub fn verb_config_check(opts: &cfg::Opts, overrides: &[CfgOverride]) -> Result<()> {
+    let doc_root_path = DocRoot::locate_current()?;
+    let cfg_path = root::cfg_file_path_for_doc_root_path(&doc_root_path);
+    if let Err(e) = DocRoot::open_with_overrides(&doc_root_path, overrides) {
+        if opts.error_format == cfg::ErrorFormat::Human {
+            if let Some(CfgParseError {
+                line: Some(line),
+                column: Some(column),
+                ..
+            }) = e.downcast_ref()
+            {
+                let cfg_toml = std::fs::read_to_string(&cfg_path).unwrap_or_default();
+                if let Some(text) = cfg_toml.lines().nth(*line) {
+                    eprintln!("{:>5} | {}", line + 1, text);
+                    // Keep tabs so that the caret is aligned
+                    let indent: String = (text.chars().take(*column))
+                        .map(|c| if c == '\t' { '\t' } else { ' ' })
+                        .collect();
+                    eprintln!("      | {}^", indent);
+                }
+            }
+        }
+        return Err(e);
+    }
+
+    if cfg_path.exists() {
+        log::info!("{:?} is valid", cfg_path);
+    } else {
+        log::info!(
+            "{:?} doesn't exist; the default configuration is used",
+            cfg_path
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,137 @@
+//! Support for encrypted documents
+//!
+//! Encrypted documents are decrypted and encrypted by external commands
+//! configured by [`EncryptionCfg`]. Plaintext is never written inside the
+//! document root. `v show` and `v edit` decrypt a document into a private
+//! temporary directory, which is removed after the viewer or editor exits
+//! (or kept if the edited plaintext couldn't be re-encrypted).
+use anyhow::{bail, Context, Result};
+use std::{
+    convert::Infallible,
+    path::{Path, PathBuf},
+};
+use veisku_core::crypt::encrypt;
+
+use crate::{cfg, cfg::EncryptionCfg, doc::DocRead, root::DocRoot, temp, OpenMode};
+
+/// Implements `verb_open` for encrypted documents. The document is decrypted
+/// into a temporary file, which is passed to the viewer or editor. In the
+/// `Edit` mode, the document is re-encrypted if the temporary file was
+/// modified.
+pub fn open_encrypted(
+    root: &DocRoot,
+    doc: &DocRead,
+    enc_cfg: &EncryptionCfg,
+    sc: &cfg::Open,
+    mode: OpenMode,
+) -> Result<Infallible> {
+    if mode == OpenMode::Open {
+        // We can't tell when the opener is done with the plaintext file
+        bail!("Encrypted documents can't be opened by `v open`; use `v show` or `v edit`");
+    }
+
+    let plaintext = doc.read_contents()?;
+
+    // Name the plaintext file without the encryption extension (e.g.,
+    // `hoge.md.age` → `hoge.md`) so that editors can recognize the file type
+    let temp_dir = temp::PrivateTempDir::new()?;
+    let temp_path = temp_dir.path().join(doc.path().file_stem().unwrap());
+    std::fs::write(&temp_path, &plaintext)
+        .with_context(|| format!("Failed to write {:?}", temp_path))?;
+
+    let mut cmd = crate::open_command(root, &temp_path, sc.cmd.as_deref(), sc.preserve_pwd, || {
+        mode.default_cmd()
+    });
+    log::debug!("Spawning {:?}", cmd);
+    let status = cmd
+        .status()
+        .with_context(|| format!("Failed to execute {:?}", cmd))?;
+
+    if mode == OpenMode::Edit {
+        save_changes(enc_cfg, doc.path(), &plaintext, temp_dir, &temp_path)?;
+    } else {
+        drop(temp_dir);
+    }
+    std::process::exit(status.code().unwrap_or(1));
+}
+
+/// Re-encrypt the document at `doc_path` if the plaintext file `temp_path`
+/// in `temp_dir` was modified from `plaintext`. On failure, `temp_dir` is
+/// kept so that the user's edits aren't lost.
+fn save_changes(
+    enc_cfg: &EncryptionCfg,
+    doc_path: &Path,
+    plaintext: &[u8],
+    temp_dir: temp::PrivateTempDir,
+    temp_path: &Path,
+) -> Result<()> {
+    let result = std::fs::read(temp_path)
+        .with_context(|| format!("Failed to read {:?}", temp_path))
+        .and_then(|new_plaintext| {
+            if new_plaintext != plaintext {
+                reencrypt(enc_cfg, doc_path, temp_path)
+            } else {
+                log::debug!("{:?} was not modified", doc_path);
+                Ok(())
+            }
+        });
+    if let Err(e) = result {
+        temp_dir.keep();
+        return Err(e.context(format!(
+            "Failed to save the changes to {:?}; the edited plaintext was kept in {:?}",
+            doc_path, temp_path
+        )));
+    }
+    Ok(())
+}
+
+/// Replace the document at `doc_path` with the encryption of the plaintext
+/// file `temp_path`.
+fn reencrypt(enc_cfg: &EncryptionCfg, doc_path: &Path, temp_path: &Path) -> Result<()> {
+    // Write to a temporary file first so that a failed encryption doesn't
+    // destroy the document
+    let mut encrypted_path = doc_path.as_os_str().to_owned();
+    encrypted_path.push(".veisku-new");
+    let encrypted_path = PathBuf::from(encrypted_path);
+
+    // Left by an earlier attempt that was interrupted
+    let _ = std::fs::remove_file(&encrypted_path);
+    if let Err(e) = encrypt(enc_cfg, temp_path, &encrypted_path) {
+        let _ = std::fs::remove_file(&encrypted_path);
+        return Err(e).with_context(|| format!("Failed to re-encrypt {:?}", doc_path));
+    }
+    // This preserves the permissions of the document and removes
+    // `encrypted_path` on failure
+    crate::doc::replace_atomic(&encrypted_path, doc_path)?;
+    log::info!("Re-encrypted {:?}", doc_path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_changes_keeps_plaintext_on_failure() {
+        let doc_dir = temp::PrivateTempDir::new().unwrap();
+        let doc_path = doc_dir.path().join("hoge.md.age");
+        std::fs::write(&doc_path, "ciphertext").unwrap();
+
+        let temp_dir = temp::PrivateTempDir::new().unwrap();
+        let temp_dir_path = temp_dir.path().to_owned();
+        let temp_path = temp_dir_path.join("hoge.md");
+        std::fs::write(&temp_path, "edited").unwrap();
+
+        let enc_cfg = EncryptionCfg {
+            decrypt: vec!["cat".to_owned()],
+            encrypt: vec!["false".to_owned()],
+        };
+        let e = save_changes(&enc_cfg, &doc_path, b"original", temp_dir, &temp_path).unwrap_err();
+        assert!(e.to_string().contains(&format!("{:?}", temp_path)));
+        assert_eq!(std::fs::read(&temp_path).unwrap(), b"edited");
+        assert_eq!(std::fs::read(&doc_path).unwrap(), b"ciphertext");
+        assert!(!doc_dir.path().join("hoge.md.age.veisku-new").exists());
+
+        std::fs::remove_dir_all(&temp_dir_path).unwrap();
+    }
+}
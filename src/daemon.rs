@@ -0,0 +1,306 @@
+//! Query daemon (`v daemon`)
+//!
+//! The daemon keeps the paths and metadata of the documents in memory and
+//! answers queries over a Unix domain socket, one connection at a time. The
+//! document root is rescanned periodically in the background, and only the
+//! documents whose modification times or sizes changed are read again.
+//!
+//! # Protocol
+//!
+//! A request is a line of JSON in the form
+//! `{"preset": PRESET, "criteria": [CRITERION, ...]}`, where the criteria use
+//! the common search query syntax. The response is a line of JSON, which is
+//! either `{"paths": [PATH, ...]}` listing the matching documents or
+//! `{"error": MESSAGE}`.
+//!
+//! Before answering a request, the index is refreshed by another scan, which
+//! only reads the documents that changed. If that takes longer than
+//! [`REFRESH_BUDGET`], the request is answered from the last complete scan,
+//! missing the documents that started matching since then. Either way,
+//! clients should check the returned documents again if they need to be
+//! exact (`v ls --daemon` does).
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::{
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{cfg, query_cache::REFRESH_BUDGET, root::DocRoot};
+
+/// The timeout for reading and writing a line on either end of a connection.
+/// The daemon handles one connection at a time, so a stalled client must not
+/// be able to block it, and a stalled daemon must not hang the client.
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize)]
+struct Request {
+    preset: String,
+    criteria: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Response {
+    Paths(Vec<PathBuf>),
+    Error(String),
+}
+
+/// The state of a document used to detect modifications
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Stamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+#[derive(Debug)]
+struct IndexEntry {
+    path: PathBuf,
+    stamp: Stamp,
+    /// `None` if the metadata couldn't be read, in which case it's read again
+    /// by the query to report the error
+    meta: Option<Value>,
+}
+
+/// Get the default socket path of the document root.
+fn socket_path(root: &DocRoot) -> PathBuf {
+    root.state_dir_path.join("daemon.sock")
+}
+
+pub fn verb_daemon(root: &DocRoot, sc: &cfg::Daemon) -> Result<()> {
+    #[cfg(unix)]
+    {
+        unix::serve(root, sc)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (root, sc);
+        bail!("`v daemon` is only available on Unix");
+    }
+}
+
+/// Send a query to the daemon of the document root and get the paths of the
+/// matching documents.
+pub fn query(root: &DocRoot, preset: &str, criteria: &[cfg::Criterion]) -> Result<Vec<PathBuf>> {
+    #[cfg(unix)]
+    {
+        let path = socket_path(root);
+        let stream = std::os::unix::net::UnixStream::connect(&path)
+            .with_context(|| format!("Failed to connect to {:?}", path))?;
+        stream
+            .set_read_timeout(Some(IO_TIMEOUT))
+            .context("Failed to set the read timeout")?;
+        stream
+            .set_write_timeout(Some(IO_TIMEOUT))
+            .context("Failed to set the write timeout")?;
+        let request = Request {
+            preset: preset.to_owned(),
+            criteria: criteria.iter().map(ToString::to_string).collect(),
+        };
+        let mut line = serde_json::to_string(&request).unwrap();
+        line.push('\n');
+        (&stream)
+            .write_all(line.as_bytes())
+            .context("Failed to send the query to the daemon")?;
+
+        let mut line = String::new();
+        BufReader::new(&stream)
+            .read_line(&mut line)
+            .context("Failed to read the response of the daemon")?;
+        match serde_json::from_str(&line).context("Malformed response from the daemon")? {
+            Response::Paths(paths) => Ok(paths),
+            Response::Error(e) => bail!("The daemon failed to process the query: {}", e),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (root, preset, criteria);
+        bail!("`v daemon` is only available on Unix");
+    }
+}
+
+/// Scan the document root, reusing the metadata in `old` for unmodified
+/// documents. Returns `None` if it takes longer than `budget`.
+fn scan(
+    root: &DocRoot,
+    old: &[IndexEntry],
+    budget: Option<Duration>,
+) -> Result<Option<Vec<IndexEntry>>> {
+    let start = Instant::now();
+    let old: std::collections::HashMap<&PathBuf, &IndexEntry> =
+        old.iter().map(|entry| (&entry.path, entry)).collect();
+    let mut entries = Vec::new();
+    for doc in root.docs() {
+        if budget.is_some_and(|budget| start.elapsed() > budget) {
+            return Ok(None);
+        }
+        let mut doc = doc.context("An error occurred while enumerating documents")?;
+        // The document might have been deleted in the meantime
+        let metadata = match std::fs::metadata(doc.path()) {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+        let stamp = Stamp {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        };
+        let path = doc.path().to_owned();
+        let meta = match old.get(&path) {
+            Some(entry) if entry.stamp == stamp => entry.meta.clone(),
+            _ => doc.ensure_meta().ok().cloned(),
+        };
+        entries.push(IndexEntry { path, stamp, meta });
+    }
+    Ok(Some(entries))
+}
+
+/// Evaluate a query on the documents in `index`.
+fn answer(root: &DocRoot, index: &[IndexEntry], request: &Request) -> Result<Vec<PathBuf>> {
+    let criteria = request
+        .criteria
+        .iter()
+        .map(|s| {
+            s.parse::<cfg::Criterion>()
+                .map_err(|e| anyhow::anyhow!("'{}': {}", s, e))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut query = crate::query::Query::new(root, &request.preset, &criteria)?;
+    query.restrict_to_docs(
+        index
+            .iter()
+            .map(|entry| (entry.path.clone(), entry.meta.clone()))
+            .collect(),
+    );
+    crate::query::select_all(root, &query)
+        .map(|doc| Ok(doc?.path().to_owned()))
+        .collect::<Result<Vec<_>>>()
+        .context("An error occurred while enumerating matching documents")
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::{
+        os::unix::net::{UnixListener, UnixStream},
+        sync::{Arc, Mutex},
+    };
+
+    use super::*;
+
+    type SharedIndex = Mutex<Arc<Vec<IndexEntry>>>;
+
+    pub fn serve(root: &DocRoot, sc: &cfg::Daemon) -> Result<()> {
+        let interval = Duration::try_from_secs_f64(sc.interval.max(0.01))
+            .map_err(|_| anyhow::anyhow!("Invalid interval: {:?}", sc.interval))?;
+        let path = match &sc.socket {
+            Some(path) => path.clone(),
+            None => {
+                root.state_dir().prepare()?;
+                socket_path(root)
+            }
+        };
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                bail!("Another daemon is already listening on {:?}", path);
+            }
+            // Left by a daemon that didn't exit cleanly
+            std::fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove the stale socket {:?}", path))?;
+        }
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+        }
+
+        let index: SharedIndex = Mutex::new(Arc::new(scan(root, &[], None)?.unwrap()));
+        log::info!("Indexed {} document(s)", index.lock().unwrap().len());
+
+        let listener =
+            UnixListener::bind(&path).with_context(|| format!("Failed to bind to {:?}", path))?;
+        log::info!("Listening on {:?}", path);
+
+        std::thread::scope(|s| {
+            s.spawn(|| loop {
+                std::thread::sleep(interval);
+                if let Err(e) = refresh(root, &index, None) {
+                    log::warn!("{:?}", e);
+                }
+            });
+
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(x) => x,
+                    Err(e) => {
+                        log::warn!("Failed to accept a connection: {:?}", e);
+                        continue;
+                    }
+                };
+                if let Err(e) = handle_connection(root, &index, stream) {
+                    log::warn!("Failed to handle a request: {:?}", e);
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Rescan the document root and replace the index (see [`scan`]).
+    /// Returns the new index, or `None` if the scan took longer than
+    /// `budget`.
+    fn refresh(
+        root: &DocRoot,
+        index: &SharedIndex,
+        budget: Option<Duration>,
+    ) -> Result<Option<Arc<Vec<IndexEntry>>>> {
+        let old = Arc::clone(&index.lock().unwrap());
+        let new = match scan(root, &old, budget)? {
+            Some(new) => Arc::new(new),
+            None => return Ok(None),
+        };
+        *index.lock().unwrap() = Arc::clone(&new);
+        Ok(Some(new))
+    }
+
+    fn handle_connection(root: &DocRoot, index: &SharedIndex, stream: UnixStream) -> Result<()> {
+        stream
+            .set_read_timeout(Some(IO_TIMEOUT))
+            .context("Failed to set the read timeout")?;
+        stream
+            .set_write_timeout(Some(IO_TIMEOUT))
+            .context("Failed to set the write timeout")?;
+
+        let mut line = String::new();
+        BufReader::new(&stream)
+            .read_line(&mut line)
+            .context("Failed to read the request")?;
+        log::debug!("Request: {:?}", line.trim_end());
+
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                let index = match refresh(root, index, Some(REFRESH_BUDGET)) {
+                    Ok(Some(index)) => index,
+                    result => {
+                        if let Err(e) = result {
+                            log::warn!("{:?}", e);
+                        }
+                        log::debug!("Answering from the last complete scan");
+                        Arc::clone(&index.lock().unwrap())
+                    }
+                };
+                match answer(root, &index, &request) {
+                    Ok(paths) => Response::Paths(paths),
+                    Err(e) => Response::Error(format!("{:#}", e)),
+                }
+            }
+            Err(e) => Response::Error(format!("Malformed request: {}", e)),
+        };
+
+        let mut line = serde_json::to_string(&response)
+            .unwrap_or_else(|e| serde_json::to_string(&Response::Error(e.to_string())).unwrap());
+        line.push('\n');
+        (&stream)
+            .write_all(line.as_bytes())
+            .context("Failed to write the response")?;
+        Ok(())
+    }
+}
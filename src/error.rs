@@ -0,0 +1,115 @@
+//! Machine-readable error output (`--error-format json`)
+use anyhow::Error;
+use serde_json::{json, Map, Value};
+use std::io::Write;
+
+use crate::{
+    doc::{self, DocRead},
+    query::SelectOneError,
+    render,
+    root::{self, DocRoot},
+};
+
+/// Write `e` to `out` as a single-line JSON object.
+///
+/// The object always contains `kind`, `message` (the outermost message), and
+/// `causes` (the messages of the whole error chain). Depending on `kind`, it
+/// contains the following fields:
+///
+///  - `"ambiguous"`: `candidates` (the paths of some of the matching
+///    documents), `total` (the number of the matching documents), and
+///    `truncated`
+///  - `"no_match"`: none
+///  - `"parse"`: `path`, `line` and `column` (one-based) if known, and
+///    `suggestion` (the key probably intended by an unknown key in
+///    `config.toml`) if any
+///  - `"other"`: none
+pub fn write_json(out: &mut impl Write, e: &Error) -> std::io::Result<()> {
+    let mut obj = Map::new();
+    obj.insert("kind".to_owned(), json!("other"));
+    obj.insert("message".to_owned(), json!(e.to_string()));
+    obj.insert(
+        "causes".to_owned(),
+        e.chain().map(|cause| json!(cause.to_string())).collect(),
+    );
+
+    for cause in e.chain() {
+        if let Some(e) = cause.downcast_ref::<SelectOneError>() {
+            match e {
+                SelectOneError::Empty => {
+                    obj.insert("kind".to_owned(), json!("no_match"));
+                }
+                SelectOneError::Ambiguous { candidates, total } => {
+                    obj.insert("kind".to_owned(), json!("ambiguous"));
+                    obj.insert(
+                        "candidates".to_owned(),
+                        candidates
+                            .iter()
+                            .map(|doc| json!(doc.path().to_string_lossy()))
+                            .collect(),
+                    );
+                    obj.insert("total".to_owned(), json!(total));
+                    obj.insert("truncated".to_owned(), json!(*total > candidates.len()));
+                }
+                SelectOneError::Misc(_) => {}
+            }
+        } else if let Some(e) = cause.downcast_ref::<doc::MetaReadError>() {
+            obj.insert("kind".to_owned(), json!("parse"));
+            obj.insert("path".to_owned(), json!(e.path.to_string_lossy()));
+        } else if let Some(e) = cause.downcast_ref::<serde_yaml::Error>() {
+            if let Some(loc) = e.location() {
+                // Account for the opening separator of the preamble
+                obj.insert("line".to_owned(), json!(loc.line() + 1));
+                obj.insert("column".to_owned(), json!(loc.column() + 1));
+            }
+        }
+    }
+
+    // A context object isn't a part of `chain()`
+    if let Some(e) = e.downcast_ref::<root::CfgParseError>() {
+        obj.insert("kind".to_owned(), json!("parse"));
+        obj.insert("path".to_owned(), json!(e.path.to_string_lossy()));
+        if let (Some(line), Some(column)) = (e.line, e.column) {
+            obj.insert("line".to_owned(), json!(line + 1));
+            obj.insert("column".to_owned(), json!(column + 1));
+        }
+        if let Some(suggestion) = &e.suggestion {
+            obj.insert("suggestion".to_owned(), json!(suggestion));
+        }
+    }
+
+    writeln!(out, "{}", Value::Object(obj))
+}
+
+/// Write the error message of [`SelectOneError::Ambiguous`], displaying the
+/// candidates in the same way as `v ls`.
+pub fn write_ambiguous(
+    out: &mut impl Write,
+    root: &DocRoot,
+    candidates: &[DocRead],
+    total: usize,
+) -> std::io::Result<()> {
+    writeln!(
+        out,
+        "Error: Ambigous document selection ({} documents matched). Candidates:",
+        total
+    )?;
+    for (i, doc) in candidates.iter().enumerate() {
+        let path = doc.path();
+        // Don't ask for a passphrase just to display the title
+        let meta = if root.cfg.encryption_for(path).is_some() {
+            serde_yaml::Value::Null
+        } else {
+            root.doc_at(path.to_owned())
+                .ensure_meta()
+                .map_or(serde_yaml::Value::Null, Clone::clone)
+        };
+        write!(out, " {:2}. ", i + 1)?;
+        render::write_doc_summary(out, &root.cfg.theme, path, &meta)?;
+        writeln!(out)?;
+    }
+    if total > candidates.len() {
+        writeln!(out, "     ({} more)", total - candidates.len())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,150 @@
+//! Document conversion (`v export`)
+use anyhow::{bail, Context, Result};
+use serde_yaml::Value;
+use std::{ffi::OsString, io::Write, path::Path};
+
+use crate::{
+    bib,
+    cfg::{self, ExportCfg},
+    ics, query,
+    root::DocRoot,
+    site,
+};
+
+pub fn verb_export(root: &DocRoot, sc: &cfg::Export) -> Result<()> {
+    let format = match (&sc.format, &sc.site) {
+        (_, Some(dir)) => return site::export_site(root, &sc.query, dir),
+        _ if sc.ics => return ics::export_ics(root, &sc.query, sc.output.as_deref()),
+        (Some(format), None) => format,
+        (None, None) => unreachable!(),
+    };
+    let export_cfg = match root.cfg.export.get(format) {
+        Some(export_cfg) => export_cfg,
+        None if format == "csl-json" => return export_csl_json(root, sc),
+        None => bail!("Unknown export format: '{}'", format),
+    };
+    if export_cfg.command.is_empty() {
+        bail!("The command for the export format '{}' is empty", format);
+    }
+    let extension = export_cfg.extension.as_deref().unwrap_or(format);
+
+    // The converter runs in the document root, so make the path absolute
+    let out_dir = std::env::current_dir()
+        .context("Failed to determine the current directory")?
+        .join(
+            sc.output
+                .as_ref()
+                .context("The output directory (`-o`) is required")?,
+        );
+
+    let query = sc.query.compile(root)?;
+
+    let mut num_exported = 0;
+    for doc_or_error in query::select_all(root, &query) {
+        let mut doc =
+            doc_or_error.context("An error occurred while enumerating matching documents")?;
+
+        // Preserve the directory layout relative to the document root
+        let rel_path = doc.path().strip_prefix(&root.path).unwrap_or(doc.path());
+        let output = out_dir.join(rel_path).with_extension(extension);
+        if let Some(parent) = output.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {:?}", parent))?;
+        }
+
+        let path = doc.path().to_owned();
+        let meta = doc
+            .ensure_meta()
+            .with_context(|| format!("Failed to read the metadata of {:?}", path))?;
+        let argv = converter_argv(export_cfg, &path, &output, meta);
+
+        log::info!("Exporting {:?} to {:?}", doc.path(), output);
+        let mut cmd = crate::new_command(&argv);
+        cmd.current_dir(&root.path);
+        crate::set_command_env(&mut cmd, root);
+        log::debug!("Spawning {:?}", cmd);
+        let status = cmd
+            .status()
+            .with_context(|| format!("Failed to execute {:?}", argv[0]))?;
+        if !status.success() {
+            bail!("The converter failed on {:?} ({})", doc.path(), status);
+        }
+
+        num_exported += 1;
+    }
+
+    log::info!("Exported {} document(s)", num_exported);
+
+    Ok(())
+}
+
+/// Export the bibliography entries as a CSL-JSON array (`--to csl-json`).
+fn export_csl_json(root: &DocRoot, sc: &cfg::Export) -> Result<()> {
+    let query = sc.query.compile(root)?;
+    let items: Vec<serde_json::Value> = bib::entries(root, &query)?
+        .iter()
+        .map(bib::to_csl)
+        .collect();
+    let mut json = serde_json::to_string_pretty(&items).unwrap();
+    json.push('\n');
+
+    match &sc.output {
+        Some(path) => {
+            std::fs::write(path, json).with_context(|| format!("Failed to write {:?}", path))?;
+            log::info!("Exported {} item(s) to {:?}", items.len(), path);
+        }
+        None => std::io::stdout().write_all(json.as_bytes())?,
+    }
+    Ok(())
+}
+
+/// Construct the converter command line for a document.
+fn converter_argv(
+    export_cfg: &ExportCfg,
+    input: &Path,
+    output: &Path,
+    meta: &Value,
+) -> Vec<OsString> {
+    // Generate the arguments for metadata fields
+    let mut meta_argv = Vec::new();
+    if let Value::Mapping(mapping) = meta {
+        for (key, value) in mapping.iter() {
+            let key = match key.as_str() {
+                Some(x) => x,
+                None => continue,
+            };
+            let values: Vec<String> = match value {
+                Value::Sequence(array) => array.iter().filter_map(scalar_to_string).collect(),
+                _ => scalar_to_string(value).into_iter().collect(),
+            };
+            for value in values.iter() {
+                meta_argv.extend(export_cfg.meta_args.iter().map(|arg| {
+                    OsString::from(arg.replace("{key}", key).replace("{value}", value))
+                }));
+            }
+        }
+    }
+
+    let mut argv = Vec::with_capacity(export_cfg.command.len() + meta_argv.len());
+    let mut meta_argv = Some(meta_argv);
+    for arg in export_cfg.command.iter() {
+        match &**arg {
+            "{input}" => argv.push(input.into()),
+            "{output}" => argv.push(output.into()),
+            "{meta}" => argv.extend(meta_argv.take().into_iter().flatten()),
+            _ => argv.push(arg.into()),
+        }
+    }
+    argv.extend(meta_argv.into_iter().flatten());
+
+    argv
+}
+
+fn scalar_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(st) => Some(st.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
@@ -0,0 +1,233 @@
+//! Recurring documents (`v generate`)
+use anyhow::{anyhow, bail, Result};
+use chrono::{Datelike, Duration, NaiveDate};
+use std::{fmt::Write as _, str::FromStr};
+
+use crate::{cfg, new, root::DocRoot, template};
+
+/// How far back to look for the last due date of a schedule
+const MAX_LOOKBACK_DAYS: i64 = 366;
+
+pub fn verb_generate(root: &DocRoot, sc: &cfg::Generate) -> Result<()> {
+    for name in sc.names.iter() {
+        if !root.cfg.generate.contains_key(name) {
+            bail!("Unknown recurring document: '{}'", name);
+        }
+    }
+    if root.cfg.generate.is_empty() {
+        log::info!("No recurring documents are defined (`generate` in `config.toml`)");
+        return Ok(());
+    }
+
+    let now = chrono::Local::now();
+    let today = now.date_naive();
+    let mut num_created = 0;
+    for (name, gen_cfg) in root.cfg.generate.iter() {
+        if !sc.names.is_empty() && !sc.names.contains(name) {
+            continue;
+        }
+        let schedule: Schedule = gen_cfg
+            .schedule
+            .parse()
+            .map_err(|e| anyhow!("Invalid `generate.{}.schedule`: {}", name, e))?;
+
+        let dates: Vec<NaiveDate> = match sc.backfill {
+            Some(days) => {
+                // There's nothing to backfill before the earliest date
+                let days = i64::from(days).min((today - NaiveDate::MIN).num_days());
+                (0..=days)
+                    .rev()
+                    .filter_map(|i| today.checked_sub_signed(Duration::days(i)))
+                    .filter(|&date| schedule.matches(date))
+                    .collect()
+            }
+            None => (0..=MAX_LOOKBACK_DAYS)
+                .filter_map(|i| today.checked_sub_signed(Duration::days(i)))
+                .find(|&date| schedule.matches(date))
+                .into_iter()
+                .collect(),
+        };
+
+        for date in dates {
+            let mut path = String::new();
+            write!(path, "{}", date.format(&gen_cfg.path))
+                .map_err(|_| anyhow!("Invalid `generate.{}.path`: {:?}", name, gen_cfg.path))?;
+            let path = root.path.join(path);
+            if path.exists() {
+                log::debug!("{:?} ({}) already exists", path, name);
+                continue;
+            }
+            println!("{}", path.display());
+            if sc.dry_run {
+                continue;
+            }
+
+            let title = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let mut vars =
+                template::Vars::new(std::iter::once(("title".to_owned(), title)).collect());
+            if let Some(time) = date
+                .and_time(now.time())
+                .and_local_timezone(chrono::Local)
+                .earliest()
+            {
+                vars = vars.with_now(time);
+            }
+            let template_name = gen_cfg.template.as_deref().unwrap_or("default");
+            new::create_from_template(root, &path, template_name, vars)?;
+            num_created += 1;
+        }
+    }
+
+    if sc.dry_run {
+        log::info!("Dry run; nothing was created");
+    } else {
+        log::info!("Created {} document(s)", num_created);
+    }
+    Ok(())
+}
+
+/// The days matching the date fields of a cron expression (`DAY MONTH
+/// WEEKDAY`)
+#[derive(Debug, PartialEq)]
+struct Schedule {
+    days: Field,
+    months: Field,
+    weekdays: Field,
+}
+
+/// A field of [`Schedule`]
+#[derive(Debug, PartialEq)]
+struct Field {
+    /// The set of matching values as a bit mask
+    mask: u64,
+    /// `false` if the field is `*`
+    restricted: bool,
+}
+
+const MONTH_NAMES: &[&str] = &[
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAY_NAMES: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+impl Schedule {
+    fn matches(&self, date: NaiveDate) -> bool {
+        let day = self.days.contains(date.day());
+        let weekday = self
+            .weekdays
+            .contains(date.weekday().num_days_from_sunday());
+        self.months.contains(date.month())
+            && if self.days.restricted && self.weekdays.restricted {
+                day || weekday
+            } else {
+                day && weekday
+            }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [days, months, weekdays] = match fields[..] {
+            [days, months, weekdays] => [days, months, weekdays],
+            _ => return Err("Expected three fields: `DAY MONTH WEEKDAY`".to_owned()),
+        };
+        let mut weekdays = Field::parse(weekdays, 0, 7, WEEKDAY_NAMES, 0)?;
+        // Sunday can be written as `7`
+        if weekdays.mask & (1 << 7) != 0 {
+            weekdays.mask = (weekdays.mask & !(1 << 7)) | 1;
+        }
+        Ok(Self {
+            days: Field::parse(days, 1, 31, &[], 1)?,
+            months: Field::parse(months, 1, 12, MONTH_NAMES, 1)?,
+            weekdays,
+        })
+    }
+}
+
+impl Field {
+    fn contains(&self, value: u32) -> bool {
+        self.mask & (1 << value) != 0
+    }
+
+    /// Parse a field whose values range over `min..=max`. `names[i]` is an
+    /// alias of the value `first_name + i`.
+    fn parse(s: &str, min: u32, max: u32, names: &[&str], first_name: u32) -> Result<Self, String> {
+        let value = |text: &str| -> Result<u32, String> {
+            let lower = text.to_ascii_lowercase();
+            let value = match names.iter().position(|&name| name == lower) {
+                Some(i) => first_name + i as u32,
+                None => text
+                    .parse()
+                    .map_err(|_| format!("Invalid value '{}'", text))?,
+            };
+            if (min..=max).contains(&value) {
+                Ok(value)
+            } else {
+                Err(format!("'{}' is out of range {}-{}", text, min, max))
+            }
+        };
+
+        let mut mask = 0u64;
+        for item in s.split(',') {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => match step.parse::<u32>() {
+                    Ok(step) if step > 0 => (range, step),
+                    _ => return Err(format!("Invalid step in '{}'", item)),
+                },
+                None => (item, 1),
+            };
+            let (start, end) = match range.split_once('-') {
+                _ if range == "*" => (min, max),
+                Some((start, end)) => (value(start)?, value(end)?),
+                // `N/STEP` means `N-MAX/STEP`
+                None if item.contains('/') => (value(range)?, max),
+                None => {
+                    let value = value(range)?;
+                    (value, value)
+                }
+            };
+            if start > end {
+                return Err(format!("Invalid range '{}'", range));
+            }
+            for value in (start..=end).step_by(step as usize) {
+                mask |= 1 << value;
+            }
+        }
+        Ok(Self {
+            mask,
+            restricted: s != "*",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule() {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        let matches = |s: &str, date| s.parse::<Schedule>().unwrap().matches(date);
+
+        // 2024-01-05 is a Friday
+        assert!(matches("* * fri", date(2024, 1, 5)));
+        assert!(!matches("* * fri", date(2024, 1, 6)));
+        assert!(matches("* * 7", date(2024, 1, 7)));
+        assert!(matches("1 * *", date(2024, 2, 1)));
+        assert!(matches("1 */3 *", date(2024, 4, 1)));
+        assert!(!matches("1 */3 *", date(2024, 5, 1)));
+        assert!(matches("1-7 * Mon-Wed", date(2024, 1, 5)));
+        assert!(matches("1,15 jan *", date(2024, 1, 15)));
+        assert!(!matches("1,15 jan *", date(2024, 2, 15)));
+
+        assert!("* *".parse::<Schedule>().is_err());
+        assert!("32 * *".parse::<Schedule>().is_err());
+        assert!("* * fri-mon".parse::<Schedule>().is_err());
+        assert!("*/0 * *".parse::<Schedule>().is_err());
+    }
+}
@@ -0,0 +1,209 @@
+//! Searching document bodies (`v grep`)
+use anyhow::{bail, Context, Result};
+use std::{io::Write, path::PathBuf};
+use veisku_core::{
+    cfg::{Criterion, RegexCriterion, SimpleCriterion},
+    doc::DocRead,
+    journal::Journal,
+};
+
+use crate::{cfg, doc, meta, pick, query, render, root::DocRoot};
+
+pub fn verb_grep(root: &DocRoot, opts: &cfg::Opts, sc: &cfg::Grep) -> Result<()> {
+    let criterion = Criterion::Simple {
+        negate: false,
+        simple_criterion: SimpleCriterion::MetaRegex(
+            "contents".to_owned(),
+            RegexCriterion {
+                pattern: sc.pattern.clone(),
+                flags: if sc.ignore_case { "i" } else { "" }.to_owned(),
+            },
+        ),
+    };
+
+    if let Some(replacement) = &sc.replace {
+        let query = sc
+            .query
+            .compile_with(root, std::slice::from_ref(&criterion))?;
+        let docs = query::select_all_sorted(root, &query)
+            .context("An error occurred while enumerating matching documents")?;
+        return replace_in_bodies(root, sc, criterion, &docs, replacement);
+    }
+
+    let registered_roots;
+    let roots: Vec<(Option<&str>, &DocRoot)> = if sc.all_roots {
+        registered_roots = crate::open_registered_roots(opts)?;
+        registered_roots
+            .iter()
+            .map(|(name, root)| (Some(&**name), root))
+            .collect()
+    } else {
+        vec![(None, root)]
+    };
+
+    let mut hits: Vec<(&DocRoot, PathBuf, query::ContentMatch)> = Vec::new();
+    let mut lines: Vec<String> = Vec::new();
+    for &(name, root) in roots.iter() {
+        let query = sc
+            .query
+            .compile_with(root, std::slice::from_ref(&criterion))?;
+        let docs = query::select_all_sorted(root, &query)
+            .context("An error occurred while enumerating matching documents")?;
+        for doc in docs {
+            let matches = query
+                .content_matches(&doc)
+                .with_context(|| format!("Failed to search {:?}", doc.path()))?;
+            for m in matches {
+                let path = sc.path_format.apply(root, doc.path());
+                let mut line = match name {
+                    Some(name) => format!("{}\t", name),
+                    None => String::new(),
+                };
+                if sc.vimgrep {
+                    line += &render::vimgrep_line(&path, m.line, m.column, &m.text);
+                } else {
+                    line += &format!("{}:{}:{}", path.display(), m.line, m.text);
+                }
+                lines.push(line);
+                hits.push((root, doc.path().to_owned(), m));
+            }
+        }
+    }
+
+    if sc.edit {
+        let (root, path, m) = match hits.len() {
+            0 => bail!("No lines matched"),
+            1 => &hits[0],
+            _ => &hits[pick::pick_line(root, &lines)?],
+        };
+        crate::record_history(root, &[path]);
+        let goto = if root.cfg.open.goto.is_empty() {
+            vec!["+{line}".to_owned()]
+        } else {
+            root.cfg.open.goto.clone()
+        };
+        return crate::exec(&mut crate::goto_command(root, &goto, path, m, false))
+            .map(|x| match x {});
+    }
+
+    let mut out = render::Pager::new(opts);
+    for line in &lines {
+        writeln!(out, "{}", line)?;
+    }
+    out.finish()?;
+
+    if hits.is_empty() {
+        crate::log_diagnostics();
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Replace the matches in the bodies of `docs` (`--replace`), asking for
+/// confirmation for each document.
+fn replace_in_bodies(
+    root: &DocRoot,
+    sc: &cfg::Grep,
+    criterion: Criterion,
+    docs: &[DocRead],
+    replacement: &str,
+) -> Result<()> {
+    if !sc.dry_run {
+        root.ensure_writable()?;
+    }
+    // Don't replace the matches of `contents:` criteria given in addition to
+    // the pattern
+    let query = query::Query::new(root, "", &[criterion])?;
+
+    let mut changes: Vec<(PathBuf, Vec<u8>, Vec<u8>, usize)> = Vec::new();
+    for doc in docs {
+        let path = doc.path();
+        if !doc.doc_type().has_preamble() {
+            log::warn!("Skipping {:?}, whose metadata can't be told apart", path);
+            continue;
+        }
+        if root.cfg.encryption_for(path).is_some() {
+            log::warn!("Skipping the encrypted document {:?}", path);
+            continue;
+        }
+        let bytes = std::fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+        let body_start = bytes.len() - doc::split_md_preamble(&bytes).1.len();
+        let body = match std::str::from_utf8(&bytes[body_start..]) {
+            Ok(body) => body,
+            Err(_) => {
+                log::warn!("Skipping {:?}, whose body is not valid UTF-8", path);
+                continue;
+            }
+        };
+        let (new_body, count) = match query.replace_content_matches(body, replacement) {
+            Some(x) => x,
+            None => {
+                log::warn!(
+                    "Skipping {:?}, whose matches differ from those in the normalized text \
+                    (Unicode normalization or `ascii_search`)",
+                    path
+                );
+                continue;
+            }
+        };
+        if new_body == body {
+            continue;
+        }
+        let mut new_bytes = bytes[..body_start].to_owned();
+        new_bytes.extend_from_slice(new_body.as_bytes());
+
+        let rel_path = path.strip_prefix(&root.path).unwrap_or(path);
+        render::write_diff(
+            &mut std::io::stdout(),
+            &root.cfg.theme,
+            rel_path,
+            &bytes,
+            &new_bytes,
+        )?;
+        if sc.dry_run {
+            continue;
+        }
+        let question = format!("Replace {} match(es) in {}?", count, rel_path.display());
+        if meta::confirm(&question, false)? {
+            changes.push((path.to_owned(), bytes, new_bytes, count));
+        }
+    }
+
+    if sc.dry_run {
+        log::info!("Dry run; nothing was modified");
+        return Ok(());
+    }
+    if changes.is_empty() {
+        log::info!("No documents were modified");
+        return Ok(());
+    }
+
+    let journal = Journal::new(root);
+    let mut recorder = journal.begin(format!(
+        "grep --replace {:?} {:?} ({} document(s))",
+        sc.pattern,
+        replacement,
+        changes.len()
+    ));
+    let (mut num_docs, mut num_matches) = (0, 0);
+    for (path, bytes, new_bytes, count) in changes.iter() {
+        // The document may have been edited while we were asking
+        if std::fs::read(path).ok().as_ref() != Some(bytes) {
+            log::warn!("{:?} was modified in the meantime; not updating it", path);
+            continue;
+        }
+        recorder
+            .write_atomic(path, new_bytes)
+            .with_context(|| format!("Failed to update {:?}", path))?;
+        num_docs += 1;
+        num_matches += count;
+    }
+    recorder.commit()?;
+
+    log::info!(
+        "Replaced {} match(es) in {} document(s)",
+        num_matches,
+        num_docs
+    );
+    Ok(())
+}
@@ -0,0 +1,187 @@
+//! Directory summaries (`v ls --group-by dir`) and duplicate reports
+//! (`v ls --duplicates-by KEY`)
+use anyhow::{Context, Result};
+use serde_yaml::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{cfg, query, render, root::DocRoot, schema};
+
+/// The maximum number of tags displayed for each directory
+const MAX_TAGS: usize = 5;
+
+#[derive(Default)]
+struct DirSummary {
+    num_docs: usize,
+    tags: HashMap<String, usize>,
+}
+
+pub fn verb_ls_group_by_dir(root: &DocRoot, opts: &cfg::Opts, sc: &cfg::List) -> Result<()> {
+    let depth = sc.depth.unwrap_or(1);
+    let query = sc.query.compile(root)?;
+
+    let mut dirs: BTreeMap<PathBuf, DirSummary> = BTreeMap::new();
+    for doc_or_error in query::select_all(root, &query) {
+        let mut doc =
+            doc_or_error.context("An error occurred while enumerating matching documents")?;
+        let path = doc.path().to_owned();
+        let rel_path = path.strip_prefix(&root.path).unwrap_or(&path);
+        let summary = dirs.entry(group_dir(rel_path, depth)).or_default();
+        summary.num_docs += 1;
+
+        let meta = doc
+            .ensure_meta()
+            .with_context(|| format!("Failed to read the metadata of {:?}", path))?;
+        let tags: Vec<String> = match &meta["tags"] {
+            Value::Sequence(tags) => tags.iter().filter_map(schema::scalar_text).collect(),
+            value => schema::scalar_text(value).into_iter().collect(),
+        };
+        for tag in tags {
+            *summary.tags.entry(tag).or_default() += 1;
+        }
+    }
+
+    let mut out = render::Pager::new(opts);
+    for (dir, summary) in &dirs {
+        let mut tags: Vec<(&String, &usize)> = summary.tags.iter().collect();
+        tags.sort_by(|(name1, count1), (name2, count2)| {
+            count2.cmp(count1).then_with(|| name1.cmp(name2))
+        });
+        let mut tag_list: Vec<String> = tags
+            .iter()
+            .take(MAX_TAGS)
+            .map(|(name, count)| format!("{}({})", name, count))
+            .collect();
+        if tags.len() > MAX_TAGS {
+            tag_list.push("...".to_owned());
+        }
+        let dir = if dir.as_os_str().is_empty() {
+            ".".to_owned()
+        } else {
+            format!("{}/", dir.display())
+        };
+        writeln!(
+            out,
+            "{:>6}  {}  {}",
+            summary.num_docs,
+            dir,
+            tag_list.join(" ")
+        )?;
+    }
+    out.finish()?;
+    Ok(())
+}
+
+pub fn verb_ls_duplicates_by(
+    root: &DocRoot,
+    opts: &cfg::Opts,
+    sc: &cfg::List,
+    key: &str,
+) -> Result<()> {
+    let query = sc.query.compile(root)?;
+
+    let mut doc_values: Vec<(PathBuf, BTreeSet<String>)> = Vec::new();
+    for doc_or_error in query::select_all(root, &query) {
+        let mut doc =
+            doc_or_error.context("An error occurred while enumerating matching documents")?;
+        let path = doc.path().to_owned();
+        let values = if key == "name" {
+            path.file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .into_iter()
+                .collect()
+        } else {
+            let meta = doc
+                .ensure_meta()
+                .with_context(|| format!("Failed to read the metadata of {:?}", path))?;
+            match &meta[key] {
+                Value::Sequence(values) => values.iter().filter_map(schema::scalar_text).collect(),
+                value => schema::scalar_text(value).into_iter().collect(),
+            }
+        };
+        doc_values.push((path, values));
+    }
+
+    let groups = duplicate_groups(doc_values);
+    let mut out = render::Pager::new(opts);
+    for (value, paths) in &groups {
+        writeln!(out, "{}: {} ({} documents)", key, value, paths.len())?;
+        for path in paths {
+            writeln!(out, "  {}", sc.path_format.apply(root, path).display())?;
+        }
+    }
+    out.finish()?;
+    if groups.is_empty() {
+        log::info!("No documents share the same `{}`", key);
+    }
+    Ok(())
+}
+
+/// Group documents by the values they have, given the values of each
+/// document. Only the values shared by more than one document are included.
+fn duplicate_groups(
+    doc_values: Vec<(PathBuf, BTreeSet<String>)>,
+) -> BTreeMap<String, Vec<PathBuf>> {
+    let mut groups: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for (path, values) in doc_values {
+        for value in values {
+            groups.entry(value).or_default().push(path.clone());
+        }
+    }
+    groups.retain(|_, paths| paths.len() > 1);
+    groups
+}
+
+/// Get the directory under which a document (given by a path relative to
+/// the document root) is counted. Directories deeper than `depth` are merged
+/// into their ancestors.
+fn group_dir(rel_path: &Path, depth: usize) -> PathBuf {
+    rel_path
+        .parent()
+        .map(|dir| dir.components().take(depth).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_dir() {
+        assert_eq!(group_dir(Path::new("a.md"), 1), Path::new(""));
+        assert_eq!(group_dir(Path::new("x/a.md"), 1), Path::new("x"));
+        assert_eq!(group_dir(Path::new("x/y/z/a.md"), 1), Path::new("x"));
+        assert_eq!(group_dir(Path::new("x/y/z/a.md"), 2), Path::new("x/y"));
+        assert_eq!(group_dir(Path::new("x/y/a.md"), 0), Path::new(""));
+    }
+
+    #[test]
+    fn test_duplicate_groups() {
+        let doc = |path: &str, values: &[&str]| {
+            (
+                PathBuf::from(path),
+                values.iter().map(|&v| v.to_owned()).collect(),
+            )
+        };
+        let groups = duplicate_groups(vec![
+            doc("a.md", &["x", "y"]),
+            doc("b.md", &["y"]),
+            doc("c.md", &[]),
+            doc("d.md", &["x", "z"]),
+        ]);
+        let groups: Vec<(&str, Vec<&Path>)> = groups
+            .iter()
+            .map(|(value, paths)| (&**value, paths.iter().map(|p| &**p).collect()))
+            .collect();
+        assert_eq!(
+            groups,
+            [
+                ("x", vec![Path::new("a.md"), Path::new("d.md")]),
+                ("y", vec![Path::new("a.md"), Path::new("b.md")]),
+            ]
+        );
+    }
+}
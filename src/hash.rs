@@ -0,0 +1,26 @@
+//! Content digests (`v hash`)
+use anyhow::{Context, Result};
+use std::io::Write;
+
+use crate::{cfg, doc::content_digest, query, render, root::DocRoot};
+
+pub fn verb_hash(root: &DocRoot, opts: &cfg::Opts, sc: &cfg::Hash) -> Result<()> {
+    let query = sc.query.compile(root)?;
+    let docs = query::select_all_sorted(root, &query)
+        .context("An error occurred while enumerating matching documents")?;
+    let mut out = render::Pager::new(opts);
+
+    for doc in docs {
+        let data = if sc.body {
+            doc.read_body()
+                .with_context(|| format!("Failed to read the body of {:?}", doc.path()))?
+        } else {
+            std::fs::read(doc.path()).with_context(|| format!("Failed to read {:?}", doc.path()))?
+        };
+        let path = sc.path_format.apply(root, doc.path());
+        writeln!(out, "{}  {}", content_digest(&data), path.display())?;
+    }
+
+    out.finish()?;
+    Ok(())
+}
@@ -0,0 +1,194 @@
+//! iCalendar export (`v export --ics`)
+use anyhow::{Context, Result};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use std::{io::Write, path::Path};
+
+use crate::{cfg, query, root::DocRoot, schema};
+
+/// The field holding the deadline of a to-do
+const DUE_FIELD: &str = "due";
+/// The field holding the date of an event
+const EVENT_FIELD: &str = "event_date";
+
+/// A date or a date and time (in the local time zone) read from a document
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum IcsDate {
+    Date(NaiveDate),
+    DateTime(NaiveDateTime),
+}
+
+pub fn export_ics(root: &DocRoot, query: &cfg::Query, output: Option<&Path>) -> Result<()> {
+    let query = query.compile(root)?;
+    let docs = query::select_all_sorted(root, &query)
+        .context("An error occurred while enumerating matching documents")?;
+
+    let stamp = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut out = String::new();
+    out.push_str("BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//veisku//veisku//EN\r\n");
+
+    let mut num_components = 0;
+    for mut doc in docs {
+        let path = doc.path().to_owned();
+        let rel_path = path.strip_prefix(&root.path).unwrap_or(&path);
+        let meta = doc
+            .ensure_meta()
+            .with_context(|| format!("Failed to read the metadata of {:?}", path))?;
+        let summary = schema::scalar_text(&meta["title"]).unwrap_or_else(|| {
+            path.file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        });
+
+        for (field, component, property) in [
+            (DUE_FIELD, "VTODO", "DUE"),
+            (EVENT_FIELD, "VEVENT", "DTSTART"),
+        ] {
+            let date = match schema::scalar_text(&meta[field]) {
+                Some(text) => match parse_date(&text) {
+                    Some(date) => date,
+                    None => {
+                        log::warn!("{:?}: `{}` isn't a date: {:?}", path, field, text);
+                        continue;
+                    }
+                },
+                None => continue,
+            };
+            let uid = format!("{}#{}@veisku", rel_path.display(), field);
+            let mut lines = vec![
+                format!("BEGIN:{}", component),
+                format!("UID:{}", escape_text(&uid)),
+                format!("DTSTAMP:{}", stamp),
+                format!("SUMMARY:{}", escape_text(&summary)),
+                format!(
+                    "DESCRIPTION:{}",
+                    escape_text(&rel_path.display().to_string())
+                ),
+                format!("{}{}", property, format_date(date)),
+            ];
+            if let (IcsDate::Date(date), "VEVENT") = (date, component) {
+                // All-day events end on the next day (exclusive)
+                if let Some(next) = date.succ_opt() {
+                    lines.push(format!("DTEND{}", format_date(IcsDate::Date(next))));
+                }
+            }
+            lines.push(format!("END:{}", component));
+            for line in &lines {
+                out.push_str(&fold_line(line));
+            }
+            num_components += 1;
+        }
+    }
+    out.push_str("END:VCALENDAR\r\n");
+
+    match output {
+        Some(path) => {
+            std::fs::write(path, out).with_context(|| format!("Failed to write {:?}", path))?;
+            log::info!("Exported {} item(s) to {:?}", num_components, path);
+        }
+        None => std::io::stdout().write_all(out.as_bytes())?,
+    }
+    Ok(())
+}
+
+/// Parse a date written in a document (`YYYY-MM-DD`, optionally followed by a
+/// time).
+fn parse_date(s: &str) -> Option<IcsDate> {
+    if !schema::is_date(s) {
+        return None;
+    }
+    let date = NaiveDate::parse_from_str(&s[..10], "%Y-%m-%d").ok()?;
+    let time = s.get(11..).and_then(|time| {
+        ["%H:%M:%S%.f", "%H:%M"]
+            .iter()
+            .find_map(|fmt| NaiveTime::parse_and_remainder(time, fmt).ok())
+    });
+    Some(match time {
+        Some((time, _)) => IcsDate::DateTime(date.and_time(time)),
+        None => IcsDate::Date(date),
+    })
+}
+
+/// Format a date as the parameters and the value of a property, e.g.,
+/// `;VALUE=DATE:20240105`. Times are written as floating local times.
+fn format_date(date: IcsDate) -> String {
+    match date {
+        IcsDate::Date(date) => date.format(";VALUE=DATE:%Y%m%d").to_string(),
+        IcsDate::DateTime(datetime) => datetime.format(":%Y%m%dT%H%M%S").to_string(),
+    }
+}
+
+/// Escape a property value of type `TEXT`.
+fn escape_text(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '\\' | ';' | ',' => {
+                out.push('\\');
+                out.push(ch);
+            }
+            '\n' => out.push_str("\\n"),
+            '\r' => {}
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+/// Terminate a content line with CRLF, folding it so that no line exceeds 75
+/// octets.
+fn fold_line(line: &str) -> String {
+    let mut out = String::with_capacity(line.len() + 2);
+    let mut len = 0;
+    for ch in line.chars() {
+        if len + ch.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            len = 1;
+        }
+        out.push(ch);
+        len += ch.len_utf8();
+    }
+    out.push_str("\r\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_date() {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 5).unwrap();
+        assert_eq!(parse_date("2024-01-05"), Some(IcsDate::Date(date)));
+        assert_eq!(
+            parse_date("2024-01-05 14:30"),
+            Some(IcsDate::DateTime(date.and_hms_opt(14, 30, 0).unwrap()))
+        );
+        assert_eq!(
+            parse_date("2024-01-05T14:30:15"),
+            Some(IcsDate::DateTime(date.and_hms_opt(14, 30, 15).unwrap()))
+        );
+        assert_eq!(parse_date("tomorrow"), None);
+        assert_eq!(
+            format_date(parse_date("2024-01-05").unwrap()),
+            ";VALUE=DATE:20240105"
+        );
+        assert_eq!(
+            format_date(parse_date("2024-01-05 14:30").unwrap()),
+            ":20240105T143000"
+        );
+    }
+
+    #[test]
+    fn test_fold_line() {
+        assert_eq!(escape_text("a, b; c\\d\ne"), "a\\, b\\; c\\\\d\\ne");
+        assert_eq!(fold_line("SUMMARY:x"), "SUMMARY:x\r\n");
+        let folded = fold_line(&format!("SUMMARY:{}", "é".repeat(40)));
+        let lines: Vec<&str> = folded.trim_end_matches("\r\n").split("\r\n").collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|line| line.len() <= 75));
+        assert_eq!(
+            lines.concat().replacen(" é", "é", 1),
+            format!("SUMMARY:{}", "é".repeat(40))
+        );
+    }
+}
@@ -0,0 +1,121 @@
+//! Stable document IDs (`v id`)
+use anyhow::{bail, Context, Result};
+use serde_yaml::Value;
+use std::{collections::HashSet, io::Write};
+use veisku_core::journal::Journal;
+
+use crate::{cfg, cfg::IdScheme, doc::DocRead, meta, query, render, root::DocRoot};
+
+pub fn verb_id(root: &DocRoot, sc: &cfg::Id) -> Result<()> {
+    match &sc.subcmd {
+        cfg::IdSubcommand::Assign(sc) => verb_id_assign(root, sc),
+    }
+}
+
+fn verb_id_assign(root: &DocRoot, sc: &cfg::IdAssign) -> Result<()> {
+    root.ensure_writable()?;
+
+    let scheme = sc.scheme.or(root.cfg.new.id).unwrap_or(IdScheme::Timestamp);
+
+    // Find the documents lacking an ID
+    let query = sc.query.compile(root)?;
+    let mut docs: Vec<DocRead> = Vec::new();
+    for doc in query::select_all(root, &query) {
+        let mut doc = doc.context("An error occurred while enumerating matching documents")?;
+        if doc_id(&mut doc)?.is_none() {
+            docs.push(doc);
+        }
+    }
+
+    if docs.is_empty() {
+        log::info!("No documents need to be updated");
+        return Ok(());
+    }
+
+    if let Some(doc) = docs
+        .iter()
+        .find(|doc| root.cfg.encryption_for(doc.path()).is_some())
+    {
+        bail!(
+            "Editing the metadata of encrypted documents is not supported: {:?}",
+            doc.path()
+        );
+    }
+
+    // Pre-flight summary
+    let mut out = std::io::stderr();
+    for doc in docs.iter_mut() {
+        let path = doc.path().to_owned();
+        let meta = doc.ensure_meta()?;
+        render::write_doc_summary(&mut out, &root.cfg.theme, &path, meta)?;
+        writeln!(out)?;
+    }
+
+    if !sc.yes && !meta::confirm(&format!("Assign IDs to {} document(s)?", docs.len()), false)? {
+        bail!("Aborted");
+    }
+
+    let mut taken = existing_ids(root)?;
+    let key = Value::String("id".to_owned());
+    let journal = Journal::new(root);
+    let mut recorder = journal.begin(format!("id assign ({} document(s))", docs.len()));
+    for doc in docs.iter() {
+        let id = generate_unique(scheme, &mut taken);
+        recorder
+            .update_meta(doc.path(), |meta| {
+                meta.insert(key.clone(), Value::String(id.clone()));
+                Ok(())
+            })
+            .with_context(|| format!("Failed to update the metadata of {:?}", doc.path()))?;
+        println!("{}\t{}", id, doc.path().display());
+    }
+    recorder.commit()?;
+
+    log::info!("Assigned IDs to {} document(s)", docs.len());
+
+    Ok(())
+}
+
+/// Get the ID of a document.
+fn doc_id(doc: &mut DocRead) -> Result<Option<String>> {
+    Ok(match &doc.ensure_meta()?["id"] {
+        Value::String(st) => Some(st.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    })
+}
+
+/// Collect the IDs used in the document root.
+pub fn existing_ids(root: &DocRoot) -> Result<HashSet<String>> {
+    let mut ids = HashSet::new();
+    for doc in root.docs() {
+        let mut doc = doc?;
+        // Unreadable metadata doesn't have an ID to conflict with
+        if let Ok(Some(id)) = doc_id(&mut doc) {
+            ids.insert(id);
+        }
+    }
+    Ok(ids)
+}
+
+/// Generate an ID not included in `taken` and add it to `taken`.
+///
+/// Timestamp IDs only have a resolution of one second, so a colliding
+/// timestamp is advanced until it's unique.
+pub fn generate_unique(scheme: IdScheme, taken: &mut HashSet<String>) -> String {
+    let id = match scheme {
+        IdScheme::Timestamp => {
+            let mut time = chrono::Local::now();
+            loop {
+                let id = time.format("%Y%m%d%H%M%S").to_string();
+                if !taken.contains(&id) {
+                    break id;
+                }
+                time += chrono::Duration::seconds(1);
+            }
+        }
+        IdScheme::Uuid => uuid::Uuid::new_v4().to_string(),
+    };
+    taken.insert(id.clone());
+    id
+}
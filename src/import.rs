@@ -0,0 +1,143 @@
+//! Importing external files as documents (`v import`)
+use anyhow::{bail, Context, Result};
+use serde_yaml::Value;
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+    path::Path,
+};
+use veisku_core::doctype::DocType;
+
+use crate::{cfg, doc, naming, root::DocRoot};
+
+pub fn verb_import(root: &DocRoot, sc: &cfg::Import) -> Result<()> {
+    let date = chrono::Local::now().format("%Y-%m-%d").to_string();
+    let dest_dir = root.path.join(&root.cfg.import.dir);
+    std::fs::create_dir_all(&dest_dir)
+        .with_context(|| format!("Failed to create {:?}", dest_dir))?;
+
+    for src in sc.files.iter() {
+        import_one(root, sc, src, &dest_dir, &date)
+            .with_context(|| format!("Failed to import {:?}", src))?;
+    }
+
+    Ok(())
+}
+
+fn import_one(
+    root: &DocRoot,
+    sc: &cfg::Import,
+    src: &Path,
+    dest_dir: &Path,
+    date: &str,
+) -> Result<()> {
+    let mut src_doc = doc::DocRead::new(src.to_owned());
+    let src_meta = src_doc.ensure_meta()?.clone();
+    let stem = src
+        .file_stem()
+        .with_context(|| format!("{:?} does not have a file name", src))?
+        .to_string_lossy();
+
+    // Adding fields to an existing preamble modifies the document
+    if !src_meta.is_null() {
+        root.ensure_writable()?;
+    }
+
+    let title = src_meta["title"]
+        .as_str()
+        .map(str::to_owned)
+        .unwrap_or_else(|| stem.clone().into_owned());
+
+    // Decide the destination path. Fall back to the source file name if the
+    // title has no characters usable in a slug.
+    let mut slug = naming::slugify(&title, &root.cfg.slug);
+    if slug.is_empty() {
+        slug = stem.clone().into_owned();
+    }
+    let vars: HashMap<&str, &str> = [("slug", &*slug), ("date", date), ("stem", &*stem)]
+        .iter()
+        .cloned()
+        .collect();
+    let mut file_name = naming::expand_name_template(&root.cfg.import.name, &vars);
+    if let Some(ext) = src.extension() {
+        file_name.push('.');
+        file_name.push_str(&ext.to_string_lossy());
+    }
+    let dest = dest_dir.join(file_name);
+    if dest.exists() {
+        bail!("{:?} already exists", dest);
+    }
+
+    let mut tags = sc.tags.clone();
+    if sc.interactive {
+        tags.extend(prompt_tags(&title)?);
+    }
+
+    if sc.move_files {
+        doc::move_file(src, &dest)?;
+    } else {
+        std::fs::copy(src, &dest)
+            .with_context(|| format!("Failed to copy {:?} to {:?}", src, dest))?;
+    }
+
+    let doc_type = DocType::detect(&dest);
+    if !doc_type.has_preamble() {
+        // Don't corrupt binary files (e.g., PDFs) by prepending a preamble
+        if !tags.is_empty() {
+            log::warn!(
+                "{:?} is a {} document, which can't have tags; ignoring the tags",
+                dest,
+                doc_type
+            );
+        }
+        println!("{}", dest.display());
+        return Ok(());
+    }
+
+    doc::update_meta(&dest, |meta| {
+        let title_key = Value::String("title".to_owned());
+        if !meta.contains_key(&title_key) {
+            meta.insert(title_key, Value::String(title.clone()));
+        }
+
+        let date_key = Value::String("date".to_owned());
+        if !meta.contains_key(&date_key) {
+            meta.insert(date_key, Value::String(date.to_owned()));
+        }
+
+        let tags_key = Value::String("tags".to_owned());
+        let new_tags = tags.into_iter().map(Value::String);
+        match meta.get_mut(&tags_key) {
+            Some(Value::Sequence(array)) => array.extend(new_tags),
+            Some(Value::Null) | None => {
+                meta.insert(tags_key, Value::Sequence(new_tags.collect()));
+            }
+            Some(_) => bail!("The field 'tags' is not a sequence"),
+        }
+
+        Ok(())
+    })?;
+
+    println!("{}", dest.display());
+
+    Ok(())
+}
+
+/// Ask the user for tags.
+fn prompt_tags(title: &str) -> Result<Vec<String>> {
+    eprint!("Tags for \"{}\" (comma-separated): ", title);
+    std::io::stderr().flush()?;
+
+    let mut line = String::new();
+    std::io::stdin()
+        .lock()
+        .read_line(&mut line)
+        .context("Failed to read the standard input")?;
+
+    Ok(line
+        .split(',')
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(str::to_owned)
+        .collect())
+}
@@ -0,0 +1,175 @@
+//! Inbox triage (`v inbox`)
+use ansi_term::Style;
+use anyhow::{bail, Context, Result};
+use console::{Key, Term};
+use serde_yaml::Value;
+use std::path::{Path, PathBuf};
+use veisku_core::{journal::Journal, link, search::SavedSearches};
+
+use crate::{browse, cfg, mv, query, root::DocRoot, OpenMode};
+
+const ACTIONS: &str = "t: tag  s: status  a: archive  o: open  n: skip  q: quit";
+
+pub fn verb_inbox(root: &DocRoot, sc: &cfg::Inbox) -> Result<()> {
+    let term = Term::stdout();
+    if !term.features().is_attended() {
+        bail!("`v inbox` requires a terminal");
+    }
+    root.ensure_writable()?;
+
+    let criteria = match SavedSearches::load(root)?.criteria(&sc.search) {
+        Some(criteria) => criteria?,
+        None => bail!(
+            "Unknown saved search: '{}' (create it by `v search save {} CRITERIA...`)",
+            sc.search,
+            sc.search
+        ),
+    };
+    let query = sc.query.compile_with(root, &criteria)?;
+    let paths: Vec<PathBuf> = query::select_all_sorted(root, &query)
+        .context("An error occurred while enumerating matching documents")?
+        .iter()
+        .map(|doc| doc.path().to_owned())
+        .collect();
+    if paths.is_empty() {
+        log::info!("Nothing to triage");
+        return Ok(());
+    }
+
+    let mut message = String::new();
+    let mut i = 0;
+    while i < paths.len() {
+        let path = &paths[i];
+        term.clear_screen()?;
+        show(root, &term, path, (i, paths.len()), sc.lines)?;
+        term.write_line("")?;
+        if !message.is_empty() {
+            term.write_line(&std::mem::take(&mut message))?;
+        }
+        term.write_line(&Style::new().reverse().paint(ACTIONS).to_string())?;
+
+        let key = match term.read_key() {
+            Ok(key) => key,
+            // Ctrl-C
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => break,
+            Err(e) => return Err(e).context("Failed to read a key"),
+        };
+        let result = match key {
+            Key::Escape | Key::Char('q') => break,
+            Key::Char('n') | Key::Char(' ') => {
+                i += 1;
+                continue;
+            }
+            Key::Char('t') => match prompt(&term, "Tag: ")? {
+                Some(tag) => browse::add_tag(root, path, &tag),
+                None => Ok(()),
+            },
+            Key::Char('s') => match prompt(&term, "Status: ")? {
+                Some(status) => set_status(root, path, &status),
+                None => Ok(()),
+            },
+            Key::Char('a') => archive(root, path).map(|()| {
+                i += 1;
+            }),
+            Key::Char('o') => {
+                let mut cmd =
+                    crate::open_command(root, path, None, false, || OpenMode::Edit.default_cmd());
+                log::debug!("Spawning {:?}", cmd);
+                cmd.status()
+                    .map(drop)
+                    .with_context(|| format!("Failed to execute {:?}", cmd))
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            message = format!("Error: {:#}", e);
+        }
+    }
+
+    term.clear_screen()?;
+    Ok(())
+}
+
+/// Display the document at `path`, which is the `index.0`-th of `index.1`
+/// documents.
+fn show(
+    root: &DocRoot,
+    term: &Term,
+    path: &Path,
+    index: (usize, usize),
+    num_lines: usize,
+) -> Result<()> {
+    let rel_path = path.strip_prefix(&root.path).unwrap_or(path);
+    let header = format!("[{}/{}] {}", index.0 + 1, index.1, rel_path.display());
+    term.write_line(&Style::new().bold().paint(header).to_string())?;
+    term.write_line("")?;
+
+    let mut doc = root.doc_at(path.to_owned());
+    if root.cfg.encryption_for(path).is_some() {
+        term.write_line("(encrypted)")?;
+        return Ok(());
+    }
+    match doc.ensure_meta() {
+        Ok(Value::Null) => term.write_line("(no metadata)")?,
+        Ok(meta) => {
+            let yaml = serde_yaml::to_string(meta)?;
+            term.write_str(yaml.strip_prefix("---\n").unwrap_or(&yaml))?;
+            if !yaml.ends_with('\n') {
+                term.write_line("")?;
+            }
+        }
+        Err(e) => term.write_line(&format!("Error: {:#}", e))?,
+    }
+
+    let lines = browse::preview(root, &doc);
+    if num_lines > 0 && !lines.is_empty() {
+        term.write_line(&Style::new().dimmed().paint("─".repeat(40)).to_string())?;
+        for line in lines.iter().take(num_lines) {
+            term.write_line(line)?;
+        }
+        if lines.len() > num_lines {
+            term.write_line(&Style::new().dimmed().paint("…").to_string())?;
+        }
+    }
+    Ok(())
+}
+
+/// Read a line from the terminal. Returns `None` if it's empty.
+fn prompt(term: &Term, label: &str) -> Result<Option<String>> {
+    term.write_str(label)?;
+    let line = term.read_line().context("Failed to read a line")?;
+    let line = line.trim();
+    Ok(Some(line.to_owned()).filter(|line| !line.is_empty()))
+}
+
+fn set_status(root: &DocRoot, path: &Path, status: &str) -> Result<()> {
+    if root.cfg.encryption_for(path).is_some() {
+        bail!("Editing the metadata of encrypted documents is not supported");
+    }
+    let journal = Journal::new(root);
+    let mut recorder = journal.begin(format!("set status of {}", path.display()));
+    recorder.update_meta(path, |meta| {
+        meta.insert(
+            Value::String("status".to_owned()),
+            Value::String(status.to_owned()),
+        );
+        Ok(())
+    })?;
+    recorder.commit()
+}
+
+/// Move the document at `path` to `inbox.archive_dir`.
+fn archive(root: &DocRoot, path: &Path) -> Result<()> {
+    let old_path = link::normalize(path);
+    let new_path = link::normalize(
+        &root.path.join(&root.cfg.inbox.archive_dir).join(
+            old_path
+                .file_name()
+                .context("The document has no file name")?,
+        ),
+    );
+    if old_path == new_path {
+        bail!("The document is already archived");
+    }
+    mv::move_doc(root, &old_path, &new_path, false)
+}
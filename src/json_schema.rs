@@ -0,0 +1,14 @@
+//! JSON Schema emission (`v schema`)
+use anyhow::Result;
+use schemars::schema_for;
+
+use crate::cfg;
+
+pub fn verb_schema(sc: &cfg::Schema) -> Result<()> {
+    let schema = match sc.kind {
+        cfg::SchemaKind::Config => schema_for!(veisku_core::cfg::Cfg),
+        cfg::SchemaKind::ListOutput => schema_for!(Vec<crate::JsonDoc>),
+    };
+    println!("{}", serde_json::to_string_pretty(&schema)?);
+    Ok(())
+}
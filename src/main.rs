@@ -1,121 +1,1041 @@
-use ansi_term::Color;
 use anyhow::{Context, Result};
 use clap::{Clap, IntoApp};
-use std::{convert::Infallible, ffi::OsString, io::Write, mem::replace, path::Path};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+    ffi::OsString,
+    fmt::Write as _,
+    io::Write,
+    mem::replace,
+    path::Path,
+};
+use veisku_core::{
+    dates, diagnostics, doc,
+    doctype::{self, DocType},
+    git, history, journal, query, query_cache, registry, root, schema, tag, timing,
+};
 
+mod attach;
+mod bib;
+mod board;
+mod browse;
+mod capture;
 mod cfg;
-mod doc;
-mod query;
+mod clipboard;
+mod commands;
+mod complete;
+mod config;
+mod crypt;
+mod daemon;
+mod error;
+mod export;
+mod generate;
+mod grep;
+mod group;
+mod hash;
+mod ics;
+mod id;
+mod import;
+mod inbox;
+mod json_schema;
+mod meta;
+mod mv;
+mod naming;
+mod new;
+mod parse_errors;
+mod periodic;
+mod pick;
 mod render;
-mod root;
+mod search;
+mod serve;
+mod shell;
+mod site;
+mod snapshot;
+mod split;
+mod state;
+mod sync;
+mod tags;
+mod temp;
+mod template;
+mod theme;
+mod trash;
+mod undo;
+mod watch;
+mod wc;
 
 fn main() -> Result<()> {
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("v=info")).init();
-
     let opts: cfg::Opts = Clap::parse();
+
+    init_logger(&opts);
     log::debug!("opts = {:#?}", opts);
 
-    let root = root::DocRoot::current().context("Failed to get the document root")?;
+    let error_format = opts.error_format;
+    let start = std::time::Instant::now();
+    if opts.timing {
+        timing::enable();
+    }
+    diagnostics::set_strict(opts.strict);
+    let result = run(opts);
+    log_diagnostics();
+    if timing::is_enabled() {
+        eprint!("{}", timing::report(start.elapsed()));
+    }
+    match result {
+        Err(e) if error_format == cfg::ErrorFormat::Json => {
+            error::write_json(&mut std::io::stderr(), &e)?;
+            std::process::exit(1);
+        }
+        result => result,
+    }
+}
+
+/// Log the conditions aggregated by [`diagnostics`]. Must be called before
+/// exiting the process early.
+pub fn log_diagnostics() {
+    for message in diagnostics::take_summary() {
+        log::warn!("{}", message);
+    }
+    if let Some(message) = diagnostics::take_skipped_summary() {
+        log::info!("{}", message);
+    }
+}
+
+fn run(opts: cfg::Opts) -> Result<()> {
+    if let Some(dir) = &opts.directory {
+        std::env::set_current_dir(dir)
+            .with_context(|| format!("Failed to change the current directory to {:?}", dir))?;
+        // Discover the document root from `dir` even if we are invoked by
+        // a script
+        std::env::remove_var("V_CONFIG");
+    }
+
+    let read_only =
+        opts.read_only || std::env::var_os("V_READ_ONLY").is_some_and(|v| !v.is_empty());
+
+    // `v config edit` must work even if the configuration is broken
+    if let Some(cfg::Subcommand::Config(cfg::Config {
+        subcmd: cfg::ConfigSubcommand::Edit(subcmd),
+    })) = &opts.subcmd
+    {
+        if read_only {
+            anyhow::bail!("`v config edit` is not allowed in read-only mode");
+        }
+        return config::verb_config_edit(subcmd);
+    }
+
+    // `v shell-init` doesn't need a document root
+    if let Some(cfg::Subcommand::ShellInit(subcmd)) = &opts.subcmd {
+        return shell::verb_shell_init(subcmd);
+    }
+
+    // Neither does `v schema`
+    if let Some(cfg::Subcommand::Schema(subcmd)) = &opts.subcmd {
+        return json_schema::verb_schema(subcmd);
+    }
+
+    let mut overrides = opts.config.clone();
+    if opts.skip_errors {
+        overrides.push("skip_errors=true".parse().unwrap());
+    }
+    if read_only {
+        overrides.push("read_only=true".parse().unwrap());
+    }
+    if opts.binary {
+        overrides.push("contents.binary=true".parse().unwrap());
+    }
+    // `v config check` reports the errors in the configuration by itself
+    if let Some(cfg::Subcommand::Config(cfg::Config {
+        subcmd: cfg::ConfigSubcommand::Check(_),
+    })) = &opts.subcmd
+    {
+        return config::verb_config_check(&opts, &overrides);
+    }
+
+    let mut root = timing::measure(timing::Phase::RootDiscovery, || {
+        root::DocRoot::current_with_overrides(&overrides)
+    })
+    .context("Failed to get the document root")?;
+    if !opts.files.is_empty() {
+        root.cfg.files = opts.files.clone();
+    }
+    root.cfg.files.extend(opts.add_files.iter().cloned());
     log::debug!("root = {:#?}", root);
+    render::set_ambiguous_width(root.cfg.ambiguous_width);
+
+    if let Some(verb) = opts.subcmd.as_ref().and_then(mutating_verb) {
+        if root.cfg.read_only {
+            anyhow::bail!("`v {}` is not allowed in read-only mode", verb);
+        }
+    }
 
-    if let Some(subcmd) = &opts.subcmd {
+    let result = if let Some(subcmd) = &opts.subcmd {
         match subcmd {
             cfg::Subcommand::Which(subcmd) => verb_which(&root, subcmd),
+            cfg::Subcommand::Root(subcmd) => verb_root(&root, subcmd),
+            cfg::Subcommand::Config(subcmd) => config::verb_config(&root, subcmd),
+            cfg::Subcommand::Theme(subcmd) => theme::verb_theme(&root, &opts, subcmd),
+            cfg::Subcommand::State(subcmd) => state::verb_state(&root, subcmd),
+            cfg::Subcommand::ShellInit(subcmd) => shell::verb_shell_init(subcmd),
+            cfg::Subcommand::Schema(subcmd) => json_schema::verb_schema(subcmd),
+            cfg::Subcommand::Complete(subcmd) => complete::verb_complete(&root, subcmd),
             cfg::Subcommand::Open(subcmd) => {
-                verb_open(&root, subcmd, default_opener).map(|x| match x {})
+                verb_open(&root, subcmd, OpenMode::Open).map(|x| match x {})
             }
             cfg::Subcommand::Show(subcmd) => {
-                verb_open(&root, subcmd, default_viewer).map(|x| match x {})
+                verb_open(&root, subcmd, OpenMode::Show).map(|x| match x {})
             }
             cfg::Subcommand::Edit(subcmd) => {
-                verb_open(&root, subcmd, default_editor).map(|x| match x {})
+                verb_open(&root, subcmd, OpenMode::Edit).map(|x| match x {})
             }
             cfg::Subcommand::Ls(subcmd) => verb_ls(&root, &opts, subcmd),
             cfg::Subcommand::Run(subcmd) => verb_run(&root, subcmd).map(|x| match x {}),
+            cfg::Subcommand::Dedupe(subcmd) => verb_dedupe(&root, &opts, subcmd),
+            cfg::Subcommand::Grep(subcmd) => grep::verb_grep(&root, &opts, subcmd),
+            cfg::Subcommand::Attach(subcmd) => attach::verb_attach(&root, subcmd),
+            cfg::Subcommand::Serve(subcmd) => serve::verb_serve(&root, subcmd),
+            cfg::Subcommand::Daemon(subcmd) => daemon::verb_daemon(&root, subcmd),
+            cfg::Subcommand::Watch(subcmd) => watch::verb_watch(&root, subcmd),
+            cfg::Subcommand::Export(subcmd) => export::verb_export(&root, subcmd),
+            cfg::Subcommand::Snapshot(subcmd) => snapshot::verb_snapshot(&root, subcmd),
+            cfg::Subcommand::Bib(subcmd) => bib::verb_bib(&root, subcmd),
+            cfg::Subcommand::Import(subcmd) => import::verb_import(&root, subcmd),
+            cfg::Subcommand::Status(subcmd) => verb_status(&root, &opts, subcmd),
+            cfg::Subcommand::Meta(subcmd) => meta::verb_meta(&root, subcmd),
+            cfg::Subcommand::New(subcmd) => new::verb_new(&root, subcmd),
+            cfg::Subcommand::Capture(subcmd) => capture::verb_capture(&root, subcmd),
+            cfg::Subcommand::Today(subcmd) => {
+                periodic::open_note(&root, cfg::Cadence::Daily, &subcmd.period)
+            }
+            cfg::Subcommand::Periodic(subcmd) => {
+                periodic::open_note(&root, subcmd.cadence, &subcmd.period)
+            }
+            cfg::Subcommand::Generate(subcmd) => generate::verb_generate(&root, subcmd),
+            cfg::Subcommand::Commands(subcmd) => commands::verb_commands(&root, subcmd),
+            cfg::Subcommand::Search(subcmd) => search::verb_search(&root, &opts, subcmd),
+            cfg::Subcommand::Validate(subcmd) => verb_validate(&root, subcmd),
+            cfg::Subcommand::ParseErrors(subcmd) => parse_errors::verb_parse_errors(&root, subcmd),
+            cfg::Subcommand::Id(subcmd) => id::verb_id(&root, subcmd),
+            cfg::Subcommand::Mv(subcmd) => mv::verb_mv(&root, subcmd),
+            cfg::Subcommand::Split(subcmd) => split::verb_split(&root, subcmd),
+            cfg::Subcommand::Tags(subcmd) => tags::verb_tags(&root, &opts, subcmd),
+            cfg::Subcommand::RenameTag(subcmd) => tags::verb_rename_tag(&root, subcmd),
+            cfg::Subcommand::Rm(subcmd) => trash::verb_rm(&root, subcmd),
+            cfg::Subcommand::Trash(subcmd) => trash::verb_trash(&root, subcmd),
+            cfg::Subcommand::Undo(subcmd) => undo::verb_undo(&root, subcmd),
+            cfg::Subcommand::Sync(subcmd) => sync::verb_sync(&root, subcmd),
+            cfg::Subcommand::Wc(subcmd) => wc::verb_wc(&root, &opts, subcmd),
+            cfg::Subcommand::Hash(subcmd) => hash::verb_hash(&root, &opts, subcmd),
+            cfg::Subcommand::Browse(subcmd) => browse::verb_browse(&root, subcmd),
+            cfg::Subcommand::Board(subcmd) => board::verb_board(&root, &opts, subcmd),
+            cfg::Subcommand::Inbox(subcmd) => inbox::verb_inbox(&root, subcmd),
         }
     } else if opts.cmd.is_empty() {
         cfg::Opts::into_app().print_help()?;
+        commands::print_help_section(&root)?;
         std::process::exit(1);
     } else {
         verb_run_script(&root, opts.cmd).map(|x| match x {})
+    };
+
+    // Display the candidates of an ambiguous selection like `v ls` does
+    if let (Err(e), cfg::ErrorFormat::Human) = (&result, opts.error_format) {
+        if let Some(query::SelectOneError::Ambiguous { candidates, total }) = e.downcast_ref() {
+            if e.chain().count() == 1 {
+                error::write_ambiguous(&mut std::io::stderr(), &root, candidates, *total)?;
+                std::process::exit(1);
+            }
+        }
+    }
+
+    result
+}
+
+/// Initialize the logger according to `-v` and `-q`.
+fn init_logger(opts: &cfg::Opts) {
+    let level = match (opts.quiet, opts.verbose) {
+        (true, _) => log::LevelFilter::Error,
+        (false, 0) => log::LevelFilter::Info,
+        (false, 1) => log::LevelFilter::Debug,
+        (false, _) => log::LevelFilter::Trace,
+    };
+
+    env_logger::Builder::new()
+        .filter_module("v", level)
+        .filter_module("veisku_core", level)
+        .parse_env(env_logger::Env::default())
+        .init();
+}
+
+fn verb_root(root: &root::DocRoot, sc: &cfg::Root) -> Result<()> {
+    if !sc.json {
+        println!("{}", root.path.display());
+        return Ok(());
+    }
+
+    #[derive(serde::Serialize)]
+    struct JsonRoot<'a> {
+        path: &'a Path,
+        config_dir: &'a Path,
+        config_file: std::path::PathBuf,
+        config_file_exists: bool,
+        files: &'a [String],
+        config: &'a cfg::Cfg,
     }
+    let config_file = root.cfg_file_path();
+    let json = serde_json::to_string_pretty(&JsonRoot {
+        path: &root.path,
+        config_dir: &root.cfg_dir_path,
+        config_file_exists: config_file.is_file(),
+        config_file,
+        files: &root.cfg.files,
+        config: &root.cfg,
+    })
+    .context("Failed to serialize the configuration")?;
+    println!("{}", json);
+    Ok(())
 }
 
-fn verb_which(root: &root::DocRoot, sc: &cfg::Query) -> Result<()> {
-    let query = query::Query::from_opt(&root.cfg, sc)?;
-    let doc = query::select_one(root, &query)?;
-    println!("{}", doc.path().display());
+fn verb_which(root: &root::DocRoot, sc: &cfg::Which) -> Result<()> {
+    if sc.quiet {
+        std::process::exit(match sc.query.select_one(root) {
+            Ok(_) => 0,
+            Err(query::SelectOneError::Empty) => 1,
+            Err(query::SelectOneError::Ambiguous { .. }) => 2,
+            Err(query::SelectOneError::Misc(e)) => {
+                eprintln!("Error: {:?}", e);
+                3
+            }
+        });
+    }
+
+    if sc.all {
+        let query = sc.query.compile(root)?;
+        let docs = query::select_all_sorted(root, &query)?;
+        if docs.is_empty() {
+            return Err(query::SelectOneError::Empty.into());
+        }
+
+        let separator = if sc.null { '\0' } else { '\n' };
+        let mut paths = String::new();
+        for doc in docs.iter() {
+            let path = sc.path_format.apply(root, doc.path());
+            paths += &format!("{}{}", quote_path(&path, sc.quote), separator);
+        }
+        print!("{}", paths);
+        if sc.copy {
+            clipboard::copy(&paths).context("Failed to copy the paths to the clipboard")?;
+        }
+        return Ok(());
+    }
+
+    let doc = sc.query.select_one(root)?;
+    let path = quote_path(&sc.path_format.apply(root, doc.path()), sc.quote);
+    println!("{}", path);
+    if sc.copy {
+        clipboard::copy(&path).context("Failed to copy the path to the clipboard")?;
+    }
     Ok(())
 }
 
-fn verb_open(
+/// Format a path for `v which`, quoting it if `--quote` is given.
+fn quote_path(path: &Path, quote: Option<cfg::Quote>) -> String {
+    match quote {
+        Some(quote) => quote.apply(&path.to_string_lossy()),
+        None => path.display().to_string(),
+    }
+}
+
+/// Specifies the variation of `verb_open`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OpenMode {
+    Open,
+    Show,
+    Edit,
+}
+
+impl OpenMode {
+    fn default_cmd(self) -> Vec<OsString> {
+        match self {
+            Self::Open => default_opener(),
+            Self::Show => default_viewer(),
+            Self::Edit => default_editor(),
+        }
+    }
+
+    /// Get the default command for the specified files, taking `openers` in
+    /// `config.toml` into account. A configured opener is used only if it
+    /// applies to all of the files.
+    fn default_cmd_for(self, root: &root::DocRoot, paths: &[&Path]) -> Vec<OsString> {
+        if self == Self::Open {
+            let mut openers = paths.iter().map(|path| find_opener(root, path));
+            if let Some(Some(opener)) = openers.next() {
+                if openers.all(|x| x.is_some_and(|x| std::ptr::eq(x, opener))) {
+                    if let Some(cmd) = resolve_opener(opener) {
+                        return cmd.into_iter().map(Into::into).collect();
+                    }
+                }
+            }
+        }
+        self.default_cmd()
+    }
+}
+
+/// Find the opener configured for the file at `path` in `openers`. The keys
+/// are looked up in the following order: the MIME type (e.g.,
+/// `application/pdf`), the wildcard MIME type (`application/*`), and the
+/// document type (`pdf`).
+fn find_opener<'a>(root: &'a root::DocRoot, path: &Path) -> Option<&'a cfg::OpenerCfg> {
+    let openers = &root.cfg.openers;
+    if openers.is_empty() {
+        return None;
+    }
+    if let Some(mime) = doctype::detect_mime(path) {
+        let wildcard = format!("{}/*", mime.split('/').next().unwrap());
+        if let Some(opener) = openers.get(mime).or_else(|| openers.get(&wildcard)) {
+            log::debug!("Detected {:?} as {}", path, mime);
+            return Some(opener);
+        }
+    }
+    openers.get(DocType::detect(path).as_str())
+}
+
+fn verb_open(root: &root::DocRoot, sc: &cfg::Open, mode: OpenMode) -> Result<Infallible> {
+    let doc = if let Some(limit) = sc.limit {
+        let query = sc.query.compile(root)?;
+        let mut docs = query::select_all_sorted(root, &query)?;
+        if docs.is_empty() {
+            return Err(query::SelectOneError::Empty.into());
+        }
+        if docs.len() > limit {
+            log::error!(
+                "{} documents matched, exceeding the limit of {}",
+                docs.len(),
+                limit
+            );
+            return Err(query::SelectOneError::ambiguous(docs, root.cfg.max_candidates).into());
+        }
+        if docs.len() > 1 {
+            if let Some(doc) = docs
+                .iter()
+                .find(|doc| root.cfg.encryption_for(doc.path()).is_some())
+            {
+                anyhow::bail!(
+                    "Opening an encrypted document ({:?}) together with other documents \
+                    is not supported",
+                    doc.path()
+                );
+            }
+            let paths: Vec<&Path> = docs.iter().map(|doc| doc.path()).collect();
+            if sc.print_only || sc.print_to.is_some() {
+                return print_selection(sc, &paths);
+            }
+            apply_meta_assignments(root, &paths, &sc.meta)?;
+            record_history(root, &paths);
+            return launch(
+                root,
+                sc,
+                mode,
+                &paths,
+                &mut open_command_multi(root, &paths, sc.cmd.as_deref(), sc.preserve_pwd, || {
+                    mode.default_cmd_for(root, &paths)
+                }),
+            );
+        }
+        docs.pop().unwrap()
+    } else if let Some(n) = sc.nth_recent {
+        select_nth_recent(root, &sc.query, n)?
+    } else {
+        sc.query.select_one(root)?
+    };
+
+    if sc.print_only || sc.print_to.is_some() {
+        return print_selection(sc, &[doc.path()]);
+    }
+    apply_meta_assignments(root, &[doc.path()], &sc.meta)?;
+    record_history(root, &[doc.path()]);
+
+    if let Some(enc_cfg) = root.cfg.encryption_for(doc.path()) {
+        return crypt::open_encrypted(root, &doc, enc_cfg, sc, mode);
+    }
+
+    if mode == OpenMode::Edit && sc.cmd.is_none() && !root.cfg.open.goto.is_empty() {
+        let query = sc.query.compile(root)?;
+        if let Some(m) = query.content_matches(&doc)?.first() {
+            let mut cmd = goto_command(root, &root.cfg.open.goto, doc.path(), m, sc.preserve_pwd);
+            return launch(root, sc, mode, &[doc.path()], &mut cmd);
+        }
+    }
+
+    launch(
+        root,
+        sc,
+        mode,
+        &[doc.path()],
+        &mut open_command(root, doc.path(), sc.cmd.as_deref(), sc.preserve_pwd, || {
+            mode.default_cmd_for(root, &[doc.path()])
+        }),
+    )
+}
+
+/// Print the paths of the selected documents instead of opening them
+/// (`--print-only` and `--print-to`).
+fn print_selection(sc: &cfg::Open, paths: &[&Path]) -> Result<Infallible> {
+    let mut out: Box<dyn Write> = if let Some(dest) = &sc.print_to {
+        // Named pipes can't be replaced atomically, so just write to it
+        Box::new(
+            std::fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(dest)
+                .with_context(|| format!("Failed to open {:?}", dest))?,
+        )
+    } else if let Some(fd3) = open_fd3() {
+        log::debug!("Writing the selection to file descriptor 3");
+        Box::new(fd3)
+    } else {
+        Box::new(std::io::stdout())
+    };
+    for path in paths {
+        writeln!(out, "{}", path.display())
+            .and_then(|()| out.flush())
+            .context("Failed to write the selection")?;
+    }
+    std::process::exit(0);
+}
+
+/// Get file descriptor 3 if it's open (e.g., `v open --print-only 3>FILE`).
+#[cfg(unix)]
+fn open_fd3() -> Option<std::fs::File> {
+    use std::os::unix::io::FromRawFd;
+    // Safety: `fcntl(F_GETFD)` only checks if the descriptor is open, which
+    // this process doesn't use otherwise
+    if unsafe { libc::fcntl(3, libc::F_GETFD) } == -1 {
+        return None;
+    }
+    Some(unsafe { std::fs::File::from_raw_fd(3) })
+}
+
+#[cfg(not(unix))]
+fn open_fd3() -> Option<std::fs::File> {
+    None
+}
+
+/// Set metadata fields of the documents before opening them (`--meta`).
+fn apply_meta_assignments(
+    root: &root::DocRoot,
+    paths: &[&Path],
+    assignments: &[cfg::Assignment],
+) -> Result<()> {
+    if assignments.is_empty() {
+        return Ok(());
+    }
+    root.ensure_writable()?;
+    if let Some(path) = paths
+        .iter()
+        .find(|path| root.cfg.encryption_for(path).is_some())
+    {
+        anyhow::bail!(
+            "Editing the metadata of encrypted documents is not supported: {:?}",
+            path
+        );
+    }
+
+    let keys: Vec<&str> = assignments.iter().map(|a| &*a.key).collect();
+    let journal = journal::Journal::new(root);
+    let mut recorder = journal.begin(format!(
+        "set {} on open ({} document(s))",
+        keys.join(", "),
+        paths.len()
+    ));
+    for path in paths {
+        recorder
+            .update_meta(path, |meta| {
+                for assignment in assignments {
+                    let key = serde_yaml::Value::String(assignment.key.clone());
+                    match &assignment.value {
+                        Some(value) => meta.insert(key, value.clone()),
+                        None => meta.remove(&key),
+                    };
+                }
+                Ok(())
+            })
+            .with_context(|| format!("Failed to update the metadata of {:?}", path))?;
+    }
+    recorder.commit()
+}
+
+/// Construct a command opening the document at `path` in the editor at the
+/// content match `m`. `goto` is the argument template (`open.goto`).
+fn goto_command(
+    root: &root::DocRoot,
+    goto: &[String],
+    path: &Path,
+    m: &query::ContentMatch,
+    preserve_pwd: bool,
+) -> std::process::Command {
+    let mut argv = default_editor();
+    argv.extend(goto_args(goto, path, m));
+    let mut cmd = new_command(&argv);
+    set_command_env(&mut cmd, root);
+    if !preserve_pwd {
+        cmd.current_dir(&root.path);
+    }
+    cmd
+}
+
+/// Expand `open.goto` for the content match `m` in the document at `path`.
+fn goto_args(goto: &[String], path: &Path, m: &query::ContentMatch) -> Vec<OsString> {
+    let path_str = path.to_string_lossy();
+    let mut args: Vec<OsString> = goto
+        .iter()
+        .map(|arg| {
+            arg.replace("{line}", &m.line.to_string())
+                .replace("{column}", &m.column.to_string())
+                .replace("{path}", &path_str)
+                .into()
+        })
+        .collect();
+    if !goto.iter().any(|arg| arg.contains("{path}")) {
+        args.push(path.into());
+    }
+    args
+}
+
+/// Select the `n`-th (one-based) most recently opened document matching
+/// `query` (`--nth-recent`).
+fn select_nth_recent(root: &root::DocRoot, query: &cfg::Query, n: usize) -> Result<doc::DocRead> {
+    if n == 0 {
+        anyhow::bail!("`--nth-recent` is one-based");
+    }
+    let history = history::History::load(root);
+    let recent: Vec<std::path::PathBuf> = history
+        .recent()
+        .into_iter()
+        .map(|path| root.path.join(path))
+        .filter(|path| path.is_file())
+        .collect();
+    let mut query = query.compile(root)?;
+    query.restrict_to_files(recent);
+    let mut docs = query::select_all(root, &query)
+        .take(n)
+        .collect::<Result<Vec<_>>>()?;
+    if docs.len() < n {
+        anyhow::bail!("Only {} recently opened document(s) matched", docs.len());
+    }
+    Ok(docs.pop().unwrap())
+}
+
+/// Record the opened documents in the history, which is used by `--rank`.
+fn record_history(root: &root::DocRoot, paths: &[&Path]) {
+    if let Err(e) = history::History::record(root, paths) {
+        log::warn!("Failed to record the history: {:?}", e);
+    }
+}
+
+/// Run the command `cmd` opening the documents at `paths`. In the spawn mode
+/// (`--spawn` or `open.spawn`), `cmd` is run as a child process, and
+/// `open.after` is executed after it exits. With `--detach`, `cmd` is
+/// launched in the background. Otherwise, `cmd` replaces the current process.
+///
+/// In the spawn mode of `v edit`, `open.stamp_field` of the changed documents
+/// is updated before running `open.after`.
+fn launch(
     root: &root::DocRoot,
     sc: &cfg::Open,
-    default_cmd: fn() -> OsString,
+    mode: OpenMode,
+    paths: &[&Path],
+    cmd: &mut std::process::Command,
 ) -> Result<Infallible> {
-    let argv0 = std::env::args_os().next().unwrap();
-    log::debug!("argv0 = {:?} (passed as V variable)", argv0);
+    if sc.detach {
+        detach(cmd);
+        log::debug!("Spawning {:?}", cmd);
+        cmd.spawn()
+            .with_context(|| format!("Failed to execute {:?}", cmd))?;
+        std::process::exit(0);
+    }
 
-    let query = query::Query::from_opt(&root.cfg, &sc.query)?;
-    let doc = query::select_one(root, &query)?;
+    if !(sc.spawn || root.cfg.open.spawn) {
+        return exec(cmd);
+    }
 
-    let argv = if let Some(cmd) = &sc.cmd {
-        let mut cmd: Vec<OsString> = cmd.clone();
+    let stamp_field = match &root.cfg.open.stamp_field {
+        Some(field) if mode == OpenMode::Edit => Some(field),
+        _ => None,
+    };
+    let old_contents: Vec<Option<Vec<u8>>> = if stamp_field.is_some() {
+        paths.iter().map(|path| std::fs::read(path).ok()).collect()
+    } else {
+        Vec::new()
+    };
 
+    log::debug!("Spawning {:?}", cmd);
+    let mut child = cmd
+        .spawn()
+        .with_context(|| format!("Failed to execute {:?}", cmd))?;
+
+    // Leave Ctrl-C to the child process so that the post-actions are
+    // executed after it exits
+    ignore_ctrl_c();
+
+    let status = child
+        .wait()
+        .context("Failed to wait for the spawned process")?;
+    log::debug!("The opener exited with {}", status);
+
+    if let Some(field) = stamp_field {
+        for (path, old_content) in paths.iter().zip(old_contents) {
+            if std::fs::read(path).ok() == old_content {
+                log::debug!("{:?} was not changed; not updating `{}`", path, field);
+            } else if let Err(e) = stamp(root, path, field) {
+                log::warn!("Failed to update `{}` of {:?}: {:?}", field, path, e);
+            }
+        }
+    }
+
+    if !root.cfg.open.after.is_empty() {
+        let after: Vec<OsString> = root.cfg.open.after.iter().map(OsString::from).collect();
+        let mut cmd = open_command_multi(root, paths, Some(&after), false, Vec::new);
+        if let Some(code) = status.code() {
+            cmd.env("V_EXIT_STATUS", code.to_string());
+        }
+        log::debug!("Spawning {:?}", cmd);
+        let after_status = cmd
+            .status()
+            .with_context(|| format!("Failed to execute {:?}", cmd))?;
+        if !after_status.success() {
+            log::warn!("`open.after` exited with {}", after_status);
+        }
+    }
+
+    std::process::exit(status.code().unwrap_or(1));
+}
+
+/// Set the metadata field `field` of the document at `path` to the current
+/// time (`open.stamp_field`).
+fn stamp(root: &root::DocRoot, path: &Path, field: &str) -> Result<()> {
+    root.ensure_writable()?;
+    if !DocType::detect(path).has_preamble() {
+        log::debug!("{:?} has no preamble; not updating `{}`", path, field);
+        return Ok(());
+    }
+    let format = root
+        .cfg
+        .open
+        .stamp_format
+        .as_deref()
+        .unwrap_or("%Y-%m-%d %H:%M:%S");
+    let mut now = String::new();
+    write!(now, "{}", chrono::Local::now().format(format))
+        .map_err(|_| anyhow::anyhow!("Invalid `open.stamp_format`: {:?}", format))?;
+    doc::update_meta(path, |mapping| {
+        mapping.insert(field.into(), now.into());
+        Ok(())
+    })
+}
+
+/// Open the specified file using `cmd` or `default_cmd`.
+fn open_path(
+    root: &root::DocRoot,
+    path: &Path,
+    cmd: Option<&[OsString]>,
+    preserve_pwd: bool,
+    default_cmd: impl FnOnce() -> Vec<OsString>,
+) -> Result<Infallible> {
+    exec(&mut open_command(
+        root,
+        path,
+        cmd,
+        preserve_pwd,
+        default_cmd,
+    ))
+}
+
+/// Construct a command to open the specified file using `cmd` or
+/// `default_cmd`.
+fn open_command(
+    root: &root::DocRoot,
+    path: &Path,
+    cmd: Option<&[OsString]>,
+    preserve_pwd: bool,
+    default_cmd: impl FnOnce() -> Vec<OsString>,
+) -> std::process::Command {
+    open_command_multi(root, &[path], cmd, preserve_pwd, default_cmd)
+}
+
+/// Like `open_command`, but passes multiple paths at once. Each `{}` in `cmd`
+/// is replaced with all of the paths.
+fn open_command_multi(
+    root: &root::DocRoot,
+    paths: &[&Path],
+    cmd: Option<&[OsString]>,
+    preserve_pwd: bool,
+    default_cmd: impl FnOnce() -> Vec<OsString>,
+) -> std::process::Command {
+    let argv = open_argv(paths, cmd, default_cmd);
+    let mut cmd = new_command(&argv);
+    set_command_env(&mut cmd, root);
+
+    if !preserve_pwd {
+        cmd.current_dir(&root.path);
+    }
+
+    cmd
+}
+
+/// Construct the argument vector of `open_command_multi`.
+fn open_argv(
+    paths: &[&Path],
+    cmd: Option<&[OsString]>,
+    default_cmd: impl FnOnce() -> Vec<OsString>,
+) -> Vec<OsString> {
+    let paths = paths.iter().map(OsString::from);
+    if let Some(cmd) = cmd {
         if cmd.iter().any(|x| x == "{}") {
-            for e in cmd.iter_mut() {
+            let mut argv = Vec::new();
+            for e in cmd.iter() {
                 if *e == "{}" {
-                    *e = doc.path().into();
+                    argv.extend(paths.clone());
+                } else {
+                    argv.push(e.clone());
                 }
             }
+            argv
         } else {
-            cmd.push(doc.path().into());
+            let mut cmd: Vec<OsString> = cmd.to_owned();
+            cmd.extend(paths);
+            cmd
         }
-
-        cmd
     } else {
-        vec![default_cmd(), doc.path().into()]
+        let mut cmd = default_cmd();
+        cmd.extend(paths);
+        cmd
+    }
+}
+
+/// Choose the first command of `opener` whose program is found. Returns the
+/// last one if none was found so that the error is reported on execution.
+fn resolve_opener(opener: &cfg::OpenerCfg) -> Option<Vec<String>> {
+    let mut candidates = opener.candidates();
+    let last = candidates.pop()?;
+    for cmd in candidates {
+        if program_exists(cmd[0].as_ref()) {
+            log::debug!("Using the opener {:?}", cmd);
+            return Some(cmd);
+        }
+        log::debug!("Skipping the opener {:?}; `{}` was not found", cmd, cmd[0]);
+    }
+    log::debug!("Using the opener {:?}", last);
+    Some(last)
+}
+
+/// Check if the specified program can be executed, searching `PATH` if it's
+/// not a path.
+fn program_exists(program: &std::ffi::OsStr) -> bool {
+    let is_executable = |path: &Path| match () {
+        #[cfg(unix)]
+        () => {
+            use std::os::unix::fs::PermissionsExt;
+            path.metadata()
+                .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+                .unwrap_or(false)
+        }
+        #[cfg(not(unix))]
+        () => path.is_file(),
     };
 
-    let mut cmd = std::process::Command::new(&argv[0]);
-    cmd.args(&argv[1..]);
-    cmd.env("V", &argv0);
+    // Windows resolves programs without an extension by `PATHEXT`
+    let extensions: Vec<OsString> = if cfg!(windows) && Path::new(program).extension().is_none() {
+        std::env::var("PATHEXT")
+            .unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_owned())
+            .split(';')
+            .map(OsString::from)
+            .collect()
+    } else {
+        vec![OsString::new()]
+    };
+    let exists = |path: &Path| {
+        extensions.iter().any(|ext| {
+            let mut path = path.as_os_str().to_owned();
+            path.push(ext);
+            is_executable(Path::new(&path))
+        })
+    };
 
-    if !sc.preserve_pwd {
-        cmd.current_dir(&root.path);
+    if Path::new(program).components().count() > 1 {
+        return exists(Path::new(program));
     }
-
-    exec(&mut cmd)
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| exists(&dir.join(program))))
+        .unwrap_or(false)
 }
 
-fn default_opener() -> OsString {
+fn default_opener() -> Vec<OsString> {
     if cfg!(target_os = "macos") {
-        "open".into()
+        vec!["open".into()]
+    } else if cfg!(windows) {
+        // The empty argument is the window title. Without it, `start` would
+        // interpret a quoted path as a title.
+        vec!["cmd".into(), "/C".into(), "start".into(), "".into()]
     } else {
-        "xdg-open".into()
+        vec!["xdg-open".into()]
     }
 }
 
-fn default_viewer() -> OsString {
+fn default_viewer() -> Vec<OsString> {
     if let Some(e) = std::env::var_os("PAGER") {
-        e
+        vec![e]
+    } else if cfg!(windows) {
+        vec!["more".into()]
     } else {
-        "less".into()
+        vec!["less".into()]
     }
 }
 
-fn default_editor() -> OsString {
+fn default_editor() -> Vec<OsString> {
     if let Some(e) = std::env::var_os("EDITOR") {
-        e
+        vec![e]
+    } else if cfg!(windows) {
+        vec!["notepad".into()]
     } else {
-        "vi".into()
+        vec!["vi".into()]
     }
 }
 
+/// Construct a `Command` from an argument vector.
+///
+/// On Windows, `cmd.exe` doesn't follow the usual command-line parsing rules,
+/// so the arguments passed to it are quoted in the way it understands.
+fn new_command(argv: &[OsString]) -> std::process::Command {
+    let mut cmd = std::process::Command::new(&argv[0]);
+
+    #[cfg(windows)]
+    {
+        if is_cmd_exe(&argv[0]) {
+            use std::os::windows::process::CommandExt;
+            for arg in argv[1..].iter() {
+                cmd.raw_arg(quote_cmd_arg(arg));
+            }
+            return cmd;
+        }
+    }
+
+    cmd.args(&argv[1..]);
+    cmd
+}
+
+#[cfg(windows)]
+fn is_cmd_exe(program: &std::ffi::OsStr) -> bool {
+    let name = Path::new(program).file_name().unwrap_or_default();
+    name.eq_ignore_ascii_case("cmd") || name.eq_ignore_ascii_case("cmd.exe")
+}
+
+/// Quote an argument for `cmd.exe`. Metacharacters lose their special meaning
+/// between double quotes. (Windows paths can't include double quotes.)
+#[cfg(windows)]
+fn quote_cmd_arg(arg: &std::ffi::OsStr) -> OsString {
+    let needs_quotes = arg.is_empty()
+        || arg
+            .to_string_lossy()
+            .contains(|c: char| " \t&|<>^(),;=%!\"".contains(c));
+    if needs_quotes {
+        let mut quoted = OsString::from("\"");
+        quoted.push(arg);
+        quoted.push("\"");
+        quoted
+    } else {
+        arg.to_owned()
+    }
+}
+
+/// The maximum length of excerpts in `ls --json --excerpt`
+const EXCERPT_LEN: usize = 200;
+
+/// An element of the output of `ls --json`, which is described by
+/// `v schema list-output`
+#[derive(serde::Serialize, schemars::JsonSchema)]
+#[schemars(rename = "Document")]
+struct JsonDoc<'a> {
+    /// The name of the document root (`--all-roots` only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    root: Option<&'a str>,
+    /// The path of the document, formatted according to `--path-format`
+    path: String,
+    /// The raw representation of `path` if it isn't valid Unicode: bytes on
+    /// Unix and UTF-16 code units on Windows
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<Vec<u16>>")]
+    raw_path: Option<serde_json::Value>,
+    /// The metadata of the document, or `null` if it has none
+    #[schemars(with = "Option<serde_json::Map<String, serde_json::Value>>")]
+    meta: &'a serde_yaml::Value,
+    /// The beginning of the body (`--excerpt` only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    excerpt: Option<String>,
+    /// The body of the document (`--with-body` only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body: Option<String>,
+}
+
+/// Open the document roots in the named-roots registry for `--all-roots`,
+/// applying the command-line options that override their configuration.
+fn open_registered_roots(opts: &cfg::Opts) -> Result<Vec<(String, root::DocRoot)>> {
+    let mut roots = registry::Registry::load()?.open_all()?;
+    for (_, root) in roots.iter_mut() {
+        root.cfg.skip_errors |= opts.skip_errors;
+        root.cfg.contents.binary |= opts.binary;
+    }
+    if roots.is_empty() {
+        log::warn!("The named-roots registry is empty");
+    }
+    Ok(roots)
+}
+
 fn verb_ls(root: &root::DocRoot, opts: &cfg::Opts, sc: &cfg::List) -> Result<()> {
-    let query = query::Query::from_opt(&root.cfg, &sc.query)?;
-    let docs = query::select_all(root, &query);
+    if sc.filter {
+        return verb_ls_filter(root, sc);
+    }
+    if sc.group_by.is_some() {
+        return group::verb_ls_group_by_dir(root, opts, sc);
+    }
+    if let Some(key) = &sc.duplicates_by {
+        return group::verb_ls_duplicates_by(root, opts, sc, key);
+    }
+
+    let registered_roots;
+    let roots: Vec<(Option<&str>, &root::DocRoot)> = if sc.all_roots {
+        registered_roots = open_registered_roots(opts)?;
+        registered_roots
+            .iter()
+            .map(|(name, root)| (Some(&**name), root))
+            .collect()
+    } else {
+        vec![(None, root)]
+    };
+    let mut queries = roots
+        .iter()
+        .map(|&(_, root)| sc.query.compile(root))
+        .collect::<Result<Vec<_>>>()?;
+    if let Some(ttl) = sc.cache_ttl {
+        restrict_to_cached_results(root, &sc.query, &mut queries[0], ttl)?;
+    }
+    if sc.daemon {
+        restrict_to_daemon_results(root, &sc.query, &mut queries[0]);
+    }
+    if sc.query.rank && sc.sort.is_some() {
+        anyhow::bail!("`--rank` and `--sort` can't be used together");
+    }
+    let rank = sc.query.is_ranked(root) && sc.sort.is_none();
     let mut out = render::Pager::new(opts);
+    if sc.align_debug {
+        let term = console::Term::stdout();
+        let column = if term.features().is_attended() {
+            (term.size().1 as usize).saturating_sub(1)
+        } else {
+            79
+        };
+        out = out.with_align_guides(column).context(WriteError)?;
+    }
 
     #[derive(Debug, thiserror::Error)]
     #[error("An error occurred while enumerating matching documents")]
@@ -129,100 +1049,545 @@ fn verb_ls(root: &root::DocRoot, opts: &cfg::Opts, sc: &cfg::List) -> Result<()>
     #[error("An error occurred while reading the metadata of {0:?}")]
     struct ReadError(std::path::PathBuf);
 
+    // Results from multiple roots are merged in a stable order
+    type Item<'a> = Result<(Option<&'a str>, &'a root::DocRoot, doc::DocRead)>;
+    let docs: Box<dyn Iterator<Item = Item<'_>> + '_> = if rank {
+        // Results from multiple roots are interleaved by relevance
+        let mut all: Vec<(u32, Item<'_>)> = Vec::new();
+        for (&(name, root), query) in roots.iter().zip(queries.iter()) {
+            let ranked = query::select_ranked(root, query).context(SearchError)?;
+            all.extend(
+                ranked
+                    .into_iter()
+                    .map(|ranked| (ranked.score, Ok((name, root, ranked.doc)))),
+            );
+        }
+        all.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+        Box::new(all.into_iter().map(|(_, item)| item))
+    } else if let Some(key) = &sc.sort {
+        // Each root's documents are sorted separately (with its own schema
+        // and locale), and the sorted runs are merged by the current root's
+        // order
+        let mut all: Vec<(serde_yaml::Value, Item<'_>)> = Vec::new();
+        for (&(name, root), query) in roots.iter().zip(queries.iter()) {
+            let docs = match sc.limit {
+                Some(limit) => {
+                    query::select_top_k(root, query, key, sc.reverse, limit).context(SearchError)?
+                }
+                None => {
+                    let docs = query::select_all_sorted(root, query).context(SearchError)?;
+                    query::sort_docs(root, docs, key, sc.reverse)?
+                }
+            };
+            all.extend(
+                docs.into_iter()
+                    .map(|(sort_key, doc)| (sort_key, Ok((name, root, doc)))),
+            );
+        }
+        let order = query::SortOrder::new(root, key, sc.reverse);
+        all.sort_by(|(a, _), (b, _)| order.compare(a, b));
+        Box::new(all.into_iter().map(|(_, item)| item))
+    } else if sc.all_roots {
+        let mut all: Vec<Item<'_>> = Vec::new();
+        for (&(name, root), query) in roots.iter().zip(queries.iter()) {
+            let docs = query::select_all_sorted(root, query).context(SearchError)?;
+            all.extend(docs.into_iter().map(|doc| Ok((name, root, doc))));
+        }
+        Box::new(all.into_iter())
+    } else {
+        Box::new(
+            query::select_all(root, &queries[0]).map(move |doc| doc.map(|doc| (None, root, doc))),
+        )
+    };
+    let docs = docs.take(sc.limit.unwrap_or(usize::MAX));
+
     if sc.simple {
+        let mut copied = String::new();
         for doc_or_error in docs {
-            let doc = doc_or_error.context(SearchError)?;
-            writeln!(out, "{}", doc).context(WriteError)?;
+            let (name, root, doc) = doc_or_error.context(SearchError)?;
+            let path = sc.path_format.apply(root, doc.path());
+            if let Some(name) = name {
+                write!(out, "{}\t", name).context(WriteError)?;
+            }
+            writeln!(out, "{}", path.display()).context(WriteError)?;
+            if sc.copy {
+                copied += &format!("{}\n", path.display());
+            }
         }
-    } else if sc.json {
-        #[derive(serde::Serialize)]
-        struct JsonDoc<'a> {
-            path: String,
-            meta: &'a serde_yaml::Value,
+        if sc.copy {
+            clipboard::copy(&copied).context("Failed to copy the paths to the clipboard")?;
         }
+    } else if sc.json {
         writeln!(out, "[").context(WriteError)?;
         for (i, doc_or_error) in docs.enumerate() {
-            let mut doc = doc_or_error.context(SearchError)?;
-            let path = doc.path().to_owned();
+            let (name, root, mut doc) = doc_or_error.context(SearchError)?;
+            let path = sc.path_format.apply(root, doc.path());
             if i > 0 {
                 write!(out, ",\n  ").context(WriteError)?;
             } else {
                 write!(out, "  ").context(WriteError)?;
             }
+            let body = if sc.excerpt || sc.with_body {
+                let body = doc
+                    .read_body()
+                    .with_context(|| format!("Failed to read the body of {:?}", path))?;
+                Some(String::from_utf8_lossy(&body).into_owned())
+            } else {
+                None
+            };
+            let excerpt = body
+                .as_deref()
+                .filter(|_| sc.excerpt)
+                .map(|body| doc::excerpt(body, EXCERPT_LEN));
             let json = serde_json::to_string(&JsonDoc {
-                path: doc.path().to_string_lossy().into_owned(),
+                root: name,
+                path: path.to_string_lossy().into_owned(),
+                raw_path: render::raw_path(&path),
                 meta: doc.ensure_meta().with_context(|| ReadError(path.clone()))?,
+                excerpt,
+                body: body.filter(|_| sc.with_body),
             })
             .unwrap();
             write!(out, "{}", json).context(WriteError)?;
         }
         writeln!(out, "\n]").context(WriteError)?;
+    } else if sc.table {
+        let default_fields = ["name", "title", "tags"].map(String::from);
+        let fields = sc.fields.as_deref().unwrap_or(&default_fields);
+
+        let mut header: Vec<String> = Vec::new();
+        if sc.all_roots {
+            header.push("root".to_owned());
+        }
+        header.extend(fields.iter().cloned());
+        let mut rows = vec![header];
+
+        // The Git history is examined only if needed
+        let uses_git_dates = fields
+            .iter()
+            .any(|field| dates::GIT_FIELDS.contains(&&**field));
+        let mut git_dates: HashMap<&Path, dates::GitDates> = HashMap::new();
+
+        for doc_or_error in docs {
+            let (name, root, mut doc) = doc_or_error.context(SearchError)?;
+            let path = sc.path_format.apply(root, doc.path());
+            let doc_type = doc.doc_type();
+            let dates = render::DateFormatter::new(&root.cfg);
+            let stem = doc
+                .path()
+                .file_stem()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned();
+            let doc_path = doc.path().to_owned();
+            let meta = doc.ensure_meta().with_context(|| ReadError(path.clone()))?;
+            let root_git_dates = uses_git_dates.then(|| {
+                &*git_dates
+                    .entry(&root.path)
+                    .or_insert_with(|| dates::GitDates::load(root))
+            });
+
+            let mut row: Vec<String> = name.map(str::to_owned).into_iter().collect();
+            row.extend(fields.iter().map(|field| match &**field {
+                "name" => stem.clone(),
+                "path" => path.display().to_string(),
+                "type" if meta["type"].is_null() => doc_type.to_string(),
+                field => {
+                    let value = match root_git_dates {
+                        Some(git_dates) => git_dates.field_value(&doc_path, meta, field),
+                        None => meta[field].clone(),
+                    };
+                    render::field_text(&root.cfg, &dates, field, &value)
+                }
+            }));
+            rows.push(row);
+        }
+
+        let term = console::Term::stdout();
+        let max_width = if term.features().is_attended() {
+            Some(term.size().1 as usize)
+        } else {
+            None
+        };
+        render::write_table(&mut out, &rows, max_width).context(WriteError)?;
+    } else if sc.vimgrep {
+        for doc_or_error in docs {
+            let (_, root, mut doc) = doc_or_error.context(SearchError)?;
+            let path = sc.path_format.apply(root, doc.path());
+            let query = &queries[roots
+                .iter()
+                .position(|&(_, other)| std::ptr::eq(other, root))
+                .unwrap()];
+            let matches = query
+                .content_matches(&doc)
+                .with_context(|| format!("Failed to search {:?}", path))?;
+            if matches.is_empty() {
+                let stem = doc
+                    .path()
+                    .file_stem()
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned();
+                let meta = doc.ensure_meta().with_context(|| ReadError(path.clone()))?;
+                let title = meta["title"].as_str().unwrap_or(&stem);
+                writeln!(out, "{}", render::vimgrep_line(&path, 1, 1, title))
+                    .context(WriteError)?;
+            }
+            for m in matches {
+                writeln!(
+                    out,
+                    "{}",
+                    render::vimgrep_line(&path, m.line, m.column, &m.text)
+                )
+                .context(WriteError)?;
+            }
+        }
+    } else if sc.long {
+        for (i, doc_or_error) in docs.enumerate() {
+            let (name, root, mut doc) = doc_or_error.context(SearchError)?;
+            let path = sc.path_format.apply(root, doc.path());
+            let fs_meta = std::fs::metadata(doc.path())
+                .with_context(|| format!("Failed to get the metadata of {:?}", path))?;
+            let meta = doc.ensure_meta().with_context(|| ReadError(path.clone()))?;
+
+            if i > 0 {
+                writeln!(out).context(WriteError)?;
+            }
+            if let Some(name) = name {
+                write!(out, "[{}] ", name).context(WriteError)?;
+            }
+            render::write_doc_details(&mut out, &root.cfg, &path, meta, &fs_meta)
+                .context(WriteError)?;
+        }
     } else {
+        let term = console::Term::stdout();
+        let wrap_width = if sc.wrap && term.features().is_attended() {
+            Some(term.size().1 as usize)
+        } else {
+            None
+        };
         for doc_or_error in docs {
-            let mut doc = doc_or_error.context(SearchError)?;
+            let (name, root, mut doc) = doc_or_error.context(SearchError)?;
             let path = doc.path().to_owned();
-            let name = path.file_stem().unwrap().to_string_lossy();
             let meta = doc.ensure_meta().with_context(|| ReadError(path.clone()))?;
 
-            // Base name
-            write!(
-                out,
-                "{} ",
-                // gray
-                Color::Fixed(245).paint(render::fit_to_width(&name, 10))
+            // The queries are compiled for each root, whose configuration
+            // (e.g., `regex.case_insensitive`) may differ
+            let query = &queries[roots
+                .iter()
+                .position(|&(_, other)| std::ptr::eq(other, root))
+                .unwrap()];
+
+            let mut column = 0;
+            if let Some(name) = name {
+                let name = format!("[{}] ", name);
+                write!(out, "{}", name).context(WriteError)?;
+                column = render::display_width(&name);
+            }
+            render::write_doc_summary_wrapped(
+                &mut out,
+                &root.cfg.theme,
+                &path,
+                meta,
+                query.highlights(),
+                wrap_width.map(|width| (column, width)),
             )
             .context(WriteError)?;
 
-            // Tags
-            if let serde_yaml::Value::Sequence(array) = &meta["tags"] {
-                let theme = &root.cfg.theme;
-                for e in array.iter() {
-                    if let serde_yaml::Value::String(st) = e {
-                        let style = theme.tags.get(&*st).unwrap_or(&theme.tag_default);
-                        write!(
-                            out,
-                            "{} ",
-                            style.ansi_term_style().paint(format!(" {} ", st))
-                        )
-                        .context(WriteError)?;
-                    }
-                }
+            writeln!(out).context(WriteError)?;
+        }
+    }
+
+    out.finish().context(WriteError)?;
+    Ok(())
+}
+
+/// Restrict `query` to the cached results of the same query (`--cache-ttl`),
+/// refreshing them first, or caching them if they are missing or expired.
+fn restrict_to_cached_results(
+    root: &root::DocRoot,
+    sc_query: &cfg::Query,
+    query: &mut query::Query,
+    ttl: u64,
+) -> Result<()> {
+    let mut cache = query_cache::QueryCache::new(root, &format!("{:?}", sc_query));
+    let cached = cache.get(root, std::time::Duration::from_secs(ttl));
+    let paths = match cached {
+        Some(query_cache::CachedResults {
+            paths,
+            refreshed: false,
+        }) => {
+            log::debug!("Using the cached results of the query");
+            paths
+        }
+        cached => {
+            // Examine only the documents that may have changed if the cached
+            // results could be refreshed
+            if let Some(cached) = cached {
+                query.restrict_to_files(cached.paths);
             }
+            let paths = query::select_all(root, query)
+                .map(|doc| Ok(doc?.path().to_owned()))
+                .collect::<Result<Vec<_>>>()
+                .context("An error occurred while enumerating matching documents")?;
+            if let Err(e) = cache.put(root, paths.clone()) {
+                log::warn!("Failed to cache the results of the query: {:?}", e);
+            }
+            paths
+        }
+    };
+    query.restrict_to_files(paths);
+    Ok(())
+}
 
-            // Title
-            let title = if let serde_yaml::Value::String(st) = &meta["title"] {
-                &**st
-            } else {
-                &*name
-            };
-            write!(out, "{}", title).context(WriteError)?;
+/// Restrict `query` to the results of the same query made to `v daemon`
+/// (`--daemon`). The query is still evaluated on them because the daemon may
+/// not have noticed recent changes yet (see [`daemon`] for when documents
+/// may be missing). Does nothing if the daemon isn't available.
+fn restrict_to_daemon_results(
+    root: &root::DocRoot,
+    sc_query: &cfg::Query,
+    query: &mut query::Query,
+) {
+    match daemon::query(root, &sc_query.preset, &sc_query.criteria) {
+        Ok(paths) => {
+            log::debug!("Using the results of the daemon");
+            query.restrict_to_files(paths);
+        }
+        Err(e) => log::debug!(
+            "Scanning the document root instead of using the daemon: {:?}",
+            e
+        ),
+    }
+}
 
-            write!(out, "\n").context(WriteError)?;
+/// `v ls --filter-stdin`
+fn verb_ls_filter(root: &root::DocRoot, sc: &cfg::List) -> Result<()> {
+    let entries = cfg::resolve_path_list(root, cfg::read_stdin_once()?)?;
+    let mut query = sc.query.compile(root)?;
+    query.restrict_to_files(entries.iter().map(|(_, path)| path.clone()).collect());
+
+    let matched = query::select_all(root, &query)
+        .map(|doc| Ok(doc?.path().to_owned()))
+        .collect::<Result<HashSet<_>>>()
+        .context("An error occurred while enumerating matching documents")?;
+
+    let mut out = std::io::stdout();
+    for (line, path) in &entries {
+        if matched.contains(path) {
+            writeln!(out, "{}", line)?;
         }
     }
+    Ok(())
+}
+
+fn verb_status(root: &root::DocRoot, opts: &cfg::Opts, sc: &cfg::Status) -> Result<()> {
+    let mut entries = git::status(&root.path).context("Failed to get the Git status")?;
+    entries.sort_by(|x, y| x.path.cmp(&y.path));
+
+    let query = sc.query.compile(root)?;
+    let mut docs: Vec<doc::DocRead> = query::select_all(root, &query)
+        .collect::<Result<_>>()
+        .context("An error occurred while enumerating matching documents")?;
+    docs.sort_by(|x, y| x.path().cmp(y.path()));
+
+    let mut out = render::Pager::new(opts);
+    for entry in entries.iter() {
+        let code = ansi_term::Color::Yellow.paint(&entry.code);
+        if let Ok(i) = docs.binary_search_by(|doc| doc.path().cmp(&entry.path)) {
+            let doc = &mut docs[i];
+            let meta = doc
+                .ensure_meta()
+                .with_context(|| format!("Failed to read the metadata of {:?}", entry.path))?;
+            write!(out, "{} ", code)?;
+            render::write_doc_summary(&mut out, &root.cfg.theme, &entry.path, meta)?;
+            writeln!(out)?;
+        } else if entry.code.contains('D') && sc.query.criteria.is_empty() {
+            // Deleted documents can't be read or searched, so display only
+            // their paths, and only when no search criteria are specified
+            writeln!(out, "{} {}", code, entry.path.display())?;
+        }
+    }
+    out.finish()?;
+
+    Ok(())
+}
+
+fn verb_validate(root: &root::DocRoot, sc: &cfg::Validate) -> Result<()> {
+    if root.cfg.schema.is_empty() {
+        log::warn!("No schema is defined in `config.toml`");
+    }
+
+    let query = sc.query.compile(root)?;
+    let mut num_invalid_docs = 0;
+    for doc_or_error in query::select_all(root, &query) {
+        let mut doc =
+            doc_or_error.context("An error occurred while enumerating matching documents")?;
+        let violations = match doc.ensure_meta() {
+            Ok(meta) => schema::validate(&root.cfg.schema, meta),
+            Err(e) => {
+                println!("{}: {:#}", doc, e);
+                num_invalid_docs += 1;
+                continue;
+            }
+        };
+        if !violations.is_empty() {
+            num_invalid_docs += 1;
+        }
+        for violation in violations {
+            println!("{}: {}: {}", doc, violation.field, violation.message);
+        }
+    }
+
+    if num_invalid_docs > 0 {
+        eprintln!("{} document(s) violated the schema", num_invalid_docs);
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn verb_dedupe(root: &root::DocRoot, opts: &cfg::Opts, sc: &cfg::Dedupe) -> Result<()> {
+    use std::{
+        collections::{hash_map::DefaultHasher, HashMap},
+        hash::{Hash, Hasher},
+    };
+
+    let query = sc.query.compile(root)?;
+
+    // Group the documents by the hash values of their bodies. The hash values
+    // aren't persisted anywhere, so `DefaultHasher` is good enough.
+    let mut groups: HashMap<u64, Vec<doc::DocRead>> = HashMap::new();
+    for doc_or_error in query::select_all(root, &query) {
+        let doc = doc_or_error.context("An error occurred while enumerating matching documents")?;
+        let body = doc
+            .read_body()
+            .with_context(|| format!("Failed to read the body of {:?}", doc.path()))?;
+
+        let mut hasher = DefaultHasher::new();
+        if sc.exact {
+            body.hash(&mut hasher);
+        } else {
+            // Ignore whitespace and letter case
+            for word in String::from_utf8_lossy(&body).split_whitespace() {
+                word.to_lowercase().hash(&mut hasher);
+            }
+        }
+
+        groups.entry(hasher.finish()).or_default().push(doc);
+    }
+
+    let mut groups: Vec<_> = groups.into_values().filter(|docs| docs.len() > 1).collect();
+    for docs in groups.iter_mut() {
+        docs.sort_by(|x, y| x.path().cmp(y.path()));
+    }
+    groups.sort_by(|x, y| x[0].path().cmp(y[0].path()));
+
+    let mut out = render::Pager::new(opts);
+    (|| -> std::io::Result<()> {
+        for (i, docs) in groups.iter().enumerate() {
+            if i > 0 {
+                writeln!(out)?;
+            }
+            for doc in docs.iter() {
+                writeln!(out, "{}", doc)?;
+            }
+        }
+        out.finish()
+    })()
+    .context("An error occurred while writing to the standard output")?;
 
-    out.finish().context(WriteError)?;
     Ok(())
 }
 
 fn verb_run(root: &root::DocRoot, sc: &cfg::Run) -> Result<Infallible> {
-    let argv0 = std::env::args_os().next().unwrap();
-    log::debug!("argv0 = {:?} (passed as V variable)", argv0);
-
-    exec(
-        std::process::Command::new(&sc.cmd[0])
-            .args(&sc.cmd[1..])
-            .env("V", &argv0)
-            .current_dir(&root.path),
-    )
+    if sc.with.is_empty() {
+        return exec(set_script_env(&mut new_command(&sc.cmd), root).current_dir(&root.path));
+    }
+
+    let query = query::Query::new(root, "default", &sc.with)?;
+    let paths: Vec<std::path::PathBuf> = query::select_all_sorted(root, &query)
+        .context("An error occurred while enumerating matching documents")?
+        .iter()
+        .map(|doc| doc.path().to_owned())
+        .collect();
+    if paths.is_empty() {
+        let criteria: Vec<String> = sc.with.iter().map(ToString::to_string).collect();
+        anyhow::bail!("No documents matched {:?}", criteria.join(" "));
+    }
+
+    // Replace `{}` with the paths
+    let cmd: Vec<OsString> = sc
+        .cmd
+        .iter()
+        .flat_map(|arg| {
+            if arg == "{}" {
+                paths.iter().map(|path| path.clone().into()).collect()
+            } else {
+                vec![arg.clone()]
+            }
+        })
+        .collect();
+
+    // The list of paths is passed through a file because it may not fit in
+    // the environment
+    let temp_dir = temp::PrivateTempDir::new()?;
+    let list_path = temp_dir.path().join("docs");
+    let mut list = Vec::new();
+    for path in paths.iter() {
+        list.extend_from_slice(path.to_string_lossy().as_bytes());
+        list.push(b'\n');
+    }
+    std::fs::write(&list_path, list).with_context(|| format!("Failed to write {:?}", list_path))?;
+
+    let mut command = new_command(&cmd);
+    set_script_env(&mut command, root)
+        .current_dir(&root.path)
+        .env("V_DOC_COUNT", paths.len().to_string())
+        .env("V_DOC_LIST", &list_path);
+
+    // Not exec-ing the command so that the list can be removed afterwards
+    log::debug!("Spawning {:?}", command);
+    let mut child = command
+        .spawn()
+        .with_context(|| format!("Failed to execute {:?}", command))?;
+    ignore_ctrl_c();
+    let status = child
+        .wait()
+        .context("Failed to wait for the spawned process")?;
+    drop(temp_dir);
+    std::process::exit(status.code().unwrap_or(1));
+}
+
+/// Get the name of `subcmd` if it always creates or modifies files (unlike
+/// verbs that check [`root::DocRoot::ensure_writable`] only when they are
+/// about to modify documents).
+fn mutating_verb(subcmd: &cfg::Subcommand) -> Option<&'static str> {
+    Some(match subcmd {
+        cfg::Subcommand::Edit(_) => "edit",
+        cfg::Subcommand::Import(_) => "import",
+        cfg::Subcommand::New(_) => "new",
+        cfg::Subcommand::Capture(_) => "capture",
+        cfg::Subcommand::Today(_) => "today",
+        cfg::Subcommand::Periodic(_) => "periodic",
+        cfg::Subcommand::Generate(sc) if !sc.dry_run => "generate",
+        cfg::Subcommand::Sync(_) => "sync",
+        cfg::Subcommand::Config(cfg::Config {
+            subcmd: cfg::ConfigSubcommand::Set(_),
+        }) => "config set",
+        cfg::Subcommand::Search(cfg::Search {
+            subcmd: cfg::SearchSubcommand::Save(_),
+        }) => "search save",
+        cfg::Subcommand::Search(cfg::Search {
+            subcmd: cfg::SearchSubcommand::Rm(_),
+        }) => "search rm",
+        _ => return None,
+    })
 }
 
 /// Locate a program at `v-custom-subcommand` or `$root/bin/custom-subcommand`
 /// and execute it.
 fn verb_run_script(root: &root::DocRoot, mut cmd: Vec<OsString>) -> Result<Infallible> {
-    let argv0 = std::env::args_os().next().unwrap();
-    log::debug!("argv0 = {:?} (passed as V variable)", argv0);
-
     let orig_cmd = replace(&mut cmd[0], OsString::new());
     let orig_cmd_path = Path::new(&orig_cmd);
     if orig_cmd_path.is_absolute() {
@@ -234,12 +1599,7 @@ fn verb_run_script(root: &root::DocRoot, mut cmd: Vec<OsString>) -> Result<Infal
     }
 
     log::debug!("Trying to exec {:?}", cmd[0]);
-    let err = match exec(
-        std::process::Command::new(&cmd[0])
-            .args(&cmd[1..])
-            .env("V", &argv0)
-            .current_dir(&root.path),
-    ) {
+    let err = match exec(set_script_env(&mut new_command(&cmd), root).current_dir(&root.path)) {
         Ok(_) => unreachable!(),
         Err(e) => e,
     };
@@ -253,18 +1613,91 @@ fn verb_run_script(root: &root::DocRoot, mut cmd: Vec<OsString>) -> Result<Infal
         cmd[0].push(&orig_cmd);
 
         log::debug!("Trying to exec {:?}", cmd[0]);
-        exec(
-            std::process::Command::new(&cmd[0])
-                .args(&cmd[1..])
-                .env("V", &argv0)
-                .current_dir(&root.path),
-        )
-        .with_context(|| format!("Could not execute {:?} or {:?}", failed_cmd, cmd[0]))
+        exec(set_script_env(&mut new_command(&cmd), root).current_dir(&root.path))
+            .with_context(|| format!("Could not execute {:?} or {:?}", failed_cmd, cmd[0]))
     } else {
         Err(err).with_context(|| format!("Could not execute {:?}", failed_cmd))
     }
 }
 
+/// Set the environment variables passed to scripts run by this program:
+/// those set by [`set_command_env`] and `V_CONFIG`, the configuration file
+/// (`.veisku/config.toml`), which might not exist. When this variable is set,
+/// root discovery is skipped, so invoking `$V` from a script always operates
+/// on the same document root.
+fn set_script_env<'a>(
+    cmd: &'a mut std::process::Command,
+    root: &root::DocRoot,
+) -> &'a mut std::process::Command {
+    set_command_env(cmd, root).env("V_CONFIG", root.cfg_file_path())
+}
+
+/// Set the environment variables passed to external commands such as
+/// editors, openers, pickers, and converters.
+///
+///  - `V` - the path to this program
+///  - `V_ROOT` - the document root
+///  - `V_PWD` - the working directory from which this program was invoked
+///  - `V_ARGC`, `V_ARGV_0`, `V_ARGV_1`, ... - the original command-line
+///    arguments of this program
+///  - `V_READ_ONLY` - `1` if the document root is in read-only mode, which
+///    is inherited by invocations of `$V`
+///
+/// `V_CONFIG` is removed because these commands may outlive this program
+/// (e.g., an editor server) and run `v` in other document roots.
+fn set_command_env<'a>(
+    cmd: &'a mut std::process::Command,
+    root: &root::DocRoot,
+) -> &'a mut std::process::Command {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    log::debug!("argv0 = {:?} (passed as V variable)", args[0]);
+
+    cmd.env("V", &args[0])
+        .env("V_ROOT", &root.path)
+        .env_remove("V_CONFIG")
+        .env("V_ARGC", args.len().to_string());
+    if let Ok(pwd) = std::env::current_dir() {
+        cmd.env("V_PWD", pwd);
+    }
+    if root.cfg.read_only {
+        cmd.env("V_READ_ONLY", "1");
+    }
+    for (i, arg) in args.iter().enumerate() {
+        cmd.env(format!("V_ARGV_{}", i), arg);
+    }
+    cmd
+}
+
+/// Configure `cmd` to run in the background, detached from the terminal and
+/// not receiving the signals sent to the current session or console.
+fn detach(cmd: &mut std::process::Command) {
+    cmd.stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        // Safety: `setsid` is async-signal-safe
+        unsafe {
+            cmd.pre_exec(|| {
+                if libc::setsid() == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        const DETACHED_PROCESS: u32 = 0x0000_0008;
+        const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+        cmd.creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP);
+    }
+}
+
 /// Exec a program.
 fn exec(cmd: &mut std::process::Command) -> Result<Infallible> {
     match () {
@@ -278,15 +1711,46 @@ fn exec(cmd: &mut std::process::Command) -> Result<Infallible> {
         #[cfg(not(unix))]
         () => {
             log::debug!("Spawning {:?}", cmd);
-            let child = cmd.spawn().context("Failed to spawn a process")?;
-            let result = child
+            let mut child = cmd.spawn().context("Failed to spawn a process")?;
+
+            // Processes attached to the same console all receive Ctrl-C. Leave
+            // it to the child process, which may handle it gracefully, and
+            // exit when it does.
+            ignore_ctrl_c();
+
+            let status = child
                 .wait()
-                .context("Failed to wait for the spawned process");
-            if result.success() {
-                std::process::exit(0);
-            } else {
-                std::process::exit(result.code().unwrap_or(1));
-            }
+                .context("Failed to wait for the spawned process")?;
+            std::process::exit(status.code().unwrap_or(1));
         }
     }
 }
+
+/// Make the current process ignore Ctrl-C. Must be called after spawning
+/// child processes, which would otherwise inherit this.
+#[cfg(unix)]
+fn ignore_ctrl_c() {
+    // Safety: `SIG_IGN` is a valid disposition for `SIGINT`
+    unsafe { libc::signal(libc::SIGINT, libc::SIG_IGN) };
+}
+
+/// Make the current process ignore Ctrl-C.
+#[cfg(windows)]
+fn ignore_ctrl_c() {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetConsoleCtrlHandler(
+            handler: Option<unsafe extern "system" fn(u32) -> i32>,
+            add: i32,
+        ) -> i32;
+    }
+
+    // Safety: A null handler with `add = TRUE` simply makes the process ignore
+    // Ctrl-C.
+    if unsafe { SetConsoleCtrlHandler(None, 1) } == 0 {
+        log::debug!("SetConsoleCtrlHandler failed");
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn ignore_ctrl_c() {}
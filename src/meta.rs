@@ -0,0 +1,364 @@
+//! Metadata manipulation (`v meta`)
+use ansi_term::Color;
+use anyhow::{bail, Context, Result};
+use serde_yaml::{Mapping, Value};
+use std::io::{BufRead, Write};
+use veisku_core::journal::Journal;
+
+use crate::{cfg, doc, doc::DocRead, query, render, root::DocRoot, temp};
+
+pub fn verb_meta(root: &DocRoot, sc: &cfg::Meta) -> Result<()> {
+    match &sc.subcmd {
+        cfg::MetaSubcommand::Show(sc) => verb_meta_show(root, sc),
+        cfg::MetaSubcommand::Edit(sc) => verb_meta_edit(root, sc),
+        cfg::MetaSubcommand::Set(sc) => verb_meta_set(root, sc),
+        cfg::MetaSubcommand::Diff(sc) => verb_meta_diff(root, sc),
+    }
+}
+
+fn verb_meta_show(root: &DocRoot, sc: &cfg::MetaShow) -> Result<()> {
+    let mut doc = sc.query.select_one(root)?;
+    let path = doc.path().to_owned();
+    let meta = doc
+        .ensure_meta()
+        .with_context(|| format!("Failed to read the metadata of {:?}", path))?;
+    let meta = match meta {
+        Value::Null => Value::Mapping(Mapping::new()),
+        meta => meta.clone(),
+    };
+
+    let text = match sc.format {
+        cfg::MetaFormat::Yaml => {
+            let yaml = serde_yaml::to_string(&meta)?;
+            let yaml = yaml.strip_prefix("---\n").unwrap_or(&yaml);
+            format!("{}\n", yaml.trim_end_matches('\n'))
+        }
+        cfg::MetaFormat::Json => {
+            serde_json::to_string_pretty(&meta)
+                .context("The metadata can't be converted to JSON")?
+                + "\n"
+        }
+        cfg::MetaFormat::Toml => {
+            let json =
+                serde_json::to_value(&meta).context("The metadata can't be converted to TOML")?;
+            let toml = toml::Value::try_from(strip_nulls(json))
+                .context("The metadata can't be converted to TOML")?;
+            toml::to_string(&toml).context("The metadata can't be converted to TOML")?
+        }
+    };
+    print!("{}", text);
+    Ok(())
+}
+
+/// Remove null values (including the fields and elements having them), which
+/// TOML can't represent.
+fn strip_nulls(value: serde_json::Value) -> serde_json::Value {
+    use serde_json::Value as Json;
+    match value {
+        Json::Object(map) => Json::Object(
+            map.into_iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key, strip_nulls(value)))
+                .collect(),
+        ),
+        Json::Array(array) => array
+            .into_iter()
+            .filter(|value| !value.is_null())
+            .map(strip_nulls)
+            .collect(),
+        value => value,
+    }
+}
+
+fn verb_meta_edit(root: &DocRoot, sc: &cfg::MetaEdit) -> Result<()> {
+    root.ensure_writable()?;
+
+    let doc = sc.query.select_one(root)?;
+    if root.cfg.encryption_for(doc.path()).is_some() {
+        bail!("Editing the metadata of encrypted documents is not supported");
+    }
+
+    let bytes =
+        std::fs::read(doc.path()).with_context(|| format!("Failed to read {:?}", doc.path()))?;
+    let preamble = std::str::from_utf8(doc::split_md_preamble(&bytes).0.unwrap_or(b""))
+        .context("Failed to decode the preamble as UTF-8")?
+        .replace("\r\n", "\n")
+        .replace('\r', "\n");
+
+    let temp_dir = temp::PrivateTempDir::new()?;
+    let mut temp_name = doc.path().file_stem().unwrap().to_owned();
+    temp_name.push(".yaml");
+    let temp_path = temp_dir.path().join(temp_name);
+    std::fs::write(&temp_path, format!("{}\n", preamble))
+        .with_context(|| format!("Failed to write {:?}", temp_path))?;
+
+    // Let the user edit the preamble until it parses
+    let new_preamble = loop {
+        let mut cmd = crate::open_command(
+            root,
+            &temp_path,
+            sc.cmd.as_deref(),
+            false,
+            crate::default_editor,
+        );
+        log::debug!("Spawning {:?}", cmd);
+        let status = cmd
+            .status()
+            .with_context(|| format!("Failed to execute {:?}", cmd))?;
+        if !status.success() {
+            bail!("The editor exited with {}; discarding the changes", status);
+        }
+
+        let new_preamble = std::fs::read_to_string(&temp_path)
+            .with_context(|| format!("Failed to read {:?}", temp_path))?;
+        match doc::parse_preamble_mapping(&new_preamble) {
+            Ok(_) => break new_preamble,
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                if !confirm("Edit again?", true)? {
+                    temp_dir.keep();
+                    bail!(
+                        "Didn't apply the changes; the edited metadata was kept in {:?}",
+                        temp_path
+                    );
+                }
+            }
+        }
+    };
+
+    let new_preamble = new_preamble.trim_end_matches('\n');
+    if new_preamble == preamble {
+        log::info!("The metadata was not modified");
+        return Ok(());
+    }
+
+    // Make sure the document wasn't modified in the meantime
+    let current_bytes =
+        std::fs::read(doc.path()).with_context(|| format!("Failed to read {:?}", doc.path()))?;
+    if current_bytes != bytes {
+        temp_dir.keep();
+        bail!(
+            "{:?} was modified while editing its metadata; the edited metadata was kept in {:?}",
+            doc.path(),
+            temp_path
+        );
+    }
+
+    let journal = Journal::new(root);
+    let mut recorder = journal.begin(format!("meta edit {}", doc.path().display()));
+    if let Err(e) = recorder.write_atomic(doc.path(), &doc::replace_preamble(&bytes, new_preamble))
+    {
+        temp_dir.keep();
+        return Err(e.context(format!(
+            "Failed to update {:?}; the edited metadata was kept in {:?}",
+            doc.path(),
+            temp_path
+        )));
+    }
+    recorder.commit()?;
+    log::info!("Updated the metadata of {:?}", doc.path());
+
+    Ok(())
+}
+
+fn verb_meta_set(root: &DocRoot, sc: &cfg::MetaSet) -> Result<()> {
+    root.ensure_writable()?;
+
+    let docs: Vec<DocRead> = if sc.all {
+        let query = sc.query.compile(root)?;
+        query::select_all(root, &query)
+            .collect::<Result<_, _>>()
+            .context("An error occurred while enumerating matching documents")?
+    } else {
+        vec![sc.query.select_one(root)?]
+    };
+
+    if let Some(doc) = docs
+        .iter()
+        .find(|doc| root.cfg.encryption_for(doc.path()).is_some())
+    {
+        bail!(
+            "Editing the metadata of encrypted documents is not supported: {:?}",
+            doc.path()
+        );
+    }
+
+    let key = Value::String(sc.assignment.key.clone());
+    let new_value = sc.assignment.value.as_ref();
+
+    // Find the documents that will actually be modified
+    let mut changes = Vec::new();
+    for mut doc in docs {
+        let path = doc.path().to_owned();
+        let meta = doc
+            .ensure_meta()
+            .with_context(|| format!("Failed to read the metadata of {:?}", path))?;
+        let old_value = if let Value::Mapping(mapping) = meta {
+            mapping.get(&key).cloned()
+        } else {
+            None
+        };
+        if old_value.as_ref() != new_value {
+            changes.push((doc, old_value));
+        }
+    }
+
+    if changes.is_empty() {
+        log::info!("No documents need to be updated");
+        return Ok(());
+    }
+
+    let apply = |meta: &mut Mapping| {
+        if let Some(value) = new_value {
+            meta.insert(key.clone(), value.clone());
+        } else {
+            meta.remove(&key);
+        }
+        Ok(())
+    };
+
+    // Pre-flight summary
+    if sc.diff {
+        let mut out = std::io::stderr();
+        for (doc, _) in changes.iter() {
+            let path = doc.path();
+            let bytes =
+                std::fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+            let new_bytes = doc::rewrite_meta(&bytes, apply)
+                .with_context(|| format!("Failed to update the metadata of {:?}", path))?;
+            let rel_path = path.strip_prefix(&root.path).unwrap_or(path);
+            render::write_diff(&mut out, &root.cfg.theme, rel_path, &bytes, &new_bytes)?;
+        }
+    } else if sc.all {
+        let mut out = std::io::stderr();
+        for (doc, old_value) in changes.iter_mut() {
+            let path = doc.path().to_owned();
+            let meta = doc.ensure_meta()?;
+            render::write_doc_summary(&mut out, &root.cfg.theme, &path, meta)?;
+            writeln!(
+                out,
+                "  {}: {} → {}",
+                sc.assignment.key,
+                value_summary(old_value.as_ref()),
+                value_summary(new_value),
+            )?;
+        }
+    }
+
+    if sc.all && !sc.yes && !confirm(&format!("Update {} document(s)?", changes.len()), false)? {
+        bail!("Aborted");
+    }
+
+    let journal = Journal::new(root);
+    let mut recorder = journal.begin(format!(
+        "meta set {}={} ({} document(s))",
+        sc.assignment.key,
+        value_summary(new_value),
+        changes.len()
+    ));
+    for (doc, _) in changes.iter() {
+        recorder
+            .update_meta(doc.path(), apply)
+            .with_context(|| format!("Failed to update the metadata of {:?}", doc.path()))?;
+    }
+    recorder.commit()?;
+
+    log::info!("Updated {} document(s)", changes.len());
+
+    Ok(())
+}
+
+fn verb_meta_diff(root: &DocRoot, sc: &cfg::MetaDiff) -> Result<()> {
+    let select = |criterion: &cfg::Criterion| -> Result<Mapping> {
+        let query = query::Query::new(root, "default", std::slice::from_ref(criterion))?;
+        let mut doc = query::select_one(root, &query)?;
+        Ok(match doc.ensure_meta()? {
+            Value::Mapping(mapping) => mapping.clone(),
+            _ => Mapping::new(),
+        })
+    };
+    let meta_a = select(&sc.a).context("Failed to select the document A")?;
+    let meta_b = select(&sc.b).context("Failed to select the document B")?;
+
+    // Keys in A's order, followed by the ones only in B
+    let keys = meta_a.iter().map(|(key, _)| key).chain(
+        meta_b
+            .iter()
+            .map(|(key, _)| key)
+            .filter(|key| !meta_a.contains_key(key)),
+    );
+
+    for key in keys {
+        let key_text = match key {
+            Value::String(st) => st.clone(),
+            _ => value_summary(Some(key)),
+        };
+        match (meta_a.get(key), meta_b.get(key)) {
+            (Some(a), Some(b)) if a == b => {
+                if sc.all {
+                    println!("  {}: {}", key_text, value_summary(Some(a)));
+                }
+            }
+            (Some(Value::Sequence(a)), Some(Value::Sequence(b))) => {
+                let mut line = format!("~ {}:", key_text);
+                for e in a.iter().filter(|e| !b.contains(e)) {
+                    line += &format!(" -{}", value_summary(Some(e)));
+                }
+                for e in b.iter().filter(|e| !a.contains(e)) {
+                    line += &format!(" +{}", value_summary(Some(e)));
+                }
+                if a.iter().all(|e| b.contains(e)) && b.iter().all(|e| a.contains(e)) {
+                    line += " (reordered)";
+                }
+                println!("{}", Color::Yellow.paint(line));
+            }
+            (Some(a), Some(b)) => {
+                let line = format!(
+                    "~ {}: {} → {}",
+                    key_text,
+                    value_summary(Some(a)),
+                    value_summary(Some(b))
+                );
+                println!("{}", Color::Yellow.paint(line));
+            }
+            (Some(a), None) => {
+                let line = format!("- {}: {}", key_text, value_summary(Some(a)));
+                println!("{}", Color::Red.paint(line));
+            }
+            (None, Some(b)) => {
+                let line = format!("+ {}: {}", key_text, value_summary(Some(b)));
+                println!("{}", Color::Green.paint(line));
+            }
+            (None, None) => unreachable!(),
+        }
+    }
+
+    Ok(())
+}
+
+/// Format a metadata field value for display. `None` represents a missing
+/// field.
+fn value_summary(value: Option<&Value>) -> String {
+    match value {
+        Some(value) => serde_json::to_string(value).unwrap_or_else(|_| "?".to_owned()),
+        None => "(none)".to_owned(),
+    }
+}
+
+/// Ask the user a yes/no question.
+pub fn confirm(question: &str, default: bool) -> Result<bool> {
+    eprint!("{} {} ", question, if default { "[Y/n]" } else { "[y/N]" });
+    std::io::stderr().flush()?;
+
+    let mut line = String::new();
+    std::io::stdin()
+        .lock()
+        .read_line(&mut line)
+        .context("Failed to read the standard input")?;
+
+    Ok(match line.trim() {
+        "y" | "Y" | "yes" => true,
+        "n" | "N" | "no" => false,
+        _ => default,
+    })
+}
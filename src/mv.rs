@@ -0,0 +1,124 @@
+//! Renaming documents (`v mv`)
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use veisku_core::{journal::Journal, link};
+
+use crate::{cfg, root::DocRoot};
+
+pub fn verb_mv(root: &DocRoot, sc: &cfg::Mv) -> Result<()> {
+    if !sc.dry_run {
+        root.ensure_writable()?;
+    }
+
+    let doc = sc.query.select_one(root)?;
+    let old_path = link::normalize(doc.path());
+
+    // Decide the destination path
+    let mut new_path = if sc.to.contains('/') {
+        root.path.join(&sc.to)
+    } else {
+        old_path.parent().unwrap().join(&sc.to)
+    };
+    if new_path.extension().is_none() {
+        if let Some(ext) = old_path.extension() {
+            new_path.set_extension(ext);
+        }
+    }
+    move_doc(root, &old_path, &link::normalize(&new_path), sc.dry_run)
+}
+
+/// Move the document at `old_path` to `new_path`, rewriting the links to it
+/// in other documents. Both paths must be normalized by [`link::normalize`].
+pub fn move_doc(root: &DocRoot, old_path: &Path, new_path: &Path, dry_run: bool) -> Result<()> {
+    if !new_path.starts_with(&root.path) {
+        bail!("{:?} is outside the document root", new_path);
+    }
+    if new_path.exists() {
+        bail!("{:?} already exists", new_path);
+    }
+
+    // Rebase the document's own relative links on the new location
+    let mut own_links = None;
+    if root.cfg.encryption_for(old_path).is_none() {
+        let bytes =
+            std::fs::read(old_path).with_context(|| format!("Failed to read {:?}", old_path))?;
+        if let Ok(text) = String::from_utf8(bytes) {
+            let (new_text, count) = link::rebase_links(&text, &root.path, old_path, new_path);
+            if count > 0 {
+                own_links = Some((new_text, count));
+            }
+        }
+    }
+
+    // Find the references to the document
+    let mut changes: Vec<(PathBuf, String, usize)> = Vec::new();
+    for other in root.docs() {
+        let other = other.context("An error occurred while enumerating documents")?;
+        let path = link::normalize(other.path());
+        if path == *old_path {
+            continue;
+        }
+        if root.cfg.encryption_for(&path).is_some() {
+            log::debug!("Skipping the encrypted document {:?}", path);
+            continue;
+        }
+        let bytes = std::fs::read(&path).with_context(|| format!("Failed to read {:?}", path))?;
+        let text = match String::from_utf8(bytes) {
+            Ok(text) => text,
+            Err(_) => {
+                log::warn!("Skipping {:?}, which is not valid UTF-8", path);
+                continue;
+            }
+        };
+
+        let (new_text, count) = link::rewrite_links(&text, &root.path, &path, old_path, new_path);
+        if count > 0 {
+            changes.push((path, new_text, count));
+        }
+    }
+
+    // Summary
+    println!("{} → {}", old_path.display(), new_path.display());
+    if let Some((_, count)) = &own_links {
+        println!("  {}: {} link(s)", new_path.display(), count);
+    }
+    for (path, _, count) in changes.iter() {
+        println!("  {}: {} link(s)", path.display(), count);
+    }
+
+    if dry_run {
+        log::info!("Dry run; nothing was modified");
+        return Ok(());
+    }
+
+    if let Some(dir) = new_path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+    }
+    let journal = Journal::new(root);
+    let relative = |path: &Path| path.strip_prefix(&root.path).unwrap_or(path).to_owned();
+    let mut recorder = journal.begin(format!(
+        "mv {} {}",
+        relative(old_path).display(),
+        relative(new_path).display()
+    ));
+    recorder.move_file(old_path, new_path)?;
+    if let Some((new_text, _)) = &own_links {
+        recorder
+            .write_atomic(new_path, new_text.as_bytes())
+            .with_context(|| format!("Failed to update the links in {:?}", new_path))?;
+    }
+
+    for (path, new_text, _) in changes.iter() {
+        recorder
+            .write_atomic(path, new_text.as_bytes())
+            .with_context(|| format!("Failed to update the links in {:?}", path))?;
+    }
+    recorder.commit()?;
+
+    log::info!(
+        "Moved the document, updating {} other document(s)",
+        changes.len()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,138 @@
+//! Document naming utilities
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+use veisku_core::collate::transliterate;
+
+use crate::cfg::SlugCfg;
+
+/// Convert a title to a string suitable as a file name, e.g.,
+/// `"Hello, World!"` → `"hello-world"`.
+pub fn slugify(title: &str, slug_cfg: &SlugCfg) -> String {
+    let sep = &*slug_cfg.separator;
+    let mut out = String::with_capacity(title.len());
+    let push_word_char = |out: &mut String, ch: char| {
+        if slug_cfg.lowercase {
+            out.extend(ch.to_lowercase());
+        } else {
+            out.push(ch);
+        }
+    };
+    let mut pending_sep = false;
+    for ch in title.chars() {
+        if ch.is_alphanumeric() {
+            if pending_sep && !out.is_empty() {
+                out.push_str(sep);
+            }
+            pending_sep = false;
+            match transliterate(ch).filter(|_| slug_cfg.transliterate) {
+                Some(ascii) => ascii.chars().for_each(|ch| push_word_char(&mut out, ch)),
+                None => push_word_char(&mut out, ch),
+            }
+        } else {
+            pending_sep = true;
+        }
+    }
+
+    if let Some(max_len) = slug_cfg.max_length {
+        if let Some((i, _)) = out.char_indices().nth(max_len) {
+            out.truncate(i);
+            // Cut at the last word boundary if there's one
+            if !sep.is_empty() {
+                if let Some(k) = out.rfind(sep).filter(|&k| k > 0) {
+                    out.truncate(k);
+                }
+            }
+        }
+    }
+
+    out
+}
+
+/// Find a path not occupied by an existing file by appending `-2`, `-3`, ...
+/// to the stem of `file_name`.
+pub fn disambiguate(dir: &Path, file_name: &str) -> PathBuf {
+    let path = dir.join(file_name);
+    if !path.exists() {
+        return path;
+    }
+
+    let (stem, ext) = match file_name.rfind('.').filter(|&i| i > 0) {
+        Some(i) => file_name.split_at(i),
+        None => (file_name, ""),
+    };
+    (2..)
+        .map(|i| dir.join(format!("{}-{}{}", stem, i, ext)))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+/// Replace the placeholders `{KEY}` in `template` with the corresponding values
+/// from `vars`. Unknown placeholders are left intact.
+pub fn expand_name_template(template: &str, vars: &HashMap<&str, &str>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(i) = rest.find('{') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        match rest
+            .find('}')
+            .and_then(|end| Some((end, vars.get(&rest[1..end])?)))
+        {
+            Some((end, value)) => {
+                out.push_str(value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slugify() {
+        let cfg = SlugCfg::default();
+        assert_eq!(slugify("Hello, World!", &cfg), "hello-world");
+        assert_eq!(slugify("  --Markright: ToC  ", &cfg), "markright-toc");
+        assert_eq!(slugify("Zürich 2020", &cfg), "zürich-2020");
+        assert_eq!(slugify("!!!", &cfg), "");
+    }
+
+    #[test]
+    fn test_slugify_custom() {
+        let cfg = SlugCfg {
+            lowercase: false,
+            transliterate: true,
+            max_length: Some(12),
+            separator: "_".to_owned(),
+        };
+        assert_eq!(slugify("Zürich Süd", &cfg), "Zurich_Sud");
+        assert_eq!(slugify("Größe des Ozeans", &cfg), "Grosse_des");
+        assert_eq!(slugify("Supercalifragilistic", &cfg), "Supercalifra");
+    }
+
+    #[test]
+    fn test_expand_name_template() {
+        let vars: HashMap<_, _> = [("date", "2020-11-20"), ("slug", "hello")]
+            .iter()
+            .cloned()
+            .collect();
+        assert_eq!(
+            expand_name_template("{date}-{slug}", &vars),
+            "2020-11-20-hello"
+        );
+        assert_eq!(
+            expand_name_template("{unknown}{slug", &vars),
+            "{unknown}{slug"
+        );
+    }
+}
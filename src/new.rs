@@ -0,0 +1,216 @@
+//! Creating documents from templates (`v new`)
+use anyhow::{bail, Context, Result};
+use serde_yaml::Value;
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{cfg, doc, id, naming, root::DocRoot, template};
+
+/// The template used when `.veisku/templates/default.*` doesn't exist.
+const DEFAULT_TEMPLATE: &str = "---\ndate: {{date}}\n---\n\n";
+
+pub fn verb_new(root: &DocRoot, sc: &cfg::New) -> Result<()> {
+    let class = match &sc.class {
+        Some(name) => match root.cfg.new.classes.get(name) {
+            Some(class) => Some(class),
+            None => {
+                let mut names: Vec<&str> = root.cfg.new.classes.keys().map(|s| &**s).collect();
+                names.sort_unstable();
+                bail!(
+                    "Unknown document class '{}' (defined classes: {})",
+                    name,
+                    if names.is_empty() {
+                        "none".to_owned()
+                    } else {
+                        names.join(", ")
+                    }
+                );
+            }
+        },
+        None => None,
+    };
+
+    let template_name = match (&sc.template, class) {
+        (Some(name), _) => name.clone(),
+        (
+            None,
+            Some(cfg::NewClassCfg {
+                template: Some(name),
+                ..
+            }),
+        ) => name.clone(),
+        // Use the template named after the class if there's one
+        (None, Some(_)) => {
+            let name = sc.class.as_deref().unwrap();
+            if find_template(&root.template_dir_path(), name)?.is_some() {
+                name.to_owned()
+            } else {
+                "default".to_owned()
+            }
+        }
+        (None, None) => "default".to_owned(),
+    };
+    let (template_text, ext) = read_template(root, &template_name)?;
+
+    // Expand the template
+    let mut user_vars: HashMap<String, String> = sc
+        .vars
+        .iter()
+        .map(|var| (var.key.clone(), var.value.clone()))
+        .collect();
+    user_vars
+        .entry("title".to_owned())
+        .or_insert_with(|| sc.title.clone());
+    let mut vars = template::Vars::new(user_vars);
+    let contents = template::expand_document(&template_text, |name, arg| vars.resolve(name, arg))
+        .with_context(|| format!("Failed to expand the template '{}'", template_name))?;
+
+    let mut id = None;
+    let contents = doc::rewrite_meta(contents.as_bytes(), |meta| {
+        let title_key = Value::String("title".to_owned());
+        if !meta.contains_key(&title_key) {
+            meta.insert(title_key, Value::String(sc.title.clone()));
+        }
+
+        // Assign an ID unless the template provides one
+        let id_key = Value::String("id".to_owned());
+        if let Some(scheme) = root.cfg.new.id {
+            if !meta.contains_key(&id_key) {
+                let new_id = id::generate_unique(scheme, &mut id::existing_ids(root)?);
+                meta.insert(id_key.clone(), Value::String(new_id));
+            }
+        }
+        id = match meta.get(&id_key) {
+            Some(Value::String(st)) => Some(st.clone()),
+            Some(Value::Number(n)) => Some(n.to_string()),
+            _ => None,
+        };
+        Ok(())
+    })
+    .with_context(|| {
+        format!(
+            "The template '{}' produced an invalid preamble",
+            template_name
+        )
+    })?;
+
+    // Decide the path
+    let date = chrono::Local::now().format("%Y-%m-%d").to_string();
+    let slug = naming::slugify(&sc.title, &root.cfg.slug);
+    let name_vars: HashMap<&str, &str> = [
+        ("slug", &*slug),
+        ("date", &*date),
+        ("id", id.as_deref().unwrap_or("")),
+    ]
+    .iter()
+    .cloned()
+    .collect();
+    let name_template = class
+        .and_then(|class| class.name.as_deref())
+        .unwrap_or(&root.cfg.new.name);
+    let mut file_name = naming::expand_name_template(name_template, &name_vars);
+    if file_name.is_empty() {
+        bail!("Could not derive a file name from the title {:?}", sc.title);
+    }
+    if let Some(ext) = ext {
+        file_name.push('.');
+        file_name.push_str(&ext);
+    }
+
+    let dest_dir = root.path.join(
+        class
+            .and_then(|class| class.dir.as_deref())
+            .unwrap_or(&root.cfg.new.dir),
+    );
+    std::fs::create_dir_all(&dest_dir)
+        .with_context(|| format!("Failed to create {:?}", dest_dir))?;
+    let dest = naming::disambiguate(&dest_dir, &file_name);
+
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&dest)
+        .and_then(|mut file| file.write_all(&contents))
+        .with_context(|| format!("Failed to create {:?}", dest))?;
+
+    println!("{}", dest.display());
+
+    if sc.edit {
+        match crate::open_path(root, &dest, None, false, crate::default_editor)? {}
+    }
+
+    Ok(())
+}
+
+/// Create a document at `path` by expanding the template `name` with `vars`.
+/// Fails if the file already exists.
+pub fn create_from_template(
+    root: &DocRoot,
+    path: &Path,
+    name: &str,
+    mut vars: template::Vars,
+) -> Result<()> {
+    let (template_text, _) = read_template(root, name)?;
+    let contents = template::expand_document(&template_text, |name, arg| vars.resolve(name, arg))
+        .with_context(|| format!("Failed to expand the template '{}'", name))?;
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+    }
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .with_context(|| format!("Failed to create {:?}", path))
+}
+
+/// Read the specified template. Returns the template and the file extension
+/// for new documents.
+fn read_template(root: &DocRoot, name: &str) -> Result<(String, Option<String>)> {
+    let dir = root.template_dir_path();
+    let path = match find_template(&dir, name)? {
+        Some(path) => path,
+        None if name == "default" => {
+            log::debug!("Using the built-in default template");
+            return Ok((DEFAULT_TEMPLATE.to_owned(), Some("md".to_owned())));
+        }
+        None => bail!("Could not find template '{}' in {:?}", name, dir),
+    };
+
+    log::debug!("Using the template {:?}", path);
+    let text =
+        std::fs::read_to_string(&path).with_context(|| format!("Failed to read {:?}", path))?;
+    let ext = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().into_owned());
+    Ok((text, ext))
+}
+
+/// Find a template file by its name (with or without its extension).
+fn find_template(dir: &Path, name: &str) -> Result<Option<PathBuf>> {
+    let path = dir.join(name);
+    if path.is_file() {
+        return Ok(Some(path));
+    }
+    if !dir.is_dir() {
+        return Ok(None);
+    }
+
+    let mut candidates = Vec::new();
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Failed to read {:?}", dir))? {
+        let path = entry?.path();
+        if path.is_file() && path.file_stem() == Some(name.as_ref()) {
+            candidates.push(path);
+        }
+    }
+
+    match candidates.len() {
+        0 => Ok(None),
+        1 => Ok(candidates.pop()),
+        _ => bail!("Template name '{}' is ambiguous: {:?}", name, candidates),
+    }
+}
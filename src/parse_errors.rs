@@ -0,0 +1,100 @@
+//! Finding documents with malformed metadata (`v parse-errors`)
+use anyhow::{bail, Context, Result};
+use veisku_core::{doc::DocRead, query, root::DocRoot};
+
+use crate::{cfg, meta::confirm};
+
+/// A problem found in the metadata of a document
+struct ParseError {
+    /// The one-based line and column numbers in the document
+    location: Option<(usize, usize)>,
+    message: String,
+}
+
+impl ParseError {
+    fn of(e: &anyhow::Error) -> Self {
+        let yaml_error = e
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<serde_yaml::Error>());
+        match yaml_error.and_then(|e| Some((e, e.location()?))) {
+            Some((yaml_error, loc)) => {
+                // Remove the location relative to the preamble, which would
+                // be confusing next to the one relative to the document
+                let message = yaml_error.to_string();
+                let suffix = format!(" at line {} column {}", loc.line(), loc.column());
+                Self {
+                    // Account for the opening separator of the preamble
+                    location: Some((loc.line() + 1, loc.column())),
+                    message: message.strip_suffix(&suffix).unwrap_or(&message).to_owned(),
+                }
+            }
+            None => Self {
+                location: None,
+                message: format!("{:#}", e.root_cause()),
+            },
+        }
+    }
+}
+
+pub fn verb_parse_errors(root: &DocRoot, sc: &cfg::ParseErrors) -> Result<()> {
+    let query = sc.query.compile(root)?;
+    let mut broken_docs: Vec<DocRead> = Vec::new();
+    for doc_or_error in query::select_all(root, &query) {
+        let doc = doc_or_error.context("An error occurred while enumerating matching documents")?;
+        if let Err(e) = doc.check_meta() {
+            let error = ParseError::of(&e);
+            match error.location {
+                Some((line, column)) => {
+                    println!("{}:{}:{}: {}", doc, line, column, error.message)
+                }
+                None => println!("{}: {}", doc, error.message),
+            }
+            broken_docs.push(doc);
+        }
+    }
+
+    if broken_docs.is_empty() {
+        return Ok(());
+    }
+    eprintln!("{} document(s) have malformed metadata", broken_docs.len());
+
+    if sc.fix {
+        for doc in &broken_docs {
+            if root.cfg.encryption_for(doc.path()).is_some() {
+                log::warn!(
+                    "Skipping {:?}, which is encrypted; use `v edit`",
+                    doc.path()
+                );
+                continue;
+            }
+            fix_doc(root, doc)?;
+        }
+        return Ok(());
+    }
+
+    std::process::exit(1);
+}
+
+/// Let the user edit `doc` until its metadata parses or they give up.
+fn fix_doc(root: &DocRoot, doc: &DocRead) -> Result<()> {
+    loop {
+        let mut cmd = crate::open_command(root, doc.path(), None, false, crate::default_editor);
+        log::debug!("Spawning {:?}", cmd);
+        let status = cmd
+            .status()
+            .with_context(|| format!("Failed to execute {:?}", cmd))?;
+        if !status.success() {
+            bail!("The editor exited with {}", status);
+        }
+
+        match doc.check_meta() {
+            Ok(()) => return Ok(()),
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                if !confirm("Edit again?", true)? {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
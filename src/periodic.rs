@@ -0,0 +1,105 @@
+//! Periodic notes (`v today` and `v periodic`)
+use anyhow::{anyhow, Result};
+use chrono::{Datelike, Duration, NaiveDate};
+use std::fmt::Write as _;
+
+use crate::{cfg, new, root::DocRoot, template};
+
+/// Open (creating if necessary) the note of the period specified by `args`.
+pub fn open_note(root: &DocRoot, cadence: cfg::Cadence, args: &cfg::PeriodArgs) -> Result<()> {
+    let (note_cfg, cadence_name) = match cadence {
+        cfg::Cadence::Daily => (&root.cfg.periodic.daily, "daily"),
+        cfg::Cadence::Weekly => (&root.cfg.periodic.weekly, "weekly"),
+        cfg::Cadence::Monthly => (&root.cfg.periodic.monthly, "monthly"),
+    };
+
+    let now = chrono::Local::now();
+    let date = match args.date {
+        Some(date) => chrono::DateTime::<chrono::Local>::from(date.start).date_naive(),
+        None => now.date_naive(),
+    };
+    let start = period_start(cadence, date, args.offset.unwrap_or(0))
+        .ok_or_else(|| anyhow!("The period is out of range"))?;
+    let mut path = String::new();
+    write!(path, "{}", start.format(&note_cfg.path)).map_err(|_| {
+        anyhow!(
+            "Invalid `periodic.{}.path`: {:?}",
+            cadence_name,
+            note_cfg.path
+        )
+    })?;
+    let path = root.path.join(path);
+
+    if !path.exists() {
+        let title = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut vars = template::Vars::new(std::iter::once(("title".to_owned(), title)).collect());
+        if let Some(time) = start
+            .and_time(now.time())
+            .and_local_timezone(chrono::Local)
+            .earliest()
+        {
+            vars = vars.with_now(time);
+        }
+        let name = note_cfg.template.as_deref().unwrap_or("default");
+        new::create_from_template(root, &path, name, vars)?;
+        log::info!("Created {:?}", path);
+    }
+
+    if args.print {
+        println!("{}", path.display());
+        return Ok(());
+    }
+
+    crate::record_history(root, &[&path]);
+    match crate::open_path(root, &path, None, false, crate::default_editor)? {}
+}
+
+/// Get the first day of the `offset`-th period after the one containing
+/// `date`. Weeks start on Monday.
+fn period_start(cadence: cfg::Cadence, date: NaiveDate, offset: i32) -> Option<NaiveDate> {
+    match cadence {
+        cfg::Cadence::Daily => date.checked_add_signed(Duration::days(offset.into())),
+        cfg::Cadence::Weekly => {
+            let monday = date - Duration::days(date.weekday().num_days_from_monday().into());
+            monday.checked_add_signed(Duration::weeks(offset.into()))
+        }
+        cfg::Cadence::Monthly => {
+            let month = date.year() * 12 + date.month0() as i32 + offset;
+            NaiveDate::from_ymd_opt(month.div_euclid(12), month.rem_euclid(12) as u32 + 1, 1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_period_start() {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        let wed = date(2024, 1, 3);
+        assert_eq!(
+            period_start(cfg::Cadence::Daily, wed, -3),
+            Some(date(2023, 12, 31))
+        );
+        assert_eq!(
+            period_start(cfg::Cadence::Weekly, wed, 0),
+            Some(date(2024, 1, 1))
+        );
+        assert_eq!(
+            period_start(cfg::Cadence::Weekly, wed, -1),
+            Some(date(2023, 12, 25))
+        );
+        assert_eq!(
+            period_start(cfg::Cadence::Monthly, wed, -1),
+            Some(date(2023, 12, 1))
+        );
+        assert_eq!(
+            period_start(cfg::Cadence::Monthly, wed, 13),
+            Some(date(2025, 2, 1))
+        );
+    }
+}
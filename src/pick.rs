@@ -0,0 +1,258 @@
+//! Interactive selection from ambiguous candidates (`--pick`)
+use anyhow::{anyhow, bail, Context};
+use serde_yaml::Value;
+use std::{
+    ffi::OsString,
+    fmt::Display,
+    io::{BufRead, Write},
+    process::Stdio,
+};
+
+use crate::{
+    cfg,
+    doc::DocRead,
+    query::{self, SelectOneError},
+    root::DocRoot,
+};
+
+/// Let the user choose one of `candidates`, using the picker command
+/// configured by `picker.command` or by entering its number.
+pub fn pick(root: &DocRoot, mut candidates: Vec<DocRead>) -> Result<DocRead, SelectOneError> {
+    match candidates.len() {
+        0 => return Err(SelectOneError::Empty),
+        1 => return Ok(candidates.pop().unwrap()),
+        _ => {}
+    }
+
+    let index = if root.cfg.picker.command.is_empty() {
+        prompt(
+            "Multiple documents matched:",
+            "Select a document",
+            &candidates,
+        )
+    } else {
+        run_picker(root, &mut candidates)
+    };
+
+    index
+        .map(|i| candidates.swap_remove(i))
+        .map_err(SelectOneError::Misc)
+}
+
+/// Let the user choose one of `lines` (e.g., search hits), using the picker
+/// command configured by `picker.command` (without the preview) or by
+/// entering its number. Returns a zero-based index.
+pub fn pick_line(root: &DocRoot, lines: &[String]) -> anyhow::Result<usize> {
+    if root.cfg.picker.command.is_empty() {
+        return prompt("Multiple lines matched:", "Select a line", lines);
+    }
+
+    let mut input = Vec::new();
+    for line in lines {
+        writeln!(input, "{}", sanitize_column(line))?;
+    }
+    // The lines aren't paths, so there's nothing to preview
+    let argv: Vec<OsString> = (root.cfg.picker.command.iter())
+        .map(|arg| arg.replace("{preview}", "").into())
+        .collect();
+    let selected = run_picker_command(root, &argv, &input)?;
+    lines
+        .iter()
+        .position(|line| sanitize_column(line) == selected)
+        .ok_or_else(|| anyhow!("The picker returned an unknown candidate: {:?}", selected))
+}
+
+/// Display the numbered candidates and read a choice. Returns a zero-based
+/// index.
+fn prompt(header: &str, question: &str, candidates: &[impl Display]) -> anyhow::Result<usize> {
+    let stderr = std::io::stderr();
+    let mut out = stderr.lock();
+    writeln!(out, "{}", header)?;
+    for (i, candidate) in candidates.iter().enumerate() {
+        writeln!(out, " {:2}. {}", i + 1, candidate)?;
+    }
+
+    let stdin = std::io::stdin();
+    let mut stdin = stdin.lock();
+    loop {
+        write!(out, "{} [1-{}]: ", question, candidates.len())?;
+        out.flush()?;
+
+        let mut line = String::new();
+        if stdin.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            return Err(anyhow!("Nothing was selected"));
+        }
+
+        match line.trim().parse::<usize>() {
+            Ok(i) if (1..=candidates.len()).contains(&i) => return Ok(i - 1),
+            _ => writeln!(out, "Invalid choice: {:?}", line.trim())?,
+        }
+    }
+}
+
+/// Pipe the candidates to the external picker command and read the choice
+/// back. Returns a zero-based index.
+fn run_picker(root: &DocRoot, candidates: &mut [DocRead]) -> anyhow::Result<usize> {
+    let mut paths = Vec::with_capacity(candidates.len());
+    let mut input = Vec::new();
+    for doc in candidates.iter_mut() {
+        let path = doc.path().to_string_lossy().into_owned();
+        let aliases = query::aliases(doc).join(",");
+        let meta = doc.ensure_meta()?;
+        let title = meta["title"].as_str().unwrap_or("");
+        let tags = match &meta["tags"] {
+            Value::Sequence(tags) => tags
+                .iter()
+                .filter_map(Value::as_str)
+                .collect::<Vec<_>>()
+                .join(","),
+            _ => String::new(),
+        };
+        writeln!(
+            input,
+            "{}\t{}\t{}\t{}",
+            path,
+            sanitize_column(title),
+            sanitize_column(&tags),
+            sanitize_column(&aliases)
+        )?;
+        paths.push(path);
+    }
+
+    let argv = picker_argv(&root.cfg.picker)?;
+    let line = run_picker_command(root, &argv, &input)?;
+
+    let path = line.split('\t').next().unwrap();
+    paths
+        .iter()
+        .position(|p| p == path)
+        .ok_or_else(|| anyhow!("The picker returned an unknown candidate: {:?}", line))
+}
+
+/// Run the picker command `argv` with `input` and return the selected line.
+fn run_picker_command(root: &DocRoot, argv: &[OsString], input: &[u8]) -> anyhow::Result<String> {
+    let mut cmd = crate::new_command(argv);
+    cmd.current_dir(&root.path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped());
+    crate::set_command_env(&mut cmd, root);
+    log::debug!("Spawning {:?}", cmd);
+    let mut child = cmd
+        .spawn()
+        .with_context(|| format!("Failed to execute the picker {:?}", argv[0]))?;
+
+    // Dropping `stdin` closes the pipe, signaling the end of the candidates
+    let write_result = child.stdin.take().unwrap().write_all(input);
+    let output = child
+        .wait_with_output()
+        .context("Failed to wait for the picker")?;
+    if let Err(e) = write_result {
+        // The picker may exit without reading everything (e.g., if the user
+        // cancels it early)
+        log::debug!("Failed to write the candidates to the picker: {:?}", e);
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line = stdout.lines().next().unwrap_or("");
+    if line.trim().is_empty() {
+        return Err(anyhow!("Nothing was selected"));
+    }
+    if !output.status.success() {
+        bail!("The picker exited with {}", output.status);
+    }
+    Ok(line.to_owned())
+}
+
+/// Construct the command line of the picker, including the preview command.
+fn picker_argv(picker_cfg: &cfg::PickerCfg) -> anyhow::Result<Vec<OsString>> {
+    let mut argv: Vec<String> = picker_cfg.command.clone();
+
+    if !picker_cfg.preview.is_empty() {
+        if !argv.iter().any(|arg| arg.contains("{preview}")) {
+            bail!("`picker.preview` is set, but `picker.command` doesn't contain `{preview}`");
+        }
+
+        let preview = picker_cfg
+            .preview
+            .iter()
+            .map(|arg| {
+                if !arg.contains("{}") {
+                    return shell_quote(arg);
+                }
+                arg.split("{}")
+                    .map(|part| {
+                        if part.is_empty() {
+                            String::new()
+                        } else {
+                            shell_quote(part)
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join("{1}")
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        for arg in argv.iter_mut() {
+            *arg = arg.replace("{preview}", &preview);
+        }
+    }
+
+    Ok(argv.into_iter().map(Into::into).collect())
+}
+
+/// Quote a string for a POSIX shell.
+fn shell_quote(arg: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        arg.to_owned()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+/// Replace the characters that would break the tab-separated columns.
+fn sanitize_column(text: &str) -> String {
+    text.replace(&['\t', '\n', '\r'][..], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_picker_argv() {
+        let picker_cfg = cfg::PickerCfg {
+            command: vec![
+                "fzf".to_owned(),
+                "--preview".to_owned(),
+                "{preview}".to_owned(),
+            ],
+            preview: vec![
+                "bat".to_owned(),
+                "--style=plain".to_owned(),
+                "{}".to_owned(),
+            ],
+        };
+        assert_eq!(
+            picker_argv(&picker_cfg).unwrap(),
+            ["fzf", "--preview", "bat --style=plain {1}"]
+        );
+
+        let picker_cfg = cfg::PickerCfg {
+            command: vec!["sk".to_owned(), "--preview={preview}".to_owned()],
+            preview: vec!["glow".to_owned(), "it's".to_owned(), "--file={}".to_owned()],
+        };
+        assert_eq!(
+            picker_argv(&picker_cfg).unwrap(),
+            ["sk", r"--preview=glow 'it'\''s' --file={1}"]
+        );
+
+        // The picker may not support previews at all
+        let picker_cfg = cfg::PickerCfg {
+            command: vec!["rofi".to_owned(), "-dmenu".to_owned()],
+            preview: vec!["cat".to_owned(), "{}".to_owned()],
+        };
+        assert!(picker_argv(&picker_cfg).is_err());
+    }
+}
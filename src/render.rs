@@ -1,43 +1,702 @@
 //! Utilities for console output
+use ansi_term::{Color, Style};
+use serde_yaml::Value;
 use std::{
+    ffi::OsString,
+    fmt::Write as _,
     io::{BufWriter, Write},
+    ops::Range,
+    path::Path,
     process::{Child, Stdio},
+    sync::atomic::{AtomicBool, Ordering},
 };
-use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+use unicode_width::UnicodeWidthChar;
 
-use crate::cfg::Opts;
+use crate::{
+    cfg::{AmbiguousWidth, Cfg, ColorCfg, FieldType, Opts, StyleCfg, ThemeCfg},
+    query::Highlights,
+    schema, tag,
+};
 
-/// Truncate the given string to a specified width and pad it with whitespace
-/// characters as needed to fill the specified width.
-pub fn fit_to_width(s: &str, width: usize) -> String {
+/// Whether East Asian Ambiguous characters are wide. Set by
+/// [`set_ambiguous_width`].
+static AMBIGUOUS_WIDE: AtomicBool = AtomicBool::new(false);
+
+/// Set the display width of East Asian Ambiguous characters
+/// (`ambiguous_width` in `config.toml`).
+pub fn set_ambiguous_width(policy: AmbiguousWidth) {
+    AMBIGUOUS_WIDE.store(policy == AmbiguousWidth::Wide, Ordering::Relaxed);
+}
+
+/// Get the display width of a character.
+fn char_width(ch: char) -> usize {
+    if AMBIGUOUS_WIDE.load(Ordering::Relaxed) {
+        ch.width_cjk().unwrap_or(0)
+    } else {
+        ch.width().unwrap_or(0)
+    }
+}
+
+/// Get the length of the ANSI escape sequence at the start of `s`, which
+/// must start with ESC.
+fn escape_len(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    debug_assert_eq!(bytes[0], 0x1b);
+    match bytes.get(1) {
+        // CSI: parameter and intermediate bytes followed by a final byte
+        Some(b'[') => bytes[2..]
+            .iter()
+            .position(|b| (0x40..=0x7e).contains(b))
+            .map_or(bytes.len(), |i| i + 3),
+        // OSC: terminated by BEL or ST (ESC `\`)
+        Some(b']') => (2..bytes.len())
+            .find_map(|i| match bytes[i] {
+                0x07 => Some(i + 1),
+                0x1b if bytes.get(i + 1) == Some(&b'\\') => Some(i + 2),
+                _ => None,
+            })
+            .unwrap_or(bytes.len()),
+        Some(_) => 1 + s[1..].chars().next().unwrap().len_utf8(),
+        None => 1,
+    }
+}
+
+/// Split `s` into ANSI escape sequences (`Err`) and characters (`Ok`).
+fn ansi_chars(mut s: &str) -> impl Iterator<Item = Result<char, &str>> {
+    std::iter::from_fn(move || {
+        let ch = s.chars().next()?;
+        if ch == '\x1b' {
+            let (escape, rest) = s.split_at(escape_len(s));
+            s = rest;
+            Some(Err(escape))
+        } else {
+            s = &s[ch.len_utf8()..];
+            Some(Ok(ch))
+        }
+    })
+}
+
+/// Get the display width of a string, ignoring ANSI escape sequences.
+pub fn display_width(s: &str) -> usize {
+    ansi_chars(s).filter_map(Result::ok).map(char_width).sum()
+}
+
+/// Truncate the given string with an ellipsis if it's wider than the
+/// specified width. ANSI escape sequences don't count toward the width, and
+/// the ellipsis is displayed in the style in effect at the truncation point.
+pub fn truncate_to_width(s: &str, width: usize) -> String {
     let ellipsis = "…";
     let ellipsis_width = 1; // width of `ellipsis`
 
     assert!(width >= ellipsis_width);
 
-    let mut out_str = s.to_owned();
-    let mut out_str_width = out_str.width();
+    if display_width(s) <= width {
+        return s.to_owned();
+    }
 
-    if out_str_width > width {
-        // Truncate
-        out_str.clear();
-        out_str_width = 0;
-        for ch in s.chars() {
-            let ch_width = ch.width().unwrap_or(0);
-            if ch_width + out_str_width > width - ellipsis_width {
-                break;
+    let mut out_str = String::new();
+    let mut out_str_width = 0;
+    let mut styled = false;
+    for item in ansi_chars(s) {
+        match item {
+            Ok(ch) => {
+                let ch_width = char_width(ch);
+                if ch_width + out_str_width > width - ellipsis_width {
+                    break;
+                }
+                out_str.push(ch);
+                out_str_width += ch_width;
+            }
+            Err(escape) => {
+                out_str.push_str(escape);
+                styled = true;
             }
-            out_str.push(ch);
-            out_str_width += ch_width;
         }
-        out_str += ellipsis;
-        out_str_width += ellipsis_width;
     }
+    out_str += ellipsis;
+    if styled {
+        // The rest of the string, which would reset the style, was dropped
+        out_str += "\x1b[0m";
+    }
+    out_str
+}
 
-    out_str.extend(std::iter::repeat(' ').take(width - out_str_width));
+/// Truncate the given string to a specified width and pad it with whitespace
+/// characters as needed to fill the specified width. ANSI escape sequences
+/// are handled as in [`truncate_to_width`].
+pub fn fit_to_width(s: &str, width: usize) -> String {
+    let mut out_str = truncate_to_width(s, width);
+    let out_str_width = display_width(&out_str);
+    out_str.extend(std::iter::repeat_n(' ', width - out_str_width));
     out_str
 }
 
+/// The maximum width of a table column
+const MAX_COLUMN_WIDTH: usize = 40;
+
+/// The minimum width of a table column, to which columns are narrowed when
+/// a table doesn't fit in the terminal
+const MIN_COLUMN_WIDTH: usize = 4;
+
+/// The separator between table columns
+const TABLE_COLUMN_SEPARATOR: &str = "  ";
+
+/// Compute the column widths of a table. Each column is as wide as its
+/// widest cell up to [`MAX_COLUMN_WIDTH`]. If the table is wider than
+/// `max_width`, the widest columns are narrowed until it fits.
+fn table_column_widths(rows: &[Vec<String>], max_width: Option<usize>) -> Vec<usize> {
+    let num_columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let mut widths = vec![0; num_columns];
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(display_width(cell).min(MAX_COLUMN_WIDTH));
+        }
+    }
+
+    if let Some(max_width) = max_width {
+        let separators = num_columns.saturating_sub(1) * TABLE_COLUMN_SEPARATOR.len();
+        let mut total: usize = widths.iter().sum::<usize>() + separators;
+        while total > max_width {
+            let widest = widths.iter_mut().max().unwrap();
+            if *widest <= MIN_COLUMN_WIDTH {
+                break;
+            }
+            *widest -= 1;
+            total -= 1;
+        }
+    }
+
+    widths
+}
+
+/// Write an aligned table. The first row is the header. Cells are truncated
+/// to fit in the column widths computed by [`table_column_widths`].
+pub fn write_table(
+    out: &mut impl Write,
+    rows: &[Vec<String>],
+    max_width: Option<usize>,
+) -> std::io::Result<()> {
+    let widths = table_column_widths(rows, max_width);
+    for (i, row) in rows.iter().enumerate() {
+        let line = widths
+            .iter()
+            .enumerate()
+            .map(|(k, &width)| fit_to_width(row.get(k).map_or("", |cell| &**cell), width))
+            .collect::<Vec<_>>()
+            .join(TABLE_COLUMN_SEPARATOR);
+        let line = line.trim_end();
+        if i == 0 {
+            writeln!(
+                out,
+                "{}",
+                ansi_term::Style::new().bold().underline().paint(line)
+            )?;
+        } else {
+            writeln!(out, "{}", line)?;
+        }
+    }
+    Ok(())
+}
+
+/// Get the text displayed in a table cell for a metadata field value.
+/// Sequence elements are separated by commas, and mappings are displayed as
+/// JSON.
+pub fn cell_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Sequence(array) => array.iter().map(cell_text).collect::<Vec<_>>().join(", "),
+        Value::Mapping(_) => serde_json::to_string(value).unwrap_or_default(),
+        _ => schema::scalar_text(value).unwrap_or_default(),
+    }
+}
+
+/// Write a one-line summary of a document (base name, tags, and title),
+/// excluding a trailing newline.
+pub fn write_doc_summary(
+    out: &mut impl Write,
+    theme: &ThemeCfg,
+    path: &Path,
+    meta: &Value,
+) -> std::io::Result<()> {
+    write_doc_summary_highlighted(out, theme, path, meta, &Highlights::default())
+}
+
+/// Like [`write_doc_summary`], but highlights the parts of the name and the
+/// title matched by search criteria.
+pub fn write_doc_summary_highlighted(
+    out: &mut impl Write,
+    theme: &ThemeCfg,
+    path: &Path,
+    meta: &Value,
+    highlights: &Highlights,
+) -> std::io::Result<()> {
+    write_doc_summary_wrapped(out, theme, path, meta, highlights, None)
+}
+
+/// The minimum width of wrapped titles. Titles starting too far right to
+/// have this width aren't wrapped.
+const MIN_WRAP_WIDTH: usize = 20;
+
+/// Like [`write_doc_summary_highlighted`], but soft-wraps the title if `wrap`
+/// is given. `wrap` is the pair of the column at which the summary starts
+/// and the maximum line width. The continuation lines are indented to align
+/// with the title.
+pub fn write_doc_summary_wrapped(
+    out: &mut impl Write,
+    theme: &ThemeCfg,
+    path: &Path,
+    meta: &Value,
+    highlights: &Highlights,
+    wrap: Option<(usize, usize)>,
+) -> std::io::Result<()> {
+    let name = path.file_stem().unwrap().to_string_lossy();
+    let highlight = ansi_term_style(&theme.highlight());
+    let mut prefix = String::new();
+
+    // Base name
+    let fitted_name = fit_to_width(&name, 10);
+    // Matches in the truncated part are not displayed
+    let kept_len = name
+        .char_indices()
+        .zip(fitted_name.chars())
+        .find(|&((_, x), y)| x != y)
+        .map_or(name.len().min(fitted_name.len()), |((i, _), _)| i);
+    let ranges: Vec<Range<usize>> = highlights
+        .name_ranges(&name)
+        .into_iter()
+        .map(|range| range.start.min(kept_len)..range.end.min(kept_len))
+        .filter(|range| !range.is_empty())
+        .collect();
+    write!(
+        prefix,
+        "{} ",
+        // gray
+        paint_highlighted(&fitted_name, &ranges, Color::Fixed(245).normal(), highlight)
+    )
+    .unwrap();
+
+    // Tags
+    if let Value::Sequence(array) = &meta["tags"] {
+        let tag_default = theme.tag_default();
+        for e in array.iter() {
+            if let Value::String(st) = e {
+                let style = tag::ancestors(st)
+                    .find_map(|tag| theme.tags.get(tag))
+                    .unwrap_or(&tag_default);
+                write!(
+                    prefix,
+                    "{} ",
+                    ansi_term_style(style).paint(format!(" {} ", st))
+                )
+                .unwrap();
+            }
+        }
+    }
+    out.write_all(prefix.as_bytes())?;
+
+    // Title
+    let (title, ranges) = if let Value::String(st) = &meta["title"] {
+        (&**st, highlights.title_ranges(st))
+    } else {
+        (&*name, highlights.name_ranges(&name))
+    };
+    let title_column = wrap.map(|(column, width)| (column + display_width(&prefix), width));
+    let lines = match title_column {
+        Some((column, width)) if column + MIN_WRAP_WIDTH <= width => {
+            wrap_ranges(title, width - column)
+        }
+        _ => std::iter::once(0..title.len()).collect(),
+    };
+    for (i, line) in lines.into_iter().enumerate() {
+        if i > 0 {
+            write!(out, "\n{}", " ".repeat(title_column.unwrap().0))?;
+        }
+        // Clip the highlighted ranges to the line
+        let line_ranges: Vec<Range<usize>> = ranges
+            .iter()
+            .map(|range| {
+                range.start.clamp(line.start, line.end) - line.start
+                    ..range.end.clamp(line.start, line.end) - line.start
+            })
+            .filter(|range| !range.is_empty())
+            .collect();
+        write!(
+            out,
+            "{}",
+            paint_highlighted(&title[line], &line_ranges, Style::new(), highlight)
+        )?;
+    }
+    Ok(())
+}
+
+/// Split `text` into lines at most `width` columns wide, breaking at
+/// whitespace where possible. Returns the byte ranges of the lines, which
+/// exclude the whitespace at the breaks.
+fn wrap_ranges(text: &str, width: usize) -> Vec<Range<usize>> {
+    let mut lines = Vec::new();
+    let mut line: Option<(Range<usize>, usize)> = None;
+    let words = text
+        .split_whitespace()
+        .map(|word| (word.as_ptr() as usize - text.as_ptr() as usize, word));
+    for (start, word) in words {
+        let mut word_range = start..start + word.len();
+        if let Some((range, line_width)) = &mut line {
+            let added_width = display_width(&text[range.end..word_range.end]);
+            if *line_width + added_width <= width {
+                range.end = word_range.end;
+                *line_width += added_width;
+                continue;
+            }
+            lines.push(range.clone());
+        }
+
+        // Break the words wider than `width`
+        loop {
+            let mut word_width = 0;
+            let end = text[word_range.clone()]
+                .char_indices()
+                .find_map(|(i, ch)| {
+                    word_width += char_width(ch);
+                    (word_width > width && i > 0).then_some(word_range.start + i)
+                })
+                .unwrap_or(word_range.end);
+            if end == word_range.end {
+                break;
+            }
+            lines.push(word_range.start..end);
+            word_range.start = end;
+        }
+        line = Some((word_range.clone(), display_width(&text[word_range])));
+    }
+    lines.extend(line.map(|(range, _)| range));
+    if lines.is_empty() {
+        lines.push(0..text.len());
+    }
+    lines
+}
+
+/// Paint `text` in `style`, except for the byte ranges `ranges` (which must
+/// be sorted and disjoint) painted in `highlight`.
+fn paint_highlighted(
+    text: &str,
+    ranges: &[Range<usize>],
+    style: Style,
+    highlight: Style,
+) -> String {
+    let mut out = String::new();
+    let mut last = 0;
+    let mut push = |segment: &str, style: Style| {
+        if !segment.is_empty() {
+            write!(out, "{}", style.paint(segment)).unwrap();
+        }
+    };
+    for range in ranges {
+        push(&text[last..range.start], style);
+        push(&text[range.clone()], highlight);
+        last = range.end;
+    }
+    push(&text[last..], style);
+    out
+}
+
+/// Formats dates in listings according to `date_format` and
+/// `relative_dates` in `config.toml`
+pub struct DateFormatter<'a> {
+    format: Option<&'a str>,
+    relative: bool,
+    today: chrono::NaiveDate,
+}
+
+impl<'a> DateFormatter<'a> {
+    pub fn new(cfg: &'a Cfg) -> Self {
+        Self {
+            format: cfg.date_format.as_deref(),
+            relative: cfg.relative_dates,
+            today: chrono::Local::now().date_naive(),
+        }
+    }
+
+    /// Reformat a date written in a document (`YYYY-MM-DD`, optionally
+    /// followed by a time). Returns `None` if `s` isn't a date or no date
+    /// format is configured.
+    pub fn format_text(&self, s: &str) -> Option<String> {
+        if !schema::is_date(s) {
+            return None;
+        }
+        let date = chrono::NaiveDate::parse_from_str(&s[..10], "%Y-%m-%d").ok()?;
+        let time = s
+            .get(11..)
+            .and_then(|time| {
+                ["%H:%M:%S%.f", "%H:%M"]
+                    .iter()
+                    .find_map(|fmt| chrono::NaiveTime::parse_and_remainder(time, fmt).ok())
+            })
+            .map_or(chrono::NaiveTime::MIN, |(time, _)| time);
+        self.format(date.and_time(time))
+    }
+
+    /// Format a date and time, falling back to `default_format` if no date
+    /// format is configured.
+    pub fn format_datetime(&self, datetime: chrono::NaiveDateTime, default_format: &str) -> String {
+        self.format(datetime)
+            .unwrap_or_else(|| datetime.format(default_format).to_string())
+    }
+
+    fn format(&self, datetime: chrono::NaiveDateTime) -> Option<String> {
+        if self.relative {
+            return Some(relative_date(datetime.date(), self.today));
+        }
+        let format = self.format?;
+        let mut out = String::new();
+        match write!(out, "{}", datetime.format(format)) {
+            Ok(()) => Some(out),
+            Err(_) => {
+                log::debug!("Invalid date format: {:?}", format);
+                None
+            }
+        }
+    }
+}
+
+/// Describe `date` relative to `today` (e.g., `yesterday`, `in 3 weeks`).
+fn relative_date(date: chrono::NaiveDate, today: chrono::NaiveDate) -> String {
+    let days = (date - today).num_days();
+    let amount = match days.abs() {
+        0 => return "today".to_owned(),
+        1 if days > 0 => return "tomorrow".to_owned(),
+        1 => return "yesterday".to_owned(),
+        n @ 2..=13 => format!("{} days", n),
+        n @ 14..=59 => format!("{} weeks", n / 7),
+        n @ 60..=364 => format!("{} months", n / 30),
+        n if n < 730 => "1 year".to_owned(),
+        n => format!("{} years", n / 365),
+    };
+    if days > 0 {
+        format!("in {}", amount)
+    } else {
+        format!("{} ago", amount)
+    }
+}
+
+/// Get the text displayed for the value of the metadata field `key`. Dates
+/// are formatted by `dates` unless the schema declares another type for the
+/// field.
+pub fn field_text(cfg: &Cfg, dates: &DateFormatter<'_>, key: &str, value: &Value) -> String {
+    match value {
+        Value::Sequence(array) => array
+            .iter()
+            .map(|e| field_text(cfg, dates, key, e))
+            .collect::<Vec<_>>()
+            .join(", "),
+        Value::String(st) => {
+            let ty = cfg.schema.get(key).and_then(|field_cfg| field_cfg.ty);
+            let formatted = match ty {
+                None | Some(FieldType::Date) => dates.format_text(st),
+                _ => None,
+            };
+            formatted.unwrap_or_else(|| st.clone())
+        }
+        _ => cell_text(value),
+    }
+}
+
+/// Write a multi-line description of a document: the path, the metadata
+/// fields (`title` first), the size, and the modification time.
+pub fn write_doc_details(
+    out: &mut impl Write,
+    cfg: &Cfg,
+    path: &Path,
+    meta: &Value,
+    fs_meta: &std::fs::Metadata,
+) -> std::io::Result<()> {
+    writeln!(
+        out,
+        "{}",
+        Color::Yellow.bold().paint(path.display().to_string())
+    )?;
+
+    let dates = DateFormatter::new(cfg);
+    let mut fields: Vec<(String, String)> = Vec::new();
+    if let Value::Mapping(mapping) = meta {
+        for (key, value) in mapping.iter() {
+            let key = cell_text(key);
+            let value = field_text(cfg, &dates, &key, value);
+            if key == "title" {
+                fields.insert(0, (key, value));
+            } else {
+                fields.push((key, value));
+            }
+        }
+    }
+    let num_meta_fields = fields.len();
+    fields.push(("size".to_owned(), format!("{} bytes", fs_meta.len())));
+    if let Ok(modified) = fs_meta.modified() {
+        let modified = chrono::DateTime::<chrono::Local>::from(modified).naive_local();
+        fields.push((
+            "modified".to_owned(),
+            dates.format_datetime(modified, "%Y-%m-%d %H:%M:%S"),
+        ));
+    }
+
+    let key_width = (fields.iter().map(|(key, _)| display_width(key)).max()).unwrap_or(0);
+    for (i, (key, value)) in fields.iter().enumerate() {
+        let key_color = if i < num_meta_fields {
+            Color::Cyan
+        } else {
+            // gray
+            Color::Fixed(245)
+        };
+        writeln!(
+            out,
+            "    {} {}",
+            key_color.paint(fit_to_width(key, key_width)),
+            value
+        )?;
+    }
+    Ok(())
+}
+
+/// Write a unified diff between the old and new contents of the file at
+/// `path` (usually relative to the document root), coloring the changed lines
+/// according to `theme`. Writes nothing if the contents are identical.
+pub fn write_diff(
+    out: &mut impl Write,
+    theme: &ThemeCfg,
+    path: &Path,
+    old: &[u8],
+    new: &[u8],
+) -> std::io::Result<()> {
+    let (old, new) = (String::from_utf8_lossy(old), String::from_utf8_lossy(new));
+    let diff = similar::TextDiff::from_lines(&*old, &*new);
+    let mut unified = diff.unified_diff();
+    let mut hunks = unified.context_radius(3).iter_hunks().peekable();
+    if hunks.peek().is_none() {
+        return Ok(());
+    }
+
+    let bold = Style::new().bold();
+    writeln!(out, "{}", bold.paint(format!("--- a/{}", path.display())))?;
+    writeln!(out, "{}", bold.paint(format!("+++ b/{}", path.display())))?;
+    let added = ansi_term_style(&theme.diff_added());
+    let removed = ansi_term_style(&theme.diff_removed());
+    for hunk in hunks {
+        writeln!(
+            out,
+            "{}",
+            Style::new().dimmed().paint(hunk.header().to_string())
+        )?;
+        for change in hunk.iter_changes() {
+            let (sign, style) = match change.tag() {
+                similar::ChangeTag::Equal => (' ', Style::new()),
+                similar::ChangeTag::Insert => ('+', added),
+                similar::ChangeTag::Delete => ('-', removed),
+            };
+            let line = change.value().trim_end_matches('\n');
+            writeln!(out, "{}", style.paint(format!("{}{}", sign, line)))?;
+            if change.missing_newline() {
+                writeln!(out, "\\ No newline at end of file")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Convert a configured text style to `ansi_term::Style`.
+fn ansi_term_style(style: &StyleCfg) -> ansi_term::Style {
+    ansi_term::Style {
+        background: style.bg.map(ansi_term_color),
+        foreground: style.fg.map(ansi_term_color),
+        is_bold: style.bold,
+        is_italic: style.italic,
+        ..Default::default()
+    }
+}
+
+fn ansi_term_color(color: ColorCfg) -> Color {
+    match color {
+        ColorCfg::Black => Color::Black,
+        ColorCfg::Red => Color::Red,
+        ColorCfg::Green => Color::Green,
+        ColorCfg::Yellow => Color::Yellow,
+        ColorCfg::Blue => Color::Blue,
+        ColorCfg::Purple => Color::Purple,
+        ColorCfg::Cyan => Color::Cyan,
+        ColorCfg::White => Color::White,
+        ColorCfg::Rgb(r, g, b) => Color::RGB(r, g, b),
+    }
+}
+
+/// Get the pager command from `$VEISKU_PAGER` or `$PAGER`. The value is split
+/// by whitespace (e.g., `less -R`), and an empty value disables the use of a
+/// pager.
+fn env_pager() -> Option<Vec<OsString>> {
+    ["VEISKU_PAGER", "PAGER"].iter().find_map(|name| {
+        let value = std::env::var(name).ok()?;
+        log::debug!("The pager is specified by `${}`", name);
+        Some(value.split_whitespace().map(OsString::from).collect())
+    })
+}
+
+/// Pads each line to `column` and draws a vertical bar after it. The lines
+/// are preceded by a ruler. If the terminal displays some characters in a
+/// different width than computed, the bars on the lines containing them are
+/// misaligned.
+pub struct AlignGuides<W: Write> {
+    inner: W,
+    column: usize,
+    line: Vec<u8>,
+}
+
+impl<W: Write> AlignGuides<W> {
+    pub fn new(mut inner: W, column: usize) -> std::io::Result<Self> {
+        let ruler: String = (1..=column)
+            .map(|i| match i % 10 {
+                0 => char::from_digit((i / 10 % 10) as u32, 10).unwrap(),
+                5 => '+',
+                _ => '-',
+            })
+            .collect();
+        writeln!(inner, "{}│", ruler)?;
+        Ok(Self {
+            inner,
+            column,
+            line: Vec::new(),
+        })
+    }
+}
+
+impl<W: Write> Write for AlignGuides<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for &b in buf {
+            if b != b'\n' {
+                self.line.push(b);
+                continue;
+            }
+            let width = display_width(&String::from_utf8_lossy(&self.line));
+            self.inner.write_all(&self.line)?;
+            if width <= self.column {
+                let padding = " ".repeat(self.column - width);
+                write!(self.inner, "{}│", padding)?;
+            }
+            self.inner.write_all(b"\n")?;
+            self.line.clear();
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for AlignGuides<W> {
+    fn drop(&mut self) {
+        // An incomplete line
+        let _ = self.inner.write_all(&self.line);
+        let _ = self.inner.flush();
+    }
+}
+
 pub struct Pager {
     /// The `Child` object representing the process of a pager. `None` if the
     /// output is directly written to the standard output.
@@ -49,6 +708,9 @@ impl Pager {
     pub fn new(opts: &Opts) -> Self {
         let pager = opts.pager.clone().unwrap_or_else(|| {
             if console::Term::stdout().features().is_attended() {
+                if let Some(pager) = env_pager() {
+                    return pager;
+                }
                 log::debug!(
                     "The pager is not specified; using the default pager because \
                         stdout connects to an attended terminal"
@@ -101,6 +763,16 @@ impl Pager {
         }
     }
 
+    /// Draw alignment guides (see [`AlignGuides`]) at `column`.
+    pub fn with_align_guides(mut self, column: usize) -> std::io::Result<Self> {
+        self.writer.flush()?;
+        let inner = std::mem::replace(&mut self.writer, BufWriter::new(Box::new(std::io::sink())))
+            .into_inner()
+            .map_err(|e| e.into_error())?;
+        self.writer = BufWriter::new(Box::new(AlignGuides::new(inner, column)?));
+        Ok(self)
+    }
+
     /// Mark the end of output and wait for the pager to exit.
     pub fn finish(mut self) -> std::io::Result<()> {
         // Close the writer
@@ -139,19 +811,136 @@ impl Drop for AutokillChild {
     }
 }
 
-#[cfg(tests)]
+/// Format a location as `PATH:LINE:COLUMN:TEXT` like `rg --vimgrep`, which
+/// is understood by Vim's quickfix list and many other editors.
+pub fn vimgrep_line(path: &Path, line: usize, column: usize, text: &str) -> String {
+    format!(
+        "{}:{}:{}:{}",
+        path.display(),
+        line,
+        column,
+        text.replace(['\r', '\n'], " ")
+    )
+}
+
+/// Get the raw representation of a path that isn't valid Unicode for
+/// machine-readable output, which is emitted alongside the lossy string so
+/// that consumers can reopen the exact file: bytes on Unix and UTF-16 code
+/// units on Windows. Returns `None` for a valid Unicode path.
+pub fn raw_path(path: &Path) -> Option<serde_json::Value> {
+    if path.to_str().is_some() {
+        return None;
+    }
+    match () {
+        #[cfg(unix)]
+        () => {
+            use std::os::unix::ffi::OsStrExt;
+            Some(path.as_os_str().as_bytes().into())
+        }
+        #[cfg(windows)]
+        () => {
+            use std::os::windows::ffi::OsStrExt;
+            Some(path.as_os_str().encode_wide().collect::<Vec<u16>>().into())
+        }
+        #[cfg(not(any(unix, windows)))]
+        () => None,
+    }
+}
+
+#[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
     fn test_fit_to_width() {
         for &pat in &["", "a", "aaaaaaaaaaa", "Здравствуите!"] {
             let out = fit_to_width(pat, 5);
-            assert!(out.width() <= 5);
+            assert!(display_width(&out) <= 5);
             if let Some(rest) = out.strip_prefix(pat) {
                 assert!(rest.chars().all(|x| x == ' '));
             } else {
-                assert!(out.ends_width("..."));
+                assert!(out.ends_with("…"));
             }
         }
     }
+
+    #[test]
+    fn test_write_diff() {
+        let diff = |old: &str, new: &str| {
+            let mut out = Vec::new();
+            write_diff(
+                &mut out,
+                &ThemeCfg::default(),
+                Path::new("a.md"),
+                old.as_bytes(),
+                new.as_bytes(),
+            )
+            .unwrap();
+            let out = String::from_utf8(out).unwrap();
+            ansi_chars(&out).filter_map(Result::ok).collect::<String>()
+        };
+        assert_eq!(diff("x\n", "x\n"), "");
+        assert_eq!(
+            diff("---\ntags: [a]\n---\nbody", "---\ntags: [b]\n---\nbody"),
+            "--- a/a.md\n+++ b/a.md\n@@ -1,4 +1,4 @@\n ---\n-tags: [a]\n+tags: [b]\n ---\n body\n\\ No newline at end of file\n"
+        );
+    }
+
+    #[test]
+    fn test_fit_to_width_ansi() {
+        let styled = "\x1b[1;33mhighlighted\x1b[0m text";
+        assert_eq!(display_width(styled), 16);
+        assert_eq!(fit_to_width(styled, 18), format!("{}  ", styled));
+        assert_eq!(fit_to_width(styled, 5), "\x1b[1;33mhigh…\x1b[0m");
+        assert_eq!(
+            fit_to_width(styled, 14),
+            "\x1b[1;33mhighlighted\x1b[0m t…\x1b[0m"
+        );
+        assert_eq!(
+            display_width("\x1b]8;;https://example.com\x07link\x1b]8;;\x1b\\"),
+            4
+        );
+    }
+
+    #[test]
+    fn test_wrap_ranges() {
+        fn wrap(text: &str, width: usize) -> Vec<&str> {
+            let lines = wrap_ranges(text, width);
+            lines.into_iter().map(|range| &text[range]).collect()
+        }
+        assert_eq!(
+            wrap("The quick brown fox jumps", 10),
+            ["The quick", "brown fox", "jumps"]
+        );
+        assert_eq!(wrap("  a  b ", 10), ["a  b"]);
+        assert_eq!(wrap("abcdefghijkl mn", 5), ["abcde", "fghij", "kl mn"]);
+        assert_eq!(wrap("日本語の文章", 5), ["日本", "語の", "文章"]);
+        assert_eq!(wrap("", 5), [""]);
+    }
+
+    #[test]
+    fn test_table_column_widths() {
+        let rows = vec![
+            vec!["name".to_owned(), "title".to_owned()],
+            vec!["a".to_owned(), "x".repeat(100)],
+            vec!["abcdef".to_owned(), "y".to_owned()],
+        ];
+        assert_eq!(table_column_widths(&rows, None), [6, MAX_COLUMN_WIDTH]);
+        assert_eq!(table_column_widths(&rows, Some(20)), [6, 12]);
+        assert_eq!(table_column_widths(&rows, Some(0)), [4, 4]);
+    }
+
+    #[test]
+    fn test_relative_date() {
+        let today = chrono::NaiveDate::from_ymd_opt(2021, 3, 1).unwrap();
+        let days = |n| relative_date(today + chrono::Duration::days(n), today);
+        assert_eq!(days(0), "today");
+        assert_eq!(days(-1), "yesterday");
+        assert_eq!(days(1), "tomorrow");
+        assert_eq!(days(-5), "5 days ago");
+        assert_eq!(days(20), "in 2 weeks");
+        assert_eq!(days(-100), "3 months ago");
+        assert_eq!(days(400), "in 1 year");
+        assert_eq!(days(-800), "2 years ago");
+    }
 }
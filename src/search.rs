@@ -0,0 +1,71 @@
+//! Saved search management (`v search`)
+use anyhow::{bail, Result};
+use clap::Clap;
+use veisku_core::search::SavedSearches;
+
+use crate::{cfg, cfg::Criterion, root::DocRoot};
+
+pub fn verb_search(root: &DocRoot, opts: &cfg::Opts, sc: &cfg::Search) -> Result<()> {
+    match &sc.subcmd {
+        cfg::SearchSubcommand::Save(sc) => verb_search_save(root, sc),
+        cfg::SearchSubcommand::Run(sc) => verb_search_run(root, opts, sc),
+        cfg::SearchSubcommand::Ls(_) => verb_search_ls(root),
+        cfg::SearchSubcommand::Rm(sc) => verb_search_rm(root, sc),
+    }
+}
+
+fn verb_search_save(root: &DocRoot, sc: &cfg::SearchSave) -> Result<()> {
+    // Validate the criteria before saving them
+    for criterion in sc.criteria.iter() {
+        if let Err(e) = criterion.parse::<Criterion>() {
+            bail!("Invalid criterion {:?}: {}", criterion, e);
+        }
+    }
+
+    SavedSearches::update(root, |searches| {
+        if searches.searches.contains_key(&sc.name) && !sc.force {
+            bail!(
+                "Saved search '{}' already exists; use `--force` to overwrite it",
+                sc.name
+            );
+        }
+        searches
+            .searches
+            .insert(sc.name.clone(), sc.criteria.clone());
+        Ok(())
+    })?;
+
+    log::info!("Saved search '{}'", sc.name);
+    Ok(())
+}
+
+fn verb_search_run(root: &DocRoot, opts: &cfg::Opts, sc: &cfg::SearchRun) -> Result<()> {
+    if !SavedSearches::load(root)?.searches.contains_key(&sc.name) {
+        bail!("Unknown saved search: '{}'", sc.name);
+    }
+
+    // Equivalent to `v ls -f NAME CRITERIA...`. The options are parsed so
+    // that the others take their default values.
+    let mut list = cfg::List::try_parse_from(["ls", &format!("--filter={}", sc.name)])?;
+    list.query.criteria = sc.criteria.clone();
+    crate::verb_ls(root, opts, &list)
+}
+
+fn verb_search_ls(root: &DocRoot) -> Result<()> {
+    for (name, criteria) in SavedSearches::load(root)?.searches.iter() {
+        println!("{}\t{}", name, criteria.join(" "));
+    }
+    Ok(())
+}
+
+fn verb_search_rm(root: &DocRoot, sc: &cfg::SearchRemove) -> Result<()> {
+    SavedSearches::update(root, |searches| {
+        if searches.searches.remove(&sc.name).is_none() {
+            bail!("Unknown saved search: '{}'", sc.name);
+        }
+        Ok(())
+    })?;
+
+    log::info!("Removed saved search '{}'", sc.name);
+    Ok(())
+}
@@ -0,0 +1,310 @@
+//! Local HTTP JSON API (`v serve`)
+//!
+//! The server handles one connection at a time, which is sufficient for local
+//! clients such as editor plugins and launcher extensions.
+//!
+//! # Endpoints
+//!
+//!  - `GET /docs?q=CRITERIA&f=PRESET` returns the matching documents'
+//!    paths and metadata. `CRITERIA` is a whitespace-separated list of search
+//!    criteria.
+//!
+//!  - `GET /docs/NAME` returns the path, metadata, and body of the document
+//!    selected by a smart name search.
+//!
+//! Requests whose `Host` header names anything other than `localhost` or an
+//! IP address (with the port the server is bound to) are rejected. This
+//! prevents web pages from reaching the server via DNS rebinding.
+//!
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{IpAddr, TcpListener, TcpStream},
+    time::Duration,
+};
+
+use crate::{cfg, doc::DocRead, query, render, root::DocRoot};
+
+/// The timeout for reading a request and writing a response. The server
+/// handles one connection at a time, so a stalled client must not be able to
+/// block it indefinitely.
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub fn verb_serve(root: &DocRoot, sc: &cfg::Serve) -> Result<()> {
+    let listener =
+        TcpListener::bind(&sc.bind).with_context(|| format!("Failed to bind to {}", sc.bind))?;
+    let port = listener.local_addr()?.port();
+    log::info!("Listening on http://{}", listener.local_addr()?);
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(x) => x,
+            Err(e) => {
+                log::warn!("Failed to accept a connection: {:?}", e);
+                continue;
+            }
+        };
+        if let Err(e) = handle_connection(root, port, stream) {
+            log::warn!("Failed to handle a request: {:?}", e);
+        }
+    }
+
+    Ok(())
+}
+
+struct Response {
+    status: &'static str,
+    body: serde_json::Value,
+}
+
+impl Response {
+    fn ok(body: impl Serialize) -> Self {
+        match serde_json::to_value(body) {
+            Ok(body) => Self {
+                status: "200 OK",
+                body,
+            },
+            Err(e) => Self::error("500 Internal Server Error", e),
+        }
+    }
+
+    fn error(status: &'static str, msg: impl std::fmt::Display) -> Self {
+        Self {
+            status,
+            body: serde_json::json!({ "error": msg.to_string() }),
+        }
+    }
+}
+
+fn handle_connection(root: &DocRoot, port: u16, stream: TcpStream) -> Result<()> {
+    stream
+        .set_read_timeout(Some(IO_TIMEOUT))
+        .context("Failed to set the read timeout")?;
+    stream
+        .set_write_timeout(Some(IO_TIMEOUT))
+        .context("Failed to set the write timeout")?;
+
+    let mut reader = BufReader::new(&stream);
+
+    // Parse the request line (e.g., `GET /docs?q=tags:work HTTP/1.1`)
+    let mut request_line = String::new();
+    reader
+        .read_line(&mut request_line)
+        .context("Failed to read the request line")?;
+    log::debug!("Request: {:?}", request_line.trim_end());
+
+    // Read the headers. We only need `Host`.
+    let mut host = None;
+    loop {
+        let mut line = String::new();
+        if reader
+            .read_line(&mut line)
+            .context("Failed to read a header")?
+            == 0
+            || line.trim_end().is_empty()
+        {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("host") {
+                host = Some(value.trim().to_owned());
+            }
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        _ if !host
+            .as_deref()
+            .is_some_and(|host| is_allowed_host(host, port)) =>
+        {
+            Response::error("403 Forbidden", "Host not allowed")
+        }
+        (Some("GET"), Some(target)) => handle_get(root, target),
+        (Some(_), Some(_)) => Response::error("405 Method Not Allowed", "Method not allowed"),
+        _ => Response::error("400 Bad Request", "Malformed request line"),
+    };
+
+    let body = serde_json::to_string(&response.body).unwrap();
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\n\
+        Content-Type: application/json; charset=utf-8\r\n\
+        Content-Length: {}\r\n\
+        Connection: close\r\n\
+        \r\n\
+        {}",
+        response.status,
+        body.len(),
+        body
+    )
+    .context("Failed to write the response")?;
+    stream.flush().context("Failed to write the response")?;
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct JsonDoc {
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    raw_path: Option<serde_json::Value>,
+    meta: serde_yaml::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body: Option<String>,
+}
+
+impl JsonDoc {
+    fn new(mut doc: DocRead, with_body: bool) -> Result<Self> {
+        let meta = doc.ensure_meta()?.clone();
+        let body = if with_body {
+            Some(String::from_utf8_lossy(&doc.read_body()?).into_owned())
+        } else {
+            None
+        };
+        Ok(Self {
+            path: doc.path().to_string_lossy().into_owned(),
+            raw_path: render::raw_path(doc.path()),
+            meta,
+            body,
+        })
+    }
+}
+
+fn handle_get(root: &DocRoot, target: &str) -> Response {
+    let (path, query_string) = match target.find('?') {
+        Some(i) => (&target[..i], &target[i + 1..]),
+        None => (target, ""),
+    };
+    let path = percent_decode(path, false);
+
+    if path == "/docs" || path == "/docs/" {
+        let mut criteria = String::new();
+        let mut preset = "default".to_owned();
+        for pair in query_string.split('&') {
+            let (key, value) = match pair.find('=') {
+                Some(i) => (&pair[..i], percent_decode(&pair[i + 1..], true)),
+                None => (pair, String::new()),
+            };
+            match key {
+                "q" => criteria = value,
+                "f" => preset = value,
+                _ => {}
+            }
+        }
+
+        let criteria: Result<Vec<cfg::Criterion>, _> =
+            criteria.split_whitespace().map(str::parse).collect();
+        let criteria = match criteria {
+            Ok(criteria) => criteria,
+            Err(e) => return Response::error("400 Bad Request", e),
+        };
+        let query = match query::Query::new(root, &preset, &criteria) {
+            Ok(x) => x,
+            Err(e) => return Response::error("400 Bad Request", format!("{:#}", e)),
+        };
+
+        let docs: Result<Vec<JsonDoc>> = query::select_all(root, &query)
+            .map(|doc_or_error| JsonDoc::new(doc_or_error?, false))
+            .collect();
+        match docs {
+            Ok(docs) => Response::ok(docs),
+            Err(e) => Response::error("500 Internal Server Error", format!("{:#}", e)),
+        }
+    } else if let Some(name) = path.strip_prefix("/docs/") {
+        let criteria = [cfg::Criterion::NameSmart(name.to_owned())];
+        let query = match query::Query::new(root, "", &criteria) {
+            Ok(x) => x,
+            Err(e) => return Response::error("400 Bad Request", format!("{:#}", e)),
+        };
+
+        match query::select_one(root, &query) {
+            Ok(doc) => match JsonDoc::new(doc, true) {
+                Ok(doc) => Response::ok(doc),
+                Err(e) => Response::error("500 Internal Server Error", format!("{:#}", e)),
+            },
+            Err(e @ query::SelectOneError::Empty) => Response::error("404 Not Found", e),
+            Err(e @ query::SelectOneError::Ambiguous { .. }) => Response::error("409 Conflict", e),
+            Err(e) => Response::error("500 Internal Server Error", e),
+        }
+    } else {
+        Response::error("404 Not Found", "Not found")
+    }
+}
+
+/// Check the value of a `Host` header. Only `localhost` and IP addresses are
+/// allowed, so that a host name controlled by someone else can't be rebound to
+/// this server.
+fn is_allowed_host(host: &str, port: u16) -> bool {
+    let (name, host_port) = match host.rsplit_once(':') {
+        Some((name, host_port)) if !host_port.ends_with(']') => (name, host_port.parse().ok()),
+        _ => (host, Some(80)),
+    };
+    if host_port != Some(port) {
+        return false;
+    }
+
+    let name = name
+        .strip_prefix('[')
+        .and_then(|x| x.strip_suffix(']'))
+        .unwrap_or(name);
+    name.eq_ignore_ascii_case("localhost") || name.parse::<IpAddr>().is_ok()
+}
+
+/// Decode a percent-encoded URL component. `+` is decoded as a space if
+/// `plus_as_space` is set, which should be the case only in query strings.
+fn percent_decode(s: &str, plus_as_space: bool) -> String {
+    fn hex_digit(b: u8) -> Option<u8> {
+        (b as char).to_digit(16).map(|x| x as u8)
+    }
+
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' if plus_as_space => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                if let (Some(hi), Some(lo)) = (hex_digit(bytes[i + 1]), hex_digit(bytes[i + 2])) {
+                    out.push((hi << 4) | lo);
+                    i += 2;
+                } else {
+                    out.push(b'%');
+                }
+            }
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(
+            percent_decode("tags%3Awork+status:open", true),
+            "tags:work status:open"
+        );
+        assert_eq!(percent_decode("/docs/c++", false), "/docs/c++");
+        assert_eq!(percent_decode("%E3%81%82", false), "あ");
+        assert_eq!(percent_decode("100%", false), "100%");
+        assert_eq!(percent_decode("%zz", false), "%zz");
+    }
+
+    #[test]
+    fn test_is_allowed_host() {
+        assert!(is_allowed_host("127.0.0.1:8375", 8375));
+        assert!(is_allowed_host("localhost:8375", 8375));
+        assert!(is_allowed_host("[::1]:8375", 8375));
+        assert!(is_allowed_host("127.0.0.1", 80));
+        assert!(!is_allowed_host("[::1]", 8375));
+        assert!(!is_allowed_host("127.0.0.1:8376", 8375));
+        assert!(!is_allowed_host("evil.example.com:8375", 8375));
+    }
+}
@@ -0,0 +1,79 @@
+//! Shell integration (`v shell-init`)
+//!
+//! A child process can't change the current directory of the shell, so `vcd`
+//! is implemented as a shell function calling `v root` and `v which`.
+use anyhow::Result;
+
+use crate::cfg;
+
+const BASH: &str = r#"vcd() {
+    local dir
+    if [ "$#" -eq 0 ]; then
+        dir=$(command v root) || return
+    else
+        dir=$(command v which --pick "$@") || return
+        dir=$(dirname -- "$dir")
+    fi
+    cd -- "$dir"
+}
+
+__v_insert_which() {
+    local text='$(v which )'
+    READLINE_LINE="${READLINE_LINE:0:READLINE_POINT}$text${READLINE_LINE:READLINE_POINT}"
+    READLINE_POINT=$((READLINE_POINT + ${#text} - 1))
+}
+
+if [[ $- == *i* ]]; then
+    bind -x '"\C-x\C-v": __v_insert_which'
+fi
+"#;
+
+const ZSH: &str = r#"vcd() {
+    local dir
+    if [ "$#" -eq 0 ]; then
+        dir=$(command v root) || return
+    else
+        dir=$(command v which --pick "$@") || return
+        dir=$(dirname -- "$dir")
+    fi
+    cd -- "$dir"
+}
+
+__v_insert_which() {
+    LBUFFER+='$(v which '
+    RBUFFER=")$RBUFFER"
+}
+
+zle -N __v_insert_which
+bindkey '^X^V' __v_insert_which
+"#;
+
+const FISH: &str = r#"function vcd
+    if test (count $argv) -eq 0
+        set dir (command v root); or return
+    else
+        set dir (command v which --pick $argv); or return
+        set dir (dirname -- $dir)
+    end
+    cd $dir
+end
+
+function __v_insert_which
+    commandline -i '(v which )'
+    commandline -C (math (commandline -C) - 1)
+end
+
+bind \cx\cv __v_insert_which
+"#;
+
+pub fn verb_shell_init(sc: &cfg::ShellInit) -> Result<()> {
+    print!(
+        "{}",
+        match sc.shell {
+            cfg::Shell::Bash => BASH,
+            cfg::Shell::Zsh => ZSH,
+            cfg::Shell::Fish => FISH,
+        }
+    );
+    Ok(())
+}
@@ -0,0 +1,371 @@
+//! Static HTML site export (`v export --site`)
+use anyhow::{bail, Context, Result};
+use serde_yaml::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+    path::{Path, PathBuf},
+};
+use veisku_core::{
+    doctype::DocType,
+    link::{self, LinkKind},
+};
+
+use crate::{cfg, query, root::DocRoot, schema};
+
+/// A document included in the site
+struct Page {
+    /// The path of the document
+    doc_path: PathBuf,
+    /// The path of the generated page (or the copied file for documents
+    /// that aren't rendered)
+    out_path: PathBuf,
+    title: String,
+    tags: Vec<String>,
+    meta: Value,
+    doc_type: DocType,
+}
+
+pub fn export_site(root: &DocRoot, query: &cfg::Query, out_dir: &Path) -> Result<()> {
+    let query = query.compile(root)?;
+    let docs = query::select_all_sorted(root, &query)
+        .context("An error occurred while enumerating matching documents")?;
+
+    let mut pages = Vec::new();
+    for mut doc in docs {
+        if root.cfg.encryption_for(doc.path()).is_some() {
+            log::warn!("Skipping {:?}, which is encrypted", doc.path());
+            continue;
+        }
+        let doc_path = doc.path().to_owned();
+        let rel_path = doc_path.strip_prefix(&root.path).unwrap_or(&doc_path);
+        let doc_type = DocType::detect(&doc_path);
+        let out_path = match doc_type {
+            DocType::Pdf | DocType::Image => out_dir.join(rel_path),
+            _ => out_dir.join(rel_path).with_extension("html"),
+        };
+        let meta = doc
+            .ensure_meta()
+            .with_context(|| format!("Failed to read the metadata of {:?}", doc_path))?
+            .clone();
+        let title = meta["title"]
+            .as_str()
+            .map(str::to_owned)
+            .unwrap_or_else(|| {
+                doc_path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            });
+        let tags = match &meta["tags"] {
+            Value::Sequence(tags) => tags.iter().filter_map(schema::scalar_text).collect(),
+            value => schema::scalar_text(value).into_iter().collect(),
+        };
+        pages.push(Page {
+            doc_path,
+            out_path,
+            title,
+            tags,
+            meta,
+            doc_type,
+        });
+    }
+
+    // Refuse to let documents overwrite each other or the index page
+    let index_path = out_dir.join("index.html");
+    let mut exported_to: HashMap<&Path, &Path> = HashMap::new();
+    for page in &pages {
+        if page.out_path == index_path {
+            bail!(
+                "{:?} would be exported to {:?}, which is reserved for the index page",
+                page.doc_path,
+                page.out_path
+            );
+        }
+        if let Some(other) = exported_to.insert(&page.out_path, &page.doc_path) {
+            bail!(
+                "{:?} and {:?} would both be exported to {:?}",
+                other,
+                page.doc_path,
+                page.out_path
+            );
+        }
+    }
+
+    let page_paths: HashMap<&Path, &Path> = pages
+        .iter()
+        .map(|page| (&*page.doc_path, &*page.out_path))
+        .collect();
+    let stems: HashMap<String, &Path> = pages
+        .iter()
+        .filter_map(|page| {
+            let stem = page.doc_path.file_stem()?.to_string_lossy().into_owned();
+            Some((stem, &*page.doc_path))
+        })
+        .collect();
+
+    for page in &pages {
+        if let Some(dir) = page.out_path.parent() {
+            std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+        }
+        let doc = veisku_core::doc::DocRead::new(page.doc_path.clone());
+        let content = match page.doc_type {
+            DocType::Pdf | DocType::Image => {
+                std::fs::copy(&page.doc_path, &page.out_path).with_context(|| {
+                    format!("Failed to copy {:?} to {:?}", page.doc_path, page.out_path)
+                })?;
+                continue;
+            }
+            DocType::Markdown => {
+                let body = doc
+                    .read_body()
+                    .with_context(|| format!("Failed to read the body of {:?}", page.doc_path))?;
+                let body = rewrite_links(
+                    &String::from_utf8_lossy(&body),
+                    &root.path,
+                    &page.doc_path,
+                    page.out_path.parent().unwrap(),
+                    &page_paths,
+                    &stems,
+                );
+                render_markdown(&body)
+            }
+            DocType::Org | DocType::Plain => {
+                let contents = doc
+                    .read_contents()
+                    .with_context(|| format!("Failed to read {:?}", page.doc_path))?;
+                format!(
+                    "<pre>{}</pre>\n",
+                    escape(&String::from_utf8_lossy(&contents))
+                )
+            }
+        };
+
+        let index =
+            link::relative_target(page.out_path.parent().unwrap(), &out_dir.join("index.html"));
+        let mut html = String::new();
+        write!(
+            html,
+            "{}<p><a href=\"{}\">Index</a></p>\n<h1>{}</h1>\n{}{}",
+            header(&page.title),
+            escape(&index),
+            escape(&page.title),
+            render_meta(&page.meta),
+            content
+        )
+        .unwrap();
+        html.push_str(FOOTER);
+        std::fs::write(&page.out_path, html)
+            .with_context(|| format!("Failed to write {:?}", page.out_path))?;
+    }
+
+    // The index page, grouped by tag
+    let mut groups: BTreeMap<&str, Vec<&Page>> = BTreeMap::new();
+    let mut untagged = Vec::new();
+    for page in &pages {
+        if page.tags.is_empty() {
+            untagged.push(page);
+        }
+        for tag in &page.tags {
+            groups.entry(tag).or_default().push(page);
+        }
+    }
+    let mut html = header("Index");
+    html.push_str("<h1>Index</h1>\n");
+    let groups = groups
+        .iter()
+        .map(|(tag, pages)| (*tag, pages))
+        .chain(Some(("Untagged", &untagged)).filter(|(_, pages)| !pages.is_empty()));
+    for (tag, pages) in groups {
+        writeln!(html, "<h2>{}</h2>\n<ul>", escape(tag)).unwrap();
+        for page in pages.iter() {
+            let href = link::relative_target(out_dir, &page.out_path);
+            writeln!(
+                html,
+                "<li><a href=\"{}\">{}</a></li>",
+                escape(&href),
+                escape(&page.title)
+            )
+            .unwrap();
+        }
+        html.push_str("</ul>\n");
+    }
+    html.push_str(FOOTER);
+    std::fs::create_dir_all(out_dir).with_context(|| format!("Failed to create {:?}", out_dir))?;
+    std::fs::write(&index_path, html)
+        .with_context(|| format!("Failed to write {:?}", index_path))?;
+
+    log::info!("Exported {} document(s) to {:?}", pages.len(), out_dir);
+    Ok(())
+}
+
+fn header(title: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+        <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+        <title>{}</title>\n</head>\n<body>\n",
+        escape(title)
+    )
+}
+
+const FOOTER: &str = "</body>\n</html>\n";
+
+fn render_markdown(text: &str) -> String {
+    use pulldown_cmark::{html, Options, Parser};
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS;
+    let mut out = String::new();
+    html::push_html(&mut out, Parser::new_ext(text, options));
+    out
+}
+
+/// Render the metadata (except the title) as a table.
+fn render_meta(meta: &Value) -> String {
+    let mapping = match meta {
+        Value::Mapping(mapping) => mapping,
+        _ => return String::new(),
+    };
+    let mut out = String::from("<table>\n");
+    for (key, value) in mapping {
+        let key = match schema::scalar_text(key) {
+            Some(key) if key != "title" => key,
+            _ => continue,
+        };
+        let value = match value {
+            Value::Sequence(items) => items
+                .iter()
+                .filter_map(schema::scalar_text)
+                .collect::<Vec<_>>()
+                .join(", "),
+            value => schema::scalar_text(value).unwrap_or_else(|| {
+                serde_yaml::to_string(value)
+                    .unwrap_or_default()
+                    .trim_start_matches("---\n")
+                    .trim()
+                    .to_owned()
+            }),
+        };
+        writeln!(
+            out,
+            "<tr><th>{}</th><td>{}</td></tr>",
+            escape(&key),
+            escape(&value)
+        )
+        .unwrap();
+    }
+    out.push_str("</table>\n");
+    out
+}
+
+/// Rewrite the links in a Markdown body so that the links to exported
+/// documents point to their pages. Wikilinks are converted to Markdown links
+/// (or to their labels if their targets aren't exported).
+///
+/// `pages` maps document paths to page paths, and `stems` maps base names to
+/// document paths. `page_dir` is the directory containing the page.
+fn rewrite_links(
+    text: &str,
+    root_path: &Path,
+    doc_path: &Path,
+    page_dir: &Path,
+    pages: &HashMap<&Path, &Path>,
+    stems: &HashMap<String, &Path>,
+) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for link in link::find_links(text) {
+        let target = &text[link.range.clone()];
+        match link.kind {
+            LinkKind::Wiki => {
+                // `[[target#fragment|label]]`
+                let start = link.range.start - 2;
+                let end = link.range.start + text[link.range.start..].find("]]").unwrap();
+                let inner = &text[link.range.start..end];
+                let label = inner.split_once('|').map_or(target, |(_, label)| label);
+                let fragment = inner
+                    .split('|')
+                    .next()
+                    .unwrap()
+                    .find('#')
+                    .map_or("", |i| &inner[i..inner.find('|').unwrap_or(inner.len())]);
+                out.push_str(&text[last..start]);
+                match stems.get(target).and_then(|path| pages.get(path)) {
+                    Some(page) => write!(
+                        out,
+                        "[{}](<{}{}>)",
+                        label,
+                        link::relative_target(page_dir, page),
+                        fragment
+                    )
+                    .unwrap(),
+                    None => out.push_str(label),
+                }
+                last = end + 2;
+            }
+            LinkKind::Markdown => {
+                let page = link::resolve_markdown_target(root_path, doc_path, target)
+                    .and_then(|path| pages.get(&*path).copied());
+                if let Some(page) = page {
+                    out.push_str(&text[last..link.range.start]);
+                    out.push_str(&link::relative_target(page_dir, page));
+                    last = link.range.end;
+                }
+            }
+        }
+    }
+    out.push_str(&text[last..]);
+    out
+}
+
+/// Escape HTML special characters.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewrite_links() {
+        let pages: HashMap<&Path, &Path> = [
+            (Path::new("/r/notes/a.md"), Path::new("/s/notes/a.html")),
+            (Path::new("/r/b.md"), Path::new("/s/b.html")),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        let stems: HashMap<String, &Path> = [
+            ("a".to_owned(), Path::new("/r/notes/a.md")),
+            ("b".to_owned(), Path::new("/r/b.md")),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        let text = rewrite_links(
+            "[[b]] [[b#x|B]] [[missing|M]] [a](a.md#y) [c](c.md)",
+            Path::new("/r"),
+            Path::new("/r/notes/doc.md"),
+            Path::new("/s/notes"),
+            &pages,
+            &stems,
+        );
+        assert_eq!(
+            text,
+            "[b](<../b.html>) [B](<../b.html#x>) M [a](a.html#y) [c](c.md)"
+        );
+    }
+}
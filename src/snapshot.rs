@@ -0,0 +1,73 @@
+//! Copying query results to a directory (`v snapshot`)
+use anyhow::{bail, Context, Result};
+use std::path::PathBuf;
+
+use crate::{cfg, query, root::DocRoot};
+
+pub fn verb_snapshot(root: &DocRoot, sc: &cfg::Snapshot) -> Result<()> {
+    let out_dir = std::env::current_dir()
+        .context("Failed to determine the current directory")?
+        .join(&sc.output);
+    if canonical_ancestor(&out_dir).starts_with(canonical_ancestor(&root.path)) {
+        bail!(
+            "The output directory {:?} must be outside the document root",
+            sc.output
+        );
+    }
+
+    let query = sc.query.compile(root)?;
+    let docs = query::select_all_sorted(root, &query)
+        .context("An error occurred while enumerating matching documents")?;
+
+    // Decide the destinations first so that nothing is copied on conflicts
+    let mut copies: Vec<(PathBuf, PathBuf)> = Vec::with_capacity(docs.len());
+    for doc in docs.iter() {
+        let rel_path = doc.path().strip_prefix(&root.path).unwrap_or(doc.path());
+        let dest = out_dir.join(rel_path);
+        if dest.exists() && !sc.force {
+            bail!("{:?} already exists; use `--force` to overwrite it", dest);
+        }
+        copies.push((doc.path().to_owned(), dest));
+    }
+
+    for (src, dest) in copies.iter() {
+        println!("{}", dest.display());
+        if sc.dry_run {
+            continue;
+        }
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {:?}", parent))?;
+        }
+        if sc.hardlink {
+            if dest.exists() {
+                std::fs::remove_file(dest)
+                    .with_context(|| format!("Failed to remove {:?}", dest))?;
+            }
+            std::fs::hard_link(src, dest)
+                .with_context(|| format!("Failed to link {:?} to {:?}", dest, src))?;
+        } else {
+            std::fs::copy(src, dest)
+                .with_context(|| format!("Failed to copy {:?} to {:?}", src, dest))?;
+        }
+    }
+
+    if sc.dry_run {
+        log::info!("Dry run; nothing was copied");
+    } else {
+        let verb = if sc.hardlink { "Linked" } else { "Copied" };
+        log::info!("{} {} document(s) to {:?}", verb, copies.len(), sc.output);
+    }
+    Ok(())
+}
+
+/// Canonicalize the nearest existing ancestor of `path` (which may not exist
+/// yet) and append the rest.
+fn canonical_ancestor(path: &std::path::Path) -> PathBuf {
+    for ancestor in path.ancestors() {
+        if let Ok(canonical) = std::fs::canonicalize(ancestor) {
+            return canonical.join(path.strip_prefix(ancestor).unwrap());
+        }
+    }
+    path.to_owned()
+}
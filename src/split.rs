@@ -0,0 +1,274 @@
+//! Splitting documents at headings (`v split`)
+use anyhow::{bail, Context, Result};
+use serde_yaml::{Mapping, Value};
+use std::path::{Path, PathBuf};
+use veisku_core::{doctype::DocType, journal::Journal};
+
+use crate::{cfg, doc, id, naming, root::DocRoot};
+
+/// The metadata fields identifying the original document, which are not
+/// copied to the new documents
+const NOT_INHERITED: &[&str] = &["title", "id", "aliases"];
+
+pub fn verb_split(root: &DocRoot, sc: &cfg::Split) -> Result<()> {
+    if !sc.dry_run {
+        root.ensure_writable()?;
+    }
+
+    let mut doc = sc.query.select_one(root)?;
+    let path = doc.path().to_owned();
+    if doc.doc_type() != DocType::Markdown {
+        bail!("Only Markdown documents can be split: {:?}", path);
+    }
+    if root.cfg.encryption_for(&path).is_some() {
+        bail!("Splitting encrypted documents is not supported: {:?}", path);
+    }
+    let meta = match doc
+        .ensure_meta()
+        .with_context(|| format!("Failed to read the metadata of {:?}", path))?
+    {
+        Value::Mapping(mapping) => mapping.clone(),
+        _ => Mapping::new(),
+    };
+
+    let bytes = std::fs::read(&path).with_context(|| format!("Failed to read {:?}", path))?;
+    let body_start = bytes.len() - doc::split_md_preamble(&bytes).1.len();
+    let body = std::str::from_utf8(&bytes[body_start..])
+        .with_context(|| format!("The body of {:?} is not valid UTF-8", path))?;
+
+    let level = match sc.level {
+        Some(level) => level,
+        None => split_level(body).context("The document has no headings")?,
+    };
+    let (intro, sections) = split_sections(body, level);
+    if sections.is_empty() {
+        bail!("The document has no level-{} headings", level);
+    }
+
+    // Plan the new documents
+    let dir = path.parent().unwrap();
+    let stem = path.file_stem().unwrap().to_string_lossy().into_owned();
+    let ext = path
+        .extension()
+        .map_or(String::new(), |ext| format!(".{}", ext.to_string_lossy()));
+    let mut taken_ids = match root.cfg.new.id {
+        Some(_) => id::existing_ids(root)?,
+        None => Default::default(),
+    };
+    let mut new_docs: Vec<(PathBuf, Vec<u8>)> = Vec::new();
+    for (i, section) in sections.iter().enumerate() {
+        let mut slug = naming::slugify(section.heading, &root.cfg.slug);
+        if slug.is_empty() {
+            slug = format!("{}-{}", stem, i + 1);
+        }
+        let new_path = (1..)
+            .map(|k| match k {
+                1 => format!("{}{}", slug, ext),
+                k => format!("{}-{}{}", slug, k, ext),
+            })
+            .map(|file_name| naming::disambiguate(dir, &file_name))
+            .find(|new_path| new_docs.iter().all(|(other, _)| other != new_path))
+            .unwrap();
+
+        let new_body = format!("Split from [[{}]].\n\n{}", stem, section.text);
+        let contents = doc::rewrite_meta(new_body.as_bytes(), |new_meta| {
+            new_meta.insert("title".into(), section.heading.into());
+            if let Some(scheme) = root.cfg.new.id {
+                let new_id = id::generate_unique(scheme, &mut taken_ids);
+                new_meta.insert("id".into(), new_id.into());
+            }
+            for (key, value) in meta.iter() {
+                if !key.as_str().is_some_and(|key| NOT_INHERITED.contains(&key)) {
+                    new_meta.insert(key.clone(), value.clone());
+                }
+            }
+            Ok(())
+        })?;
+        new_docs.push((new_path, contents));
+    }
+
+    // Replace the sections with links to the new documents
+    let mut new_bytes = bytes[..body_start].to_owned();
+    let intro = intro.trim_end();
+    if !intro.is_empty() {
+        new_bytes.extend_from_slice(intro.as_bytes());
+        new_bytes.extend_from_slice(b"\n\n");
+    }
+    for (new_path, _) in new_docs.iter() {
+        let new_stem = new_path.file_stem().unwrap().to_string_lossy();
+        new_bytes.extend_from_slice(format!("- [[{}]]\n", new_stem).as_bytes());
+    }
+
+    let rel = |path: &Path| path.strip_prefix(&root.path).unwrap_or(path).to_owned();
+    for ((new_path, _), section) in new_docs.iter().zip(sections.iter()) {
+        println!("create {}: {}", rel(new_path).display(), section.heading);
+    }
+    println!(
+        "rewrite {}: {} section(s) replaced with links",
+        rel(&path).display(),
+        sections.len()
+    );
+
+    if sc.dry_run {
+        log::info!("Dry run; nothing was modified");
+        return Ok(());
+    }
+
+    let journal = Journal::new(root);
+    let mut recorder = journal.begin(format!(
+        "split {} ({} document(s))",
+        rel(&path).display(),
+        new_docs.len()
+    ));
+    for (new_path, contents) in new_docs.iter() {
+        recorder.create(new_path, contents)?;
+    }
+    recorder
+        .write_atomic(&path, &new_bytes)
+        .with_context(|| format!("Failed to update {:?}", path))?;
+    recorder.commit()?;
+
+    log::info!("Split {:?} into {} document(s)", path, new_docs.len());
+    Ok(())
+}
+
+/// A section of a Markdown document
+#[derive(Debug, PartialEq)]
+struct Section<'a> {
+    /// The text of the heading
+    heading: &'a str,
+    /// The text following the heading line, without the surrounding blank
+    /// lines
+    text: String,
+}
+
+/// Parse an ATX heading (e.g., `## Title ##`). Returns the level and the text.
+fn parse_heading(line: &str) -> Option<(usize, &str)> {
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+    let level = trimmed.bytes().take_while(|&b| b == b'#').count();
+    let rest = &trimmed[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with([' ', '\t'])) {
+        return None;
+    }
+    // Remove the optional closing sequence
+    let text = rest.trim();
+    let text = match text.trim_end_matches('#') {
+        stripped if stripped.is_empty() || stripped.ends_with([' ', '\t']) => stripped.trim_end(),
+        _ => text,
+    };
+    Some((level, text))
+}
+
+/// Iterate over the lines of `body` outside fenced code blocks, along with
+/// their byte offsets.
+fn lines_outside_code(body: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut fence: Option<&str> = None;
+    body.split_inclusive('\n')
+        .scan(0, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some((start, line))
+        })
+        .filter(move |(_, line)| {
+            let trimmed = line.trim();
+            match fence {
+                Some(marker) => {
+                    if trimmed.starts_with(marker) {
+                        fence = None;
+                    }
+                    false
+                }
+                None => {
+                    fence = ["```", "~~~"]
+                        .iter()
+                        .copied()
+                        .find(|marker| trimmed.starts_with(marker));
+                    fence.is_none()
+                }
+            }
+        })
+}
+
+/// Choose the level of the headings to split at: the shallowest level having
+/// more than one heading, or the shallowest level if there's no such level.
+fn split_level(body: &str) -> Option<usize> {
+    let mut counts = [0usize; 7];
+    for (_, line) in lines_outside_code(body) {
+        if let Some((level, _)) = parse_heading(line) {
+            counts[level] += 1;
+        }
+    }
+    (1..=6)
+        .find(|&level| counts[level] > 1)
+        .or_else(|| (1..=6).find(|&level| counts[level] > 0))
+}
+
+/// Split `body` at the headings of `level`. Returns the text before the first
+/// heading and the sections.
+fn split_sections(body: &str, level: usize) -> (&str, Vec<Section<'_>>) {
+    let headings: Vec<(usize, usize, &str)> = lines_outside_code(body)
+        .filter_map(|(start, line)| match parse_heading(line) {
+            Some((l, text)) if l == level => Some((start, start + line.len(), text)),
+            _ => None,
+        })
+        .collect();
+    let intro = &body[..headings.first().map_or(body.len(), |&(start, _, _)| start)];
+    let sections = headings
+        .iter()
+        .enumerate()
+        .map(|(i, &(_, text_start, heading))| {
+            let end = headings
+                .get(i + 1)
+                .map_or(body.len(), |&(start, _, _)| start);
+            let text = body[text_start..end].trim_matches(['\n', '\r']);
+            Section {
+                heading,
+                text: if text.is_empty() {
+                    String::new()
+                } else {
+                    format!("{}\n", text)
+                },
+            }
+        })
+        .collect();
+    (intro, sections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_heading() {
+        assert_eq!(parse_heading("# Title\n"), Some((1, "Title")));
+        assert_eq!(parse_heading("  ## A b ##"), Some((2, "A b")));
+        assert_eq!(parse_heading("### C#"), Some((3, "C#")));
+        assert_eq!(parse_heading("#hashtag"), None);
+        assert_eq!(parse_heading("    # code"), None);
+        assert_eq!(parse_heading("####### seven"), None);
+    }
+
+    #[test]
+    fn test_split_sections() {
+        let body = "# Notes\nintro\n\n## A\n\na text\n```\n## not a heading\n```\n\n## B\n";
+        assert_eq!(split_level(body), Some(2));
+        let (intro, sections) = split_sections(body, 2);
+        assert_eq!(intro, "# Notes\nintro\n\n");
+        assert_eq!(
+            sections,
+            [
+                Section {
+                    heading: "A",
+                    text: "a text\n```\n## not a heading\n```\n".to_owned(),
+                },
+                Section {
+                    heading: "B",
+                    text: String::new(),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,43 @@
+//! State management (`v state`)
+use anyhow::Result;
+use veisku_core::state::{self, Component};
+
+use crate::{cfg, root::DocRoot};
+
+pub fn verb_state(root: &DocRoot, sc: &cfg::State) -> Result<()> {
+    match &sc.subcmd {
+        cfg::StateSubcommand::Clear(sc) => verb_state_clear(root, sc),
+    }
+}
+
+fn verb_state_clear(root: &DocRoot, sc: &cfg::StateClear) -> Result<()> {
+    let components: Vec<Component> = if sc.all {
+        Component::ALL.to_vec()
+    } else if sc.components.is_empty() {
+        vec![Component::Cache]
+    } else {
+        sc.components.clone()
+    };
+    if components.contains(&Component::Trash) && !sc.dry_run {
+        root.ensure_writable()?;
+    }
+
+    let state_dir = root.state_dir();
+    for &component in components.iter() {
+        let path = state_dir.component_path(component);
+        if !path.exists() {
+            log::info!("The {} is already empty", component.name());
+            continue;
+        }
+        if sc.dry_run {
+            println!("remove {}", path.display());
+        } else if state::clear(path.parent().unwrap(), component)? {
+            log::info!("Removed {:?}", path);
+        }
+    }
+
+    if sc.dry_run {
+        log::info!("Dry run; nothing was modified");
+    }
+    Ok(())
+}
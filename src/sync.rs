@@ -0,0 +1,63 @@
+//! Synchronizing the document root (`v sync`)
+use ansi_term::Style;
+use anyhow::{bail, Context, Result};
+use std::ffi::OsString;
+
+use crate::{cfg, root::DocRoot};
+
+pub fn verb_sync(root: &DocRoot, sc: &cfg::Sync) -> Result<()> {
+    let steps = &root.cfg.sync.steps;
+    if steps.is_empty() {
+        bail!(
+            "No sync pipeline is configured. Please define `sync.steps` in {:?}",
+            root.cfg_file_path()
+        );
+    }
+
+    let date = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+
+    for (i, step) in steps.iter().enumerate() {
+        if step.command.is_empty() {
+            bail!("The command of step {} is empty", i + 1);
+        }
+        let argv: Vec<OsString> = step
+            .command
+            .iter()
+            .map(|arg| arg.replace("{date}", &date).into())
+            .collect();
+        let display = argv
+            .iter()
+            .map(|arg| arg.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        if log::log_enabled!(log::Level::Info) {
+            eprintln!("{}", Style::new().bold().paint(format!("==> {}", display)));
+        }
+        if sc.dry_run {
+            continue;
+        }
+
+        let mut cmd = crate::new_command(&argv);
+        cmd.current_dir(&root.path);
+        crate::set_script_env(&mut cmd, root);
+        log::debug!("Spawning {:?}", cmd);
+        let status = cmd
+            .status()
+            .with_context(|| format!("Failed to execute {:?}", cmd))?;
+
+        if !status.success() {
+            if step.allow_failure {
+                log::info!("Step {} exited with {}; continuing", i + 1, status);
+            } else {
+                bail!("Step {} (`{}`) exited with {}", i + 1, display, status);
+            }
+        }
+    }
+
+    if !sc.dry_run {
+        log::info!("Sync completed");
+    }
+
+    Ok(())
+}
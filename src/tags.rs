@@ -0,0 +1,310 @@
+//! Tag management (`v tags`, `v rename-tag`)
+use anyhow::{Context, Result};
+use serde_yaml::{Mapping, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Write,
+    path::PathBuf,
+};
+use veisku_core::{doctype::DocType, journal::Journal};
+
+use crate::{cfg, doc, query, render, root::DocRoot, schema, tag};
+
+pub fn verb_tags(root: &DocRoot, opts: &cfg::Opts, sc: &cfg::Tags) -> Result<()> {
+    let query = sc.query.compile(root)?;
+    let mut doc_tags: Vec<BTreeSet<String>> = Vec::new();
+    for doc_or_error in query::select_all(root, &query) {
+        let mut doc =
+            doc_or_error.context("An error occurred while enumerating matching documents")?;
+        let path = doc.path().to_owned();
+        let meta = doc
+            .ensure_meta()
+            .with_context(|| format!("Failed to read the metadata of {:?}", path))?;
+        doc_tags.push(match &meta["tags"] {
+            Value::Sequence(tags) => tags.iter().filter_map(schema::scalar_text).collect(),
+            value => schema::scalar_text(value).into_iter().collect(),
+        });
+    }
+
+    let mut out = render::Pager::new(opts);
+    if sc.tree {
+        for (components, count) in tag_tree(&doc_tags) {
+            writeln!(
+                out,
+                "{:>6}  {}{}",
+                count,
+                "  ".repeat(components.len() - 1),
+                components.last().unwrap()
+            )?;
+        }
+    } else {
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for tag in doc_tags.iter().flatten() {
+            *counts.entry(tag).or_default() += 1;
+        }
+        let mut counts: Vec<(&str, usize)> = counts.into_iter().collect();
+        counts.sort_by(|(_, count1), (_, count2)| count2.cmp(count1));
+        for (tag, count) in counts {
+            writeln!(out, "{:>6}  {}", count, tag)?;
+        }
+    }
+    out.finish()?;
+    Ok(())
+}
+
+/// Count the documents having each tag or its descendants, given the tags of
+/// each document. The keys are the components of the tags, so iterating the
+/// result visits a tag before its descendants.
+fn tag_tree(doc_tags: &[BTreeSet<String>]) -> BTreeMap<Vec<&str>, usize> {
+    let mut tree: BTreeMap<Vec<&str>, usize> = BTreeMap::new();
+    for tags in doc_tags {
+        let nodes: BTreeSet<&str> = tags.iter().flat_map(|t| tag::ancestors(t)).collect();
+        for node in nodes {
+            *tree
+                .entry(node.split(tag::SEPARATOR).collect())
+                .or_default() += 1;
+        }
+    }
+    tree
+}
+
+pub fn verb_rename_tag(root: &DocRoot, sc: &cfg::RenameTag) -> Result<()> {
+    if !sc.dry_run {
+        root.ensure_writable()?;
+    }
+
+    // Find the documents to update
+    let mut changes: Vec<(PathBuf, Vec<u8>, bool, usize)> = Vec::new();
+    for doc in root.docs() {
+        let doc = doc.context("An error occurred while enumerating documents")?;
+        let path = doc.path().to_owned();
+        let doc_type = doc.doc_type();
+        if matches!(doc_type, DocType::Pdf | DocType::Image) {
+            continue;
+        }
+        if root.cfg.encryption_for(&path).is_some() {
+            log::debug!("Skipping the encrypted document {:?}", path);
+            continue;
+        }
+        let bytes = std::fs::read(&path).with_context(|| format!("Failed to read {:?}", path))?;
+
+        let mut in_meta = false;
+        let mut new_bytes = if doc_type.has_preamble() {
+            let new_bytes = doc::rewrite_meta(&bytes, |meta| {
+                in_meta = rename_in_meta(meta, &sc.old, &sc.new);
+                Ok(())
+            });
+            match new_bytes {
+                Ok(new_bytes) => new_bytes,
+                Err(e) => {
+                    log::warn!("Skipping {:?}: {:#}", path, e);
+                    continue;
+                }
+            }
+        } else {
+            bytes.clone()
+        };
+
+        let mut num_inline = 0;
+        if sc.inline {
+            let body_start = new_bytes.len() - doc::split_md_preamble(&new_bytes).1.len();
+            match std::str::from_utf8(&new_bytes[body_start..]) {
+                Ok(body) => {
+                    let (new_body, count) = rename_inline(body, &sc.old, &sc.new);
+                    if count > 0 {
+                        num_inline = count;
+                        new_bytes.truncate(body_start);
+                        new_bytes.extend_from_slice(new_body.as_bytes());
+                    }
+                }
+                Err(_) => log::warn!("Skipping the body of {:?}, which is not valid UTF-8", path),
+            }
+        }
+
+        if new_bytes != bytes {
+            changes.push((path, new_bytes, in_meta, num_inline));
+        }
+    }
+
+    // Summary
+    for (path, new_bytes, in_meta, num_inline) in changes.iter() {
+        let rel_path = path.strip_prefix(&root.path).unwrap_or(path);
+        if sc.diff {
+            let bytes =
+                std::fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+            let mut out = std::io::stdout();
+            render::write_diff(&mut out, &root.cfg.theme, rel_path, &bytes, new_bytes)?;
+            continue;
+        }
+        let mut parts = Vec::new();
+        if *in_meta {
+            parts.push("tags".to_owned());
+        }
+        if *num_inline > 0 {
+            parts.push(format!("{} inline tag(s)", num_inline));
+        }
+        println!("{}: {}", rel_path.display(), parts.join(", "));
+    }
+
+    if changes.is_empty() {
+        log::info!("No documents have the tag '{}'", sc.old);
+        return Ok(());
+    }
+
+    if sc.dry_run {
+        log::info!("Dry run; nothing was modified");
+        return Ok(());
+    }
+
+    let journal = Journal::new(root);
+    let mut recorder = journal.begin(format!(
+        "rename-tag {} {} ({} document(s))",
+        sc.old,
+        sc.new,
+        changes.len()
+    ));
+    for (path, new_bytes, _, _) in changes.iter() {
+        recorder
+            .write_atomic(path, new_bytes)
+            .with_context(|| format!("Failed to update {:?}", path))?;
+    }
+    recorder.commit()?;
+
+    log::info!(
+        "Renamed the tag '{}' to '{}' in {} document(s)",
+        sc.old,
+        sc.new,
+        changes.len()
+    );
+
+    Ok(())
+}
+
+/// Replace the tag `old` with `new` in the `tags` field of `meta`. Returns
+/// `true` if the field was modified.
+fn rename_in_meta(meta: &mut Mapping, old: &str, new: &str) -> bool {
+    let old = Value::String(old.to_owned());
+    let new = Value::String(new.to_owned());
+    match meta.get_mut(&Value::String("tags".to_owned())) {
+        Some(Value::Sequence(tags)) if tags.contains(&old) => {
+            let mut seen_new = false;
+            tags.retain_mut(|tag| {
+                if *tag == old {
+                    *tag = new.clone();
+                }
+                if *tag == new {
+                    // Keep only the first occurrence of `new`
+                    !std::mem::replace(&mut seen_new, true)
+                } else {
+                    true
+                }
+            });
+            true
+        }
+        Some(tag) if *tag == old => {
+            *tag = new;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Check if `c` can be a part of an inline tag.
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '/')
+}
+
+/// Replace the inline tags `#old` in `text` with `#new`. A `#` preceded by a
+/// word character (e.g., a URL fragment) doesn't start a tag. Returns the new
+/// text and the number of replaced tags.
+fn rename_inline(text: &str, old: &str, new: &str) -> (String, usize) {
+    let pattern = format!("#{}", old);
+    let mut out = String::with_capacity(text.len());
+    let mut count = 0;
+    let mut rest = text;
+    while let Some(i) = rest.find(&pattern) {
+        let end = i + pattern.len();
+        let preceded = out
+            .chars()
+            .chain(rest[..i].chars())
+            .next_back()
+            .is_some_and(|c| is_tag_char(c) || c == '#' || c == '&');
+        let followed = rest[end..].chars().next().is_some_and(is_tag_char);
+        out.push_str(&rest[..i]);
+        if preceded || followed {
+            out.push_str(&pattern);
+        } else {
+            out.push('#');
+            out.push_str(new);
+            count += 1;
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    (out, count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tag_tree() {
+        let doc_tags: Vec<BTreeSet<String>> = vec![
+            ["project/alpha", "project/beta"]
+                .iter()
+                .map(|&t| t.into())
+                .collect(),
+            ["project-x", "project/alpha"]
+                .iter()
+                .map(|&t| t.into())
+                .collect(),
+        ];
+        let tree: Vec<(String, usize)> = tag_tree(&doc_tags)
+            .into_iter()
+            .map(|(components, count)| (components.join("/"), count))
+            .collect();
+        assert_eq!(
+            tree,
+            [
+                ("project".to_owned(), 2),
+                ("project/alpha".to_owned(), 2),
+                ("project/beta".to_owned(), 1),
+                ("project-x".to_owned(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rename_in_meta() {
+        let rename = |yaml: &str| {
+            let mut meta: Mapping = serde_yaml::from_str(yaml).unwrap();
+            let modified = rename_in_meta(&mut meta, "todo", "task");
+            (meta[&Value::from("tags")].clone(), modified)
+        };
+        let seq = |tags: &[&str]| Value::Sequence(tags.iter().map(|&t| t.into()).collect());
+        assert_eq!(
+            rename("tags: [a, todo, b]"),
+            (seq(&["a", "task", "b"]), true)
+        );
+        assert_eq!(rename("tags: [task, todo]"), (seq(&["task"]), true));
+        assert_eq!(rename("tags: [a]"), (seq(&["a"]), false));
+        assert_eq!(rename("tags: todo"), (Value::from("task"), true));
+    }
+
+    #[test]
+    fn test_rename_inline() {
+        assert_eq!(
+            rename_inline(
+                "#todo call #todo-later\n(#todo) a#todo #todo/x",
+                "todo",
+                "task"
+            ),
+            (
+                "#task call #todo-later\n(#task) a#todo #todo/x".to_owned(),
+                2
+            )
+        );
+        assert_eq!(rename_inline("##todo &#todo", "todo", "task").1, 0);
+    }
+}
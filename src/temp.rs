@@ -0,0 +1,65 @@
+//! Temporary files
+use anyhow::{Context, Result};
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// A temporary directory only accessible by the current user. The directory is
+/// removed when dropped.
+pub struct PrivateTempDir(PathBuf);
+
+impl PrivateTempDir {
+    pub fn new() -> Result<Self> {
+        // A random name can't be claimed in advance by other users, and
+        // `create` fails instead of reusing a directory that already exists
+        const MAX_ATTEMPTS: usize = 16;
+        let mut attempt = 0;
+        loop {
+            let path =
+                std::env::temp_dir().join(format!("veisku-{}", uuid::Uuid::new_v4().to_simple()));
+
+            let mut builder = std::fs::DirBuilder::new();
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::DirBuilderExt;
+                builder.mode(0o700);
+            }
+            match builder.create(&path) {
+                Ok(()) => return Ok(Self(path)),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists && attempt < MAX_ATTEMPTS => {
+                    attempt += 1;
+                }
+                Err(e) => {
+                    return Err(e).with_context(|| {
+                        format!("Failed to create a temporary directory {:?}", path)
+                    })
+                }
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Leave the directory in place instead of removing it (e.g., to preserve
+    /// the user's work after a failure), and return its path.
+    pub fn keep(self) -> PathBuf {
+        let path = self.0.clone();
+        std::mem::forget(self);
+        path
+    }
+}
+
+impl Drop for PrivateTempDir {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.0) {
+            log::warn!(
+                "Failed to remove the temporary directory {:?}: {:?}",
+                self.0,
+                e
+            );
+        }
+    }
+}
@@ -0,0 +1,227 @@
+//! Document templates (`v new`)
+//!
+//! A template is an ordinary file in which `{{NAME}}` and `{{NAME:ARG}}` are
+//! replaced with the values of variables. Values inserted into a YAML
+//! preamble are escaped so that they can't add fields or break the preamble
+//! (see [`expand_document`]).
+use anyhow::{anyhow, bail, Result};
+use serde_yaml::Value;
+use std::{collections::HashMap, fmt::Write};
+
+use crate::clipboard;
+
+/// Replace the placeholders `{{NAME}}` and `{{NAME:ARG}}` in `template` with
+/// the values returned by `resolve`.
+pub fn expand(
+    template: &str,
+    mut resolve: impl FnMut(&str, Option<&str>) -> Result<String>,
+) -> Result<String> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(i) = rest.find("{{") {
+        out.push_str(&rest[..i]);
+        rest = &rest[i + 2..];
+
+        let end = rest
+            .find("}}")
+            .ok_or_else(|| anyhow!("Unterminated placeholder: `{{{{{}`", rest))?;
+        let placeholder = rest[..end].trim();
+        rest = &rest[end + 2..];
+
+        let (name, arg) = match placeholder.find(':') {
+            Some(i) => (&placeholder[..i], Some(&placeholder[i + 1..])),
+            None => (placeholder, None),
+        };
+        out.push_str(&resolve(name, arg)?);
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Like [`expand`], but values placed in the YAML preamble of `template` are
+/// escaped. A placeholder making up a whole value (e.g., `title: {{title}}` or
+/// `- {{tag}}`) is replaced with a YAML scalar, quoted if necessary. Line
+/// breaks in other placeholders in the preamble are replaced with spaces.
+pub fn expand_document(
+    template: &str,
+    mut resolve: impl FnMut(&str, Option<&str>) -> Result<String>,
+) -> Result<String> {
+    let mut out = String::with_capacity(template.len());
+    let mut lines = template.split_inclusive('\n');
+    let mut rest = template;
+
+    if let Some(first_line) = lines.next().filter(|line| line.trim_end() == "---") {
+        out.push_str(first_line);
+        rest = &rest[first_line.len()..];
+
+        for line in lines {
+            let content = line.trim_end_matches(&['\r', '\n'][..]);
+            let newline = &line[content.len()..];
+            rest = &rest[line.len()..];
+
+            if content == "---" || content == "..." {
+                out.push_str(line);
+                break;
+            }
+
+            match split_whole_value(content) {
+                Some((prefix, placeholder)) => {
+                    out.push_str(prefix);
+                    out.push_str(&yaml_scalar(&expand(placeholder, &mut resolve)?));
+                }
+                None => out.push_str(&expand(content, |name, arg| {
+                    Ok(resolve(name, arg)?.replace(&['\r', '\n'][..], " "))
+                })?),
+            }
+            out.push_str(newline);
+        }
+    }
+
+    out.push_str(&expand(rest, resolve)?);
+    Ok(out)
+}
+
+/// Split a preamble line `key: {{NAME}}` or `- {{NAME}}` into the part before
+/// the placeholder and the placeholder.
+fn split_whole_value(line: &str) -> Option<(&str, &str)> {
+    let value_start = if let Some(item) = line.trim_start().strip_prefix("- ") {
+        line.len() - item.len()
+    } else {
+        let i = line.find(':')?;
+        if line[..i].contains("{{") {
+            return None;
+        }
+        i + 1
+    };
+    let value = line[value_start..].trim();
+    let is_placeholder = value.starts_with("{{")
+        && value.ends_with("}}")
+        && value.matches("{{").count() == 1
+        && value.len() >= 4;
+    if !is_placeholder {
+        return None;
+    }
+    let end = line.len() - line[value_start..].trim_start().len();
+    Some((&line[..end], value))
+}
+
+/// Render `value` as a YAML scalar. `value` is inserted as-is if it reads back
+/// as the same string, number, or boolean, and double-quoted otherwise.
+fn yaml_scalar(value: &str) -> String {
+    let is_plain = !value.contains(&['\r', '\n'][..])
+        && match serde_yaml::from_str::<Value>(value) {
+            Ok(Value::String(st)) => st == value,
+            Ok(Value::Number(n)) => n.to_string() == value,
+            Ok(Value::Bool(b)) => b.to_string() == value,
+            _ => false,
+        };
+    if is_plain {
+        value.to_owned()
+    } else {
+        serde_json::to_string(value).unwrap()
+    }
+}
+
+/// The variables available to templates.
+///
+///  - User-defined variables (including `title`)
+///  - `date`, `date:FORMAT` - the current date and time, formatted by `FORMAT`
+///    (`%Y-%m-%d` by default). See [`chrono::format::strftime`] for the
+///    syntax.
+///  - `uuid` - a random UUID, which is the same throughout an instantiation
+///  - `clipboard` - the contents of the system clipboard
+pub struct Vars {
+    user: HashMap<String, String>,
+    now: chrono::DateTime<chrono::Local>,
+    uuid: uuid::Uuid,
+    clipboard: Option<String>,
+}
+
+impl Vars {
+    pub fn new(user: HashMap<String, String>) -> Self {
+        Self {
+            user,
+            now: chrono::Local::now(),
+            uuid: uuid::Uuid::new_v4(),
+            clipboard: None,
+        }
+    }
+
+    /// Use `now` as the current date and time of `date`.
+    pub fn with_now(self, now: chrono::DateTime<chrono::Local>) -> Self {
+        Self { now, ..self }
+    }
+
+    /// Get the value of the specified variable.
+    pub fn resolve(&mut self, name: &str, arg: Option<&str>) -> Result<String> {
+        if let Some(value) = self.user.get(name) {
+            if arg.is_some() {
+                bail!("User-defined variable '{}' does not take an argument", name);
+            }
+            return Ok(value.clone());
+        }
+
+        match (name, arg) {
+            ("date", fmt) => {
+                let fmt = fmt.unwrap_or("%Y-%m-%d");
+                let mut out = String::new();
+                write!(out, "{}", self.now.format(fmt))
+                    .map_err(|_| anyhow!("Invalid date format: {:?}", fmt))?;
+                Ok(out)
+            }
+            ("uuid", None) => Ok(self.uuid.to_string()),
+            ("clipboard", None) => {
+                if self.clipboard.is_none() {
+                    self.clipboard = Some(clipboard::paste()?);
+                }
+                Ok(self.clipboard.clone().unwrap())
+            }
+            ("uuid", Some(_)) | ("clipboard", Some(_)) => {
+                bail!("Variable '{}' does not take an argument", name)
+            }
+            _ => bail!("Unknown template variable: '{}'", name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let resolve = |name: &str, arg: Option<&str>| -> Result<String> {
+            Ok(format!("<{}|{}>", name, arg.unwrap_or("-")))
+        };
+        assert_eq!(
+            expand("a {{title}} b {{ date:%Y }}{{x}}", resolve).unwrap(),
+            "a <title|-> b <date|%Y><x|->"
+        );
+        assert_eq!(expand("{ {} }", resolve).unwrap(), "{ {} }");
+        assert!(expand("{{title", resolve).is_err());
+    }
+
+    #[test]
+    fn test_expand_document() {
+        let resolve = |name: &str, _: Option<&str>| -> Result<String> {
+            Ok(match name {
+                "n" => "42",
+                "a" => "a: b",
+                _ => "x\nid: 1",
+            }
+            .to_owned())
+        };
+        assert_eq!(
+            expand_document(
+                "---\ntitle: {{a}}\nn: {{ n }}\nt: [{{x}}]\n- {{x}}\n---\n{{x}}",
+                resolve
+            )
+            .unwrap(),
+            "---\ntitle: \"a: b\"\nn: 42\nt: [x id: 1]\n- \"x\\nid: 1\"\n---\nx\nid: 1"
+        );
+        assert_eq!(
+            expand_document("{{a}}\n---\n{{x}}", resolve).unwrap(),
+            "a: b\n---\nx\nid: 1"
+        );
+    }
+}
@@ -0,0 +1,66 @@
+//! Theme preview (`v theme`)
+use anyhow::Result;
+use serde_yaml::{Mapping, Value};
+use std::{io::Write, path::Path};
+
+use crate::{cfg, query, render, root::DocRoot};
+
+pub fn verb_theme(root: &DocRoot, opts: &cfg::Opts, sc: &cfg::Theme) -> Result<()> {
+    match &sc.subcmd {
+        cfg::ThemeSubcommand::Preview(sc) => verb_theme_preview(root, opts, sc),
+    }
+}
+
+fn verb_theme_preview(root: &DocRoot, opts: &cfg::Opts, sc: &cfg::ThemePreview) -> Result<()> {
+    let mut theme = root.cfg.theme.clone();
+    if let Some(base) = sc.base {
+        theme.base = base;
+    }
+
+    // Show the configured tag styles in addition to `tag_default`
+    let mut tags: Vec<&str> = theme.tags.keys().map(|tag| &**tag).collect();
+    tags.sort_unstable();
+    tags.push("tag_default");
+
+    let samples = [
+        (
+            "2026-10-12-weekly-meeting",
+            "{tags: [work, meeting], title: Weekly meeting notes}",
+        ),
+        ("reading-list", "{tags: [books], title: Reading list}"),
+        ("scratch", "{}"),
+    ];
+    // Highlight "meet" as in `v ls /meet/ title:/meet/`
+    let criteria = ["/meet/".parse().unwrap(), "title:/meet/".parse().unwrap()];
+    let query = query::Query::new(root, "", &criteria)?;
+
+    let mut out = render::Pager::new(opts);
+    for (name, meta) in samples.iter() {
+        let meta: Value = serde_yaml::from_str(meta)?;
+        let path = Path::new(name).with_extension("md");
+        render::write_doc_summary_highlighted(&mut out, &theme, &path, &meta, query.highlights())?;
+        writeln!(out)?;
+    }
+    let mut meta = Mapping::new();
+    meta.insert(
+        Value::from("tags"),
+        Value::Sequence(tags.iter().map(|&tag| tag.into()).collect()),
+    );
+    render::write_doc_summary(
+        &mut out,
+        &theme,
+        Path::new("tags.md"),
+        &Value::Mapping(meta),
+    )?;
+    writeln!(out)?;
+    writeln!(out)?;
+    render::write_diff(
+        &mut out,
+        &theme,
+        Path::new("diff.md"),
+        b"---\nstatus: draft\n---\n",
+        b"---\nstatus: done\n---\n",
+    )?;
+    out.finish()?;
+    Ok(())
+}
@@ -0,0 +1,121 @@
+//! Removing documents (`v rm`) and restoring them (`v trash`)
+use anyhow::{bail, Context, Result};
+use std::{io::Write, path::Path};
+use veisku_core::{
+    journal::Journal,
+    trash::{Trash, TrashEntry},
+};
+
+use crate::{cfg, meta, query, render, root::DocRoot};
+
+pub fn verb_rm(root: &DocRoot, sc: &cfg::Rm) -> Result<()> {
+    root.ensure_writable()?;
+
+    let query = sc.query.compile(root)?;
+    let mut docs = query::select_all_sorted(root, &query)
+        .context("An error occurred while enumerating matching documents")?;
+    if docs.is_empty() {
+        return Err(query::SelectOneError::Empty.into());
+    }
+
+    // Pre-flight summary
+    let mut out = std::io::stderr();
+    for doc in docs.iter_mut() {
+        let path = doc.path().to_owned();
+        match doc.ensure_meta() {
+            Ok(meta) => render::write_doc_summary(&mut out, &root.cfg.theme, &path, meta)?,
+            Err(_) => write!(out, "{}", path.display())?,
+        }
+        writeln!(out)?;
+    }
+
+    let question = if sc.permanent {
+        format!("Permanently delete {} document(s)?", docs.len())
+    } else {
+        format!("Move {} document(s) to the trash?", docs.len())
+    };
+    if !sc.yes && !meta::confirm(&question, false)? {
+        bail!("Aborted");
+    }
+
+    let trash = Trash::new(root);
+    let journal = Journal::new(root);
+    let mut recorder = journal.begin(format!("rm ({} document(s))", docs.len()));
+    for doc in docs.iter() {
+        if sc.permanent {
+            std::fs::remove_file(doc.path())
+                .with_context(|| format!("Failed to remove {:?}", doc.path()))?;
+        } else {
+            let entry = recorder.trash(&trash, doc.path())?;
+            log::debug!("Moved {:?} to the trash as {}", doc.path(), entry.id);
+        }
+    }
+    recorder.commit()?;
+
+    if sc.permanent {
+        log::info!("Deleted {} document(s)", docs.len());
+    } else {
+        log::info!(
+            "Moved {} document(s) to the trash; `v trash restore` restores them",
+            docs.len()
+        );
+    }
+    Ok(())
+}
+
+pub fn verb_trash(root: &DocRoot, sc: &cfg::Trash) -> Result<()> {
+    match &sc.subcmd {
+        cfg::TrashSubcommand::Ls(_) => verb_trash_ls(root),
+        cfg::TrashSubcommand::Restore(sc) => verb_trash_restore(root, sc),
+    }
+}
+
+fn verb_trash_ls(root: &DocRoot) -> Result<()> {
+    let entries = Trash::new(root).entries()?;
+    let id_width = entries.iter().map(|e| e.id.len()).max().unwrap_or(0);
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    for entry in entries.iter().rev() {
+        let time = chrono::DateTime::from_timestamp(entry.time, 0)
+            .map(|time| time.with_timezone(&chrono::Local))
+            .map_or_else(String::new, |time| {
+                time.format("%Y-%m-%d %H:%M:%S").to_string()
+            });
+        writeln!(
+            out,
+            "{:id_width$}  {}  {}",
+            entry.id,
+            time,
+            entry.path.display(),
+            id_width = id_width
+        )?;
+    }
+    Ok(())
+}
+
+fn verb_trash_restore(root: &DocRoot, sc: &cfg::TrashRestore) -> Result<()> {
+    root.ensure_writable()?;
+
+    let trash = Trash::new(root);
+    let entries = trash.entries()?;
+    let find = |doc: &str| -> Option<&TrashEntry> {
+        entries
+            .iter()
+            .find(|e| e.id == doc)
+            .or_else(|| entries.iter().rev().find(|e| e.path == Path::new(doc)))
+    };
+
+    let mut to_restore: Vec<&TrashEntry> = Vec::new();
+    for doc in sc.docs.iter() {
+        match find(doc) {
+            Some(entry) => to_restore.push(entry),
+            None => bail!("'{}' is not in the trash", doc),
+        }
+    }
+
+    for entry in to_restore {
+        let path = trash.restore(entry)?;
+        println!("{}", path.display());
+    }
+    Ok(())
+}
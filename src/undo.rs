@@ -0,0 +1,45 @@
+//! Reverting the last operation (`v undo`)
+use anyhow::Result;
+use veisku_core::{
+    journal::{Change, Journal, Operation},
+    trash::Trash,
+};
+
+use crate::{cfg, root::DocRoot};
+
+pub fn verb_undo(root: &DocRoot, sc: &cfg::Undo) -> Result<()> {
+    let journal = Journal::new(root);
+
+    if sc.dry_run {
+        match journal.operations()?.pop() {
+            Some(op) => print_operation(&op),
+            None => log::info!("There is nothing to undo"),
+        }
+        log::info!("Dry run; nothing was modified");
+        return Ok(());
+    }
+
+    root.ensure_writable()?;
+    match journal.undo(&Trash::new(root))? {
+        Some(op) => {
+            print_operation(&op);
+            log::info!("Reverted `{}`", op.description);
+        }
+        None => log::info!("There is nothing to undo"),
+    }
+    Ok(())
+}
+
+fn print_operation(op: &Operation) {
+    println!("{}", op.description);
+    for change in op.changes.iter() {
+        match change {
+            Change::Write { path, .. } => println!("  rewrite {}", path.display()),
+            Change::Create { path, .. } => println!("  create {}", path.display()),
+            Change::Move { from, to } => {
+                println!("  move {} → {}", from.display(), to.display())
+            }
+            Change::Trash { entry } => println!("  remove {}", entry.path.display()),
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! Watching for document changes (`v watch`)
+use anyhow::{Context, Result};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsString,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::{cfg, query, root::DocRoot};
+
+/// The state of a document used to detect modifications
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Stamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+#[derive(Debug, Default)]
+struct Snapshot {
+    /// The matching documents
+    matching: BTreeMap<PathBuf, Stamp>,
+    /// All documents in the document root, which tell documents that were
+    /// created or deleted from those that started or stopped matching
+    all: BTreeSet<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Event {
+    Created,
+    Modified,
+    Deleted,
+    /// An existing document started matching the query.
+    Matched,
+    /// A document stopped matching the query but still exists.
+    Unmatched,
+}
+
+impl Event {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Created => "created",
+            Self::Modified => "modified",
+            Self::Deleted => "deleted",
+            Self::Matched => "matched",
+            Self::Unmatched => "unmatched",
+        }
+    }
+}
+
+pub fn verb_watch(root: &DocRoot, sc: &cfg::Watch) -> Result<()> {
+    let query = sc.query.compile(root)?;
+    let interval = Duration::try_from_secs_f64(sc.interval.max(0.01))
+        .map_err(|_| anyhow::anyhow!("Invalid interval: {:?}", sc.interval))?;
+
+    let mut snapshot = take_snapshot(root, &query)?;
+    log::info!("Watching {} document(s)", snapshot.matching.len());
+
+    loop {
+        std::thread::sleep(interval);
+
+        let new_snapshot = match take_snapshot(root, &query) {
+            Ok(x) => x,
+            Err(e) => {
+                log::warn!("{:?}", e);
+                continue;
+            }
+        };
+        for (path, event) in diff(&snapshot, &new_snapshot) {
+            log::debug!("{} {:?}", event.as_str(), path);
+            match &sc.cmd {
+                Some(cmd) => run_command(root, cmd, path, event),
+                None => {
+                    let mut stdout = std::io::stdout();
+                    writeln!(stdout, "{} {}", event.as_str(), path.display())?;
+                    stdout.flush()?;
+                }
+            }
+        }
+        snapshot = new_snapshot;
+    }
+}
+
+fn take_snapshot(root: &DocRoot, query: &query::Query) -> Result<Snapshot> {
+    let mut snapshot = Snapshot::default();
+    for entry in root.doc_files() {
+        let entry = entry.context("An error occurred while enumerating documents")?;
+        snapshot.all.insert(entry.into_path());
+    }
+    for doc_or_error in query::select_all(root, query) {
+        let doc = doc_or_error.context("An error occurred while enumerating matching documents")?;
+        // The document might have been deleted in the meantime
+        if let Ok(metadata) = std::fs::metadata(doc.path()) {
+            let stamp = Stamp {
+                modified: metadata.modified().ok(),
+                len: metadata.len(),
+            };
+            snapshot.matching.insert(doc.path().to_owned(), stamp);
+        }
+    }
+    Ok(snapshot)
+}
+
+/// List the changes between two snapshots.
+fn diff<'a>(old: &'a Snapshot, new: &'a Snapshot) -> Vec<(&'a Path, Event)> {
+    let mut changes = Vec::new();
+    for (path, stamp) in &new.matching {
+        match old.matching.get(path) {
+            None if old.all.contains(path) => changes.push((&**path, Event::Matched)),
+            None => changes.push((&**path, Event::Created)),
+            Some(old_stamp) if old_stamp != stamp => changes.push((&**path, Event::Modified)),
+            Some(_) => {}
+        }
+    }
+    for path in old.matching.keys() {
+        if !new.matching.contains_key(path) {
+            let event = if new.all.contains(path) {
+                Event::Unmatched
+            } else {
+                Event::Deleted
+            };
+            changes.push((&**path, event));
+        }
+    }
+    changes
+}
+
+fn run_command(root: &DocRoot, cmd: &[OsString], path: &Path, event: Event) {
+    let mut cmd = crate::open_command(root, path, Some(cmd), false, Vec::new);
+    cmd.env("V_EVENT", event.as_str());
+    log::debug!("Spawning {:?}", cmd);
+    match cmd.status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => log::warn!("The command exited with {} for {:?}", status, path),
+        Err(e) => log::warn!("Failed to execute {:?}: {}", cmd, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let stamp = |len| Stamp {
+            modified: None,
+            len,
+        };
+        let snapshot = |matching: &[(&str, u64)], all: &[&str]| Snapshot {
+            matching: (matching.iter())
+                .map(|&(path, len)| (PathBuf::from(path), stamp(len)))
+                .collect(),
+            all: all.iter().map(PathBuf::from).collect(),
+        };
+        let old = snapshot(
+            &[("a", 1), ("b", 1), ("c", 1), ("e", 1)],
+            &["a", "b", "c", "e", "f"],
+        );
+        let new = snapshot(
+            &[("a", 1), ("b", 2), ("d", 1), ("f", 1)],
+            &["a", "b", "d", "e", "f"],
+        );
+        assert_eq!(
+            diff(&old, &new),
+            [
+                (Path::new("b"), Event::Modified),
+                (Path::new("d"), Event::Created),
+                (Path::new("f"), Event::Matched),
+                (Path::new("c"), Event::Deleted),
+                (Path::new("e"), Event::Unmatched),
+            ]
+        );
+    }
+}
@@ -0,0 +1,70 @@
+//! Word counts (`v wc`)
+use anyhow::{Context, Result};
+use serde_yaml::Value;
+use std::{collections::BTreeMap, io::Write};
+use veisku_core::stats::{StatsCache, TextStats};
+
+use crate::{cfg, query, render, root::DocRoot, schema};
+
+pub fn verb_wc(root: &DocRoot, opts: &cfg::Opts, sc: &cfg::Wc) -> Result<()> {
+    let query = sc.query.compile(root)?;
+    let docs = query::select_all_sorted(root, &query)
+        .context("An error occurred while enumerating matching documents")?;
+    let cache = StatsCache::load(root);
+    let mut out = render::Pager::new(opts);
+
+    let mut total = TextStats::default();
+    let mut groups: BTreeMap<String, (usize, TextStats)> = BTreeMap::new();
+
+    let heading = if sc.by.is_some() { "group" } else { "path" };
+    writeln!(out, "{:>8} {:>9}  {}", "words", "chars", heading)?;
+
+    for mut doc in docs {
+        let stats = cache
+            .get(&doc)
+            .with_context(|| format!("Failed to count the words in {:?}", doc.path()))?;
+        add(&mut total, stats);
+
+        if let Some(key) = &sc.by {
+            let path = doc.path().to_owned();
+            let meta = doc
+                .ensure_meta()
+                .with_context(|| format!("Failed to read the metadata of {:?}", path))?;
+            // A document is counted once in each group of a sequence
+            let names: Vec<String> = match &meta[&**key] {
+                Value::Sequence(array) => array.iter().filter_map(schema::scalar_text).collect(),
+                value => schema::scalar_text(value).into_iter().collect(),
+            };
+            if names.is_empty() {
+                let group = groups.entry("(none)".to_owned()).or_default();
+                group.0 += 1;
+                add(&mut group.1, stats);
+            }
+            for name in names {
+                let group = groups.entry(name).or_default();
+                group.0 += 1;
+                add(&mut group.1, stats);
+            }
+        } else {
+            writeln!(out, "{:>8} {:>9}  {}", stats.words, stats.chars, doc)?;
+        }
+    }
+
+    for (name, (num_docs, stats)) in groups.iter() {
+        writeln!(
+            out,
+            "{:>8} {:>9}  {} ({} document(s))",
+            stats.words, stats.chars, name, num_docs
+        )?;
+    }
+
+    writeln!(out, "{:>8} {:>9}  total", total.words, total.chars)?;
+
+    out.finish()?;
+    Ok(())
+}
+
+fn add(acc: &mut TextStats, stats: TextStats) {
+    acc.words += stats.words;
+    acc.chars += stats.chars;
+}